@@ -0,0 +1,103 @@
+//! Compara la cantidad de reservas de memoria y el RSS de una tabla con claves y valores chicos
+//! guardados como String y como Cadena. Cada escenario corre en un proceso aparte para que
+//! la memoria liberada por uno no cambie la medicion del otro.
+//!
+//! cargo bench --bench cadenas
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/cadena.rs"]
+mod cadena;
+
+use cadena::Cadena;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::hash::Hash;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const CLAVES: usize = 1_000_000;
+
+/// Asignador que cuenta las reservas vivas pedidas al asignador del sistema
+struct Contador;
+
+static RESERVAS: AtomicUsize = AtomicUsize::new(0);
+static LIBERACIONES: AtomicUsize = AtomicUsize::new(0);
+
+fn reservas_vivas() -> usize {
+    RESERVAS.load(Ordering::Relaxed) - LIBERACIONES.load(Ordering::Relaxed)
+}
+
+unsafe impl GlobalAlloc for Contador {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        RESERVAS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIBERACIONES.fetch_add(1, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ASIGNADOR: Contador = Contador;
+
+/// Memoria residente del proceso en KiB, segun /proc/self/status
+fn rss_kib() -> usize {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|estado| {
+            estado
+                .lines()
+                .find_map(|l| l.strip_prefix("VmRSS:"))
+                .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+fn medir<K: Hash + Eq, V>(
+    crear: impl Fn(String) -> K,
+    valor: impl Fn(String) -> V,
+) -> (usize, usize) {
+    let rss_inicial = rss_kib();
+    let reservas_iniciales = reservas_vivas();
+    let mut tabla = HashMap::with_capacity(CLAVES);
+    for i in 0..CLAVES {
+        tabla.insert(crear(format!("usuario:{}", i)), valor(i.to_string()));
+    }
+    let reservas = reservas_vivas() - reservas_iniciales;
+    let rss = rss_kib().saturating_sub(rss_inicial);
+    drop(tabla);
+    (reservas, rss)
+}
+
+fn main() {
+    let argumentos: Vec<String> = env::args().collect();
+    if let Some(escenario) = argumentos.iter().find(|a| a.starts_with("--escenario=")) {
+        let (reservas, rss) = match escenario.trim_start_matches("--escenario=") {
+            "cadena" => medir(Cadena::from, Cadena::from),
+            _ => medir(|c| c, |v| v),
+        };
+        println!("{} {}", reservas, rss);
+        return;
+    }
+
+    println!("{} claves `usuario:<n>` con valores `<n>`", CLAVES);
+    println!("{:<10}{:>16}{:>14}", "tipo", "reservas vivas", "RSS (KiB)");
+    for escenario in &["string", "cadena"] {
+        let salida = Command::new(env::current_exe().expect("ejecutable del benchmark"))
+            .arg(format!("--escenario={}", escenario))
+            .output()
+            .expect("no se pudo ejecutar el escenario");
+        let salida = String::from_utf8_lossy(&salida.stdout);
+        let mut valores = salida.split_whitespace();
+        println!(
+            "{:<10}{:>16}{:>14}",
+            escenario,
+            valores.next().unwrap_or("?"),
+            valores.next().unwrap_or("?")
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, Rol, TipoRedis};
+use crate::config::Config;
+use crate::interceptor::{Interceptor, Peticion};
+
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::Duration;
+
+/// Almacen persistente que respalda a las claves string de un prefijo, lo implementa quien
+/// embebe el servidor, por ejemplo sobre una base de datos relacional. El servidor funciona como
+/// cache: trae del almacen las claves que no tiene al leerlas (read-through) y le envia cada
+/// escritura o borrado (write-through). Las operaciones se ejecutan en otro hilo con una espera
+/// maxima, por lo que un almacen lento o colgado no bloquea a los clientes
+pub trait AlmacenExterno: Send + Sync {
+    /// Valor de la clave en el almacen, ninguno si no esta
+    fn leer(&self, clave: &str) -> io::Result<Option<String>>;
+
+    /// Guarda el valor de la clave en el almacen
+    fn escribir(&self, clave: &str, valor: &str) -> io::Result<()>;
+
+    /// Borra la clave del almacen
+    fn borrar(&self, clave: &str) -> io::Result<()>;
+}
+
+/// Almacenes externos registrados por prefijo de clave. Una clave la respalda el almacen del
+/// prefijo mas largo que coincide con ella, las claves que no coinciden con ninguno solo existen
+/// en el servidor
+#[derive(Default)]
+pub struct AlmacenesExternos {
+    almacenes: RwLock<Vec<(String, Arc<dyn AlmacenExterno>)>>,
+    lecturas: AtomicU64,
+    aciertos: AtomicU64,
+    escrituras: AtomicU64,
+    errores: AtomicU64,
+    esperas_agotadas: AtomicU64,
+}
+
+impl AlmacenesExternos {
+    pub fn new() -> Self {
+        AlmacenesExternos::default()
+    }
+
+    fn almacenes(&self) -> RwLockReadGuard<'_, Vec<(String, Arc<dyn AlmacenExterno>)>> {
+        match self.almacenes.read() {
+            Ok(a) => a,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    /// Registra el almacen que respalda a las claves del prefijo, reemplazando al anterior
+    pub fn registrar(&self, prefijo: &str, almacen: Arc<dyn AlmacenExterno>) {
+        let mut almacenes = match self.almacenes.write() {
+            Ok(a) => a,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        almacenes.retain(|(p, _)| p != prefijo);
+        almacenes.push((prefijo.to_string(), almacen));
+    }
+
+    /// Almacen que respalda a la clave, ninguno si la clave no tiene almacen externo
+    pub fn almacen_de(&self, clave: &str) -> Option<Arc<dyn AlmacenExterno>> {
+        self.almacenes()
+            .iter()
+            .filter(|(prefijo, _)| clave.starts_with(prefijo.as_str()))
+            .max_by_key(|(prefijo, _)| prefijo.len())
+            .map(|(_, almacen)| Arc::clone(almacen))
+    }
+
+    /// Predicado que indica si alguna de las claves tiene almacen externo
+    pub fn respalda_alguna(&self, claves: &[String]) -> bool {
+        let almacenes = self.almacenes();
+        claves.iter().any(|clave| {
+            almacenes
+                .iter()
+                .any(|(prefijo, _)| clave.starts_with(prefijo.as_str()))
+        })
+    }
+
+    /// Trae la clave del almacen que la respalda. Devuelve ninguno si no esta, si no tiene almacen
+    /// o si el almacen fallo o no respondio a tiempo, en cuyo caso la lectura es un miss
+    pub fn leer(&self, clave: &str, espera: Duration) -> Option<String> {
+        let almacen = self.almacen_de(clave)?;
+        self.lecturas.fetch_add(1, Ordering::Relaxed);
+        let clave = clave.to_string();
+        match self.contar(con_espera(espera, move || almacen.leer(&clave))) {
+            Ok(Some(valor)) => {
+                self.aciertos.fetch_add(1, Ordering::Relaxed);
+                Some(valor)
+            }
+            _ => None,
+        }
+    }
+
+    /// Envia al almacen que respalda a la clave su nuevo valor, o su borrado si es ninguno
+    pub fn escribir(&self, clave: &str, valor: Option<String>, espera: Duration) -> io::Result<()> {
+        let almacen = match self.almacen_de(clave) {
+            Some(a) => a,
+            None => return Ok(()),
+        };
+        self.escrituras.fetch_add(1, Ordering::Relaxed);
+        let clave = clave.to_string();
+        self.contar(con_espera(espera, move || match valor {
+            Some(valor) => almacen.escribir(&clave, &valor),
+            None => almacen.borrar(&clave),
+        }))
+    }
+
+    fn contar<T>(&self, resultado: io::Result<T>) -> io::Result<T> {
+        match &resultado {
+            Err(e) if e.kind() == ErrorKind::TimedOut => {
+                self.esperas_agotadas.fetch_add(1, Ordering::Relaxed)
+            }
+            Err(_) => self.errores.fetch_add(1, Ordering::Relaxed),
+            Ok(_) => 0,
+        };
+        resultado
+    }
+
+    /// Seccion Externalstore de INFO
+    pub fn seccion_info(&self) -> Vec<String> {
+        let prefijos: Vec<String> = self.almacenes().iter().map(|(p, _)| p.clone()).collect();
+        vec![
+            "# Externalstore".to_string(),
+            format!("external_store_prefixes:{}", prefijos.join(",")),
+            format!(
+                "external_store_reads:{}",
+                self.lecturas.load(Ordering::Relaxed)
+            ),
+            format!(
+                "external_store_hits:{}",
+                self.aciertos.load(Ordering::Relaxed)
+            ),
+            format!(
+                "external_store_writes:{}",
+                self.escrituras.load(Ordering::Relaxed)
+            ),
+            format!(
+                "external_store_errors:{}",
+                self.errores.load(Ordering::Relaxed)
+            ),
+            format!(
+                "external_store_timeouts:{}",
+                self.esperas_agotadas.load(Ordering::Relaxed)
+            ),
+            "".to_string(),
+        ]
+    }
+}
+
+impl fmt::Debug for AlmacenesExternos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefijos: Vec<String> = self.almacenes().iter().map(|(p, _)| p.clone()).collect();
+        f.debug_struct("AlmacenesExternos")
+            .field("prefijos", &prefijos)
+            .finish()
+    }
+}
+
+/// Conecta a los comandos con los almacenes externos. Solo el maestro habla con los almacenes,
+/// las replicas reciben lo que el trae o escribe
+pub struct RespaldoExterno {
+    almacenes: Arc<AlmacenesExternos>,
+    config: Arc<Mutex<Config>>,
+    tabla: Arc<Mutex<BaseDeDatos>>,
+}
+
+impl RespaldoExterno {
+    pub fn new(config: Arc<Mutex<Config>>, tabla: Arc<Mutex<BaseDeDatos>>) -> Self {
+        let almacenes = match config.lock() {
+            Ok(c) => c.almacenes_externos(),
+            Err(envenenado) => envenenado.into_inner().almacenes_externos(),
+        };
+        RespaldoExterno {
+            almacenes,
+            config,
+            tabla,
+        }
+    }
+
+    /// Espera maxima de cada operacion si el comando accede a claves respaldadas y este servidor
+    /// es el maestro, ninguna si el comando no tiene que hablar con los almacenes
+    fn espera(&self, claves: &[String]) -> Option<Duration> {
+        if !self.almacenes.respalda_alguna(claves) {
+            return None;
+        }
+        match self.tabla.lock() {
+            Ok(bdd) if bdd.rol() == Rol::Maestro => (),
+            _ => return None,
+        }
+        self.config.lock().ok().map(|c| c.espera_almacen_externo())
+    }
+
+    /// Read-through: guarda en la tabla las claves respaldadas por un almacen externo que no
+    /// estan, con el valor que tiene el almacen. Se consulta al almacen sin bloquear la base de
+    /// datos, y una clave que otro cliente escribio mientras tanto no se pisa
+    fn traer(&self, claves: &[String], espera: Duration) {
+        let faltantes: Vec<&String> = match self.tabla.lock() {
+            Ok(mut bdd) => claves.iter().filter(|c| !bdd.existe_clave(c)).collect(),
+            Err(_) => return,
+        };
+        for clave in faltantes {
+            if let Some(valor) = self.almacenes.leer(clave, espera) {
+                if let Ok(mut bdd) = self.tabla.lock() {
+                    if !bdd.existe_clave(clave) {
+                        bdd.guardar_valor(clave.to_string(), TipoRedis::Str(valor.into()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write-through: envia a los almacenes externos el valor de las claves que escribio el
+    /// comando, o su borrado si ya no existen. Las claves que guardan otro tipo de dato no se envian
+    fn enviar(&self, claves: &[String], espera: Duration) {
+        for clave in claves {
+            if self.almacenes.almacen_de(clave).is_none() {
+                continue;
+            }
+            let valor = match self.tabla.lock() {
+                Ok(bdd) => match bdd.obtener_como_str(clave) {
+                    Ok(valor) => valor.map(|v| v.to_string()),
+                    Err(_) => continue,
+                },
+                Err(_) => return,
+            };
+            let _ = self.almacenes.escribir(clave, valor, espera);
+        }
+    }
+}
+
+impl Interceptor for RespaldoExterno {
+    fn antes(&self, peticion: &Peticion) -> Option<ResultadoRedis> {
+        if peticion.es_lectura() {
+            if let Some(espera) = self.espera(&peticion.claves) {
+                self.traer(&peticion.claves, espera);
+            }
+        }
+        None
+    }
+
+    fn despues(&self, peticion: &Peticion, resultado: &ResultadoRedis, _duracion: Duration) {
+        if !peticion.es_escritura() || matches!(resultado, ResultadoRedis::Error(_)) {
+            return;
+        }
+        if let Some(espera) = self.espera(&peticion.claves) {
+            self.enviar(&peticion.claves, espera);
+        }
+    }
+}
+
+/// Ejecuta la operacion en otro hilo y espera su resultado como maximo la duracion indicada.
+/// Si no termina a tiempo devuelve un error TimedOut y el hilo queda terminando la operacion
+fn con_espera<T, F>(espera: Duration, operacion: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        let _ = tx.send(operacion());
+    });
+    match rx.recv_timeout(espera) {
+        Ok(resultado) => resultado,
+        Err(_) => Err(io::Error::new(
+            ErrorKind::TimedOut,
+            "el almacen externo no respondio a tiempo",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::ResultadoRedis;
+    use crate::config::Config;
+    use crate::replicacion::codificar_comando;
+    use crate::resp;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::collections::HashMap;
+    use std::io::{BufReader, Write};
+    use std::net::TcpStream;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct AlmacenEnMemoria {
+        valores: Mutex<HashMap<String, String>>,
+        demora: Duration,
+    }
+
+    impl AlmacenExterno for AlmacenEnMemoria {
+        fn leer(&self, clave: &str) -> io::Result<Option<String>> {
+            thread::sleep(self.demora);
+            Ok(self.valores.lock().unwrap().get(clave).cloned())
+        }
+
+        fn escribir(&self, clave: &str, valor: &str) -> io::Result<()> {
+            self.valores
+                .lock()
+                .unwrap()
+                .insert(clave.to_string(), valor.to_string());
+            Ok(())
+        }
+
+        fn borrar(&self, clave: &str) -> io::Result<()> {
+            self.valores.lock().unwrap().remove(clave);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn cada_clave_usa_el_almacen_del_prefijo_mas_largo_y_con_espera_maxima() {
+        let espera = Duration::from_millis(200);
+        let usuarios = Arc::new(AlmacenEnMemoria::default());
+        let lento = Arc::new(AlmacenEnMemoria {
+            demora: Duration::from_secs(5),
+            ..AlmacenEnMemoria::default()
+        });
+        let almacenes = AlmacenesExternos::new();
+        almacenes.registrar("user:", usuarios.clone());
+        almacenes.registrar("user:lento:", lento.clone());
+
+        almacenes
+            .escribir("user:1", Some("ana".to_string()), espera)
+            .unwrap();
+        almacenes
+            .escribir("otra", Some("x".to_string()), espera)
+            .unwrap();
+        assert_eq!(Some("ana".to_string()), almacenes.leer("user:1", espera));
+        assert_eq!(None, almacenes.leer("otra", espera));
+        assert!(!almacenes.respalda_alguna(&["otra".to_string()]));
+
+        almacenes.escribir("user:1", None, espera).unwrap();
+        assert_eq!(None, almacenes.leer("user:1", espera));
+
+        lento
+            .valores
+            .lock()
+            .unwrap()
+            .insert("user:lento:1".to_string(), "b".to_string());
+        assert_eq!(None, almacenes.leer("user:lento:1", espera));
+
+        let info = almacenes.seccion_info();
+        assert!(info.contains(&"external_store_reads:3".to_string()));
+        assert!(info.contains(&"external_store_hits:1".to_string()));
+        assert!(info.contains(&"external_store_writes:2".to_string()));
+        assert!(info.contains(&"external_store_timeouts:1".to_string()));
+    }
+
+    #[test]
+    fn el_servidor_trae_del_almacen_las_claves_que_faltan_y_le_envia_las_escrituras() {
+        let almacen = Arc::new(AlmacenEnMemoria::default());
+        almacen
+            .valores
+            .lock()
+            .unwrap()
+            .insert("user:1".to_string(), "ana".to_string());
+        let config = Config::new();
+        config
+            .almacenes_externos()
+            .registrar("user:", almacen.clone());
+        let servidor = ServidorDePrueba::iniciar_con(config).unwrap();
+        let mut conexion = TcpStream::connect(servidor.direccion()).unwrap();
+        let mut lector = BufReader::new(conexion.try_clone().unwrap());
+        let mut enviar = |tokens: &[&str]| {
+            let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+            conexion
+                .write_all(codificar_comando(&tokens).as_bytes())
+                .unwrap();
+            resp::leer(&mut lector).unwrap()
+        };
+
+        assert_eq!(
+            ResultadoRedis::BulkStr("ana".to_string()),
+            enviar(&["GET", "user:1"])
+        );
+        assert_eq!(ResultadoRedis::Int(1), enviar(&["EXISTS", "user:1"]));
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            enviar(&["SET", "user:2", "bob"])
+        );
+        assert_eq!(ResultadoRedis::Int(1), enviar(&["DEL", "user:1"]));
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            enviar(&["SET", "otra", "x"])
+        );
+
+        let valores = almacen.valores.lock().unwrap().clone();
+        assert_eq!(
+            HashMap::from([("user:2".to_string(), "bob".to_string())]),
+            valores
+        );
+    }
+}
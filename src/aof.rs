@@ -0,0 +1,720 @@
+use crate::base_de_datos::{Instantanea, ResultadoRedis};
+use crate::cadena::Cadena;
+use crate::cliente::{TipoCliente, Token};
+use crate::comando_info::ComandoInfo;
+use crate::eventos::{Evento, Suscriptor};
+use crate::log_handler::Logger;
+use crate::parser::{Parser, ParserError};
+use crate::perfilador::{Etapa, PERFILADOR};
+use crate::persistencia::{deserializar_tabla, serializar_tabla};
+use crate::redis_error::RedisError;
+use crate::replicacion::{codificar_comando, forma_propagada, se_propaga, TOKEN_MAESTRO};
+use crate::valor::Valor;
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Cada cuanto el hilo de fsync sincroniza el archivo con la politica everysec
+const INTERVALO_FSYNC: Duration = Duration::from_secs(1);
+/// Duracion de un fsync a partir de la cual se considera que el disco no da abasto
+const FSYNC_DEMORADO: Duration = Duration::from_secs(2);
+/// Encabezado del preambulo que escribe BGREWRITEAOF, seguido del largo en bytes de la tabla
+const PREAMBULO: &str = "PREAMBULO:";
+
+/// Cuando se fuerzan a disco las escrituras del archivo, segun appendfsync
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoliticaFsync {
+    /// always: despues de cada escritura, antes de responder al cliente
+    Siempre,
+    /// everysec: una vez por segundo desde un hilo dedicado, se pierde a lo sumo un segundo
+    CadaSegundo,
+    /// no: cuando lo decida el sistema operativo
+    Nunca,
+}
+
+impl PoliticaFsync {
+    /// Interpreta el valor de appendfsync, si no es valido se usa everysec como en redis
+    pub fn desde(valor: &str) -> Self {
+        match valor.trim().to_lowercase().as_str() {
+            "always" => PoliticaFsync::Siempre,
+            "no" => PoliticaFsync::Nunca,
+            _ => PoliticaFsync::CadaSegundo,
+        }
+    }
+
+    pub fn nombre(&self) -> &'static str {
+        match self {
+            PoliticaFsync::Siempre => "always",
+            PoliticaFsync::CadaSegundo => "everysec",
+            PoliticaFsync::Nunca => "no",
+        }
+    }
+
+    fn codigo(&self) -> u8 {
+        *self as u8
+    }
+
+    fn desde_codigo(codigo: u8) -> Self {
+        match codigo {
+            0 => PoliticaFsync::Siempre,
+            2 => PoliticaFsync::Nunca,
+            _ => PoliticaFsync::CadaSegundo,
+        }
+    }
+}
+
+/// Archivo de solo agregado: guarda cada escritura como el comando que la produjo, en la misma
+/// forma en la que se propaga a las replicas, y al iniciar el servidor se vuelve a ejecutar para
+/// reconstruir la tabla. Las escrituras se agregan al archivo en el momento, mientras que el
+/// fsync depende de la politica configurada con appendfsync.
+/// BGREWRITEAOF compacta el archivo: lo reemplaza por un preambulo con la tabla en el formato del
+/// archivo de persistencia seguido de los comandos que llegaron mientras se escribia, por lo que
+/// al reiniciar solo se vuelven a ejecutar los comandos posteriores a la ultima reescritura
+#[derive(Debug)]
+pub struct Aof {
+    ruta: String,
+    escritor: Mutex<Escritor>,
+    /// Otro descriptor del mismo archivo, para que el fsync no bloquee a las escrituras
+    sincronizador: Mutex<File>,
+    politica: AtomicU8,
+    /// Hay escrituras que todavia no se forzaron a disco
+    pendiente: AtomicBool,
+    fsyncs_demorados: AtomicU64,
+    ultima_escritura_fallida: AtomicBool,
+    ultima_reescritura_fallida: AtomicBool,
+}
+
+#[derive(Debug)]
+struct Escritor {
+    archivo: File,
+    /// Comandos agregados desde que empezo la reescritura en curso, que se copian al final del
+    /// archivo nuevo. Ninguno si no se esta reescribiendo
+    reescritura: Option<Vec<u8>>,
+}
+
+impl Aof {
+    /// Abre el archivo para agregar al final, creandolo si no existe
+    pub fn abrir(ruta: &str, politica: PoliticaFsync) -> io::Result<Self> {
+        let archivo = OpenOptions::new().create(true).append(true).open(ruta)?;
+        let sincronizador = archivo.try_clone()?;
+        Ok(Aof {
+            ruta: ruta.to_string(),
+            escritor: Mutex::new(Escritor {
+                archivo,
+                reescritura: None,
+            }),
+            sincronizador: Mutex::new(sincronizador),
+            politica: AtomicU8::new(politica.codigo()),
+            pendiente: AtomicBool::new(false),
+            fsyncs_demorados: AtomicU64::new(0),
+            ultima_escritura_fallida: AtomicBool::new(false),
+            ultima_reescritura_fallida: AtomicBool::new(false),
+        })
+    }
+
+    pub fn politica(&self) -> PoliticaFsync {
+        PoliticaFsync::desde_codigo(self.politica.load(Ordering::SeqCst))
+    }
+
+    /// Cambia la politica de fsync, por ejemplo con CONFIG SET appendfsync
+    pub fn configurar(&self, valor: &str) {
+        self.politica
+            .store(PoliticaFsync::desde(valor).codigo(), Ordering::SeqCst);
+    }
+
+    /// Inicia el hilo que con la politica everysec fuerza a disco las escrituras pendientes una vez
+    /// por segundo, y avisa en el log cuando un fsync tarda mas de lo esperado porque el disco no
+    /// da abasto. El hilo termina cuando se libera el archivo
+    pub fn iniciar_fsync(aof: &Arc<Aof>, logger: Logger) {
+        let aof: Weak<Aof> = Arc::downgrade(aof);
+        thread::spawn(move || loop {
+            thread::sleep(INTERVALO_FSYNC);
+            let aof = match aof.upgrade() {
+                Some(aof) => aof,
+                None => return,
+            };
+            if aof.politica() != PoliticaFsync::CadaSegundo {
+                continue;
+            }
+            if let Some(demora) = aof.sincronizar_pendiente() {
+                if demora > FSYNC_DEMORADO {
+                    aof.fsyncs_demorados.fetch_add(1, Ordering::SeqCst);
+                    logger.log_coneccion(
+                        "Servidor".to_string(),
+                        format!(
+                            "El fsync del AOF tardo {} ms, el disco no da abasto con appendfsync everysec",
+                            demora.as_millis()
+                        ),
+                    );
+                }
+            }
+        });
+    }
+
+    /// Fuerza a disco las escrituras pendientes y devuelve cuanto tardo, o ninguno si no habia
+    pub fn sincronizar_pendiente(&self) -> Option<Duration> {
+        if !self.pendiente.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        let inicio = Instant::now();
+        if self.sincronizador().sync_data().is_err() {
+            self.pendiente.store(true, Ordering::SeqCst);
+        }
+        Some(inicio.elapsed())
+    }
+
+    /// Fuerza a disco todo lo escrito sin importar la politica, por ejemplo al cerrar el servidor
+    pub fn sincronizar(&self) {
+        self.pendiente.store(false, Ordering::SeqCst);
+        if self.sincronizador().sync_data().is_ok() {}
+    }
+
+    fn sincronizador(&self) -> MutexGuard<'_, File> {
+        match self.sincronizador.lock() {
+            Ok(s) => s,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    fn escritor(&self) -> MutexGuard<'_, Escritor> {
+        match self.escritor.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    fn agregar(&self, comandos: &[Vec<String>]) {
+        if comandos.is_empty() {
+            return;
+        }
+        let inicio = Instant::now();
+        let codificados: String = comandos.iter().map(|c| codificar_comando(c)).collect();
+        let mut escritor = self.escritor();
+        if let Some(reescritura) = &mut escritor.reescritura {
+            reescritura.extend_from_slice(codificados.as_bytes());
+        }
+        let escrito = escritor.archivo.write_all(codificados.as_bytes()).is_ok();
+        self.ultima_escritura_fallida
+            .store(!escrito, Ordering::SeqCst);
+        match self.politica() {
+            PoliticaFsync::Siempre => {
+                if escritor.archivo.sync_data().is_err() {
+                    self.ultima_escritura_fallida.store(true, Ordering::SeqCst);
+                }
+            }
+            PoliticaFsync::CadaSegundo => self.pendiente.store(true, Ordering::SeqCst),
+            PoliticaFsync::Nunca => (),
+        }
+        PERFILADOR.registrar(Etapa::Persistencia, inicio.elapsed());
+    }
+
+    /// Empieza a reescribir el archivo en otro hilo a partir de la tabla. Se debe llamar con la base
+    /// de datos bloqueada, para que los comandos que se agregan al nuevo archivo sean exactamente
+    /// los posteriores a la tabla. Devuelve falso si ya habia una reescritura en curso
+    pub fn reescribir_en_segundo_plano(
+        aof: &Arc<Aof>,
+        tabla: Instantanea,
+        ahora: SystemTime,
+    ) -> bool {
+        {
+            let mut escritor = aof.escritor();
+            if escritor.reescritura.is_some() {
+                return false;
+            }
+            escritor.reescritura = Some(Vec::new());
+        }
+        let aof = Arc::clone(aof);
+        thread::spawn(move || {
+            let fallida = aof.reescribir(&tabla, ahora).is_err();
+            aof.ultima_reescritura_fallida
+                .store(fallida, Ordering::SeqCst);
+        });
+        true
+    }
+
+    /// Escribe el preambulo en un archivo temporal y, con las escrituras bloqueadas, le agrega los
+    /// comandos acumulados y lo pone en lugar del archivo actual. Si falla se conserva el actual
+    fn reescribir(&self, tabla: &Instantanea, ahora: SystemTime) -> io::Result<()> {
+        let temporal = format!("{}.tmp", self.ruta);
+        let contenido = serializar_tabla(tabla, ahora);
+        let mut nuevo = File::create(&temporal)?;
+        let resultado = nuevo
+            .write_all(format!("{}{}\n{}", PREAMBULO, contenido.len(), contenido).as_bytes())
+            .and_then(|_| self.reemplazar(nuevo, &temporal));
+        if resultado.is_err() {
+            self.escritor().reescritura = None;
+            if fs::remove_file(&temporal).is_ok() {}
+        }
+        resultado
+    }
+
+    fn reemplazar(&self, mut nuevo: File, temporal: &str) -> io::Result<()> {
+        let mut escritor = self.escritor();
+        if let Some(acumulados) = &escritor.reescritura {
+            nuevo.write_all(acumulados)?;
+        }
+        nuevo.sync_data()?;
+        fs::rename(temporal, &self.ruta)?;
+        let archivo = OpenOptions::new().append(true).open(&self.ruta)?;
+        *self.sincronizador() = archivo.try_clone()?;
+        escritor.archivo = archivo;
+        escritor.reescritura = None;
+        Ok(())
+    }
+
+    /// Indica si fallo la ultima escritura en el archivo
+    pub fn escritura_fallida(&self) -> bool {
+        self.ultima_escritura_fallida.load(Ordering::SeqCst)
+    }
+
+    /// Campos del AOF para la seccion Persistence de INFO
+    pub fn info(&self) -> Vec<String> {
+        let estado = match self.escritura_fallida() {
+            true => "err",
+            false => "ok",
+        };
+        vec![
+            format!("aof_fsync_policy:{}", self.politica().nombre()),
+            format!("aof_last_write_status:{}", estado),
+            format!(
+                "aof_delayed_fsync:{}",
+                self.fsyncs_demorados.load(Ordering::SeqCst)
+            ),
+            format!(
+                "aof_rewrite_in_progress:{}",
+                self.escritor().reescritura.is_some() as u8
+            ),
+            format!(
+                "aof_last_bgrewrite_status:{}",
+                match self.ultima_reescritura_fallida.load(Ordering::SeqCst) {
+                    true => "err",
+                    false => "ok",
+                }
+            ),
+        ]
+    }
+}
+
+/// Agrega al archivo las mismas escrituras que se propagan a las replicas: los comandos de
+/// escritura exitosos, los que llegan del maestro y un DEL por cada clave expirada. Las
+/// expiraciones relativas se agregan como instantes absolutos. Los comandos sobre una misma clave
+/// se publican antes de soltar sus bloqueos, por lo que se agregan en el orden en que se ejecutaron
+impl Suscriptor for Aof {
+    fn recibir(&self, eventos: &[Evento], _tabla: &Instantanea, ahora: SystemTime) {
+        let comandos: Vec<Vec<String>> = eventos
+            .iter()
+            .filter_map(|evento| match evento {
+                Evento::ClaveExpirada(clave) => Some(vec!["DEL".to_string(), clave.to_string()]),
+                Evento::ComandoEjecutado {
+                    cliente,
+                    comando,
+                    escritura,
+                    exitoso,
+                    ..
+                } if se_propaga(*cliente, *escritura, *exitoso) => forma_propagada(comando.clone())
+                    .map(|comando| con_expiracion_absoluta(comando, ahora)),
+                _ => None,
+            })
+            .collect();
+        self.agregar(&comandos);
+    }
+
+    fn nombre(&self) -> &str {
+        "aof"
+    }
+
+    fn es_critico(&self) -> bool {
+        true
+    }
+}
+
+/// Reescribe las expiraciones relativas al instante `ahora` como instantes absolutos, para que
+/// al cargar el archivo las claves venzan cuando debian y no a partir de la carga: SET con EX o
+/// PX pasa a SET con PXAT, EXPIRE a PEXPIREAT y RESTORE con ttl a RESTORE con ABSTTL
+fn con_expiracion_absoluta(mut comando: Vec<String>, ahora: SystemTime) -> Vec<String> {
+    let absoluta = |vida_util: Duration| {
+        ahora
+            .checked_add(vida_util)
+            .and_then(|instante| instante.duration_since(UNIX_EPOCH).ok())
+            .map(|desde_epoch| desde_epoch.as_millis().to_string())
+    };
+    let entero = |comando: &[String], i: usize| comando.get(i).and_then(|t| t.parse::<u64>().ok());
+
+    match comando[0].as_str() {
+        "SET" => {
+            let opcion = (3..comando.len()).find(|&i| {
+                comando[i].eq_ignore_ascii_case("EX") || comando[i].eq_ignore_ascii_case("PX")
+            });
+            if let Some(i) = opcion {
+                let en_segundos = comando[i].eq_ignore_ascii_case("EX");
+                let vida_util = entero(&comando, i + 1).map(|n| match en_segundos {
+                    true => Duration::from_secs(n),
+                    false => Duration::from_millis(n),
+                });
+                if let Some(ms) = vida_util.and_then(absoluta) {
+                    comando[i] = "PXAT".to_string();
+                    comando[i + 1] = ms;
+                }
+            }
+        }
+        "EXPIRE" => {
+            if let Some(ms) = entero(&comando, 2)
+                .map(Duration::from_secs)
+                .and_then(absoluta)
+            {
+                comando[0] = "PEXPIREAT".to_string();
+                comando[2] = ms;
+            }
+        }
+        "RESTORE" if !comando.iter().any(|t| t.eq_ignore_ascii_case("ABSTTL")) => {
+            let ttl = entero(&comando, 2).filter(|ttl| *ttl > 0);
+            if let Some(ms) = ttl.map(Duration::from_millis).and_then(absoluta) {
+                comando[2] = ms;
+                comando.push("ABSTTL".to_string());
+            }
+        }
+        _ => (),
+    }
+    comando
+}
+
+/// Lee el archivo y entrega la tabla del preambulo, si lo tiene, y luego los comandos en orden
+/// para volver a ejecutarlos. Acepta tanto los archivos reescritos con BGREWRITEAOF como los que
+/// solo tienen comandos. Del preambulo se descartan las claves que ya expiraron en el instante
+/// indicado. Si el archivo termina con un comando incompleto, por ejemplo porque el servidor se
+/// cayo mientras escribia, se ignora ese comando. Devuelve la cantidad de comandos leidos
+pub fn cargar<P, F>(
+    ruta: &str,
+    ahora: SystemTime,
+    preambulo: P,
+    mut ejecutar: F,
+) -> io::Result<usize>
+where
+    P: FnOnce(HashMap<Cadena, Valor>),
+    F: FnMut(ComandoInfo),
+{
+    let contenido = fs::read(ruta)?;
+    let comandos = match separar_preambulo(&contenido)? {
+        Some((tabla, comandos)) => {
+            preambulo(deserializar_tabla(tabla, ahora));
+            comandos
+        }
+        None => &contenido[..],
+    };
+    let mut parser = Parser::new(comandos);
+    let mut cargados = 0;
+    loop {
+        match parser.siguiente_comando() {
+            Ok(comando) => {
+                ejecutar(comando);
+                cargados += 1;
+            }
+            Err(ParserError::MensajeVacioError) => return Ok(cargados),
+            Err(ParserError::Lectura(error)) => return Err(error),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
+
+/// Separa la tabla del preambulo de los comandos que le siguen. Devuelve ninguno si el archivo
+/// solo tiene comandos, o un error si el preambulo esta truncado
+fn separar_preambulo(contenido: &[u8]) -> io::Result<Option<(&str, &[u8])>> {
+    let resto = match contenido.strip_prefix(PREAMBULO.as_bytes()) {
+        Some(resto) => resto,
+        None => return Ok(None),
+    };
+    let invalido = || io::Error::new(io::ErrorKind::InvalidData, "preambulo invalido");
+    let fin_largo = resto
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(invalido)?;
+    let largo: usize = std::str::from_utf8(&resto[..fin_largo])
+        .ok()
+        .and_then(|l| l.parse().ok())
+        .ok_or_else(invalido)?;
+    let resto = &resto[fin_largo + 1..];
+    let tabla = resto.get(..largo).ok_or_else(invalido)?;
+    let tabla = std::str::from_utf8(tabla).map_err(|_| invalido())?;
+    Ok(Some((tabla, &resto[largo..])))
+}
+
+/// Cliente sin conexion con el que el servidor ejecuta comandos propios, como los que se
+/// vuelven a ejecutar al cargar el AOF. Tiene los permisos del maestro y descarta las respuestas
+#[derive(Debug, Clone)]
+pub struct ClienteInterno {
+    descripcion: String,
+}
+
+impl ClienteInterno {
+    pub fn new(descripcion: &str) -> Self {
+        ClienteInterno {
+            descripcion: descripcion.to_string(),
+        }
+    }
+}
+
+impl TipoCliente for ClienteInterno {
+    fn obtener_comando(&mut self) -> Result<Option<ComandoInfo>, RedisError> {
+        Ok(None)
+    }
+
+    fn obtener_addr(&self) -> String {
+        self.descripcion.clone()
+    }
+
+    fn esta_conectado(&self) -> bool {
+        false
+    }
+
+    fn enviar_resultado(&mut self, _resultado: &ResultadoRedis) -> Result<(), RedisError> {
+        Ok(())
+    }
+
+    fn enviar_mensaje(&mut self, _mensaje: String) -> Result<(), RedisError> {
+        Ok(())
+    }
+
+    fn obtener_token(&self) -> Token {
+        TOKEN_MAESTRO
+    }
+
+    fn soporta_comando(&self, _comando: &str) -> bool {
+        true
+    }
+
+    fn suscripciones(&self) -> usize {
+        0
+    }
+
+    fn agregar_suscripcion(&self) -> usize {
+        0
+    }
+
+    fn quitar_suscripcion(&self) -> usize {
+        0
+    }
+
+    fn cerrar(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::TipoRedis;
+    use crate::config::Config;
+    use crate::resp;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::BufReader;
+    use std::net::TcpStream;
+
+    #[test]
+    fn se_agregan_las_escrituras_exitosas_y_se_vuelven_a_cargar_en_orden() {
+        let ruta = "aof_test_escrituras.aof";
+        let _ = fs::remove_file(ruta);
+        let aof = Aof::abrir(ruta, PoliticaFsync::Siempre).unwrap();
+        let tabla = Arc::new(HashMap::new());
+        let ejecutado =
+            |comando: &[&str], escritura: bool, exitoso: bool| Evento::ComandoEjecutado {
+                cliente: 1,
+                comando: comando.iter().map(|t| t.to_string()).collect(),
+                claves: vec![],
+                escritura,
+                exitoso,
+            };
+
+        aof.recibir(
+            &[
+                ejecutado(&["SET", "a", "1"], true, true),
+                ejecutado(&["GET", "a"], false, true),
+                ejecutado(&["INCR", "a"], true, false),
+                Evento::ClaveExpirada("b".to_string()),
+            ],
+            &tabla,
+            SystemTime::now(),
+        );
+        drop(aof);
+
+        let mut cargados = Vec::new();
+        let cantidad = cargar(
+            ruta,
+            SystemTime::now(),
+            |_| panic!("no hay preambulo"),
+            |c| {
+                let mut comando = vec![c.get_nombre()];
+                comando.extend_from_slice(c.tokens());
+                cargados.push(comando.join(" "));
+            },
+        )
+        .unwrap();
+        fs::remove_file(ruta).unwrap();
+
+        assert_eq!(2, cantidad);
+        assert_eq!(vec!["SET a 1", "DEL b"], cargados);
+    }
+
+    #[test]
+    fn las_expiraciones_relativas_se_agregan_como_instantes_absolutos() {
+        let ahora = UNIX_EPOCH + Duration::from_secs(1_000);
+        let absoluta = |tokens: &[&str]| {
+            con_expiracion_absoluta(tokens.iter().map(|t| t.to_string()).collect(), ahora).join(" ")
+        };
+
+        assert_eq!(
+            "SET a 1 NX PXAT 1010000",
+            absoluta(&["SET", "a", "1", "NX", "ex", "10"])
+        );
+        assert_eq!(
+            "SET a 1 PXAT 1000250",
+            absoluta(&["SET", "a", "1", "PX", "250"])
+        );
+        assert_eq!(
+            "PEXPIREAT a 1005000 GT",
+            absoluta(&["EXPIRE", "a", "5", "GT"])
+        );
+        assert_eq!(
+            "RESTORE a 1000300 datos REPLACE ABSTTL",
+            absoluta(&["RESTORE", "a", "300", "datos", "REPLACE"])
+        );
+        assert_eq!(
+            "RESTORE a 0 datos",
+            absoluta(&["RESTORE", "a", "0", "datos"])
+        );
+        assert_eq!("SET a 1", absoluta(&["SET", "a", "1"]));
+        assert_eq!("EXPIREAT a 5", absoluta(&["EXPIREAT", "a", "5"]));
+    }
+
+    #[test]
+    fn la_reescritura_deja_la_tabla_en_el_preambulo_y_los_comandos_posteriores() {
+        let ruta = "aof_test_reescritura.aof";
+        let _ = fs::remove_file(ruta);
+        let aof = Arc::new(Aof::abrir(ruta, PoliticaFsync::Nunca).unwrap());
+        let vacia = Arc::new(HashMap::new());
+        aof.recibir(
+            &[Evento::ClaveExpirada("a".to_string())],
+            &vacia,
+            SystemTime::now(),
+        );
+
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            Cadena::from("b"),
+            Valor::no_expirable(TipoRedis::Str("1".into())),
+        );
+        aof.escritor().reescritura = Some(Vec::new());
+        aof.recibir(
+            &[Evento::ClaveExpirada("c".to_string())],
+            &vacia,
+            SystemTime::now(),
+        );
+        aof.reescribir(&Arc::new(tabla), SystemTime::now()).unwrap();
+        aof.recibir(
+            &[Evento::ClaveExpirada("d".to_string())],
+            &vacia,
+            SystemTime::now(),
+        );
+
+        let mut claves = Vec::new();
+        let mut cargados = Vec::new();
+        let cantidad = cargar(
+            ruta,
+            SystemTime::now(),
+            |tabla| claves.extend(tabla.keys().map(|c| c.to_string())),
+            |c| cargados.push(c.tokens()[0].to_string()),
+        )
+        .unwrap();
+        fs::remove_file(ruta).unwrap();
+
+        assert_eq!(vec!["b"], claves);
+        assert_eq!(2, cantidad);
+        assert_eq!(vec!["c", "d"], cargados);
+        assert_eq!("aof_rewrite_in_progress:0", aof.info()[3]);
+    }
+
+    #[test]
+    fn la_politica_everysec_deja_el_fsync_pendiente_para_el_hilo() {
+        let ruta = "aof_test_everysec.aof";
+        let _ = fs::remove_file(ruta);
+        let aof = Aof::abrir(ruta, PoliticaFsync::desde("everysec")).unwrap();
+        let tabla = Arc::new(HashMap::new());
+
+        assert_eq!(None, aof.sincronizar_pendiente());
+        aof.recibir(
+            &[Evento::ClaveExpirada("a".to_string())],
+            &tabla,
+            SystemTime::now(),
+        );
+        assert!(aof.sincronizar_pendiente().is_some());
+        assert_eq!(None, aof.sincronizar_pendiente());
+
+        aof.configurar("no");
+        aof.recibir(
+            &[Evento::ClaveExpirada("b".to_string())],
+            &tabla,
+            SystemTime::now(),
+        );
+        assert_eq!(None, aof.sincronizar_pendiente());
+        assert_eq!("aof_fsync_policy:no", aof.info()[0]);
+        fs::remove_file(ruta).unwrap();
+    }
+
+    #[test]
+    fn las_escrituras_concurrentes_sobre_una_clave_se_agregan_en_el_orden_en_que_se_ejecutaron() {
+        let ruta = "aof_test_concurrentes.aof";
+        let dump = "aof_test_concurrentes.rb";
+        let _ = fs::remove_file(ruta);
+        let mut config = Config::new();
+        config.set("appendonly".to_string(), "yes".to_string());
+        config.set("appendfilename".to_string(), ruta.to_string());
+        config.set("appendfsync".to_string(), "always".to_string());
+        config.set("dbfilename".to_string(), dump.to_string());
+        let servidor = ServidorDePrueba::iniciar_con_persistencia(config).unwrap();
+        let direccion = servidor.direccion();
+        let enviar = move |tokens: &[&str]| {
+            let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+            let mut conexion = TcpStream::connect(direccion).unwrap();
+            let mut lector = BufReader::new(conexion.try_clone().unwrap());
+            conexion
+                .write_all(codificar_comando(&tokens).as_bytes())
+                .unwrap();
+            resp::leer(&mut lector).unwrap()
+        };
+
+        let hilos: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|letra| {
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        enviar(&["APPEND", "texto", letra]);
+                    }
+                })
+            })
+            .collect();
+        for hilo in hilos {
+            hilo.join().unwrap();
+        }
+        let valor = enviar(&["GET", "texto"]);
+        servidor.detener();
+
+        let mut recuperado = String::new();
+        cargar(
+            ruta,
+            SystemTime::now(),
+            |_| panic!("no hay preambulo"),
+            |c| {
+                if c.get_nombre() == "APPEND" {
+                    recuperado.push_str(&c.tokens()[1]);
+                }
+            },
+        )
+        .unwrap();
+        fs::remove_file(ruta).unwrap();
+        let _ = fs::remove_file(dump);
+
+        assert_eq!(ResultadoRedis::BulkStr(recuperado), valor);
+    }
+}
@@ -1,10 +1,22 @@
-use crate::observer::{Observable, Observer};
-
+use crate::cadena::Cadena;
 use crate::canal::Canal;
+use crate::cliente::{Cliente, Token};
+use crate::codificacion::{codificacion, LimitesDeCodificacion};
+use crate::conjunto::Conjunto;
+use crate::desfragmentacion::{self, Fragmentacion};
+use crate::estadisticas::Estadisticas;
+use crate::eventos::{BusDeEventos, Evento, IdSuscripcion, Suscriptor};
+use crate::log_handler::Logger;
+use crate::reloj::{Reloj, RelojDelSistema};
+use crate::seguimiento_claves::{OpcionesSeguimiento, SeguimientoDeClaves, CANAL_INVALIDACION};
 use crate::valor::Valor;
 
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, PartialEq)]
 
@@ -16,111 +28,399 @@ pub enum ResultadoRedis {
     Vector(Vec<ResultadoRedis>),
     Nil,
     Error(String),
-    Vacio,
+    /// Varias respuestas que se envian una detras de otra, como las confirmaciones de SUBSCRIBE
+    Varios(Vec<ResultadoRedis>),
+}
+
+/// Mensaje de error canonico al operar sobre una clave que almacena otro tipo de dato
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// Error que se produce al obtener una clave que almacena un tipo de dato distinto al esperado
+#[derive(Debug, PartialEq)]
+pub struct TipoIncorrectoError;
+
+impl TipoIncorrectoError {
+    /// Devuelve el error con el mensaje canonico de redis
+    pub fn a_resultado(&self) -> ResultadoRedis {
+        ResultadoRedis::Error(WRONGTYPE.to_string())
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 /// Los posibles tipos de datos que maneja el servidor redis
 pub enum TipoRedis {
-    Str(String),
+    Str(Cadena),
     Lista(Vec<String>),
-    Set(HashSet<String>),
+    Set(Conjunto),
     Canal(Canal),
 }
+/// Vista inmutable de la tabla en un momento dado. Se obtiene en O(1) y la primera escritura
+/// posterior copia la tabla, por lo que se puede recorrer sin bloquear a la base de datos
+pub type Instantanea = Arc<HashMap<Cadena, Valor>>;
+
+/// Condicion que debe cumplir la expiracion actual de una clave para reemplazarla,
+/// corresponde a los modificadores NX, XX, GT y LT de EXPIRE
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CondicionExpiracion {
+    Siempre,
+    /// NX, solo si la clave no tiene expiracion
+    SinExpiracion,
+    /// XX, solo si la clave ya tiene expiracion
+    ConExpiracion,
+    /// GT, solo si la nueva expiracion es posterior a la actual. Una clave sin expiracion nunca la cumple
+    Mayor,
+    /// LT, solo si la nueva expiracion es anterior a la actual. Una clave sin expiracion siempre la cumple
+    Menor,
+}
+
+impl CondicionExpiracion {
+    fn se_cumple(&self, actual: Option<SystemTime>, nueva: SystemTime) -> bool {
+        match (self, actual) {
+            (CondicionExpiracion::Siempre, _) => true,
+            (CondicionExpiracion::SinExpiracion, actual) => actual.is_none(),
+            (CondicionExpiracion::ConExpiracion, actual) => actual.is_some(),
+            (CondicionExpiracion::Mayor, Some(actual)) => nueva > actual,
+            (CondicionExpiracion::Mayor, None) => false,
+            (CondicionExpiracion::Menor, Some(actual)) => nueva < actual,
+            (CondicionExpiracion::Menor, None) => true,
+        }
+    }
+}
+
+/// Rol del servidor respecto de la replicacion
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Rol {
+    Maestro,
+    Replica,
+}
+
+/// Contadores de las claves con expiracion, se mantienen en cada escritura
+/// para que INFO no tenga que recorrer la tabla
+#[derive(Default)]
+struct Expiraciones {
+    cantidad: usize,
+    suma_en_milisegundos: u128,
+}
+
+impl Expiraciones {
+    fn contar(tabla: &HashMap<Cadena, Valor>) -> Self {
+        let mut expiraciones = Expiraciones::default();
+        tabla.values().for_each(|v| expiraciones.sumar(v));
+        expiraciones
+    }
+
+    fn sumar(&mut self, valor: &Valor) {
+        if let Some(instante) = valor.expira_en() {
+            self.cantidad += 1;
+            self.suma_en_milisegundos += milisegundos_desde_epoch(instante);
+        }
+    }
+
+    fn restar(&mut self, valor: &Valor) {
+        if let Some(instante) = valor.expira_en() {
+            self.cantidad -= 1;
+            self.suma_en_milisegundos -= milisegundos_desde_epoch(instante);
+        }
+    }
+
+    /// Promedio en milisegundos del tiempo de vida restante de las claves con expiracion
+    fn ttl_promedio(&self, ahora: SystemTime) -> u128 {
+        if self.cantidad == 0 {
+            return 0;
+        }
+        let ahora = milisegundos_desde_epoch(ahora);
+        (self.suma_en_milisegundos / self.cantidad as u128).saturating_sub(ahora)
+    }
+}
+
+fn milisegundos_desde_epoch(instante: SystemTime) -> u128 {
+    instante
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
 /// Base de datos donde se almacenan todos los elementos almacenados
 pub struct BaseDeDatos {
-    hashmap: HashMap<String, Valor>,
-    observadores: Vec<Box<dyn Observer + Send>>,
+    hashmap: Instantanea,
+    /// Claves ordenadas por su hash, que es el orden estable en el que las recorre SCAN
+    recorrido: BTreeSet<(u64, Cadena)>,
+    expiraciones: Expiraciones,
+    eventos: BusDeEventos,
+    /// Eventos de la operacion en curso, se publican juntos al terminarla
+    eventos_pendientes: Vec<Evento>,
+    rol: Rol,
+    estadisticas: Arc<Estadisticas>,
+    limites: LimitesDeCodificacion,
+    seguimiento: SeguimientoDeClaves,
+    /// Hora con la que se decide si una clave expiro
+    reloj: Arc<dyn Reloj>,
+    /// Tiempo que se conservan las claves expiradas para las lecturas que aceptan valores viejos
+    gracia_de_expiracion: Duration,
+    /// Canales a los que se suscribio cada cliente, para quitarlo de todos sin recorrer la tabla
+    canales_por_cliente: HashMap<Token, BTreeSet<String>>,
+    /// Donde se registran los suscriptores que entran en panico
+    logger: Option<Logger>,
 }
 
 impl BaseDeDatos {
     /// Devuelve el valor que corresponde a la clave enviada por parametro
     pub fn obtener_valor(&self, clave: &str) -> Option<&TipoRedis> {
         match self.hashmap.get(clave) {
-            Some(v) => v.get(),
+            Some(v) => v.get_al(self.reloj.ahora()),
             None => None,
         }
     }
-    /// Devuelve el tiempo de expiracion de una clave almacenada en la base de datos
+
+    /// Registra en las estadisticas si existe cada una de las claves que lee un comando de
+    /// lectura. Las busquedas de los comandos de escritura no cuentan, como en redis
+    pub fn registrar_busquedas(&self, claves: &[String]) {
+        for clave in claves {
+            self.estadisticas
+                .busqueda(self.obtener_valor(clave).is_some());
+        }
+    }
+    /// Devuelve el string almacenado en la clave, ninguno si la clave no existe
+    /// o un error si la clave almacena otro tipo de dato
+    pub fn obtener_como_str(&self, clave: &str) -> Result<Option<&str>, TipoIncorrectoError> {
+        match self.obtener_valor(clave) {
+            Some(TipoRedis::Str(valor)) => Ok(Some(valor.as_str())),
+            None => Ok(None),
+            _ => Err(TipoIncorrectoError),
+        }
+    }
+
+    /// Como obtener_como_str pero tambien devuelve el string de una clave que expiro hace menos
+    /// que la gracia de expiracion, junto con verdadero si es un valor viejo
+    pub fn obtener_como_str_aunque_expiro(
+        &self,
+        clave: &str,
+    ) -> Result<Option<(&str, bool)>, TipoIncorrectoError> {
+        let ahora = self.reloj.ahora();
+        let (valor, viejo) = match self.hashmap.get(clave) {
+            Some(v) => (
+                v.get_al(self.fin_de_gracia(ahora)),
+                v.esta_expirado_al(ahora),
+            ),
+            None => (None, false),
+        };
+        match valor {
+            Some(TipoRedis::Str(valor)) => Ok(Some((valor.as_str(), viejo))),
+            None => Ok(None),
+            _ => Err(TipoIncorrectoError),
+        }
+    }
+
+    /// Devuelve la lista almacenada en la clave, ninguna si la clave no existe
+    /// o un error si la clave almacena otro tipo de dato
+    pub fn obtener_como_lista(
+        &self,
+        clave: &str,
+    ) -> Result<Option<&Vec<String>>, TipoIncorrectoError> {
+        match self.obtener_valor(clave) {
+            Some(TipoRedis::Lista(lista)) => Ok(Some(lista)),
+            None => Ok(None),
+            _ => Err(TipoIncorrectoError),
+        }
+    }
+
+    /// Devuelve el set almacenado en la clave, ninguno si la clave no existe
+    /// o un error si la clave almacena otro tipo de dato
+    pub fn obtener_como_set(&self, clave: &str) -> Result<Option<&Conjunto>, TipoIncorrectoError> {
+        match self.obtener_valor(clave) {
+            Some(TipoRedis::Set(set)) => Ok(Some(set)),
+            None => Ok(None),
+            _ => Err(TipoIncorrectoError),
+        }
+    }
+
+    /// Devuelve el canal almacenado en la clave, ninguno si la clave no existe
+    /// o un error si la clave almacena otro tipo de dato
+    pub fn obtener_como_canal(&self, clave: &str) -> Result<Option<&Canal>, TipoIncorrectoError> {
+        match self.obtener_valor(clave) {
+            Some(TipoRedis::Canal(canal)) => Ok(Some(canal)),
+            None => Ok(None),
+            _ => Err(TipoIncorrectoError),
+        }
+    }
+
+    /// Devuelve los segundos que le quedan de vida a una clave almacenada en la base de datos,
+    /// -1 si la clave no expira o -2 si no existe
     pub fn obtener_expiracion(&self, clave: &str) -> isize {
+        let ahora = self.reloj.ahora();
         match self.hashmap.get(clave) {
-            Some(v) => v.obtener_expiracion(),
-            None => -2,
+            Some(v) if !v.esta_expirado_al(ahora) => v.obtener_expiracion_al(ahora),
+            _ => -2,
         }
     }
-    /// Guarda una valor con un tiempo de expiracion enviado por parametro
+
+    /// Igual que obtener_expiracion pero con precision de milisegundos
+    pub fn obtener_expiracion_en_milisegundos(&self, clave: &str) -> isize {
+        let ahora = self.reloj.ahora();
+        match self.hashmap.get(clave) {
+            Some(v) if !v.esta_expirado_al(ahora) => v.obtener_expiracion_en_milisegundos_al(ahora),
+            _ => -2,
+        }
+    }
+
+    /// Guarda una valor con un tiempo de expiracion enviado por parametro,
+    /// si el instante en el que expira no es representable el valor no expira
     pub fn guardar_valor_con_expiracion(
         &mut self,
         clave: String,
-        expiracion: u64,
+        expiracion: Duration,
         valor: TipoRedis,
     ) {
-        self.hashmap
-            .insert(clave, Valor::expirable(valor, expiracion));
-        self.notificar_observadores(self.hashmap.clone());
+        match self.reloj.ahora().checked_add(expiracion) {
+            Some(expira_en) => self.guardar_valor_con_expiracion_absoluta(clave, expira_en, valor),
+            None => self.guardar_valor(clave, valor),
+        }
     }
-    /// Dada una clave con expiracion almacenada en la base de datos, actualiza su 'tiempo de vida' con el parametro 'expiracion'
-    /// # Arguments
-    ///
-    /// * `self` - Referencia a la bases de datos
-    /// * `clave` - Clave con la que se identifica un elemento almacenado en la base de datos
-    /// * `expiracion` - Nuevo valor de expiracion de la clave
-    ///
-    pub fn actualizar_valor_con_expiracion(&mut self, clave: String, expiracion: u64) -> usize {
-        match self.hashmap.get_mut(&clave) {
-            Some(v) => {
-                v.actualizar_expiracion(expiracion);
+
+    /// Guarda un valor que expira en el instante absoluto indicado, si ya paso la clave queda expirada
+    pub fn guardar_valor_con_expiracion_absoluta(
+        &mut self,
+        clave: String,
+        instante: SystemTime,
+        valor: TipoRedis,
+    ) {
+        self.insertar(clave, Valor::expirable_en(valor, instante));
+        self.publicar_eventos();
+    }
+    /// Dada una clave almacenada en la base de datos, hace que expire en el instante absoluto 'instante'.
+    /// Si el instante ya paso la clave queda expirada
+    pub fn actualizar_valor_con_expiracion_absoluta(
+        &mut self,
+        clave: String,
+        instante: SystemTime,
+    ) -> usize {
+        self.actualizar_expiracion_si(clave, instante, CondicionExpiracion::Siempre)
+    }
+
+    /// Igual que actualizar_valor_con_expiracion_absoluta pero solo si la expiracion actual
+    /// de la clave cumple la condicion, devuelve 0 si la clave no existe o no la cumple
+    pub fn actualizar_expiracion_si(
+        &mut self,
+        clave: String,
+        instante: SystemTime,
+        condicion: CondicionExpiracion,
+    ) -> usize {
+        let ahora = self.reloj.ahora();
+        let resultado = match Arc::make_mut(&mut self.hashmap).get_mut(clave.as_str()) {
+            Some(v)
+                if !v.esta_expirado_al(ahora) && condicion.se_cumple(v.expira_en(), instante) =>
+            {
+                self.expiraciones.restar(v);
+                v.expirar_en(instante);
+                self.expiraciones.sumar(v);
+                self.eventos_pendientes.push(Evento::ClaveEscrita(clave));
                 1
             }
-            None => 0,
-        }
+            _ => 0,
+        };
+        self.publicar_eventos();
+        resultado
     }
 
+    /// Quita la expiracion de una clave, devuelve 1 si la clave existia y tenia expiracion o 0 si no
     pub fn actualizar_valor_sin_expiracion(&mut self, clave: String) -> usize {
-        match self.hashmap.get_mut(&clave) {
-            Some(v) => {
-                v.hacer_persistente();
-                1
+        let ahora = self.reloj.ahora();
+        let resultado = match Arc::make_mut(&mut self.hashmap).get_mut(clave.as_str()) {
+            Some(v) if !v.esta_expirado_al(ahora) => {
+                self.expiraciones.restar(v);
+                let persistida = v.persistir();
+                if persistida {
+                    self.eventos_pendientes.push(Evento::ClaveEscrita(clave));
+                }
+                persistida as usize
             }
-            None => 0,
-        }
+            _ => 0,
+        };
+        self.publicar_eventos();
+        resultado
     }
 
+    /// Guarda el valor en la clave descartando la expiracion que tuviera la clave anterior.
+    /// Es la regla de las escrituras que reemplazan el valor completo, como SET o GETSET
     pub fn guardar_valor(&mut self, clave: String, valor: TipoRedis) {
-        self.hashmap.insert(clave, Valor::no_expirable(valor));
+        self.insertar(clave, Valor::no_expirable(valor));
 
-        self.notificar_observadores(self.hashmap.clone());
+        self.publicar_eventos();
     }
 
-    pub fn guardar_valores(&mut self, parametros: Vec<String>) {
-        let mut index = 0;
-        while index != parametros.len() - 1 {
-            let clave = &parametros[index];
-            let valor = &parametros[index + 1];
+    /// Reemplaza el dato almacenado en la clave conservando su expiracion,
+    /// si la clave no existe o expiro se guarda sin expiracion.
+    /// Es la regla de las escrituras que modifican el valor existente, como APPEND, INCRBY o LPUSH
+    pub fn actualizar_valor(&mut self, clave: String, mut valor: TipoRedis) {
+        self.limites.ajustar(&mut valor);
+        let ahora = self.reloj.ahora();
+        match Arc::make_mut(&mut self.hashmap).get_mut(clave.as_str()) {
+            Some(v) if !v.esta_expirado_al(ahora) => {
+                v.reemplazar(valor);
+                self.eventos_pendientes.push(Evento::ClaveEscrita(clave));
+            }
+            _ => self.insertar(clave, Valor::no_expirable(valor)),
+        }
+        self.publicar_eventos();
+    }
 
-            self.hashmap.insert(
-                clave.to_string(),
-                Valor::no_expirable(TipoRedis::Str(valor.to_string())),
-            );
+    pub fn guardar_valores(&mut self, parametros: &[String]) {
+        for par in parametros.chunks(2) {
+            if let [clave, valor] = par {
+                self.insertar(
+                    clave.to_string(),
+                    Valor::no_expirable(TipoRedis::Str(valor.as_str().into())),
+                );
+            }
+        }
+        self.publicar_eventos();
+    }
 
-            index += 1;
+    /// Guarda cantidad claves de la forma prefijo:N con el valor value:N, sin reemplazar las
+    /// que ya existen. Si se indica un tamanio el valor se completa con ceros o se recorta hasta
+    /// tenerlo. Devuelve la cantidad de claves creadas
+    pub fn poblar(&mut self, cantidad: usize, prefijo: &str, tamanio: Option<usize>) -> usize {
+        Arc::make_mut(&mut self.hashmap).reserve(cantidad);
+        let ahora = self.reloj.ahora();
+        let mut creadas = 0;
+        for i in 0..cantidad {
+            let clave = format!("{}:{}", prefijo, i);
+            if self
+                .hashmap
+                .get(clave.as_str())
+                .is_some_and(|v| !v.esta_expirado_al(ahora))
+            {
+                continue;
+            }
+            let mut valor = format!("value:{}", i);
+            if let Some(tamanio) = tamanio {
+                valor.truncate(tamanio);
+                valor.push_str(&"\0".repeat(tamanio - valor.len()));
+            }
+            self.insertar(clave, Valor::no_expirable(TipoRedis::Str(valor.into())));
+            creadas += 1;
         }
-        self.notificar_observadores(self.hashmap.clone());
+        self.publicar_eventos();
+        creadas
     }
 
     pub fn existe_clave(&mut self, clave: &str) -> bool {
         match self.hashmap.get(clave) {
-            Some(v) => !v.expiro(),
+            Some(v) => !v.esta_expirado_al(self.reloj.ahora()),
             None => false,
         }
     }
 
     pub fn eliminar_clave(&mut self, clave: &str) -> usize {
-        let valor = match self.hashmap.remove(clave) {
-            Some(_) => 1,
+        let valor = match self.quitar(clave) {
+            Some(_) => {
+                self.eventos_pendientes
+                    .push(Evento::ClaveEliminada(clave.to_string()));
+                1
+            }
             None => 0,
         };
-        self.notificar_observadores(self.hashmap.clone());
+        self.publicar_eventos();
         valor
     }
     /// Dado un valor ya almacenado en la base de datos, lo copia en una nueva clave
@@ -140,8 +440,24 @@ impl BaseDeDatos {
         Some(())
     }
 
+    /// Mueve el valor almacenado en una clave a una nueva clave conservando su expiracion.
+    /// Devuelve ninguno si la clave actual no existe
+    pub fn renombrar_clave(&mut self, clave_actual: &str, clave_nueva: &str) -> Option<()> {
+        let ahora = self.reloj.ahora();
+        let valor = match self.quitar(clave_actual) {
+            Some(v) if !v.esta_expirado_al(ahora) => v,
+            _ => return None,
+        };
+
+        self.eventos_pendientes
+            .push(Evento::ClaveEliminada(clave_actual.to_string()));
+        self.insertar(clave_nueva.to_string(), valor);
+        self.publicar_eventos();
+        Some(())
+    }
+
     pub fn actualizar_ultimo_acceso(&mut self, clave: String) -> isize {
-        match self.hashmap.get_mut(&clave) {
+        match Arc::make_mut(&mut self.hashmap).get_mut(clave.as_str()) {
             Some(v) => {
                 v.actualizar_ultimo_acceso();
                 1
@@ -149,6 +465,137 @@ impl BaseDeDatos {
             None => 0,
         }
     }
+    /// Elimina las claves enviadas que ya expiraron hace mas que la gracia de expiracion y devuelve
+    /// cuantas se eliminaron. Solo el maestro elimina claves expiradas y publica su expiracion para que se propague como DEL,
+    /// una replica las conserva (las lecturas ya las ven como inexistentes) hasta recibir el DEL del maestro
+    pub fn expirar_claves(&mut self, claves: &[String]) -> usize {
+        if self.rol == Rol::Replica {
+            return 0;
+        }
+
+        let limite = self.fin_de_gracia(self.reloj.ahora());
+        let expiradas: Vec<String> = claves
+            .iter()
+            .filter(|c| {
+                self.hashmap
+                    .get(c.as_str())
+                    .is_some_and(|v| v.esta_expirado_al(limite))
+            })
+            .cloned()
+            .collect();
+        if expiradas.is_empty() {
+            return 0;
+        }
+
+        for clave in &expiradas {
+            self.quitar(clave);
+            self.eventos_pendientes
+                .push(Evento::ClaveExpirada(clave.to_string()));
+        }
+        self.invalidar_claves(&expiradas, None);
+        let cantidad = expiradas.len();
+        self.estadisticas.claves_expiradas(cantidad);
+        self.publicar_eventos();
+        cantidad
+    }
+
+    /// Suscribe a los eventos de la base de datos, despues de los suscriptores que ya estaban.
+    /// Devuelve el identificador con el que se lo puede quitar
+    pub fn suscribir(&mut self, suscriptor: Box<dyn Suscriptor + Send>) -> IdSuscripcion {
+        self.eventos.suscribir(suscriptor)
+    }
+
+    /// Quita al suscriptor, devuelve falso si no estaba suscripto
+    pub fn desuscribir(&mut self, id: IdSuscripcion) -> bool {
+        self.eventos.desuscribir(id)
+    }
+
+    /// Publica un evento junto con los pendientes, por ejemplo la ejecucion de un comando
+    /// o el reemplazo de la tabla al sincronizarse con el maestro
+    pub fn emitir(&mut self, evento: Evento) {
+        self.eventos_pendientes.push(evento);
+        self.publicar_eventos();
+    }
+
+    /// Entrega a los suscriptores los eventos de la operacion que termino. Los suscriptores que
+    /// entran en panico se cuentan en las estadisticas y se registran en el log
+    fn publicar_eventos(&mut self) {
+        let eventos = std::mem::take(&mut self.eventos_pendientes);
+        for panico in self
+            .eventos
+            .emitir(&eventos, &self.hashmap, self.reloj.ahora())
+        {
+            self.estadisticas.suscriptor_en_panico();
+            if let Some(logger) = &self.logger {
+                logger.log_coneccion(
+                    "Servidor".to_string(),
+                    format!(
+                        "El suscriptor {} entro en panico: {}{}",
+                        panico.nombre,
+                        panico.mensaje,
+                        match panico.quitado {
+                            true => ", se lo quito del bus de eventos",
+                            false => ", sigue suscripto por ser critico",
+                        }
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Logger con el que se registran los suscriptores que entran en panico
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = Some(logger);
+    }
+
+    /// Devuelve las estadisticas del servidor que actualiza la base de datos
+    pub fn estadisticas(&self) -> Arc<Estadisticas> {
+        Arc::clone(&self.estadisticas)
+    }
+
+    /// Cambia el rol de la base de datos respecto de la replicacion
+    pub fn set_rol(&mut self, rol: Rol) {
+        self.rol = rol;
+    }
+
+    pub fn rol(&self) -> Rol {
+        self.rol
+    }
+
+    /// Activa CLIENT TRACKING para el cliente
+    pub fn activar_seguimiento(&mut self, cliente: Token, opciones: OpcionesSeguimiento) {
+        self.seguimiento.activar(cliente, opciones);
+    }
+
+    /// Desactiva CLIENT TRACKING para el cliente, por ejemplo al desconectarse
+    pub fn desactivar_seguimiento(&mut self, cliente: Token) {
+        self.seguimiento.desactivar(cliente);
+    }
+
+    /// Registra las claves que leyo el cliente para avisarle cuando se modifiquen
+    pub fn registrar_lecturas(&mut self, cliente: Token, claves: &[String]) {
+        self.seguimiento.registrar_lecturas(cliente, claves);
+    }
+
+    /// Envia por __redis__:invalidate las claves modificadas a los clientes que las siguen.
+    /// `origen` es el cliente que las modifico, si se conoce
+    pub fn invalidar_claves(&mut self, claves: &[String], origen: Option<Token>) {
+        for (destino, claves) in self.seguimiento.invalidar(claves, origen) {
+            let contenido = claves.into_iter().map(ResultadoRedis::BulkStr).collect();
+            self.enviar_invalidacion(destino, ResultadoRedis::Vector(contenido));
+        }
+    }
+
+    fn enviar_invalidacion(&self, destino: Token, contenido: ResultadoRedis) {
+        if let Some(TipoRedis::Canal(canal)) = self
+            .hashmap
+            .get(CANAL_INVALIDACION)
+            .and_then(|v| v.get_al(self.reloj.ahora()))
+        {
+            canal.clone().enviar_a(destino, contenido);
+        }
+    }
+
     /// Devuelve todas las claves que matchean con un patron
     /// # Arguments
     ///
@@ -156,34 +603,29 @@ impl BaseDeDatos {
     /// * `re` - Patron de referencia
     ///
     pub fn claves(&self, re: &str) -> Vec<String> {
-        let regex = match Regex::new(re) {
-            Ok(r) => r,
-            Err(_) => return Vec::new(),
-        };
+        claves_que_coinciden(&self.hashmap, re, self.reloj.ahora())
+    }
 
-        self.hashmap
-            .keys()
-            .cloned()
-            .into_iter()
-            .filter(|c| regex.is_match(c))
-            .collect()
+    /// Devuelve una instantanea de la tabla que se puede recorrer sin mantener tomado el lock
+    /// de la base de datos. Mientras exista, las escrituras no la modifican
+    pub fn instantanea(&self) -> Instantanea {
+        Arc::clone(&self.hashmap)
     }
-    /// Dado un elemento de tipo string, lo actulaliza con un nuevo valor
+    /// Dado un elemento de tipo string, lo actulaliza con un nuevo valor devolviendo el anterior.
+    /// Si la clave almacena otro tipo de dato no se modifica y se devuelve un error
     pub fn intercambiar_valor(
         &mut self,
         clave: String,
-        valor_nuevo: TipoRedis,
-    ) -> Option<TipoRedis> {
-        let valor = match self.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(_)) => return Some(TipoRedis::Lista(vec![])),
-            Some(TipoRedis::Set(_)) => return Some(TipoRedis::Set(HashSet::new())),
-            Some(TipoRedis::Str(valor)) => Some(TipoRedis::Str(valor.to_string())),
-            Some(TipoRedis::Canal(_)) => None,
-            None => None,
-        };
+        valor_nuevo: String,
+    ) -> Result<Option<String>, TipoIncorrectoError> {
+        let valor = self.obtener_como_str(&clave)?.map(str::to_string);
 
-        self.hashmap.insert(clave, Valor::no_expirable(valor_nuevo));
-        valor
+        self.insertar(
+            clave,
+            Valor::no_expirable(TipoRedis::Str(valor_nuevo.into())),
+        );
+        self.publicar_eventos();
+        Ok(valor)
     }
     /// Devuelve una lista con todos los canales activos de la base de datos
     pub fn canales_activos(&self, re: &str) -> Vec<String> {
@@ -201,10 +643,138 @@ impl BaseDeDatos {
         canales
     }
 
+    /// Suscribe al cliente al canal, creandolo si no existe. Devuelve falso si ya estaba
+    /// suscripto o un error si la clave almacena otro tipo de dato
+    pub fn suscribir_a_canal(
+        &mut self,
+        clave: String,
+        cliente: &Cliente,
+    ) -> Result<bool, TipoIncorrectoError> {
+        let mut canal = match self.obtener_como_canal(&clave)? {
+            Some(c) => c.clone(),
+            None => Canal::new(clave.clone()),
+        };
+        if !canal.suscribirse(cliente.clone()) {
+            return Ok(false);
+        }
+        self.canales_por_cliente
+            .entry(cliente.obtener_token())
+            .or_default()
+            .insert(clave.clone());
+        self.guardar_valor(clave, TipoRedis::Canal(canal));
+        Ok(true)
+    }
+
+    /// Quita al cliente del canal, devuelve si estaba suscripto o un error si la clave almacena
+    /// otro tipo de dato
+    pub fn desuscribir_de_canal(
+        &mut self,
+        clave: String,
+        cliente: &Cliente,
+    ) -> Result<bool, TipoIncorrectoError> {
+        let mut canal = match self.obtener_como_canal(&clave)? {
+            Some(c) => c.clone(),
+            None => return Ok(false),
+        };
+        let token = cliente.obtener_token();
+        if let Some(canales) = self.canales_por_cliente.get_mut(&token) {
+            canales.remove(&clave);
+            if canales.is_empty() {
+                self.canales_por_cliente.remove(&token);
+            }
+        }
+        if !canal.desuscribirse(cliente) {
+            return Ok(false);
+        }
+        self.guardar_valor(clave, TipoRedis::Canal(canal));
+        Ok(true)
+    }
+
+    /// Quita al cliente de todos los canales a los que estaba suscripto,
+    /// devuelve los nombres de los canales de los que se lo quito
+    pub fn desuscribir_de_todos(&mut self, cliente: &Cliente) -> Vec<String> {
+        let claves = self
+            .canales_por_cliente
+            .remove(&cliente.obtener_token())
+            .unwrap_or_default();
+        let mut nombres = Vec::new();
+        for clave in claves {
+            let mut canal = match self.obtener_valor(&clave) {
+                Some(TipoRedis::Canal(canal)) => canal.clone(),
+                _ => continue,
+            };
+            if canal.desuscribirse(cliente) {
+                self.actualizar_valor(clave.clone(), TipoRedis::Canal(canal));
+                nombres.push(clave);
+            }
+        }
+        nombres
+    }
+
     pub fn borrar_claves(&mut self) {
-        self.hashmap = HashMap::new();
+        for destino in self.seguimiento.invalidar_todo() {
+            self.enviar_invalidacion(destino, ResultadoRedis::Nil);
+        }
+        self.hashmap = Arc::new(HashMap::new());
+        self.recorrido.clear();
+        self.expiraciones = Expiraciones::default();
+
+        self.eventos_pendientes.push(Evento::BaseReemplazada);
+        self.publicar_eventos();
+    }
+
+    /// Devuelve la tabla con todos los valores almacenados
+    pub fn tabla(&self) -> &HashMap<Cadena, Valor> {
+        &self.hashmap
+    }
+
+    /// Reemplaza todos los valores almacenados por los de la tabla enviada
+    /// sin publicar eventos, por lo que el archivo de persistencia no se modifica
+    pub fn reemplazar_tabla(&mut self, tabla: HashMap<Cadena, Valor>) {
+        self.expiraciones = Expiraciones::contar(&tabla);
+        self.recorrido = orden_de_recorrido(&tabla);
+        self.hashmap = Arc::new(tabla);
+        self.ajustar_codificaciones();
+    }
+
+    /// Cambia los limites de las representaciones compactas. Las colecciones que ya
+    /// los superan pasan a la representacion general, las demas se ajustan al modificarse
+    pub fn set_limites(&mut self, limites: LimitesDeCodificacion) {
+        if self.limites == limites {
+            return;
+        }
+        self.limites = limites;
+        self.ajustar_codificaciones();
+    }
 
-        self.notificar_observadores(self.hashmap.clone());
+    /// Devuelve la codificacion interna del valor almacenado en la clave, como OBJECT ENCODING
+    pub fn codificacion(&self, clave: &str) -> Option<&'static str> {
+        self.obtener_valor(clave).map(codificacion)
+    }
+
+    fn ajustar_codificaciones(&mut self) {
+        for valor in Arc::make_mut(&mut self.hashmap).values_mut() {
+            valor.ajustar_codificacion(&self.limites);
+        }
+    }
+
+    /// Estima cuanta de la memoria reservada por la tabla y sus colecciones esta sin usar
+    pub fn fragmentacion(&self) -> Fragmentacion {
+        desfragmentacion::estimar(&self.hashmap, self.reloj.ahora())
+    }
+
+    /// Achica la tabla y las colecciones a su largo justo, liberando la capacidad que quedo
+    /// reservada tras borrar elementos. Devuelve la cantidad estimada de bytes liberados
+    pub fn desfragmentar(&mut self) -> usize {
+        let antes = self.fragmentacion();
+        let tabla = Arc::make_mut(&mut self.hashmap);
+        for valor in tabla.values_mut() {
+            valor.compactar();
+        }
+        tabla.shrink_to_fit();
+        antes
+            .reservado
+            .saturating_sub(self.fragmentacion().reservado)
     }
 
     pub fn cantidad_claves(&self) -> usize {
@@ -216,57 +786,232 @@ impl BaseDeDatos {
 
         info.push(format!("cantidad de claves:{}", self.hashmap.len()));
         info.push(format!("capacidad:{}", self.hashmap.capacity()));
+        info.push("".to_string());
+
+        info.push("# Keyspace".to_string());
+        if !self.hashmap.is_empty() {
+            info.push(format!(
+                "db0:keys={},expires={},avg_ttl={}",
+                self.hashmap.len(),
+                self.expiraciones.cantidad,
+                self.expiraciones.ttl_promedio(self.reloj.ahora())
+            ));
+        }
 
         info
     }
 
+    /// Inserta el valor en la tabla manteniendo los contadores de expiraciones
+    /// y registra la escritura para publicarla al terminar la operacion
+    fn insertar(&mut self, clave: String, mut valor: Valor) {
+        valor.ajustar_codificacion(&self.limites);
+        self.expiraciones.sumar(&valor);
+        self.eventos_pendientes
+            .push(Evento::ClaveEscrita(clave.clone()));
+        if !self.hashmap.contains_key(clave.as_str()) {
+            self.recorrido
+                .insert((hash_de_clave(&clave), Cadena::new(&clave)));
+        }
+        if let Some(anterior) = Arc::make_mut(&mut self.hashmap).insert(clave.into(), valor) {
+            self.expiraciones.restar(&anterior);
+        }
+    }
+
+    /// Quita el valor de la tabla manteniendo los contadores de expiraciones
+    fn quitar(&mut self, clave: &str) -> Option<Valor> {
+        let valor = Arc::make_mut(&mut self.hashmap).remove(clave);
+        if let Some(v) = &valor {
+            self.expiraciones.restar(v);
+            self.recorrido
+                .remove(&(hash_de_clave(clave), Cadena::new(clave)));
+        }
+        valor
+    }
+
+    /// Recorre las claves a partir del cursor examinando como mucho `cantidad`, como SCAN.
+    /// Las claves se recorren en el orden de su hash, por lo que una clave que existe durante todo el recorrido
+    /// se devuelve aunque se agreguen o eliminen otras. Devuelve el cursor para continuar, 0 si se termino el recorrido
+    pub fn recorrer_claves(&self, cursor: u64, cantidad: usize) -> (u64, Vec<String>) {
+        let mut pendientes = self.recorrido.range((cursor, Cadena::new(""))..).peekable();
+        let mut claves = Vec::new();
+        let mut ultimo_hash = None;
+        while let Some((hash, clave)) = pendientes.peek() {
+            // Las claves con el mismo hash se devuelven juntas para que el cursor no saltee ninguna
+            if claves.len() >= cantidad.max(1) && ultimo_hash != Some(*hash) {
+                return (*hash, claves);
+            }
+            ultimo_hash = Some(*hash);
+            claves.push(clave.to_string());
+            pendientes.next();
+        }
+        (0, claves)
+    }
+
+    /// Reemplaza el reloj con el que se deciden las expiraciones, para que los tests
+    /// controlen el paso del tiempo
+    #[cfg(test)]
+    pub fn set_reloj(&mut self, reloj: Arc<dyn Reloj>) {
+        self.reloj = reloj;
+    }
+
+    /// Tiempo que se conservan las claves despues de expirar, opcion stale-while-expired
+    pub fn set_gracia_de_expiracion(&mut self, gracia: Duration) {
+        self.gracia_de_expiracion = gracia;
+    }
+
+    /// Hora con la que se decide si una clave expiro hace mas que la gracia de expiracion,
+    /// en cuyo caso ya no se puede leer ni siquiera como valor viejo
+    fn fin_de_gracia(&self, ahora: SystemTime) -> SystemTime {
+        ahora
+            .checked_sub(self.gracia_de_expiracion)
+            .unwrap_or(ahora)
+    }
+
+    /// Hora actual segun el reloj de la base de datos
+    pub fn ahora(&self) -> SystemTime {
+        self.reloj.ahora()
+    }
+
     #[allow(dead_code)]
     pub fn new() -> Self {
         BaseDeDatos {
-            hashmap: HashMap::<String, Valor>::new(),
-            observadores: vec![],
+            hashmap: Arc::new(HashMap::<Cadena, Valor>::new()),
+            recorrido: BTreeSet::new(),
+            expiraciones: Expiraciones::default(),
+            eventos: BusDeEventos::new(),
+            eventos_pendientes: Vec::new(),
+            rol: Rol::Maestro,
+            estadisticas: Arc::new(Estadisticas::new()),
+            limites: LimitesDeCodificacion::default(),
+            seguimiento: SeguimientoDeClaves::new(),
+            reloj: Arc::new(RelojDelSistema),
+            gracia_de_expiracion: Duration::from_millis(0),
+            canales_por_cliente: HashMap::new(),
+            logger: None,
         }
     }
 
-    pub fn new_con(tabla_persistida: HashMap<String, Valor>) -> Self {
-        BaseDeDatos {
-            hashmap: tabla_persistida,
-            observadores: vec![],
-        }
+    pub fn new_con(tabla_persistida: HashMap<Cadena, Valor>) -> Self {
+        let mut bdd = BaseDeDatos {
+            expiraciones: Expiraciones::contar(&tabla_persistida),
+            recorrido: orden_de_recorrido(&tabla_persistida),
+            hashmap: Arc::new(tabla_persistida),
+            eventos: BusDeEventos::new(),
+            eventos_pendientes: Vec::new(),
+            rol: Rol::Maestro,
+            estadisticas: Arc::new(Estadisticas::new()),
+            limites: LimitesDeCodificacion::default(),
+            seguimiento: SeguimientoDeClaves::new(),
+            reloj: Arc::new(RelojDelSistema),
+            gracia_de_expiracion: Duration::from_millis(0),
+            canales_por_cliente: HashMap::new(),
+            logger: None,
+        };
+        bdd.ajustar_codificaciones();
+        bdd
     }
 }
 
-impl Observable for BaseDeDatos {
-    fn notificar_observadores(&self, bdd: HashMap<String, Valor>) {
-        self.observadores
-            .iter()
-            .for_each(|o| o.actualizar(bdd.clone()))
-    }
+/// Devuelve las claves de la tabla vigentes en el instante indicado que matchean con un patron
+pub fn claves_que_coinciden(
+    tabla: &HashMap<Cadena, Valor>,
+    re: &str,
+    ahora: SystemTime,
+) -> Vec<String> {
+    let regex = match Regex::new(re) {
+        Ok(r) => r,
+        Err(_) => return Vec::new(),
+    };
 
-    fn agregar_observador(&mut self, o: Box<dyn Observer + Send>) {
-        self.observadores.push(o);
-    }
+    tabla
+        .iter()
+        .filter(|(clave, valor)| !valor.esta_expirado_al(ahora) && regex.is_match(clave))
+        .map(|(clave, _)| clave.to_string())
+        .collect()
+}
+
+fn orden_de_recorrido(tabla: &HashMap<Cadena, Valor>) -> BTreeSet<(u64, Cadena)> {
+    tabla
+        .keys()
+        .map(|clave| (hash_de_clave(clave), clave.clone()))
+        .collect()
+}
+
+fn hash_de_clave(clave: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    clave.hash(&mut hasher);
+    hasher.finish()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-    use std::time::Duration;
+    use crate::log_handler::Mensaje;
+    use crate::reloj::RelojSimulado;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    struct Registro(Arc<Mutex<Vec<Evento>>>);
+
+    impl Suscriptor for Registro {
+        fn recibir(&self, eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            self.0.lock().unwrap().extend_from_slice(eventos);
+        }
+    }
+
+    fn registrar_eventos(data_base: &mut BaseDeDatos) -> Arc<Mutex<Vec<Evento>>> {
+        let eventos = Arc::new(Mutex::new(Vec::new()));
+        data_base.suscribir(Box::new(Registro(Arc::clone(&eventos))));
+        eventos
+    }
+
+    struct Defectuoso;
+
+    impl Suscriptor for Defectuoso {
+        fn recibir(&self, _eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            panic!("suscriptor defectuoso");
+        }
+
+        fn nombre(&self) -> &str {
+            "metricas"
+        }
+    }
+
+    #[test]
+    fn un_suscriptor_en_panico_se_registra_en_el_log_y_en_las_estadisticas() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut data_base = BaseDeDatos::new();
+        data_base.set_logger(Logger::new(tx));
+        data_base.suscribir(Box::new(Defectuoso));
+
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+
+        match rx.try_recv() {
+            Ok(Mensaje::InfoConeccion(_, mensaje)) => assert_eq!(
+                "El suscriptor metricas entro en panico: suscriptor defectuoso, se lo quito del bus de eventos",
+                mensaje
+            ),
+            _ => panic!("no se registro el panico"),
+        }
+        assert!(data_base
+            .estadisticas()
+            .info()
+            .contains(&"event_subscriber_panics:1".to_string()));
+    }
 
     #[test]
     fn base_de_datos_devuelve_una_copia_de_un_elemento_almacenado() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
 
         let valor = data_base.obtener_valor("clave");
-        assert_eq!(&TipoRedis::Str("valor".to_string()), valor.unwrap());
+        assert_eq!(&TipoRedis::Str("valor".into()), valor.unwrap());
     }
 
     #[test]
     fn base_de_datos_elimina_valor_almacenado() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
 
         assert!(data_base.existe_clave("clave"));
 
@@ -278,14 +1023,367 @@ mod tests {
     #[test]
     fn si_se_guarda_una_clave_que_expira_en_1_segundo_cuando_se_la_quiere_recuperar_no_se_encuentra(
     ) {
+        let reloj = Arc::new(RelojSimulado::new());
         let mut data_base = BaseDeDatos::new();
+        data_base.set_reloj(reloj.clone());
         data_base.guardar_valor_con_expiracion(
             "clave".to_string(),
+            Duration::from_secs(1),
+            TipoRedis::Str("valor".into()),
+        );
+
+        reloj.avanzar(Duration::from_millis(500));
+        assert_eq!(1, data_base.obtener_expiracion("clave"));
+        reloj.avanzar(Duration::from_millis(499));
+        assert_eq!(1, data_base.obtener_expiracion_en_milisegundos("clave"));
+        assert!(data_base.obtener_valor("clave").is_some());
+        assert_eq!(vec!["clave".to_string()], data_base.claves(".*"));
+
+        reloj.avanzar(Duration::from_millis(1));
+        assert_eq!(None, data_base.obtener_valor("clave"));
+        assert_eq!(-2, data_base.obtener_expiracion("clave"));
+        assert!(!data_base.existe_clave("clave"));
+        assert!(data_base.claves(".*").is_empty());
+    }
+
+    fn base_con_clave_expirada(rol: Rol) -> BaseDeDatos {
+        let reloj = Arc::new(RelojSimulado::new());
+        let mut data_base = BaseDeDatos::new();
+        data_base.set_rol(rol);
+        data_base.set_reloj(reloj.clone());
+        data_base.guardar_valor_con_expiracion(
+            "clave".to_string(),
+            Duration::from_millis(10),
+            TipoRedis::Str("valor".into()),
+        );
+        reloj.avanzar(Duration::from_millis(10));
+        data_base
+    }
+
+    #[test]
+    fn el_maestro_elimina_las_claves_expiradas_y_publica_su_expiracion() {
+        let mut data_base = base_con_clave_expirada(Rol::Maestro);
+        let eventos = registrar_eventos(&mut data_base);
+
+        assert_eq!(
             1,
-            TipoRedis::Str("valor".to_string()),
+            data_base.expirar_claves(&["clave".to_string(), "otra".to_string()])
+        );
+        assert_eq!(0, data_base.cantidad_claves());
+        assert_eq!(
+            vec![Evento::ClaveExpirada("clave".to_string())],
+            *eventos.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn una_replica_no_elimina_claves_expiradas_pero_no_las_devuelve() {
+        let mut data_base = base_con_clave_expirada(Rol::Replica);
+        let eventos = registrar_eventos(&mut data_base);
+
+        assert_eq!(0, data_base.expirar_claves(&["clave".to_string()]));
+        assert_eq!(1, data_base.cantidad_claves());
+        assert_eq!(None, data_base.obtener_valor("clave"));
+        assert!(eventos.lock().unwrap().is_empty());
+
+        assert_eq!(1, data_base.eliminar_clave("clave"));
+        assert_eq!(0, data_base.cantidad_claves());
+    }
+
+    #[test]
+    fn durante_la_gracia_de_expiracion_la_clave_expirada_se_lee_como_vieja() {
+        let reloj = Arc::new(RelojSimulado::new());
+        let mut data_base = BaseDeDatos::new();
+        data_base.set_reloj(reloj.clone());
+        data_base.set_gracia_de_expiracion(Duration::from_millis(100));
+        data_base.guardar_valor_con_expiracion(
+            "clave".to_string(),
+            Duration::from_millis(10),
+            TipoRedis::Str("valor".into()),
+        );
+        assert_eq!(
+            Ok(Some(("valor", false))),
+            data_base.obtener_como_str_aunque_expiro("clave")
         );
 
-        thread::sleep(Duration::from_secs(2));
+        reloj.avanzar(Duration::from_millis(50));
+        assert_eq!(0, data_base.expirar_claves(&["clave".to_string()]));
         assert_eq!(None, data_base.obtener_valor("clave"));
+        assert_eq!(
+            Ok(Some(("valor", true))),
+            data_base.obtener_como_str_aunque_expiro("clave")
+        );
+
+        reloj.avanzar(Duration::from_millis(60));
+        assert_eq!(Ok(None), data_base.obtener_como_str_aunque_expiro("clave"));
+        assert_eq!(1, data_base.expirar_claves(&["clave".to_string()]));
+        assert_eq!(0, data_base.cantidad_claves());
+    }
+
+    #[test]
+    fn cada_operacion_publica_los_eventos_de_las_claves_que_modifico() {
+        let mut data_base = BaseDeDatos::new();
+        let eventos = registrar_eventos(&mut data_base);
+
+        data_base.guardar_valores(&["a".to_string(), "1".to_string()]);
+        data_base.renombrar_clave("a", "b");
+        assert_eq!(
+            0,
+            data_base.actualizar_valor_sin_expiracion("b".to_string())
+        );
+        data_base.eliminar_clave("b");
+        data_base.eliminar_clave("b");
+        data_base.borrar_claves();
+
+        assert_eq!(
+            vec![
+                Evento::ClaveEscrita("a".to_string()),
+                Evento::ClaveEliminada("a".to_string()),
+                Evento::ClaveEscrita("b".to_string()),
+                Evento::ClaveEliminada("b".to_string()),
+                Evento::BaseReemplazada,
+            ],
+            *eventos.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn info_informa_la_cantidad_de_claves_y_de_expiraciones() {
+        let mut data_base = BaseDeDatos::new();
+        assert_eq!(Some(&"# Keyspace".to_string()), data_base.info().last());
+
+        data_base.guardar_valor("a".to_string(), TipoRedis::Str("1".into()));
+        data_base.guardar_valor_con_expiracion(
+            "b".to_string(),
+            Duration::from_secs(100),
+            TipoRedis::Str("2".into()),
+        );
+        data_base.guardar_valor_con_expiracion(
+            "c".to_string(),
+            Duration::from_secs(100),
+            TipoRedis::Str("3".into()),
+        );
+        data_base.actualizar_valor_sin_expiracion("c".to_string());
+        data_base.actualizar_valor_con_expiracion_absoluta(
+            "a".to_string(),
+            SystemTime::now() + Duration::from_secs(100),
+        );
+        data_base.renombrar_clave("a", "d");
+        data_base.guardar_valor("b".to_string(), TipoRedis::Str("4".into()));
+
+        let keyspace = data_base.info().pop().unwrap();
+        let avg_ttl: u128 = keyspace
+            .strip_prefix("db0:keys=3,expires=1,avg_ttl=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(avg_ttl > 99_000 && avg_ttl <= 100_000);
+    }
+
+    #[test]
+    fn recorrer_claves_devuelve_cada_clave_que_sigue_existiendo_aunque_cambie_la_tabla() {
+        let mut data_base = BaseDeDatos::new();
+        for i in 0..20 {
+            data_base.guardar_valor(format!("clave{}", i), TipoRedis::Str(i.to_string().into()));
+        }
+
+        let (mut cursor, mut vistas) = data_base.recorrer_claves(0, 5);
+        assert_eq!(5, vistas.len());
+        data_base.eliminar_clave(&vistas[0]);
+        data_base.guardar_valor("nueva".to_string(), TipoRedis::Str("n".into()));
+        while cursor != 0 {
+            let (siguiente, claves) = data_base.recorrer_claves(cursor, 5);
+            vistas.extend(claves);
+            cursor = siguiente;
+        }
+
+        for i in 0..20 {
+            assert!(vistas.contains(&format!("clave{}", i)));
+        }
+        assert_eq!(vistas.len(), vistas.iter().collect::<HashSet<_>>().len());
+    }
+
+    #[test]
+    fn la_expiracion_solo_se_actualiza_si_se_cumple_la_condicion() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+        let en = |segundos| SystemTime::now() + Duration::from_secs(segundos);
+
+        assert_eq!(
+            0,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(100),
+                CondicionExpiracion::ConExpiracion
+            )
+        );
+        assert_eq!(
+            0,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(100),
+                CondicionExpiracion::Mayor
+            )
+        );
+        assert_eq!(
+            1,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(100),
+                CondicionExpiracion::SinExpiracion
+            )
+        );
+        assert_eq!(
+            0,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(50),
+                CondicionExpiracion::SinExpiracion
+            )
+        );
+        assert_eq!(
+            0,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(200),
+                CondicionExpiracion::Menor
+            )
+        );
+        assert_eq!(
+            1,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(50),
+                CondicionExpiracion::Menor
+            )
+        );
+        assert_eq!(
+            1,
+            data_base.actualizar_expiracion_si(
+                "clave".to_string(),
+                en(200),
+                CondicionExpiracion::Mayor
+            )
+        );
+        assert!(data_base.obtener_expiracion("clave") > 150);
+    }
+
+    #[test]
+    fn obtener_como_un_tipo_distinto_al_almacenado_devuelve_error_de_tipo() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Lista(vec!["a".to_string()]));
+
+        assert_eq!(
+            Ok(Some(&vec!["a".to_string()])),
+            data_base.obtener_como_lista("clave")
+        );
+        assert_eq!(
+            Err(TipoIncorrectoError),
+            data_base.obtener_como_str("clave")
+        );
+        assert_eq!(
+            Err(TipoIncorrectoError),
+            data_base.obtener_como_set("clave")
+        );
+        assert_eq!(Ok(None), data_base.obtener_como_set("otra_clave"));
+        assert_eq!(
+            ResultadoRedis::Error(WRONGTYPE.to_string()),
+            TipoIncorrectoError.a_resultado()
+        );
+    }
+
+    #[test]
+    fn intercambiar_valor_no_modifica_una_clave_de_otro_tipo() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Set(Conjunto::new()));
+
+        assert_eq!(
+            Err(TipoIncorrectoError),
+            data_base.intercambiar_valor("clave".to_string(), "valor".to_string())
+        );
+        assert_eq!(
+            Some(&TipoRedis::Set(Conjunto::new())),
+            data_base.obtener_valor("clave")
+        );
+    }
+
+    #[test]
+    fn persistir_una_clave_solo_tiene_efecto_si_la_clave_expiraba() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("fija".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor_con_expiracion(
+            "volatil".to_string(),
+            Duration::from_secs(100),
+            TipoRedis::Str("valor".into()),
+        );
+
+        assert_eq!(
+            0,
+            data_base.actualizar_valor_sin_expiracion("fija".to_string())
+        );
+        assert_eq!(
+            1,
+            data_base.actualizar_valor_sin_expiracion("volatil".to_string())
+        );
+        assert_eq!(-1, data_base.obtener_expiracion("volatil"));
+        assert_eq!(-2, data_base.obtener_expiracion("inexistente"));
+    }
+
+    #[test]
+    fn actualizar_un_valor_conserva_su_expiracion_y_guardarlo_la_descarta() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor_con_expiracion(
+            "clave".to_string(),
+            Duration::from_secs(100),
+            TipoRedis::Str("valor".into()),
+        );
+
+        data_base.actualizar_valor("clave".to_string(), TipoRedis::Str("otro".into()));
+        assert!(data_base.obtener_expiracion("clave") > 0);
+        assert_eq!(
+            Some(&TipoRedis::Str("otro".into())),
+            data_base.obtener_valor("clave")
+        );
+
+        data_base.renombrar_clave("clave", "nueva");
+        assert!(!data_base.existe_clave("clave"));
+        assert!(data_base.obtener_expiracion("nueva") > 0);
+
+        data_base.guardar_valor("nueva".to_string(), TipoRedis::Str("valor".into()));
+        assert_eq!(-1, data_base.obtener_expiracion("nueva"));
+    }
+
+    #[test]
+    fn poblar_crea_las_claves_que_faltan_con_valores_del_tamanio_pedido() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("key:1".to_string(), TipoRedis::Str("propio".into()));
+
+        assert_eq!(2, data_base.poblar(3, "key", None));
+        assert_eq!(Ok(Some("value:0")), data_base.obtener_como_str("key:0"));
+        assert_eq!(Ok(Some("propio")), data_base.obtener_como_str("key:1"));
+
+        data_base.poblar(2, "corto", Some(3));
+        data_base.poblar(1, "largo", Some(10));
+        assert_eq!(Ok(Some("val")), data_base.obtener_como_str("corto:1"));
+        assert_eq!(
+            Ok(Some("value:0\0\0\0")),
+            data_base.obtener_como_str("largo:0")
+        );
+    }
+
+    #[test]
+    fn una_instantanea_no_ve_las_escrituras_posteriores() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+
+        let instantanea = data_base.instantanea();
+        data_base.guardar_valor("otra".to_string(), TipoRedis::Str("valor".into()));
+        data_base.borrar_claves();
+
+        assert_eq!(
+            vec!["clave".to_string()],
+            claves_que_coinciden(&instantanea, ".*", data_base.ahora())
+        );
+        assert_eq!(0, data_base.cantidad_claves());
     }
 }
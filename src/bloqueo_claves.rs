@@ -0,0 +1,289 @@
+use crate::cluster::parte_con_hash;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Cantidad de franjas en las que se reparten las claves
+const FRANJAS: usize = 256;
+
+/// Capa de bloqueos por clave. Cada clave se asigna a una franja con su propio lock,
+/// de modo que los comandos sobre claves distintas pueden ejecutarse en paralelo
+/// y los comandos sobre la misma clave se ejecutan de a uno. Las claves con el mismo
+/// hash tag comparten franja, igual que en el cluster comparten slot
+#[derive(Debug)]
+pub struct BloqueoPorClave {
+    franjas: Vec<Mutex<()>>,
+    contencion: Vec<ContencionDeFranja>,
+}
+
+/// Cuantas veces se tomo el lock de una franja y cuanto se espero para tomarlo, en microsegundos
+#[derive(Debug, Default)]
+struct ContencionDeFranja {
+    adquisiciones: AtomicU64,
+    espera_total: AtomicU64,
+    espera_maxima: AtomicU64,
+}
+
+/// Locks tomados para ejecutar un comando, se liberan al salir de alcance
+pub struct Bloqueo<'a> {
+    _guardas: Vec<MutexGuard<'a, ()>>,
+}
+
+impl Default for BloqueoPorClave {
+    fn default() -> Self {
+        BloqueoPorClave::con_franjas(FRANJAS)
+    }
+}
+
+impl BloqueoPorClave {
+    pub fn new() -> Self {
+        BloqueoPorClave::default()
+    }
+
+    /// Instancia la capa de bloqueos con una cantidad determinada de franjas
+    pub fn con_franjas(cantidad: usize) -> Self {
+        BloqueoPorClave {
+            franjas: (0..cantidad.max(1)).map(|_| Mutex::new(())).collect(),
+            contencion: (0..cantidad.max(1))
+                .map(|_| ContencionDeFranja::default())
+                .collect(),
+        }
+    }
+
+    /// Bloquea las franjas de todas las claves enviadas. Las franjas se toman siempre en orden
+    /// creciente y una sola vez, por lo que los comandos de varias claves no pueden bloquearse entre si
+    pub fn bloquear(&self, claves: &[String]) -> Bloqueo<'_> {
+        let mut indices: Vec<usize> = claves.iter().map(|c| self.franja(c)).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        self.tomar(indices)
+    }
+
+    /// Bloquea todas las franjas, para las escrituras que no declaran sus claves y pueden
+    /// modificar cualquiera de ellas
+    pub fn bloquear_todas(&self) -> Bloqueo<'_> {
+        self.tomar((0..self.franjas.len()).collect())
+    }
+
+    fn tomar(&self, indices: Vec<usize>) -> Bloqueo<'_> {
+        let guardas = indices
+            .into_iter()
+            .map(|i| {
+                let inicio = Instant::now();
+                let guarda = match self.franjas[i].lock() {
+                    Ok(guarda) => guarda,
+                    Err(envenenado) => envenenado.into_inner(),
+                };
+                let espera = inicio.elapsed().as_micros() as u64;
+                let contencion = &self.contencion[i];
+                contencion.adquisiciones.fetch_add(1, Ordering::Relaxed);
+                contencion.espera_total.fetch_add(espera, Ordering::Relaxed);
+                contencion
+                    .espera_maxima
+                    .fetch_max(espera, Ordering::Relaxed);
+                guarda
+            })
+            .collect();
+        Bloqueo { _guardas: guardas }
+    }
+
+    /// Seccion Locks de INFO con los totales de todas las franjas y el detalle de las que
+    /// tuvieron que esperar alguna vez, las esperas se informan en microsegundos
+    pub fn seccion_info(&self) -> Vec<String> {
+        let mut info = vec!["# Locks".to_string()];
+        let mut detalle = Vec::new();
+        let (mut adquisiciones, mut espera_total, mut espera_maxima) = (0, 0, 0);
+        for (i, contencion) in self.contencion.iter().enumerate() {
+            let franja_adquisiciones = contencion.adquisiciones.load(Ordering::Relaxed);
+            let franja_espera = contencion.espera_total.load(Ordering::Relaxed);
+            let franja_maxima = contencion.espera_maxima.load(Ordering::Relaxed);
+            adquisiciones += franja_adquisiciones;
+            espera_total += franja_espera;
+            espera_maxima = espera_maxima.max(franja_maxima);
+            if franja_espera > 0 {
+                detalle.push(format!(
+                    "lock_shard{}:acquisitions={},wait_us={},max_wait_us={}",
+                    i, franja_adquisiciones, franja_espera, franja_maxima
+                ));
+            }
+        }
+        info.push(format!("lock_shards:{}", self.franjas.len()));
+        info.push(format!("lock_acquisitions:{}", adquisiciones));
+        info.push(format!("lock_wait_time_us:{}", espera_total));
+        info.push(format!("lock_max_wait_us:{}", espera_maxima));
+        info.append(&mut detalle);
+        info.push("".to_string());
+        info
+    }
+
+    fn franja(&self, clave: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        parte_con_hash(clave).hash(&mut hasher);
+        (hasher.finish() as usize) % self.franjas.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::ResultadoRedis;
+    use crate::replicacion::codificar_comando;
+    use crate::resp;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::{BufReader, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn claves(valores: &[&str]) -> Vec<String> {
+        valores.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn comandos_sobre_claves_distintas_no_se_bloquean() {
+        let bloqueos = Arc::new(BloqueoPorClave::con_franjas(2));
+        let _primero = bloqueos.bloquear(&claves(&["a"]));
+        let otra_clave = (0..)
+            .map(|i| format!("clave{}", i))
+            .find(|c| bloqueos.franja(c) != bloqueos.franja("a"))
+            .unwrap();
+
+        let clon = Arc::clone(&bloqueos);
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let _segundo = clon.bloquear(&[otra_clave]);
+            tx.send(()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn comandos_sobre_la_misma_clave_se_ejecutan_de_a_uno() {
+        let bloqueos = Arc::new(BloqueoPorClave::new());
+        let primero = bloqueos.bloquear(&claves(&["a", "b"]));
+
+        let clon = Arc::clone(&bloqueos);
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            let _segundo = clon.bloquear(&claves(&["b"]));
+            tx.send(()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(primero);
+        assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn bloquear_varias_claves_en_distinto_orden_no_produce_deadlock() {
+        let bloqueos = Arc::new(BloqueoPorClave::con_franjas(4));
+        let hilos: Vec<_> = (0..4)
+            .map(|i| {
+                let clon = Arc::clone(&bloqueos);
+                thread::spawn(move || {
+                    let mut orden = claves(&["a", "b", "c", "a"]);
+                    orden.rotate_left(i);
+                    for _ in 0..1000 {
+                        let _bloqueo = clon.bloquear(&orden);
+                    }
+                })
+            })
+            .collect();
+
+        for hilo in hilos {
+            assert!(hilo.join().is_ok());
+        }
+    }
+
+    #[test]
+    fn la_espera_por_una_franja_ocupada_se_informa_en_info() {
+        let bloqueos = Arc::new(BloqueoPorClave::con_franjas(4));
+        let primero = bloqueos.bloquear(&claves(&["a"]));
+
+        let clon = Arc::clone(&bloqueos);
+        let hilo = thread::spawn(move || {
+            let _segundo = clon.bloquear(&claves(&["a"]));
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(primero);
+        hilo.join().unwrap();
+
+        let info = bloqueos.seccion_info();
+        let franja = format!("lock_shard{}:acquisitions=2,", bloqueos.franja("a"));
+        assert!(info.contains(&"lock_acquisitions:2".to_string()));
+        assert!(info.iter().any(|l| l.starts_with(&franja)));
+        let maxima: u64 = info
+            .iter()
+            .find_map(|l| l.strip_prefix("lock_max_wait_us:"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(maxima >= 40_000);
+    }
+
+    #[test]
+    fn las_claves_con_el_mismo_hash_tag_comparten_franja() {
+        let bloqueos = BloqueoPorClave::new();
+        assert_eq!(
+            bloqueos.franja("{usuario}.nombre"),
+            bloqueos.franja("{usuario}.edad")
+        );
+        assert_eq!(bloqueos.franja("usuario"), bloqueos.franja("{usuario}"));
+    }
+
+    fn ejecutar_varias_veces(direccion: SocketAddr, tokens: &[&str], veces: usize) {
+        let mut conexion = TcpStream::connect(direccion).unwrap();
+        let mut lector = BufReader::new(conexion.try_clone().unwrap());
+        let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+        for _ in 0..veces {
+            conexion
+                .write_all(codificar_comando(&tokens).as_bytes())
+                .unwrap();
+            assert!(!matches!(
+                resp::leer(&mut lector).unwrap(),
+                ResultadoRedis::Error(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn incrby_y_append_concurrentes_sobre_la_misma_clave_no_pierden_escrituras() {
+        let servidor = ServidorDePrueba::iniciar().unwrap();
+        let direccion = servidor.direccion();
+        let hilos: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(move || {
+                    ejecutar_varias_veces(direccion, &["INCRBY", "contador", "1"], 200);
+                    ejecutar_varias_veces(direccion, &["APPEND", "texto", "x"], 200);
+                })
+            })
+            .collect();
+        for hilo in hilos {
+            hilo.join().unwrap();
+        }
+
+        {
+            let mut conexion = TcpStream::connect(direccion).unwrap();
+            let mut lector = BufReader::new(conexion.try_clone().unwrap());
+            let mut enviar = |tokens: &[&str]| {
+                let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+                conexion
+                    .write_all(codificar_comando(&tokens).as_bytes())
+                    .unwrap();
+                resp::leer(&mut lector).unwrap()
+            };
+            assert_eq!(
+                ResultadoRedis::BulkStr("800".to_string()),
+                enviar(&["GET", "contador"])
+            );
+            assert_eq!(ResultadoRedis::Int(800), enviar(&["STRLEN", "texto"]));
+        }
+        servidor.detener();
+    }
+}
@@ -0,0 +1,171 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/// Largo maximo en bytes de una cadena que se guarda en linea, sin reservar memoria aparte
+pub const MAX_EN_LINEA: usize = 22;
+
+/// Cadena inmutable que guarda en linea los textos de hasta MAX_EN_LINEA bytes, y el resto
+/// en una unica reserva del largo justo. Ocupa lo mismo que un String, pero los valores chicos,
+/// que son la mayoria, no generan reservas de memoria que fragmenten el heap
+#[derive(Clone)]
+pub struct Cadena(Representacion);
+
+#[derive(Clone)]
+enum Representacion {
+    EnLinea {
+        largo: u8,
+        bytes: [u8; MAX_EN_LINEA],
+    },
+    Reservada(Box<str>),
+}
+
+impl Cadena {
+    pub fn new(texto: &str) -> Self {
+        if texto.len() > MAX_EN_LINEA {
+            return Cadena(Representacion::Reservada(texto.into()));
+        }
+        let mut bytes = [0; MAX_EN_LINEA];
+        bytes[..texto.len()].copy_from_slice(texto.as_bytes());
+        Cadena(Representacion::EnLinea {
+            largo: texto.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // Los bytes se copiaron de un &str, por lo que siempre son UTF-8 valido
+            Representacion::EnLinea { largo, bytes } => {
+                std::str::from_utf8(&bytes[..*largo as usize]).unwrap_or_default()
+            }
+            Representacion::Reservada(texto) => texto,
+        }
+    }
+
+    /// Indica si la cadena se guarda en linea, sin memoria reservada aparte
+    pub fn esta_en_linea(&self) -> bool {
+        matches!(self.0, Representacion::EnLinea { .. })
+    }
+}
+
+impl Deref for Cadena {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for Cadena {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Cadena {
+    fn from(texto: &str) -> Self {
+        Cadena::new(texto)
+    }
+}
+
+/// Los textos largos reutilizan la reserva del String, achicandola al largo justo
+impl From<String> for Cadena {
+    fn from(texto: String) -> Self {
+        if texto.len() > MAX_EN_LINEA {
+            Cadena(Representacion::Reservada(texto.into_boxed_str()))
+        } else {
+            Cadena::new(&texto)
+        }
+    }
+}
+
+impl From<Cadena> for String {
+    fn from(cadena: Cadena) -> Self {
+        match cadena.0 {
+            Representacion::Reservada(texto) => texto.into_string(),
+            Representacion::EnLinea { .. } => cadena.as_str().to_string(),
+        }
+    }
+}
+
+impl PartialEq for Cadena {
+    fn eq(&self, otra: &Self) -> bool {
+        self.as_str() == otra.as_str()
+    }
+}
+
+impl Eq for Cadena {}
+
+impl PartialEq<str> for Cadena {
+    fn eq(&self, otra: &str) -> bool {
+        self.as_str() == otra
+    }
+}
+
+impl PartialEq<&str> for Cadena {
+    fn eq(&self, otra: &&str) -> bool {
+        self.as_str() == *otra
+    }
+}
+
+impl PartialOrd for Cadena {
+    fn partial_cmp(&self, otra: &Self) -> Option<Ordering> {
+        Some(self.cmp(otra))
+    }
+}
+
+impl Ord for Cadena {
+    fn cmp(&self, otra: &Self) -> Ordering {
+        self.as_str().cmp(otra.as_str())
+    }
+}
+
+/// Se hashea igual que el &str que contiene, como exige Borrow<str>
+impl Hash for Cadena {
+    fn hash<H: Hasher>(&self, estado: &mut H) {
+        self.as_str().hash(estado)
+    }
+}
+
+impl fmt::Debug for Cadena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Cadena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn las_cadenas_chicas_se_guardan_en_linea_y_las_largas_no() {
+        let chica = Cadena::from("valor".to_string());
+        let limite = Cadena::from("a".repeat(MAX_EN_LINEA));
+        let larga = Cadena::from("a".repeat(MAX_EN_LINEA + 1));
+
+        assert!(chica.esta_en_linea() && limite.esta_en_linea());
+        assert!(!larga.esta_en_linea());
+        assert_eq!("valor", chica.as_str());
+        assert_eq!(MAX_EN_LINEA + 1, larga.len());
+        assert_eq!("a".repeat(MAX_EN_LINEA + 1), String::from(larga));
+        assert_eq!(std::mem::size_of::<String>(), std::mem::size_of::<Cadena>());
+    }
+
+    #[test]
+    fn respeta_los_caracteres_de_varios_bytes() {
+        let texto = "ñandú über straße";
+        let cadena = Cadena::from(texto);
+        assert_eq!(texto, cadena.as_str());
+        assert_eq!(Some('ñ'), cadena.chars().next());
+        assert_eq!(cadena, Cadena::from(texto.to_string()));
+    }
+}
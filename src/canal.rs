@@ -1,6 +1,5 @@
 use crate::base_de_datos::ResultadoRedis;
-use crate::cliente::Cliente;
-use crate::redis_error::RedisError;
+use crate::cliente::{Cliente, Token};
 
 /// Representa un canal donde se pueden suscribir clientes y publicar mensajes
 #[derive(Debug, PartialEq, Clone)]
@@ -17,12 +16,17 @@ impl Canal {
         }
     }
 
-    pub fn suscribirse(&mut self, suscriptor: Cliente) {
-        if self.notificar_suscripcion(suscriptor.clone()).is_ok() {
-            self.suscriptores.push(suscriptor)
+    /// Suscribe al cliente al canal, devuelve falso si ya estaba suscripto
+    pub fn suscribirse(&mut self, suscriptor: Cliente) -> bool {
+        if self.suscriptores.contains(&suscriptor) {
+            return false;
         }
+        self.suscriptores.push(suscriptor);
+        true
     }
 
+    /// Envia el mensaje a todos los suscriptores sin esperar a que lo lean, devuelve a cuantos
+    /// se les pudo enviar. Los suscriptores lentos no demoran al que publica
     pub fn publicar(&mut self, mensaje: String) -> usize {
         let mut publicados: usize = 0;
         let resultado = ResultadoRedis::Vector(vec![
@@ -31,7 +35,7 @@ impl Canal {
             ResultadoRedis::BulkStr(mensaje),
         ]);
         for suscriptor in &mut self.suscriptores {
-            match suscriptor.enviar_resultado(&resultado) {
+            match suscriptor.enviar_push(&resultado) {
                 Ok(_) => publicados += 1,
                 Err(_) => continue,
             }
@@ -39,18 +43,29 @@ impl Canal {
         publicados
     }
 
-    pub fn desuscribirse(&mut self, suscriptor: Cliente) {
-        let index = match self
+    /// Envia el contenido como mensaje del canal a un unico suscriptor,
+    /// devuelve falso si no esta suscripto o no se le pudo enviar
+    pub fn enviar_a(&mut self, token: Token, contenido: ResultadoRedis) -> bool {
+        let resultado = ResultadoRedis::Vector(vec![
+            ResultadoRedis::BulkStr("message".to_string()),
+            ResultadoRedis::BulkStr(self.nombre.clone()),
+            contenido,
+        ]);
+        match self
             .suscriptores
-            .iter()
-            .position(|x| *x == suscriptor.clone())
+            .iter_mut()
+            .find(|s| s.obtener_token() == token)
         {
-            Some(i) => i,
-            None => return,
-        };
+            Some(suscriptor) => suscriptor.enviar_push(&resultado).is_ok(),
+            None => false,
+        }
+    }
 
-        self.suscriptores.remove(index);
-        if self.notificar_desubscripcion(suscriptor).is_ok() {}
+    /// Quita al suscriptor del canal, devuelve si estaba suscripto
+    pub fn desuscribirse(&mut self, suscriptor: &Cliente) -> bool {
+        let cantidad = self.suscriptores.len();
+        self.suscriptores.retain(|s| s != suscriptor);
+        cantidad != self.suscriptores.len()
     }
 
     pub fn es_activo(&self) -> bool {
@@ -60,22 +75,4 @@ impl Canal {
     pub fn len(&self) -> usize {
         self.suscriptores.len()
     }
-
-    fn notificar_suscripcion(&self, mut suscriptor: Cliente) -> Result<(), RedisError> {
-        let cant = self.suscriptores.len() + 1;
-        let resultado = ResultadoRedis::Vector(vec![
-            ResultadoRedis::BulkStr("subscribe".to_string()),
-            ResultadoRedis::BulkStr(self.nombre.clone()),
-            ResultadoRedis::Int(cant as isize),
-        ]);
-        suscriptor.enviar_resultado(&resultado)
-    }
-    fn notificar_desubscripcion(&self, mut suscriptor: Cliente) -> Result<(), RedisError> {
-        let resultado = ResultadoRedis::Vector(vec![
-            ResultadoRedis::BulkStr("unsubscribe".to_string()),
-            ResultadoRedis::BulkStr(self.nombre.clone()),
-            ResultadoRedis::Int(self.suscriptores.len() as isize),
-        ]);
-        suscriptor.enviar_resultado(&resultado)
-    }
 }
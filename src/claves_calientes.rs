@@ -0,0 +1,135 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, TryLockError};
+
+/// Cantidad de contadores por fila del sketch
+const ANCHO: usize = 1024;
+/// Cantidad de filas del sketch, cada una con su propia funcion de hash
+const PROFUNDIDAD: usize = 4;
+/// Cantidad de claves mas accedidas que se recuerdan
+const CAPACIDAD_TOP: usize = 32;
+
+/// Seguimiento aproximado de las claves mas accedidas. Los accesos se cuentan en un
+/// count-min sketch de tamanio fijo, que nunca subestima, y se mantienen las claves
+/// con mayor estimacion para informarlas en HOTKEYS e INFO.
+/// Los contadores son atomicos y el top solo se actualiza si ningun otro hilo lo esta usando,
+/// por lo que registrar un acceso nunca espera a otro comando
+#[derive(Debug)]
+pub struct ClavesCalientes {
+    contadores: Vec<AtomicU64>,
+    top: Mutex<Vec<(String, u64)>>,
+}
+
+impl ClavesCalientes {
+    pub fn new() -> Self {
+        ClavesCalientes {
+            contadores: (0..ANCHO * PROFUNDIDAD)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            top: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registra un acceso a cada una de las claves. Si el top esta ocupado la clave solo se
+    /// cuenta en el sketch y entra al top en alguno de sus proximos accesos
+    pub fn registrar(&self, claves: &[String]) {
+        for clave in claves {
+            let estimacion = self.contar(clave);
+            let mut top = match self.top.try_lock() {
+                Ok(t) => t,
+                Err(TryLockError::Poisoned(envenenado)) => envenenado.into_inner(),
+                Err(TryLockError::WouldBlock) => continue,
+            };
+            actualizar_top(&mut top, clave, estimacion);
+        }
+    }
+
+    /// Devuelve las claves mas accedidas junto con su cantidad estimada de accesos,
+    /// ordenadas de la mas accedida a la menos accedida
+    pub fn mas_accedidas(&self, cantidad: usize) -> Vec<(String, u64)> {
+        let mut top = match self.top.lock() {
+            Ok(t) => t.clone(),
+            Err(envenenado) => envenenado.into_inner().clone(),
+        };
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(cantidad);
+        top
+    }
+
+    /// Incrementa los contadores de la clave y devuelve su cantidad estimada de accesos
+    fn contar(&self, clave: &str) -> u64 {
+        (0..PROFUNDIDAD)
+            .map(|fila| {
+                let indice = fila * ANCHO + columna(fila, clave);
+                self.contadores[indice].fetch_add(1, Ordering::Relaxed) + 1
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+impl Default for ClavesCalientes {
+    fn default() -> Self {
+        ClavesCalientes::new()
+    }
+}
+
+fn actualizar_top(top: &mut Vec<(String, u64)>, clave: &str, estimacion: u64) {
+    if let Some(entrada) = top.iter_mut().find(|(c, _)| c == clave) {
+        entrada.1 = estimacion;
+    } else if top.len() < CAPACIDAD_TOP {
+        top.push((clave.to_string(), estimacion));
+    } else if let Some(minima) = top.iter_mut().min_by_key(|(_, accesos)| *accesos) {
+        if minima.1 < estimacion {
+            *minima = (clave.to_string(), estimacion);
+        }
+    }
+}
+
+fn columna(fila: usize, clave: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    fila.hash(&mut hasher);
+    clave.hash(&mut hasher);
+    (hasher.finish() as usize) % ANCHO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn las_claves_mas_accedidas_se_informan_en_orden() {
+        let calientes = ClavesCalientes::new();
+        for i in 0..100 {
+            calientes.registrar(&["popular".to_string()]);
+            if i % 2 == 0 {
+                calientes.registrar(&["tibia".to_string()]);
+            }
+            calientes.registrar(&[format!("fria{}", i)]);
+        }
+
+        let top = calientes.mas_accedidas(2);
+        assert_eq!(2, top.len());
+        assert_eq!("popular", top[0].0);
+        assert!(top[0].1 >= 100);
+        assert_eq!("tibia", top[1].0);
+        assert!(top[1].1 >= 50);
+    }
+
+    #[test]
+    fn registrar_no_espera_a_quien_tiene_tomado_el_top() {
+        let calientes = ClavesCalientes::new();
+        calientes.registrar(&["a".to_string()]);
+
+        let top = calientes.top.lock().unwrap();
+        calientes.registrar(&["a".to_string(), "b".to_string()]);
+        drop(top);
+
+        calientes.registrar(&["b".to_string()]);
+        assert_eq!(
+            vec![("b".to_string(), 2), ("a".to_string(), 1)],
+            calientes.mas_accedidas(2)
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::base_de_datos::ResultadoRedis;
 use crate::cliente_http::ClienteHttp;
 use crate::cliente_redis::ClienteRedis;
+use crate::cola_de_salida::LimiteDeSalida;
 use crate::comando_info::ComandoInfo;
 use crate::redis_error::RedisError;
 use std::fmt;
@@ -12,7 +13,7 @@ use std::net::TcpStream;
 pub type Token = i64;
 
 /// Interfaz de Cliente
-pub type Cliente = Box<dyn TipoCliente + Send>;
+pub type Cliente = Box<dyn TipoCliente + Send + Sync>;
 
 /// Mensajes publicos que un Cliente debe implementar
 pub trait TipoCliente: ClienteClone + ClienteDebug {
@@ -28,13 +29,33 @@ pub trait TipoCliente: ClienteClone + ClienteDebug {
     /// Devuelve una descripcion del Cliente
     fn obtener_addr(&self) -> String;
 
-    fn envio_informacion(&self) -> bool;
+    /// Predicado que indica si el Cliente ya envio otro comando completo que se puede procesar
+    /// sin esperar, como ocurre con los comandos pipelineados
+    fn hay_comando_pendiente(&self) -> bool {
+        false
+    }
 
+    /// Predicado que indica si se debe seguir esperando comandos del Cliente
     fn esta_conectado(&self) -> bool;
 
     /// enviar el resultado procesandolo en el protocolo especifico
     fn enviar_resultado(&mut self, resultado: &ResultadoRedis) -> Result<(), RedisError>;
 
+    /// Envia un mensaje que no responde a un comando del Cliente, como los de pub/sub.
+    /// Quien lo envia no debe quedar bloqueado aunque el Cliente no este leyendo
+    fn enviar_push(&mut self, resultado: &ResultadoRedis) -> Result<(), RedisError> {
+        self.enviar_resultado(resultado)
+    }
+
+    /// Cambia el limite de los mensajes push pendientes del Cliente, por ejemplo al pasar a ser
+    /// una replica
+    fn cambiar_limite_de_salida(&self, _limite: LimiteDeSalida) {}
+
+    /// Cantidad de mensajes push que se descartaron porque el Cliente no los leia a tiempo
+    fn mensajes_descartados(&self) -> usize {
+        0
+    }
+
     /// envia un mensaje sin procesar al Cliente
     fn enviar_mensaje(&mut self, mensaje: String) -> Result<(), RedisError>;
 
@@ -42,14 +63,26 @@ pub trait TipoCliente: ClienteClone + ClienteDebug {
 
     /// Predicado que indica si un Cliente puede enviar determinado comando
     fn soporta_comando(&self, comando: &str) -> bool;
+
+    /// Cantidad de canales a los que esta suscripto el Cliente, es compartida por todas sus copias
+    fn suscripciones(&self) -> usize;
+
+    /// Registra que el Cliente se suscribio a un canal y devuelve la nueva cantidad de suscripciones
+    fn agregar_suscripcion(&self) -> usize;
+
+    /// Registra que el Cliente se desuscribio de un canal y devuelve la nueva cantidad de suscripciones
+    fn quitar_suscripcion(&self) -> usize;
+
+    /// Envia las respuestas pendientes y cierra la conexion, luego el Cliente deja de estar conectado
+    fn cerrar(&mut self);
 }
 
 pub trait ClienteClone {
-    fn clone_box(&self) -> Box<dyn TipoCliente + Send>;
+    fn clone_box(&self) -> Box<dyn TipoCliente + Send + Sync>;
 }
 
-impl Clone for Box<dyn TipoCliente + Send> {
-    fn clone(&self) -> Box<dyn TipoCliente + Send> {
+impl Clone for Box<dyn TipoCliente + Send + Sync> {
+    fn clone(&self) -> Box<dyn TipoCliente + Send + Sync> {
         self.clone_box()
     }
 }
@@ -74,7 +107,7 @@ impl Eq for Cliente {}
 
 impl<T> ClienteDebug for T
 where
-    T: 'static + TipoCliente + Debug + Send,
+    T: 'static + TipoCliente + Debug + Send + Sync,
 {
     fn fmt_box(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.fmt(f)
@@ -83,20 +116,25 @@ where
 
 impl<T> ClienteClone for T
 where
-    T: 'static + TipoCliente + Clone + Send,
+    T: 'static + TipoCliente + Clone + Send + Sync,
 {
-    fn clone_box(&self) -> Box<dyn TipoCliente + Send> {
+    fn clone_box(&self) -> Box<dyn TipoCliente + Send + Sync> {
         Box::new(self.clone())
     }
 }
 
 /// Crea a un cliente especifico dependiendo de como sea el protocolo que utilice
 /// ya sea HTTP o Redis
-pub fn crear_cliente(id: Token, timeout: u64, stream: TcpStream) -> Box<dyn TipoCliente + Send> {
+pub fn crear_cliente(
+    id: Token,
+    timeout: u64,
+    limite: LimiteDeSalida,
+    stream: TcpStream,
+) -> Box<dyn TipoCliente + Send + Sync> {
     let mut buffer = [0; 1024];
     match stream.peek(&mut buffer) {
         Ok(_) => (),
-        Err(_) => return Box::new(ClienteRedis::new(id, timeout, stream)),
+        Err(_) => return Box::new(ClienteRedis::new(id, timeout, stream).limitar_salida(limite)),
     };
 
     let mensaje = match String::from_utf8(buffer.to_vec()) {
@@ -106,6 +144,6 @@ pub fn crear_cliente(id: Token, timeout: u64, stream: TcpStream) -> Box<dyn Tipo
     if mensaje.contains("HTTP") {
         Box::new(ClienteHttp::new(id, stream))
     } else {
-        Box::new(ClienteRedis::new(id, timeout, stream))
+        Box::new(ClienteRedis::new(id, timeout, stream).limitar_salida(limite))
     }
 }
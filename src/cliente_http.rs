@@ -8,7 +8,7 @@ use std::fs::{read_to_string, File};
 
 use std::fmt;
 use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
 
 /// Representa un Cliente que se comunica utilizando el protocolo HTTP
 pub struct ClienteHttp {
@@ -17,6 +17,10 @@ pub struct ClienteHttp {
     mando: bool,
     pag_index: String,
     icono: Vec<u8>,
+    /// La request pidio las metricas, la respuesta se envia como texto plano
+    metricas: bool,
+    /// La request fue a /readyz, la respuesta es 503 si el servidor no esta listo
+    disponibilidad: bool,
 }
 
 impl ClienteHttp {
@@ -47,11 +51,39 @@ impl ClienteHttp {
             icono: buffer,
             socket: Some(socket),
             mando: false,
+            metricas: false,
+            disponibilidad: false,
         }
     }
 
-    /// Procesa requests Get devolviendo que no recibio ningun comando
+    /// Procesa requests Get devolviendo que no recibio ningun comando,
+    /// salvo /metrics que se responde con la seccion Keyprefixes de INFO y /readyz con la
+    /// seccion Readiness. /healthz responde sin consultar al servidor, solo indica que el
+    /// proceso esta vivo y acepta conexiones
     fn manejar_get(&mut self, comando: ComandoHttp) -> Result<Option<ComandoInfo>, RedisError> {
+        if comando.get_argumento() == Some("/metrics".to_string()) {
+            self.metricas = true;
+            return Ok(Some(ComandoInfo::new(vec![
+                "INFO".to_string(),
+                "keyprefixes".to_string(),
+            ])));
+        }
+        if comando.get_argumento() == Some("/readyz".to_string()) {
+            self.metricas = true;
+            self.disponibilidad = true;
+            return Ok(Some(ComandoInfo::new(vec![
+                "INFO".to_string(),
+                "readiness".to_string(),
+            ])));
+        }
+        if comando.get_argumento() == Some("/healthz".to_string()) {
+            let respuesta =
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=UTF-8\r\n\r\nok\n";
+            return match self.enviar_mensaje(respuesta.to_string()) {
+                Ok(_) => Ok(None),
+                Err(_) => Err(RedisError::Server),
+            };
+        }
         if comando.get_argumento() == Some("/favicon.ico".to_string()) {
             let respuesta = format!(
                 "HTTP/1.1 200 OK\r\nContent-Type: image/gif\r\nContent-Length: {}\r\n\r\n",
@@ -113,20 +145,31 @@ impl ClienteHttp {
         }
     }
 
+    /// Estado HTTP de la respuesta con las lineas de INFO, que para /readyz depende de `ready`
+    fn estado_de_metricas(&self, lineas: &[ResultadoRedis]) -> &'static str {
+        let listo = lineas
+            .iter()
+            .any(|linea| *linea == ResultadoRedis::BulkStr("ready:1".to_string()));
+        match !self.disponibilidad || listo {
+            true => "200 OK",
+            false => "503 Service Unavailable",
+        }
+    }
+
     fn enviar_bytes(&mut self, bytes: &[u8]) -> Result<(), RedisError> {
         let socket = match &mut self.socket {
             None => return Err(RedisError::Coneccion),
             Some(t) => t,
         };
 
-        match socket.write(bytes) {
+        match socket.write_all(bytes) {
             Ok(_) => (),
-            Err(_) => return Err(RedisError::Coneccion),
+            Err(e) => return Err(RedisError::from(e)),
         };
 
         match socket.flush() {
             Ok(_) => Ok(()),
-            Err(_) => Err(RedisError::Coneccion),
+            Err(e) => Err(RedisError::from(e)),
         }
     }
 }
@@ -148,7 +191,7 @@ impl TipoCliente for ClienteHttp {
         let parser = HttpParser::new(stream);
         let comando_http = match parser.parsear_stream() {
             Ok(orden) => orden,
-            Err(_) => return Err(RedisError::Server),
+            Err(e) => return Err(RedisError::from(e)),
         };
 
         match comando_http.get_metodo().as_str() {
@@ -170,10 +213,6 @@ impl TipoCliente for ClienteHttp {
         }
     }
 
-    fn envio_informacion(&self) -> bool {
-        !self.mando
-    }
-
     fn esta_conectado(&self) -> bool {
         !self.mando
     }
@@ -181,7 +220,20 @@ impl TipoCliente for ClienteHttp {
     fn enviar_resultado(&mut self, resultado: &ResultadoRedis) -> Result<(), RedisError> {
         self.mando = true;
 
-        let mensaje = format!("HTTP/1.1 200 OK\r\n\r\n{}", parsear_respuesta(resultado));
+        let mensaje = match resultado {
+            ResultadoRedis::Vector(lineas) if self.metricas => format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=UTF-8\r\n\r\n{}",
+                self.estado_de_metricas(lineas),
+                lineas
+                    .iter()
+                    .filter_map(|linea| match linea {
+                        ResultadoRedis::BulkStr(l) => Some(format!("{}\n", l)),
+                        _ => None,
+                    })
+                    .collect::<String>()
+            ),
+            _ => format!("HTTP/1.1 200 OK\r\n\r\n{}", parsear_respuesta(resultado)),
+        };
         self.enviar_mensaje(mensaje)
     }
 
@@ -205,8 +257,12 @@ impl TipoCliente for ClienteHttp {
             "TTL",
             "TOUCH",
             "KEYS",
+            "SCAN",
             "SORT",
             "TYPE",
+            "OBJECT",
+            "DUMP",
+            "RESTORE",
             "LINDEX",
             "LPOP",
             "RPOP",
@@ -235,12 +291,33 @@ impl TipoCliente for ClienteHttp {
             "GETDEL",
             "FLUSHDB",
             "DBSIZE",
+            "CLUSTER",
             "CONFIG",
+            "HOTKEYS",
             "INFO",
             "PING",
         ];
         comandos.iter().any(|&c| c == comando)
     }
+
+    fn suscripciones(&self) -> usize {
+        0
+    }
+
+    fn agregar_suscripcion(&self) -> usize {
+        0
+    }
+
+    fn quitar_suscripcion(&self) -> usize {
+        0
+    }
+
+    fn cerrar(&mut self) {
+        self.mando = true;
+        if let Some(socket) = self.socket.take() {
+            socket.shutdown(Shutdown::Both).ok();
+        }
+    }
 }
 
 impl Clone for ClienteHttp {
@@ -251,6 +328,8 @@ impl Clone for ClienteHttp {
             socket: self.obtener_socket(),
             pag_index: self.pag_index.clone(),
             icono: self.icono.clone(),
+            metricas: self.metricas,
+            disponibilidad: self.disponibilidad,
         }
     }
 }
@@ -271,3 +350,34 @@ impl fmt::Debug for ClienteHttp {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn pedir(servidor: &ServidorDePrueba, ruta: &str) -> String {
+        let mut conexion = TcpStream::connect(servidor.direccion()).unwrap();
+        // En una sola escritura, el servidor detecta HTTP mirando el primer segmento
+        let pedido = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", ruta);
+        conexion.write_all(pedido.as_bytes()).unwrap();
+        let mut respuesta = String::new();
+        conexion.read_to_string(&mut respuesta).unwrap();
+        respuesta
+    }
+
+    #[test]
+    fn las_sondas_de_salud_responden_sin_hablar_resp() {
+        let servidor = ServidorDePrueba::iniciar().unwrap();
+
+        let vivo = pedir(&servidor, "/healthz");
+        assert!(vivo.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(vivo.ends_with("\r\n\r\nok\n"));
+
+        let listo = pedir(&servidor, "/readyz");
+        assert!(listo.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(listo.contains("loading:0\n"));
+        assert!(listo.contains("ready:1\n"));
+    }
+}
@@ -1,21 +1,56 @@
 use crate::base_de_datos::ResultadoRedis;
 use crate::cliente::{TipoCliente, Token};
+use crate::cola_de_salida::{BloquesDeRespuesta, ColaDeSalida, LimiteDeSalida, TAMANIO_BLOQUE};
 use crate::comando_info::ComandoInfo;
-use crate::parser::{parsear_respuesta, Parser};
+use crate::parser::Parser;
 use crate::redis_error::RedisError;
+use crate::resp;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
 
 use std::fmt;
 use std::io::Write;
-use std::net::TcpStream;
+use std::net::{Shutdown, TcpStream};
+use std::ops::Deref;
 
-/// Representa a un Cliente que envia mensajes utilizando el protocolo redis
+/// Cuanto se espera al cerrar a que el escritor envie las respuestas pendientes
+const ESPERA_CIERRE: Duration = Duration::from_secs(1);
+
+/// Representa a un Cliente que envia mensajes utilizando el protocolo redis.
+/// Las respuestas a comandos que llegaron en la misma lectura se acumulan
+/// y se envian juntas con una sola escritura. Todo lo que se envia al cliente, sus
+/// respuestas y los mensajes de pub/sub que le envian otros hilos, pasa por su cola de
+/// salida y lo escribe en el socket un hilo escritor propio del cliente
 pub struct ClienteRedis {
     id: Token,
-    canales: usize,
+    suscripciones: Arc<AtomicUsize>,
     timeout: Option<Duration>,
     ultimo_mensaje: Instant,
     socket: Option<TcpStream>,
+    parser: Option<Parser<TcpStream>>,
+    respuestas_pendientes: Vec<u8>,
+    /// Compartida por todas las copias del cliente
+    salida: Arc<Salida>,
+}
+
+/// Extremo de la cola de salida que comparten las copias del cliente. Al descartarse la
+/// ultima copia se termina la cola, y el escritor termina luego de enviar lo pendiente
+struct Salida(Arc<ColaDeSalida>);
+
+impl Deref for Salida {
+    type Target = ColaDeSalida;
+
+    fn deref(&self) -> &ColaDeSalida {
+        &self.0
+    }
+}
+
+impl Drop for Salida {
+    fn drop(&mut self) {
+        self.0.terminar();
+    }
 }
 
 impl ClienteRedis {
@@ -32,15 +67,34 @@ impl ClienteRedis {
             t => Some(Duration::from_secs(t)),
         };
 
+        // La lectura bloquea hasta recibir datos o hasta que pase el timeout,
+        // en cuyo caso se revisa si el cliente sigue vigente
+        stream.set_read_timeout(duracion).ok();
+
+        let cola = Arc::new(ColaDeSalida::new(LimiteDeSalida::default()));
+        match stream.try_clone() {
+            Ok(socket) => iniciar_escritor(socket, Arc::clone(&cola)),
+            Err(_) => cola.finalizar(),
+        }
+
         ClienteRedis {
             id,
-            canales: 0,
+            suscripciones: Arc::new(AtomicUsize::new(0)),
             timeout: duracion,
             ultimo_mensaje: Instant::now(),
             socket: Some(stream),
+            parser: None,
+            respuestas_pendientes: Vec::new(),
+            salida: Arc::new(Salida(cola)),
         }
     }
 
+    /// Limita la cantidad de mensajes de pub/sub que pueden quedar pendientes de envio
+    pub fn limitar_salida(self, limite: LimiteDeSalida) -> Self {
+        self.salida.limitar(limite);
+        self
+    }
+
     fn obtener_socket(&self) -> Option<TcpStream> {
         let socket = match &self.socket {
             None => return None,
@@ -52,6 +106,48 @@ impl ClienteRedis {
             Err(_) => None,
         }
     }
+
+    /// Envia al escritor todas las respuestas acumuladas
+    fn vaciar_respuestas(&mut self) -> Result<(), RedisError> {
+        self.vaciar_respuestas_con(None)
+    }
+
+    /// Envia al escritor las respuestas acumuladas seguidas de la respuesta indicada, que se
+    /// serializa en bloques de TAMANIO_BLOQUE a medida que el escritor los envia. Solo se ahorra
+    /// la copia serializada: la respuesta ya esta completa en memoria
+    fn vaciar_respuestas_con(
+        &mut self,
+        respuesta: Option<&ResultadoRedis>,
+    ) -> Result<(), RedisError> {
+        if self.socket.is_none() {
+            return Err(RedisError::Coneccion);
+        }
+
+        let mut bloques = BloquesDeRespuesta::new(&self.salida);
+        let resultado =
+            bloques
+                .write_all(&self.respuestas_pendientes)
+                .and_then(|_| match respuesta {
+                    Some(r) => resp::escribir(r, &mut bloques),
+                    None => Ok(()),
+                });
+        self.respuestas_pendientes.clear();
+        resultado.map_err(RedisError::from)?;
+        bloques.terminar().map_err(|_| RedisError::Coneccion)
+    }
+}
+
+/// Inicia el hilo que escribe en el socket lo que llega a la cola de salida. Termina cuando la
+/// cola se cierra, cuando falla la escritura o cuando ya no queda ninguna copia del cliente
+fn iniciar_escritor(mut socket: TcpStream, salida: Arc<ColaDeSalida>) {
+    thread::spawn(move || {
+        while let Ok(trama) = salida.siguiente() {
+            if socket.write_all(&trama).is_err() {
+                salida.cerrar();
+            }
+        }
+        salida.finalizar();
+    });
 }
 
 impl TipoCliente for ClienteRedis {
@@ -60,17 +156,28 @@ impl TipoCliente for ClienteRedis {
     /// # Resultados
     ///
     /// * `Ok(Some(c))` - Se obtiene el comando enviado correctamente
+    /// * `Err(RedisError::Timeout)` - No se recibio nada durante el timeout
+    /// * `Err(RedisError::Cierre)` - El cliente cerro la conexion
     /// * `Err(e)` - Se produjo un error al la hora de obtener el comando
     fn obtener_comando(&mut self) -> Result<Option<ComandoInfo>, RedisError> {
-        let stream = match self.obtener_socket() {
-            Some(s) => s,
+        if self.parser.is_none() {
+            let stream = match self.obtener_socket() {
+                Some(s) => s,
+                None => return Err(RedisError::Coneccion),
+            };
+            self.parser = Some(Parser::new(stream));
+        }
+        let parser = match &mut self.parser {
+            Some(p) => p,
             None => return Err(RedisError::Coneccion),
         };
-        let parser = Parser::new(stream);
 
-        match parser.parsear_stream() {
-            Ok(orden) => Ok(Some(orden)),
-            Err(_) => Err(RedisError::Server),
+        match parser.siguiente_comando() {
+            Ok(orden) => {
+                self.ultimo_mensaje = Instant::now();
+                Ok(Some(orden))
+            }
+            Err(e) => Err(RedisError::from(e)),
         }
     }
 
@@ -86,55 +193,72 @@ impl TipoCliente for ClienteRedis {
         }
     }
 
-    fn envio_informacion(&self) -> bool {
-        let socket = match &self.socket {
-            None => return false,
-            Some(t) => t,
-        };
-
-        match socket.peek(&mut [0; 128]) {
-            Ok(len) => len > 0,
-            Err(_) => false,
+    fn hay_comando_pendiente(&self) -> bool {
+        match &self.parser {
+            Some(p) => p.hay_comando_completo(),
+            None => false,
         }
     }
 
     fn esta_conectado(&self) -> bool {
-        let socket = match &self.socket {
-            None => return false,
-            Some(t) => t,
-        };
-
-        let esta_conectado = match socket.peek(&mut [0; 128]) {
-            Ok(len) => len != 0,
-            Err(_) => false,
-        };
-
-        let paso_el_timeout = match self.timeout {
-            Some(d) => self.ultimo_mensaje.elapsed() > d,
-            None => false,
-        };
+        if self.socket.is_none() {
+            return false;
+        }
 
-        esta_conectado && !paso_el_timeout
+        match self.timeout {
+            Some(d) => self.ultimo_mensaje.elapsed() <= d,
+            None => true,
+        }
     }
 
+    /// Acumula la respuesta mientras queden comandos de la misma lectura por procesar,
+    /// cuando se procesa el ultimo se envian todas las respuestas juntas
     fn enviar_resultado(&mut self, resultado: &ResultadoRedis) -> Result<(), RedisError> {
-        let mensaje = parsear_respuesta(resultado);
-        self.enviar_mensaje(mensaje)
+        self.ultimo_mensaje = Instant::now();
+        let acumulable =
+            self.respuestas_pendientes.len() + resp::largo(resultado) <= TAMANIO_BLOQUE;
+
+        match &self.parser {
+            Some(p) if p.hay_comando_completo() && acumulable => {
+                self.respuestas_pendientes
+                    .extend_from_slice(resp::codificar(resultado).as_bytes());
+                Ok(())
+            }
+            _ => self.vaciar_respuestas_con(Some(resultado)),
+        }
     }
 
     fn enviar_mensaje(&mut self, mensaje: String) -> Result<(), RedisError> {
         self.ultimo_mensaje = Instant::now();
+        self.respuestas_pendientes
+            .extend_from_slice(mensaje.as_bytes());
+        self.vaciar_respuestas()
+    }
+    /// Encola el mensaje para que lo envie el hilo escritor del cliente, sin esperar a que se
+    /// envie. Si la cola esta llena y la politica es desconectar, se cierra la conexion del cliente
+    fn enviar_push(&mut self, resultado: &ResultadoRedis) -> Result<(), RedisError> {
+        match self
+            .salida
+            .encolar_push(resp::codificar(resultado).into_bytes())
+        {
+            Ok(()) => Ok(()),
+            Err(_) => {
+                if let Some(socket) = &self.socket {
+                    socket.shutdown(Shutdown::Both).ok();
+                }
+                Err(RedisError::Coneccion)
+            }
+        }
+    }
 
-        let socket = match &mut self.socket {
-            None => return Err(RedisError::Coneccion),
-            Some(t) => t,
-        };
+    fn cambiar_limite_de_salida(&self, limite: LimiteDeSalida) {
+        self.salida.limitar(limite);
+    }
 
-        match socket.write(mensaje.as_bytes()) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(RedisError::Coneccion),
-        }
+    fn mensajes_descartados(&self) -> usize {
+        self.salida.descartados()
     }
+
     fn obtener_token(&self) -> Token {
         self.id
     }
@@ -142,16 +266,49 @@ impl TipoCliente for ClienteRedis {
     fn soporta_comando(&self, _comando: &str) -> bool {
         true
     }
+
+    fn suscripciones(&self) -> usize {
+        self.suscripciones.load(Ordering::SeqCst)
+    }
+
+    fn agregar_suscripcion(&self) -> usize {
+        self.suscripciones.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn quitar_suscripcion(&self) -> usize {
+        match self
+            .suscripciones
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |s| s.checked_sub(1))
+        {
+            Ok(anterior) => anterior - 1,
+            Err(_) => 0,
+        }
+    }
+
+    fn cerrar(&mut self) {
+        if !self.respuestas_pendientes.is_empty() {
+            self.vaciar_respuestas().ok();
+        }
+        self.salida.terminar();
+        self.salida.esperar_fin(ESPERA_CIERRE);
+        self.parser = None;
+        if let Some(socket) = self.socket.take() {
+            socket.shutdown(Shutdown::Both).ok();
+        }
+    }
 }
 
 impl Clone for ClienteRedis {
     fn clone(&self) -> Self {
         ClienteRedis {
             id: self.id,
-            canales: self.canales,
+            suscripciones: Arc::clone(&self.suscripciones),
             timeout: self.timeout,
             ultimo_mensaje: self.ultimo_mensaje,
             socket: self.obtener_socket(),
+            parser: None,
+            respuestas_pendientes: Vec::new(),
+            salida: Arc::clone(&self.salida),
         }
     }
 }
@@ -174,3 +331,82 @@ impl fmt::Debug for ClienteRedis {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{ErrorKind, Read};
+    use std::net::TcpListener;
+
+    #[test]
+    fn cerrar_envia_las_respuestas_pendientes_y_cierra_el_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut usuario = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut cliente = ClienteRedis::new(1, 0, listener.accept().unwrap().0);
+
+        usuario
+            .write_all(b"*1\r\n$4\r\nQUIT\r\n*1\r\n$4\r\nPING\r\n")
+            .unwrap();
+        assert!(cliente.obtener_comando().unwrap().is_some());
+        cliente
+            .enviar_resultado(&ResultadoRedis::StrSimple("OK".to_string()))
+            .unwrap();
+        cliente.cerrar();
+
+        let mut respuesta = Vec::new();
+        usuario.read_to_end(&mut respuesta).unwrap();
+        assert_eq!(b"+OK\r\n".to_vec(), respuesta);
+        assert!(!cliente.esta_conectado());
+    }
+
+    #[test]
+    fn las_respuestas_a_comandos_pipelineados_se_envian_juntas() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut usuario = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut cliente = ClienteRedis::new(1, 0, listener.accept().unwrap().0);
+
+        usuario
+            .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n")
+            .unwrap();
+        let pong = ResultadoRedis::StrSimple("PONG".to_string());
+
+        assert!(cliente.obtener_comando().unwrap().is_some());
+        cliente.enviar_resultado(&pong).unwrap();
+        usuario.set_nonblocking(true).unwrap();
+        let mut buffer = [0; 64];
+        assert_eq!(
+            ErrorKind::WouldBlock,
+            usuario.read(&mut buffer).unwrap_err().kind()
+        );
+
+        assert!(cliente.obtener_comando().unwrap().is_some());
+        cliente.enviar_resultado(&pong).unwrap();
+        usuario.set_nonblocking(false).unwrap();
+        let mut respuesta = [0; 14];
+        usuario.read_exact(&mut respuesta).unwrap();
+        assert_eq!(b"+PONG\r\n+PONG\r\n", &respuesta);
+    }
+
+    #[test]
+    fn las_respuestas_mas_grandes_que_un_bloque_llegan_completas() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut usuario = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut cliente = ClienteRedis::new(1, 0, listener.accept().unwrap().0);
+        let respuesta = ResultadoRedis::Vector(
+            (0..2000)
+                .map(|i| ResultadoRedis::BulkStr(format!("{:0>100}", i)))
+                .collect(),
+        );
+        let esperado = resp::codificar(&respuesta).into_bytes();
+        assert!(esperado.len() > 10 * TAMANIO_BLOQUE);
+
+        let lector = std::thread::spawn(move || {
+            let mut recibido = Vec::new();
+            usuario.read_to_end(&mut recibido).unwrap();
+            recibido
+        });
+        cliente.enviar_resultado(&respuesta).unwrap();
+        cliente.cerrar();
+        assert_eq!(esperado, lector.join().unwrap());
+    }
+}
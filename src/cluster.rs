@@ -0,0 +1,543 @@
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
+use crate::cliente::Token;
+use crate::config::Config;
+use crate::fnv::Fnv1a;
+use crate::interceptor::{Interceptor, Peticion};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+/// Cantidad de slots en los que se reparten las claves del cluster
+pub const CANTIDAD_SLOTS: usize = 16384;
+
+/// Nodo del cluster junto con los rangos de slots de los que es duenio
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nodo {
+    pub id: String,
+    pub host: String,
+    pub puerto: u16,
+    pub slots: Vec<(u16, u16)>,
+}
+
+/// Motivo por el que un comando no se puede ejecutar en este nodo
+#[derive(Debug, PartialEq)]
+pub enum Redireccion {
+    /// El slot pertenece a otro nodo, el cliente debe actualizar su mapa de slots
+    Movido(u16, String),
+    /// El slot se esta migrando y las claves ya no estan en este nodo, el cliente debe
+    /// reintentar solo este comando en el nodo destino precedido por ASKING
+    Preguntar(u16, String),
+    /// El slot se esta migrando y solo algunas de las claves siguen en este nodo
+    Reintentar,
+    /// Las claves del comando pertenecen a distintos slots
+    SlotsCruzados,
+    /// Ningun nodo atiende el slot
+    SinDuenio(u16),
+}
+
+impl Redireccion {
+    /// Devuelve el error con el formato que entienden los clientes de cluster
+    pub fn a_resultado(&self) -> ResultadoRedis {
+        ResultadoRedis::Error(match self {
+            Redireccion::Movido(slot, direccion) => format!("MOVED {} {}", slot, direccion),
+            Redireccion::Preguntar(slot, direccion) => format!("ASK {} {}", slot, direccion),
+            Redireccion::Reintentar => {
+                "TRYAGAIN Multiple keys request during rehashing of slot".to_string()
+            }
+            Redireccion::SlotsCruzados => {
+                "CROSSSLOT Keys in request don't hash to the same slot".to_string()
+            }
+            Redireccion::SinDuenio(slot) => format!("CLUSTERDOWN Hash slot {} not served", slot),
+        })
+    }
+}
+
+/// Estado del cluster tal como lo conoce este nodo. Los nodos y la asignacion de slots
+/// se leen de la configuracion (cluster-enabled y cluster-nodes) y solo cambian con
+/// CLUSTER SETSLOT, ya que no hay descubrimiento de nodos entre si
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EstadoCluster {
+    habilitado: bool,
+    propio: String,
+    nodos: Vec<Nodo>,
+    /// Slots propios que se estan moviendo, con el identificador del nodo destino
+    migrando: HashMap<u16, String>,
+    /// Slots de otro nodo que se estan recibiendo, con el identificador del nodo origen
+    importando: HashMap<u16, String>,
+    /// Clientes que enviaron ASKING y todavia no enviaron el comando siguiente
+    preguntando: HashSet<Token>,
+}
+
+impl EstadoCluster {
+    /// Arma el estado del cluster a partir de la direccion propia y de la descripcion de los nodos,
+    /// con el formato `host:puerto rango [rango ...], host:puerto rango ...` donde cada rango es
+    /// `inicio-fin` o un unico slot. Los rangos invalidos se ignoran
+    pub fn new(habilitado: bool, direccion: &str, descripcion: &str) -> Self {
+        let mut nodos: Vec<Nodo> = descripcion
+            .split(',')
+            .filter_map(|entrada| parsear_nodo(entrada.trim()))
+            .collect();
+        if !nodos.iter().any(|n| direccion_de(n) == direccion) {
+            if let Some(nodo) = parsear_nodo(direccion) {
+                nodos.insert(0, nodo);
+            }
+        }
+
+        EstadoCluster {
+            habilitado,
+            propio: id_de_nodo(direccion),
+            nodos,
+            ..EstadoCluster::default()
+        }
+    }
+
+    pub fn habilitado(&self) -> bool {
+        self.habilitado
+    }
+
+    /// Identificador de este nodo, de 40 caracteres hexadecimales como en redis
+    pub fn mi_id(&self) -> &str {
+        &self.propio
+    }
+
+    /// Rangos de slots asignados ordenados por su comienzo, junto con el nodo duenio
+    pub fn slots(&self) -> Vec<(u16, u16, &Nodo)> {
+        let mut rangos: Vec<(u16, u16, &Nodo)> = self
+            .nodos
+            .iter()
+            .flat_map(|n| n.slots.iter().map(move |(i, f)| (*i, *f, n)))
+            .collect();
+        rangos.sort_by_key(|(inicio, _, _)| *inicio);
+        rangos
+    }
+
+    /// Devuelve las lineas de CLUSTER INFO. El cluster esta ok si todos los slots tienen duenio
+    pub fn info(&self) -> Vec<String> {
+        let mut asignados = vec![false; CANTIDAD_SLOTS];
+        for (inicio, fin, _) in self.slots() {
+            for slot in asignados
+                .iter_mut()
+                .take(fin as usize + 1)
+                .skip(inicio as usize)
+            {
+                *slot = true;
+            }
+        }
+        let cantidad = asignados.iter().filter(|a| **a).count();
+        vec![
+            format!(
+                "cluster_state:{}",
+                if cantidad == CANTIDAD_SLOTS {
+                    "ok"
+                } else {
+                    "fail"
+                }
+            ),
+            format!("cluster_slots_assigned:{}", cantidad),
+            format!("cluster_slots_ok:{}", cantidad),
+            "cluster_slots_pfail:0".to_string(),
+            "cluster_slots_fail:0".to_string(),
+            format!("cluster_known_nodes:{}", self.nodos.len()),
+            format!(
+                "cluster_size:{}",
+                self.nodos.iter().filter(|n| !n.slots.is_empty()).count()
+            ),
+            "cluster_current_epoch:0".to_string(),
+            "cluster_my_epoch:0".to_string(),
+        ]
+    }
+
+    /// Nodo duenio del slot, si alguno lo tiene asignado
+    pub fn duenio(&self, slot: u16) -> Option<&Nodo> {
+        self.nodos
+            .iter()
+            .find(|n| n.slots.iter().any(|(i, f)| *i <= slot && slot <= *f))
+    }
+
+    fn nodo(&self, id: &str) -> Result<&Nodo, String> {
+        match self.nodos.iter().find(|n| n.id == id) {
+            Some(nodo) => Ok(nodo),
+            None => Err(format!("ERR I don't know about node {}", id)),
+        }
+    }
+
+    fn es_duenio(&self, slot: u16) -> bool {
+        self.duenio(slot).is_some_and(|n| n.id == self.propio)
+    }
+
+    /// Marca un slot propio como en migracion hacia el nodo destino
+    pub fn migrar_slot(&mut self, slot: u16, destino: &str) -> Result<(), String> {
+        if !self.es_duenio(slot) {
+            return Err(format!("ERR I'm not the owner of hash slot {}", slot));
+        }
+        self.nodo(destino)?;
+        self.migrando.insert(slot, destino.to_string());
+        Ok(())
+    }
+
+    /// Marca un slot de otro nodo como en importacion desde el nodo origen
+    pub fn importar_slot(&mut self, slot: u16, origen: &str) -> Result<(), String> {
+        if self.es_duenio(slot) {
+            return Err(format!("ERR I'm already the owner of hash slot {}", slot));
+        }
+        self.nodo(origen)?;
+        self.importando.insert(slot, origen.to_string());
+        Ok(())
+    }
+
+    /// Cancela la migracion o importacion del slot
+    pub fn estabilizar_slot(&mut self, slot: u16) {
+        self.migrando.remove(&slot);
+        self.importando.remove(&slot);
+    }
+
+    /// Asigna el slot al nodo indicado, lo que da por terminada su migracion
+    pub fn asignar_slot(&mut self, slot: u16, id: &str) -> Result<(), String> {
+        self.nodo(id)?;
+        for nodo in self.nodos.iter_mut() {
+            nodo.quitar_slot(slot);
+            if nodo.id == id {
+                nodo.agregar_slot(slot);
+            }
+        }
+        self.estabilizar_slot(slot);
+        Ok(())
+    }
+
+    /// Registra que el cliente envio ASKING, lo que vale solo para su proximo comando
+    pub fn marcar_preguntando(&mut self, cliente: Token) {
+        self.preguntando.insert(cliente);
+    }
+
+    /// Olvida la marca de ASKING del cliente y devuelve si la tenia
+    pub fn tomar_preguntando(&mut self, cliente: Token) -> bool {
+        self.preguntando.remove(&cliente)
+    }
+
+    /// Decide si un comando sobre las claves se ejecuta en este nodo. `faltantes` es la cantidad
+    /// de claves que no existen localmente y `preguntando` si el cliente envio ASKING antes
+    pub fn redireccion(
+        &self,
+        claves: &[String],
+        faltantes: usize,
+        preguntando: bool,
+    ) -> Option<Redireccion> {
+        let slot = slot_de_clave(claves.first()?);
+        if claves.iter().any(|c| slot_de_clave(c) != slot) {
+            return Some(Redireccion::SlotsCruzados);
+        }
+
+        if self.es_duenio(slot) {
+            let destino = self.migrando.get(&slot).and_then(|id| self.nodo(id).ok());
+            return match destino {
+                Some(nodo) if faltantes == claves.len() => {
+                    Some(Redireccion::Preguntar(slot, direccion_de(nodo)))
+                }
+                Some(_) if faltantes > 0 => Some(Redireccion::Reintentar),
+                _ => None,
+            };
+        }
+        if preguntando && self.importando.contains_key(&slot) {
+            return None;
+        }
+        match self.duenio(slot) {
+            Some(nodo) => Some(Redireccion::Movido(slot, direccion_de(nodo))),
+            None => Some(Redireccion::SinDuenio(slot)),
+        }
+    }
+
+    /// Seccion Cluster de INFO
+    pub fn seccion_info(&self) -> Vec<String> {
+        vec![
+            "# Cluster".to_string(),
+            format!("cluster_enabled:{}", self.habilitado as u8),
+            "".to_string(),
+        ]
+    }
+}
+
+impl Nodo {
+    fn quitar_slot(&mut self, slot: u16) {
+        let mut rangos = Vec::new();
+        for (inicio, fin) in self.slots.drain(..) {
+            if slot < inicio || fin < slot {
+                rangos.push((inicio, fin));
+                continue;
+            }
+            if inicio < slot {
+                rangos.push((inicio, slot - 1));
+            }
+            if slot < fin {
+                rangos.push((slot + 1, fin));
+            }
+        }
+        self.slots = rangos;
+    }
+
+    fn agregar_slot(&mut self, slot: u16) {
+        self.slots.push((slot, slot));
+        self.slots.sort_unstable();
+        let mut rangos: Vec<(u16, u16)> = Vec::new();
+        for (inicio, fin) in self.slots.drain(..) {
+            match rangos.last_mut() {
+                Some(ultimo) if inicio as usize <= ultimo.1 as usize + 1 => {
+                    ultimo.1 = ultimo.1.max(fin)
+                }
+                _ => rangos.push((inicio, fin)),
+            }
+        }
+        self.slots = rangos;
+    }
+}
+
+/// Slot al que pertenece la clave, CRC16 (XMODEM) de su parte con hash modulo la cantidad de slots
+pub fn slot_de_clave(clave: &str) -> u16 {
+    (crc16(parte_con_hash(clave).as_bytes()) as usize % CANTIDAD_SLOTS) as u16
+}
+
+/// Parte de la clave que se usa para repartirla. Si la clave tiene un hash tag, es decir
+/// texto no vacio entre la primera '{' y la primera '}' que le sigue, solo se usa ese texto,
+/// de modo que {user:1000}.nombre y {user:1000}.edad caen en el mismo slot. Si no, la clave completa
+pub fn parte_con_hash(clave: &str) -> &str {
+    let inicio = match clave.find('{') {
+        Some(inicio) => inicio + 1,
+        None => return clave,
+    };
+    match clave[inicio..].find('}') {
+        Some(largo) if largo > 0 => &clave[inicio..inicio + largo],
+        _ => clave,
+    }
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Deriva el identificador de un nodo a partir de su direccion, de modo que todos los nodos
+/// calculan el mismo identificador para cada uno de los demas sin tener que intercambiarlo
+pub fn id_de_nodo(direccion: &str) -> String {
+    let mut id = String::new();
+    for semilla in 0..3u64 {
+        let mut hash = Fnv1a::con_semilla(semilla);
+        hash.agregar(direccion.as_bytes());
+        id += &format!("{:016x}", hash.suma());
+    }
+    id.truncate(40);
+    id
+}
+
+fn direccion_de(nodo: &Nodo) -> String {
+    format!("{}:{}", nodo.host, nodo.puerto)
+}
+
+fn parsear_nodo(entrada: &str) -> Option<Nodo> {
+    let mut partes = entrada.split_whitespace();
+    let direccion = partes.next()?;
+    let (host, puerto) = direccion.rsplit_once(':')?;
+    let puerto = puerto.parse().ok()?;
+    Some(Nodo {
+        id: id_de_nodo(direccion),
+        host: host.to_string(),
+        puerto,
+        slots: partes.filter_map(parsear_rango).collect(),
+    })
+}
+
+fn parsear_rango(rango: &str) -> Option<(u16, u16)> {
+    let (inicio, fin) = match rango.split_once('-') {
+        Some((i, f)) => (i.parse::<u16>().ok()?, f.parse::<u16>().ok()?),
+        None => {
+            let slot = rango.parse::<u16>().ok()?;
+            (slot, slot)
+        }
+    };
+    if inicio > fin || fin as usize >= CANTIDAD_SLOTS {
+        return None;
+    }
+    Some((inicio, fin))
+}
+
+/// Atiende ASKING y, en modo cluster, redirige los comandos cuyas claves no se atienden en este
+/// nodo antes de ejecutarlos
+pub struct RedireccionDeCluster {
+    config: Arc<Mutex<Config>>,
+    tabla: Arc<Mutex<BaseDeDatos>>,
+}
+
+impl RedireccionDeCluster {
+    pub fn new(config: Arc<Mutex<Config>>, tabla: Arc<Mutex<BaseDeDatos>>) -> Self {
+        RedireccionDeCluster { config, tabla }
+    }
+
+    /// Habilita al cliente a ejecutar su proximo comando sobre un slot que se esta importando
+    fn asking(&self, token: Token) -> ResultadoRedis {
+        match self.config.lock() {
+            Ok(mut c) if c.cluster().habilitado() => {
+                c.cluster_mut().marcar_preguntando(token);
+                ResultadoRedis::StrSimple("OK".to_string())
+            }
+            Ok(_) => {
+                ResultadoRedis::Error("ERR This instance has cluster support disabled".to_string())
+            }
+            Err(_) => ResultadoRedis::Error("ERR when accessing config".to_string()),
+        }
+    }
+}
+
+impl Interceptor for RedireccionDeCluster {
+    /// Verifica que las claves del comando se atiendan en este nodo, consumiendo la marca de
+    /// ASKING del cliente. Si no es asi devuelve la redireccion que debe seguir el cliente
+    fn antes(&self, peticion: &Peticion) -> Option<ResultadoRedis> {
+        if peticion.comando.get_nombre() == "ASKING" {
+            return Some(self.asking(peticion.token()));
+        }
+        match self.config.lock() {
+            Ok(c) if c.cluster().habilitado() => (),
+            Ok(_) => return None,
+            Err(_) => {
+                return Some(ResultadoRedis::Error(
+                    "ERR when accessing config".to_string(),
+                ))
+            }
+        }
+        let faltantes = match self.tabla.lock() {
+            Ok(mut bdd) => {
+                bdd.expirar_claves(&peticion.claves);
+                let claves = &peticion.claves;
+                claves.iter().filter(|c| !bdd.existe_clave(c)).count()
+            }
+            Err(_) => {
+                return Some(ResultadoRedis::Error(
+                    "ERR when accessing the database".to_string(),
+                ))
+            }
+        };
+        let mut c = match self.config.lock() {
+            Ok(c) => c,
+            Err(_) => {
+                return Some(ResultadoRedis::Error(
+                    "ERR when accessing config".to_string(),
+                ))
+            }
+        };
+        let preguntando = c.cluster_mut().tomar_preguntando(peticion.token());
+        c.cluster()
+            .redireccion(&peticion.claves, faltantes, preguntando)
+            .map(|redireccion| redireccion.a_resultado())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn el_estado_del_cluster_se_arma_desde_la_configuracion() {
+        let cluster = EstadoCluster::new(
+            true,
+            "127.0.0.1:7000",
+            "127.0.0.1:7000 0-8191, 127.0.0.1:7001 8192-16000 16001-16383 99999",
+        );
+
+        assert_eq!(40, cluster.mi_id().len());
+        assert_eq!(id_de_nodo("127.0.0.1:7000"), cluster.mi_id());
+        assert_eq!(2, cluster.nodos.len());
+        assert_eq!(
+            vec![(0, 8191), (8192, 16000), (16001, 16383)],
+            cluster
+                .slots()
+                .iter()
+                .map(|(i, f, _)| (*i, *f))
+                .collect::<Vec<_>>()
+        );
+        assert!(cluster.info().contains(&"cluster_state:ok".to_string()));
+        assert!(cluster.info().contains(&"cluster_size:2".to_string()));
+    }
+
+    #[test]
+    fn un_nodo_que_no_figura_en_la_configuracion_se_conoce_sin_slots() {
+        let cluster = EstadoCluster::new(true, "127.0.0.1:7002", "127.0.0.1:7000 0-100");
+
+        assert_eq!(2, cluster.nodos.len());
+        assert!(cluster.info().contains(&"cluster_state:fail".to_string()));
+        assert!(cluster
+            .info()
+            .contains(&"cluster_slots_assigned:101".to_string()));
+    }
+
+    #[test]
+    fn el_slot_de_una_clave_es_el_mismo_que_en_redis() {
+        assert_eq!(12182, slot_de_clave("foo"));
+        assert_eq!(866, slot_de_clave("hello"));
+        assert_eq!(0x31c3, crc16(b"123456789"));
+    }
+
+    #[test]
+    fn las_claves_con_el_mismo_hash_tag_caen_en_el_mismo_slot() {
+        assert_eq!("user:1000", parte_con_hash("{user:1000}.nombre"));
+        assert_eq!(
+            slot_de_clave("{user:1000}.nombre"),
+            slot_de_clave("{user:1000}.edad")
+        );
+        assert_eq!("bar", parte_con_hash("foo{bar}{zap}"));
+        assert_eq!("{}.clave", parte_con_hash("{}.clave"));
+        assert_eq!("{sin_cierre", parte_con_hash("{sin_cierre"));
+        assert_eq!("a{}}", parte_con_hash("a{}}"));
+        assert_eq!(slot_de_clave("foo"), slot_de_clave("{foo}"));
+    }
+
+    #[test]
+    fn durante_la_migracion_las_claves_faltantes_se_redirigen_con_ask() {
+        let origen = "127.0.0.1:7000";
+        let destino = "127.0.0.1:7001";
+        let descripcion = "127.0.0.1:7000 0-16383, 127.0.0.1:7001";
+        let mut cluster_origen = EstadoCluster::new(true, origen, descripcion);
+        let mut cluster_destino = EstadoCluster::new(true, destino, descripcion);
+        let claves = vec!["foo".to_string()];
+
+        cluster_origen
+            .migrar_slot(12182, &id_de_nodo(destino))
+            .unwrap();
+        cluster_destino
+            .importar_slot(12182, &id_de_nodo(origen))
+            .unwrap();
+        assert_eq!(None, cluster_origen.redireccion(&claves, 0, false));
+        assert_eq!(
+            Some(Redireccion::Preguntar(12182, destino.to_string())),
+            cluster_origen.redireccion(&claves, 1, false)
+        );
+        assert_eq!(
+            Some(Redireccion::Movido(12182, origen.to_string())),
+            cluster_destino.redireccion(&claves, 1, false)
+        );
+        assert_eq!(None, cluster_destino.redireccion(&claves, 1, true));
+
+        cluster_destino
+            .asignar_slot(12182, &id_de_nodo(destino))
+            .unwrap();
+        assert_eq!(None, cluster_destino.redireccion(&claves, 1, false));
+        assert_eq!(
+            vec![(0, 12181), (12182, 12182), (12183, 16383)],
+            cluster_destino
+                .slots()
+                .iter()
+                .map(|(i, f, _)| (*i, *f))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(Redireccion::SlotsCruzados),
+            cluster_destino.redireccion(&["foo".to_string(), "hello".to_string()], 0, false)
+        );
+    }
+}
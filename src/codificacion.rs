@@ -0,0 +1,55 @@
+use crate::base_de_datos::TipoRedis;
+use crate::conjunto::son_enteros;
+
+/// Limites por debajo de los cuales los sets usan su representacion compacta.
+/// Se configuran con las opciones set-max-listpack-entries, set-max-listpack-value
+/// y set-max-intset-entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitesDeCodificacion {
+    pub set_max_listpack_entradas: usize,
+    pub set_max_listpack_valor: usize,
+    pub set_max_intset_entradas: usize,
+}
+
+impl LimitesDeCodificacion {
+    /// Indica si un set con estos elementos puede mantenerse en la representacion compacta
+    pub fn admite_set_compacto(&self, elementos: &[String]) -> bool {
+        if son_enteros(elementos) && elementos.len() <= self.set_max_intset_entradas {
+            return true;
+        }
+        elementos.len() <= self.set_max_listpack_entradas
+            && elementos
+                .iter()
+                .all(|e| e.len() <= self.set_max_listpack_valor)
+    }
+
+    /// Pasa el valor a su representacion general si supera los limites
+    pub fn ajustar(&self, valor: &mut TipoRedis) {
+        if let TipoRedis::Set(set) = valor {
+            set.ajustar(self);
+        }
+    }
+}
+
+impl Default for LimitesDeCodificacion {
+    fn default() -> Self {
+        LimitesDeCodificacion {
+            set_max_listpack_entradas: 128,
+            set_max_listpack_valor: 64,
+            set_max_intset_entradas: 512,
+        }
+    }
+}
+
+/// Nombre de la codificacion del valor como lo informa OBJECT ENCODING, segun como esta
+/// guardado en memoria: las cadenas en linea son embstr y las reservadas raw, y las listas,
+/// que siempre se guardan en un vector, quicklist
+pub fn codificacion(valor: &TipoRedis) -> &'static str {
+    match valor {
+        TipoRedis::Str(s) if s.esta_en_linea() => "embstr",
+        TipoRedis::Str(_) => "raw",
+        TipoRedis::Lista(_) => "quicklist",
+        TipoRedis::Set(set) => set.codificacion(),
+        TipoRedis::Canal(_) => "raw",
+    }
+}
@@ -0,0 +1,477 @@
+use crate::eventos;
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Cantidad de mensajes pendientes que admite por defecto la cola de un suscriptor
+const MENSAJES_PENDIENTES: usize = 1000;
+/// Cantidad de escrituras pendientes que admite por defecto la cola de una replica
+const ESCRITURAS_PENDIENTES_REPLICA: usize = 100_000;
+
+/// Tamaño de los bloques en los que se parten las respuestas. Las respuestas mas grandes se
+/// serializan de a un bloque, lo que evita la copia serializada completa pero no el
+/// ResultadoRedis, que el handler ya armo entero antes de responder
+pub const TAMANIO_BLOQUE: usize = 16 * 1024;
+
+/// Cantidad de bloques de respuesta que pueden esperar a ser escritos en el socket
+const BLOQUES_EN_VUELO: usize = 4;
+
+/// Que hacer con un suscriptor cuya cola de mensajes pendientes se lleno
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoliticaDeDesborde {
+    /// Se descarta el mensaje nuevo y se cuenta como descartado
+    Descartar,
+    /// Se desconecta al suscriptor, como hace redis al superar client-output-buffer-limit
+    Desconectar,
+}
+
+/// Limite de la cola de mensajes pendientes de cada cliente, se configura con
+/// pubsub-max-pending y pubsub-overflow-policy (drop o disconnect). Las replicas tienen su
+/// propio limite, replica-max-pending, como client-output-buffer-limit replica en redis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimiteDeSalida {
+    pub mensajes: usize,
+    pub politica: PoliticaDeDesborde,
+}
+
+impl Default for LimiteDeSalida {
+    fn default() -> Self {
+        LimiteDeSalida {
+            mensajes: MENSAJES_PENDIENTES,
+            politica: PoliticaDeDesborde::Desconectar,
+        }
+    }
+}
+
+impl LimiteDeSalida {
+    /// Arma el limite a partir de los valores de configuracion, usando los valores por
+    /// defecto para los que falten o sean invalidos
+    pub fn desde(mensajes: Option<&String>, politica: Option<&String>) -> Self {
+        let por_defecto = LimiteDeSalida::default();
+        LimiteDeSalida {
+            mensajes: match mensajes.and_then(|m| m.parse().ok()) {
+                Some(m) if m > 0 => m,
+                _ => por_defecto.mensajes,
+            },
+            politica: match politica.map(|p| p.trim().to_lowercase()) {
+                Some(p) if p == "drop" => PoliticaDeDesborde::Descartar,
+                Some(p) if p == "disconnect" => PoliticaDeDesborde::Desconectar,
+                _ => por_defecto.politica,
+            },
+        }
+    }
+
+    /// Limite de la cola de una replica. Una replica no puede perder escrituras, por lo que al
+    /// superarlo siempre se la desconecta para que vuelva a sincronizarse
+    pub fn de_replica(mensajes: Option<&str>) -> Self {
+        LimiteDeSalida {
+            mensajes: match mensajes.and_then(|m| m.trim().parse().ok()) {
+                Some(m) if m > 0 => m,
+                _ => ESCRITURAS_PENDIENTES_REPLICA,
+            },
+            politica: PoliticaDeDesborde::Desconectar,
+        }
+    }
+}
+
+/// Error al encolar en una cola que se cerro, porque se lleno con la politica Desconectar,
+/// fallo la escritura en el socket o se desconecto el cliente
+#[derive(Debug, PartialEq)]
+pub struct ColaCerrada;
+
+#[derive(Debug, Default)]
+struct Pendientes {
+    /// Bloques de las respuestas, junto con si terminan la respuesta
+    respuestas: VecDeque<(Vec<u8>, bool)>,
+    /// Mensajes push ya serializados, como los de pub/sub
+    tramas: VecDeque<Vec<u8>>,
+    /// Se envio una parte de una respuesta y falta el resto, mientras tanto no se envian pushes
+    en_respuesta: bool,
+    /// Se cierra al terminar de enviar las respuestas pendientes
+    terminando: bool,
+    cerrada: bool,
+    /// El escritor ya no escribe en el socket
+    finalizada: bool,
+}
+
+/// Cola de lo que se envia a un cliente, la vacia un unico hilo escritor por cliente que es
+/// el unico que escribe en su socket. Las respuestas se encolan de a una por vez, ya que cada
+/// BloquesDeRespuesta toma el lugar de productor hasta terminar la suya, y los mensajes push
+/// solo se envian entre respuestas completas, por lo que nunca se intercalan en medio de una
+/// trama. Quien envia un push nunca se bloquea: si el cliente no lee y los pushes pendientes
+/// superan el limite, se aplica la politica de desborde. Quien envia una respuesta si espera
+/// cuando hay demasiados bloques en vuelo, por eso no se pueden encolar respuestas mientras se
+/// emiten los eventos de la base de datos
+#[derive(Debug)]
+pub struct ColaDeSalida {
+    /// Lo toma quien encola una respuesta hasta encolar su ultimo bloque
+    productor: Mutex<()>,
+    pendientes: Mutex<Pendientes>,
+    hay_pendientes: Condvar,
+    hay_lugar: Condvar,
+    finalizo: Condvar,
+    limite: Mutex<LimiteDeSalida>,
+    descartados: AtomicUsize,
+}
+
+impl ColaDeSalida {
+    pub fn new(limite: LimiteDeSalida) -> Self {
+        ColaDeSalida {
+            productor: Mutex::new(()),
+            pendientes: Mutex::new(Pendientes::default()),
+            hay_pendientes: Condvar::new(),
+            hay_lugar: Condvar::new(),
+            finalizo: Condvar::new(),
+            limite: Mutex::new(limite),
+            descartados: AtomicUsize::new(0),
+        }
+    }
+
+    fn pendientes(&self) -> MutexGuard<'_, Pendientes> {
+        match self.pendientes.lock() {
+            Ok(p) => p,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    fn limite(&self) -> LimiteDeSalida {
+        match self.limite.lock() {
+            Ok(l) => *l,
+            Err(envenenado) => *envenenado.into_inner(),
+        }
+    }
+
+    /// Cambia el limite de los mensajes push pendientes
+    pub fn limitar(&self, limite: LimiteDeSalida) {
+        match self.limite.lock() {
+            Ok(mut l) => *l = limite,
+            Err(envenenado) => *envenenado.into_inner() = limite,
+        }
+    }
+
+    /// Agrega el mensaje push a la cola sin bloquear. Si la cola esta llena lo descarta o, con
+    /// la politica Desconectar, cierra la cola y devuelve error para que se desconecte al cliente
+    pub fn encolar_push(&self, trama: Vec<u8>) -> Result<(), ColaCerrada> {
+        let limite = self.limite();
+        let mut pendientes = self.pendientes();
+        if pendientes.cerrada || pendientes.terminando {
+            return Err(ColaCerrada);
+        }
+        if pendientes.tramas.len() >= limite.mensajes {
+            self.descartados.fetch_add(1, Ordering::SeqCst);
+            if limite.politica == PoliticaDeDesborde::Descartar {
+                return Ok(());
+            }
+            drop(pendientes);
+            self.cerrar();
+            return Err(ColaCerrada);
+        }
+        pendientes.tramas.push_back(trama);
+        self.hay_pendientes.notify_one();
+        Ok(())
+    }
+
+    /// Agrega un bloque de una respuesta, esperando mientras haya BLOQUES_EN_VUELO sin enviar.
+    /// El ultimo bloque de cada respuesta se indica con `fin`. Falla si se llama desde un
+    /// suscriptor del bus de eventos, que no debe esperar con la base de datos tomada
+    fn encolar_bloque(&self, bloque: Vec<u8>, fin: bool) -> Result<(), ColaCerrada> {
+        if eventos::emitiendo() {
+            return Err(ColaCerrada);
+        }
+        let mut pendientes = self.pendientes();
+        while !pendientes.cerrada && pendientes.respuestas.len() >= BLOQUES_EN_VUELO {
+            pendientes = match self.hay_lugar.wait(pendientes) {
+                Ok(p) => p,
+                Err(envenenado) => envenenado.into_inner(),
+            };
+        }
+        if pendientes.cerrada || pendientes.terminando {
+            return Err(ColaCerrada);
+        }
+        pendientes.respuestas.push_back((bloque, fin));
+        self.hay_pendientes.notify_one();
+        Ok(())
+    }
+
+    /// Espera lo siguiente que se debe escribir en el socket, devuelve error cuando la cola se
+    /// cierra o cuando termina y ya se envio todo lo pendiente
+    pub fn siguiente(&self) -> Result<Vec<u8>, ColaCerrada> {
+        let mut pendientes = self.pendientes();
+        loop {
+            if pendientes.cerrada {
+                return Err(ColaCerrada);
+            }
+            if let Some((bloque, fin)) = pendientes.respuestas.pop_front() {
+                pendientes.en_respuesta = !fin;
+                self.hay_lugar.notify_one();
+                return Ok(bloque);
+            }
+            if !pendientes.en_respuesta {
+                if pendientes.terminando {
+                    pendientes.cerrada = true;
+                    return Err(ColaCerrada);
+                }
+                if let Some(trama) = pendientes.tramas.pop_front() {
+                    return Ok(trama);
+                }
+            }
+            pendientes = match self.hay_pendientes.wait(pendientes) {
+                Ok(p) => p,
+                Err(envenenado) => envenenado.into_inner(),
+            };
+        }
+    }
+
+    /// Cierra la cola una vez enviadas las respuestas pendientes, los pushes se descartan
+    pub fn terminar(&self) {
+        let mut pendientes = self.pendientes();
+        pendientes.terminando = true;
+        pendientes.tramas.clear();
+        self.hay_pendientes.notify_all();
+    }
+
+    /// Cierra la cola descartando lo pendiente, quienes la usan dejan de esperar
+    pub fn cerrar(&self) {
+        let mut pendientes = self.pendientes();
+        pendientes.cerrada = true;
+        pendientes.respuestas.clear();
+        pendientes.tramas.clear();
+        self.hay_pendientes.notify_all();
+        self.hay_lugar.notify_all();
+    }
+
+    /// Lo indica el escritor al dejar de escribir en el socket
+    pub fn finalizar(&self) {
+        let mut pendientes = self.pendientes();
+        pendientes.cerrada = true;
+        pendientes.finalizada = true;
+        self.hay_lugar.notify_all();
+        self.finalizo.notify_all();
+    }
+
+    /// Espera a lo sumo `espera` a que el escritor termine de escribir lo pendiente
+    pub fn esperar_fin(&self, espera: Duration) {
+        let pendientes = self.pendientes();
+        if let Err(envenenado) = self
+            .finalizo
+            .wait_timeout_while(pendientes, espera, |p| !p.finalizada)
+        {
+            drop(envenenado.into_inner());
+        }
+    }
+
+    #[cfg(test)]
+    fn esta_cerrada(&self) -> bool {
+        self.pendientes().cerrada
+    }
+
+    /// Cantidad de mensajes push que no entraron en la cola
+    pub fn descartados(&self) -> usize {
+        self.descartados.load(Ordering::SeqCst)
+    }
+
+    /// Cantidad de mensajes push que esperan ser enviados
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.pendientes().tramas.len()
+    }
+}
+
+/// Destino de escritura que parte lo que recibe en bloques de TAMANIO_BLOQUE y los encola
+/// como partes de una misma respuesta. Mientras existe es el unico productor de respuestas de
+/// la cola, la respuesta se completa con `terminar`
+pub struct BloquesDeRespuesta<'a> {
+    salida: &'a ColaDeSalida,
+    bloque: Vec<u8>,
+    _productor: MutexGuard<'a, ()>,
+}
+
+impl<'a> BloquesDeRespuesta<'a> {
+    /// Espera a que termine la respuesta que se este encolando
+    pub fn new(salida: &'a ColaDeSalida) -> Self {
+        let productor = match salida.productor.lock() {
+            Ok(p) => p,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        BloquesDeRespuesta {
+            salida,
+            bloque: Vec::new(),
+            _productor: productor,
+        }
+    }
+
+    /// Encola lo que queda como ultimo bloque de la respuesta
+    pub fn terminar(self) -> Result<(), ColaCerrada> {
+        self.salida.encolar_bloque(self.bloque, true)
+    }
+}
+
+impl Write for BloquesDeRespuesta<'_> {
+    fn write(&mut self, datos: &[u8]) -> io::Result<usize> {
+        if self.bloque.len() >= TAMANIO_BLOQUE {
+            let bloque = std::mem::replace(&mut self.bloque, Vec::with_capacity(TAMANIO_BLOQUE));
+            self.salida
+                .encolar_bloque(bloque, false)
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        }
+        let cantidad = datos.len().min(TAMANIO_BLOQUE - self.bloque.len());
+        self.bloque.extend_from_slice(&datos[..cantidad]);
+        Ok(cantidad)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::Instantanea;
+    use crate::eventos::{BusDeEventos, Evento, Suscriptor};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::SystemTime;
+
+    fn limite(mensajes: usize, politica: PoliticaDeDesborde) -> LimiteDeSalida {
+        LimiteDeSalida { mensajes, politica }
+    }
+
+    #[test]
+    fn con_la_politica_descartar_la_cola_llena_descarta_y_cuenta_los_mensajes() {
+        let cola = ColaDeSalida::new(limite(2, PoliticaDeDesborde::Descartar));
+        for i in 0..5u8 {
+            assert_eq!(Ok(()), cola.encolar_push(vec![i]));
+        }
+        assert_eq!(2, cola.len());
+        assert_eq!(3, cola.descartados());
+        assert_eq!(Ok(vec![0]), cola.siguiente());
+        assert_eq!(Ok(()), cola.encolar_push(vec![9]));
+        assert_eq!(Ok(vec![1]), cola.siguiente());
+        assert_eq!(Ok(vec![9]), cola.siguiente());
+        assert_eq!(0, cola.len());
+    }
+
+    #[test]
+    fn con_la_politica_desconectar_la_cola_llena_se_cierra() {
+        let cola = ColaDeSalida::new(limite(1, PoliticaDeDesborde::Desconectar));
+        assert_eq!(Ok(()), cola.encolar_push(vec![1]));
+        assert_eq!(Err(ColaCerrada), cola.encolar_push(vec![2]));
+        assert!(cola.esta_cerrada());
+        assert_eq!(1, cola.descartados());
+        assert_eq!(Err(ColaCerrada), cola.siguiente());
+        assert_eq!(Err(ColaCerrada), cola.encolar_push(vec![3]));
+    }
+
+    #[test]
+    fn los_pushes_no_se_intercalan_en_medio_de_una_respuesta() {
+        let cola = ColaDeSalida::new(LimiteDeSalida::default());
+        let mut bloques = BloquesDeRespuesta::new(&cola);
+        bloques.write_all(&[b'a'; TAMANIO_BLOQUE + 1]).unwrap();
+        assert_eq!(Ok(()), cola.encolar_push(b"push".to_vec()));
+        bloques.write_all(b"b").unwrap();
+        bloques.terminar().unwrap();
+
+        let recibido: Vec<Vec<u8>> = (0..3).map(|_| cola.siguiente().unwrap()).collect();
+        assert_eq!(0, cola.len());
+        assert_eq!(TAMANIO_BLOQUE, recibido[0].len());
+        assert_eq!(b"ab".to_vec(), recibido[1]);
+        assert_eq!(b"push".to_vec(), recibido[2]);
+    }
+
+    #[test]
+    fn las_respuestas_de_distintos_productores_no_se_intercalan() {
+        let cola = Arc::new(ColaDeSalida::new(LimiteDeSalida::default()));
+        let productores: Vec<_> = [b'a', b'b']
+            .iter()
+            .map(|&letra| {
+                let cola = Arc::clone(&cola);
+                thread::spawn(move || {
+                    let mut bloques = BloquesDeRespuesta::new(&cola);
+                    for _ in 0..3 {
+                        bloques.write_all(&[letra; TAMANIO_BLOQUE]).unwrap();
+                        thread::yield_now();
+                    }
+                    bloques.terminar().unwrap();
+                })
+            })
+            .collect();
+
+        let mut recibido = Vec::new();
+        while recibido.len() < 6 * TAMANIO_BLOQUE {
+            recibido.extend(cola.siguiente().unwrap());
+        }
+        for productor in productores {
+            productor.join().unwrap();
+        }
+        let (primera, segunda) = recibido.split_at(3 * TAMANIO_BLOQUE);
+        assert!(primera.iter().all(|&b| b == primera[0]));
+        assert!(segunda.iter().all(|&b| b == segunda[0]));
+        assert_ne!(primera[0], segunda[0]);
+    }
+
+    struct Respondedor(Arc<ColaDeSalida>, Arc<Mutex<Vec<Result<(), ColaCerrada>>>>);
+
+    impl Suscriptor for Respondedor {
+        fn recibir(&self, _eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            let mut bloques = BloquesDeRespuesta::new(&self.0);
+            bloques.write_all(b"+OK\r\n").unwrap();
+            let mut resultados = self.1.lock().unwrap();
+            resultados.push(bloques.terminar());
+            resultados.push(self.0.encolar_push(b"push".to_vec()));
+        }
+    }
+
+    #[test]
+    fn un_suscriptor_del_bus_no_puede_encolar_respuestas_pero_si_pushes() {
+        let cola = Arc::new(ColaDeSalida::new(LimiteDeSalida::default()));
+        let resultados = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = BusDeEventos::new();
+        bus.suscribir(Box::new(Respondedor(
+            Arc::clone(&cola),
+            Arc::clone(&resultados),
+        )));
+
+        bus.emitir(
+            &[Evento::ClaveEscrita("a".to_string())],
+            &Arc::new(HashMap::new()),
+            SystemTime::now(),
+        );
+        assert_eq!(vec![Err(ColaCerrada), Ok(())], *resultados.lock().unwrap());
+        assert_eq!(Ok(b"push".to_vec()), cola.siguiente());
+        assert_eq!(Ok(()), cola.encolar_bloque(b"+OK\r\n".to_vec(), true));
+    }
+
+    #[test]
+    fn al_terminar_se_envian_las_respuestas_pendientes_y_se_cierra() {
+        let cola = ColaDeSalida::new(LimiteDeSalida::default());
+        cola.encolar_push(b"push".to_vec()).unwrap();
+        cola.encolar_bloque(b"+OK\r\n".to_vec(), true).unwrap();
+        cola.terminar();
+        assert_eq!(
+            Err(ColaCerrada),
+            cola.encolar_bloque(b"+OK\r\n".to_vec(), true)
+        );
+        assert_eq!(Ok(b"+OK\r\n".to_vec()), cola.siguiente());
+        assert_eq!(Err(ColaCerrada), cola.siguiente());
+    }
+
+    #[test]
+    fn el_limite_se_lee_de_la_configuracion() {
+        let mensajes = "10".to_string();
+        let politica = "DROP".to_string();
+        assert_eq!(
+            limite(10, PoliticaDeDesborde::Descartar),
+            LimiteDeSalida::desde(Some(&mensajes), Some(&politica))
+        );
+        let invalido = "0".to_string();
+        assert_eq!(
+            LimiteDeSalida::default(),
+            LimiteDeSalida::desde(Some(&invalido), None)
+        );
+    }
+}
@@ -1,14 +1,18 @@
 use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
 use crate::cliente::Cliente;
+use crate::comando_client_handler::{es_comando_client, ComandoClientHandler};
 use crate::comando_info::ComandoInfo;
 use crate::comando_key_handler::{es_comando_key, ComandoKeyHandler};
 use crate::comando_list_handler::{es_comando_list, ComandoListHandler};
 use crate::comando_nulo_handler::ComandoNuloHandler;
 use crate::comando_pubsub_handler::{es_comando_pubsub, ComandoPubSubHandler};
+use crate::comando_registro_handler::{es_comando_registrado, ComandoRegistroHandler};
+use crate::comando_replicacion_handler::{es_comando_replicacion, ComandoReplicacionHandler};
 use crate::comando_server_handler::{es_comando_server, ComandoServerHandler};
 use crate::comando_set_handler::{es_comando_set, ComandoSetHandler};
 use crate::comando_string_handler::{es_comando_string, ComandoStringHandler};
 use crate::config::Config;
+use crate::registro_comandos::RegistroDeComandos;
 
 use std::sync::{Arc, Mutex};
 
@@ -22,6 +26,7 @@ pub fn crear_comando_handler(
     comando: ComandoInfo,
     cliente: Cliente,
     config: Arc<Mutex<Config>>,
+    registro: Arc<RegistroDeComandos>,
 ) -> Box<dyn ComandoHandler> {
     if !cliente.soporta_comando(comando.get_nombre().as_str()) {
         Box::new(ComandoNuloHandler::new(comando))
@@ -35,8 +40,14 @@ pub fn crear_comando_handler(
         Box::new(ComandoListHandler::new(comando))
     } else if es_comando_pubsub(comando.get_nombre().as_str()) {
         Box::new(ComandoPubSubHandler::new(comando, cliente))
+    } else if es_comando_client(comando.get_nombre().as_str()) {
+        Box::new(ComandoClientHandler::new(comando, cliente))
+    } else if es_comando_replicacion(comando.get_nombre().as_str()) {
+        Box::new(ComandoReplicacionHandler::new(comando, cliente, config))
     } else if es_comando_server(comando.get_nombre().as_str()) {
         Box::new(ComandoServerHandler::new(comando, config))
+    } else if es_comando_registrado(comando.get_nombre().as_str(), &registro) {
+        Box::new(ComandoRegistroHandler::new(comando, registro))
     } else {
         Box::new(ComandoNuloHandler::new(comando))
     }
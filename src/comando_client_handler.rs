@@ -0,0 +1,233 @@
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
+use crate::cliente::Cliente;
+use crate::comando::ComandoHandler;
+use crate::comando_info::ComandoInfo;
+use crate::seguimiento_claves::OpcionesSeguimiento;
+use crate::subcomando::Subcomandos;
+use std::sync::{Arc, Mutex};
+
+type FuncionClient = fn(&mut ComandoInfo, Cliente, Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis;
+
+/// Manejador del comando CLIENT, que opera sobre la conexion del cliente que lo envia
+pub struct ComandoClientHandler {
+    cliente: Cliente,
+    comando: ComandoInfo,
+}
+
+impl ComandoClientHandler {
+    pub fn new(comando: ComandoInfo, cliente: Cliente) -> Self {
+        ComandoClientHandler { cliente, comando }
+    }
+}
+
+impl ComandoHandler for ComandoClientHandler {
+    fn ejecutar(mut self: Box<Self>, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+        let subcomandos = Subcomandos::<FuncionClient>::new("CLIENT")
+            .agregar(
+                "ID",
+                2,
+                "ID",
+                "Return the ID of the current connection.",
+                client_id,
+            )
+            .agregar(
+                "INFO",
+                2,
+                "INFO",
+                "Return information about the current client connection.",
+                client_info,
+            )
+            .agregar(
+                "TRACKING",
+                -3,
+                "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]] [NOLOOP]",
+                "Control server assisted client side caching.",
+                client_tracking,
+            );
+
+        match subcomandos.resolver(&mut self.comando) {
+            Ok(subcomando) => subcomando(&mut self.comando, self.cliente, bdd),
+            Err(respuesta) => respuesta,
+        }
+    }
+}
+
+/// Se encarga de detectar si el comando es CLIENT
+pub fn es_comando_client(comando: &str) -> bool {
+    comando == "CLIENT"
+}
+
+/// Devuelve el identificador de la conexion, que se usa en el REDIRECT de CLIENT TRACKING
+fn client_id(
+    _comando: &mut ComandoInfo,
+    cliente: Cliente,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+) -> ResultadoRedis {
+    ResultadoRedis::Int(cliente.obtener_token() as isize)
+}
+
+/// Describe la conexion en una linea de campos clave=valor, como CLIENT INFO de redis.
+/// dropped cuenta los mensajes de pub/sub que se descartaron porque el cliente no los leia
+fn client_info(
+    _comando: &mut ComandoInfo,
+    cliente: Cliente,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+) -> ResultadoRedis {
+    ResultadoRedis::BulkStr(format!(
+        "id={} sub={} dropped={}\n",
+        cliente.obtener_token(),
+        cliente.suscripciones(),
+        cliente.mensajes_descartados()
+    ))
+}
+
+/// Activa o desactiva el seguimiento de las claves que lee el cliente. Como el servidor solo habla RESP2,
+/// las invalidaciones se publican en __redis__:invalidate al cliente indicado con REDIRECT,
+/// que debe estar suscripto a ese canal desde otra conexion
+fn client_tracking(
+    comando: &mut ComandoInfo,
+    cliente: Cliente,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+) -> ResultadoRedis {
+    let activar = match comando.get_parametro().map(|m| m.to_uppercase()) {
+        Some(modo) if modo == "ON" => true,
+        Some(modo) if modo == "OFF" => false,
+        _ => return ResultadoRedis::Error("ERR syntax error".to_string()),
+    };
+
+    let mut redireccion = None;
+    let mut opciones = OpcionesSeguimiento {
+        redireccion: cliente.obtener_token(),
+        bcast: false,
+        prefijos: Vec::new(),
+        noloop: false,
+    };
+    while let Some(opcion) = comando.get_parametro() {
+        match opcion.to_uppercase().as_str() {
+            "BCAST" => opciones.bcast = true,
+            "NOLOOP" => opciones.noloop = true,
+            "REDIRECT" => match comando.get_parametro().map(|id| id.parse()) {
+                Some(Ok(id)) => redireccion = Some(id),
+                _ => {
+                    return ResultadoRedis::Error("ERR Invalid client ID for REDIRECT".to_string())
+                }
+            },
+            "PREFIX" => match comando.get_parametro() {
+                Some(prefijo) => opciones.prefijos.push(prefijo),
+                None => return ResultadoRedis::Error("ERR syntax error".to_string()),
+            },
+            _ => return ResultadoRedis::Error("ERR syntax error".to_string()),
+        }
+    }
+
+    let mut bdd = match bdd.lock() {
+        Ok(bdd) => bdd,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    if !activar {
+        bdd.desactivar_seguimiento(cliente.obtener_token());
+        return ResultadoRedis::StrSimple("OK".to_string());
+    }
+
+    if !opciones.prefijos.is_empty() && !opciones.bcast {
+        return ResultadoRedis::Error(
+            "ERR PREFIX option requires BCAST mode to be enabled".to_string(),
+        );
+    }
+    match redireccion {
+        Some(id) => opciones.redireccion = id,
+        None => return ResultadoRedis::Error(
+            "ERR CLIENT TRACKING requires REDIRECT to a client subscribed to __redis__:invalidate"
+                .to_string(),
+        ),
+    }
+    bdd.activar_seguimiento(cliente.obtener_token(), opciones);
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::TipoRedis;
+    use crate::canal::Canal;
+    use crate::cliente_redis::ClienteRedis;
+    use crate::seguimiento_claves::CANAL_INVALIDACION;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+
+    fn conexion(id: i64) -> (Cliente, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let usuario = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let cliente: Cliente = Box::new(ClienteRedis::new(id, 0, listener.accept().unwrap().0));
+        (cliente, usuario)
+    }
+
+    fn ejecutar(
+        tokens: &[&str],
+        cliente: &Cliente,
+        bdd: &Arc<Mutex<BaseDeDatos>>,
+    ) -> ResultadoRedis {
+        let comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+        Box::new(ComandoClientHandler::new(comando, cliente.clone())).ejecutar(Arc::clone(bdd))
+    }
+
+    #[test]
+    fn las_claves_leidas_se_invalidan_en_el_cliente_de_redireccion() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let (lector, _usuario_lector) = conexion(1);
+        let (receptor, mut usuario_receptor) = conexion(2);
+        let mut canal = Canal::new(CANAL_INVALIDACION.to_string());
+        canal.suscribirse(receptor.clone());
+        bdd.lock()
+            .unwrap()
+            .guardar_valor(CANAL_INVALIDACION.to_string(), TipoRedis::Canal(canal));
+
+        assert_eq!(
+            ResultadoRedis::Int(1),
+            ejecutar(&["CLIENT", "ID"], &lector, &bdd)
+        );
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            ejecutar(
+                &["CLIENT", "TRACKING", "on", "REDIRECT", "2"],
+                &lector,
+                &bdd
+            )
+        );
+        let mut b = bdd.lock().unwrap();
+        b.registrar_lecturas(1, &["clave".to_string()]);
+        b.invalidar_claves(&["clave".to_string(), "otra".to_string()], Some(3));
+        drop(b);
+
+        let esperado =
+            "*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$5\r\nclave\r\n";
+        let mut respuesta = vec![0; esperado.len()];
+        usuario_receptor
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        usuario_receptor.read_exact(&mut respuesta).unwrap();
+        assert_eq!(esperado.as_bytes(), &respuesta[..]);
+    }
+
+    #[test]
+    fn prefix_sin_bcast_o_sin_redirect_es_un_error() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let (cliente, _usuario) = conexion(1);
+
+        assert_eq!(
+            ResultadoRedis::Error(
+                "ERR PREFIX option requires BCAST mode to be enabled".to_string()
+            ),
+            ejecutar(
+                &["CLIENT", "TRACKING", "ON", "REDIRECT", "2", "PREFIX", "a"],
+                &cliente,
+                &bdd
+            )
+        );
+        assert!(matches!(
+            ejecutar(&["CLIENT", "TRACKING", "ON", "BCAST"], &cliente, &bdd),
+            ResultadoRedis::Error(_)
+        ));
+    }
+}
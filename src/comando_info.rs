@@ -52,11 +52,45 @@ impl ComandoInfo {
         }
         None
     }
+
+    /// Cantidad de parametros del comando, sin contar el nombre
+    pub fn len(&self) -> usize {
+        self.parametros.len()
+    }
+
+    /// Predicado que indica si el comando no tiene parametros
+    pub fn is_empty(&self) -> bool {
+        self.parametros.is_empty()
+    }
+
+    /// Devuelve el parametro de la posicion indicada sin modificar el iterador interno.
+    /// La posicion 0 corresponde a la clave
+    pub fn get(&self, indice: usize) -> Option<String> {
+        self.parametros.get(indice).map(|p| p.to_string())
+    }
+
+    /// Devuelve los parametros que todavia no fueron consumidos por get_parametro
+    pub fn resto(&self) -> Vec<String> {
+        match self.parametros.get(self.index..) {
+            Some(resto) => resto.to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Vuelve el iterador interno al primer parametro
+    pub fn rewind(&mut self) {
+        self.index = 0;
+    }
+
+    /// Vista de todos los parametros del comando
+    pub fn tokens(&self) -> &[String] {
+        &self.parametros
+    }
     /// Devuelve iterativamente los parametros. Cuando ya no quedan parametros devuelve None
     ///
     ///# Examples
     ///
-    /// ```
+    /// ```text
     /// COMANDO : LPUSH LISTA 1 2 3 4 5
     ///
     /// comando_info.get_parametros()
@@ -153,4 +187,31 @@ mod tests {
             comando_info.get_parametros()
         );
     }
+
+    #[test]
+    fn comando_info_permite_acceder_por_indice_y_volver_a_recorrer_los_parametros() {
+        let parametros = vec![
+            "SET".to_string(),
+            "clave".to_string(),
+            "valor".to_string(),
+            "EX".to_string(),
+            "10".to_string(),
+        ];
+        let mut comando_info = ComandoInfo::new(parametros);
+
+        assert_eq!(4, comando_info.len());
+        assert_eq!(Some("valor".to_string()), comando_info.get(1));
+        assert_eq!(None, comando_info.get(4));
+
+        assert_eq!(Some("clave".to_string()), comando_info.get_clave());
+        assert_eq!(Some("valor".to_string()), comando_info.get_parametro());
+        assert_eq!(
+            vec!["EX".to_string(), "10".to_string()],
+            comando_info.resto()
+        );
+
+        comando_info.rewind();
+        assert_eq!(Some("clave".to_string()), comando_info.get_parametro());
+        assert_eq!(&["clave", "valor", "EX", "10"], comando_info.tokens());
+    }
 }
@@ -1,9 +1,19 @@
-use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, TipoRedis};
+use crate::base_de_datos::{
+    claves_que_coinciden, BaseDeDatos, CondicionExpiracion, ResultadoRedis, TipoRedis, WRONGTYPE,
+};
 use crate::comando::{Comando, ComandoHandler};
 use crate::comando_info::ComandoInfo;
-use std::iter::FromIterator;
+use crate::glob::glob_a_regex;
+use crate::opciones_parser::OpcionesParser;
+use crate::persistencia::{deserializar_valor, serializar_valor};
+use crate::resp;
+use crate::subcomando::Subcomandos;
+use regex::Regex;
+use std::io::{BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
 use std::sync::{Arc, Mutex, MutexGuard};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, UNIX_EPOCH};
+type FuncionKey = fn(&mut ComandoInfo, Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis;
 /// Manejador de comando del tipo key
 pub struct ComandoKeyHandler {
     comando: ComandoInfo,
@@ -18,12 +28,18 @@ impl ComandoKeyHandler {
             "EXISTS" => exists,
             "RENAME" => rename,
             "EXPIRE" => expire,
-            "EXPIREAT" => expireat,
+            "EXPIREAT" | "PEXPIREAT" => expireat,
             "PERSIST" => persist,
             "TTL" => ttl,
+            "PTTL" => pttl,
             "TOUCH" => touch,
             "KEYS" => keys,
+            "SCAN" => scan,
             "SORT" => sort,
+            "OBJECT" => object,
+            "DUMP" => dump,
+            "RESTORE" => restore,
+            "MIGRATE" => migrate,
             _ => tipo,
         };
         ComandoKeyHandler {
@@ -41,8 +57,25 @@ impl ComandoHandler for ComandoKeyHandler {
 /// Se encarga de detectar si el comando corresponde a los implementados del tipo key
 pub fn es_comando_key(comando: &str) -> bool {
     let comandos = vec![
-        "COPY", "DEL", "EXISTS", "RENAME", "EXPIRE", "EXPIREAT", "PERSIST", "TTL", "TOUCH", "KEYS",
-        "SORT", "TYPE",
+        "COPY",
+        "DEL",
+        "EXISTS",
+        "RENAME",
+        "EXPIRE",
+        "EXPIREAT",
+        "PEXPIREAT",
+        "PERSIST",
+        "TTL",
+        "PTTL",
+        "TOUCH",
+        "KEYS",
+        "SCAN",
+        "SORT",
+        "TYPE",
+        "OBJECT",
+        "DUMP",
+        "RESTORE",
+        "MIGRATE",
     ];
     comandos.iter().any(|&c| c == comando)
 }
@@ -84,15 +117,20 @@ fn rename(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoR
             )
         }
     };
-    let clon = Arc::clone(&bdd);
-    match copy(comando, bdd) {
-        ResultadoRedis::Error(_) => ResultadoRedis::Error("ERR no such key".to_string()),
-        _ => {
-            let vector = vec!["rename".to_string(), clave];
-            let mut comando = ComandoInfo::new(vector);
-            del(&mut comando, clon);
-            ResultadoRedis::StrSimple("Ok".to_string())
+    let clave_nueva = match comando.get_parametro() {
+        Some(p) => p,
+        None => {
+            return ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'rename' command".to_string(),
+            )
         }
+    };
+    match bdd.lock() {
+        Ok(mut bdd) => match bdd.renombrar_clave(&clave, &clave_nueva) {
+            Some(_) => ResultadoRedis::StrSimple("Ok".to_string()),
+            None => ResultadoRedis::Error("ERR no such key".to_string()),
+        },
+        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
 /// Retorna un string que representa el tipo de valor almacenado en una clave. Los tipos que puede retornar son: string, list, set (no consideramos los tipos de datos que no se implementan en el proyecto)
@@ -115,6 +153,219 @@ fn tipo(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
+/// Devuelve informacion sobre la representacion interna de los valores almacenados
+fn object(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let subcomandos = Subcomandos::<FuncionKey>::new("OBJECT").agregar(
+        "ENCODING",
+        3,
+        "ENCODING <key>",
+        "Return the kind of internal representation used in order to store the value associated with a <key>.",
+        object_encoding,
+    );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, bdd),
+        Err(respuesta) => respuesta,
+    }
+}
+/// Devuelve la codificacion del valor almacenado en la clave o nil si no existe
+fn object_encoding(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let clave = match comando.get_parametro() {
+        Some(c) => c,
+        None => {
+            return ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'object|encoding' command".to_string(),
+            )
+        }
+    };
+    match bdd.lock() {
+        Ok(bdd) => match bdd.codificacion(&clave) {
+            Some(codificacion) => ResultadoRedis::BulkStr(codificacion.to_string()),
+            None => ResultadoRedis::Nil,
+        },
+        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    }
+}
+/// Devuelve el valor de la clave serializado para recrearlo con RESTORE, o nil si no existe
+fn dump(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let clave = match comando.get_clave() {
+        Some(c) => c,
+        None => {
+            return ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'dump' command".to_string(),
+            )
+        }
+    };
+    match bdd.lock() {
+        Ok(bdd) => match bdd.obtener_valor(&clave).and_then(serializar_valor) {
+            Some(serializado) => ResultadoRedis::BulkStr(serializado),
+            None => ResultadoRedis::Nil,
+        },
+        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    }
+}
+/// Crea la clave con un valor obtenido con DUMP. El ttl esta en milisegundos y 0 indica que
+/// no expira, con ABSTTL es el instante en milisegundos desde epoch en el que expira
+fn restore(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let (clave, ttl, serializado) = match (comando.get(0), comando.get(1), comando.get(2)) {
+        (Some(c), Some(t), Some(s)) => (c, t, s),
+        _ => {
+            return ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'restore' command".to_string(),
+            )
+        }
+    };
+    let ttl: u64 = match ttl.parse() {
+        Ok(t) => t,
+        Err(_) => return ResultadoRedis::Error("ERR Invalid TTL value, must be >= 0".to_string()),
+    };
+    let opciones = match OpcionesParser::new()
+        .bandera("REPLACE")
+        .bandera("ABSTTL")
+        .parsear(&comando.tokens()[3..])
+    {
+        Ok(o) => o,
+        Err(e) => return e.a_resultado(),
+    };
+    let valor = match deserializar_valor(&serializado) {
+        Some(v) => v,
+        None => {
+            return ResultadoRedis::Error(
+                "ERR DUMP payload version or checksum are wrong".to_string(),
+            )
+        }
+    };
+
+    let mut bdd = match bdd.lock() {
+        Ok(bdd) => bdd,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    if bdd.existe_clave(&clave) {
+        if !opciones.tiene("REPLACE") {
+            return ResultadoRedis::Error("BUSYKEY Target key name already exists.".to_string());
+        }
+        bdd.eliminar_clave(&clave);
+    }
+
+    let vida_util = if opciones.tiene("ABSTTL") && ttl > 0 {
+        match (UNIX_EPOCH + Duration::from_millis(ttl)).duration_since(bdd.ahora()) {
+            Ok(restante) => Some(restante),
+            Err(_) => return ResultadoRedis::StrSimple("OK".to_string()),
+        }
+    } else if ttl > 0 {
+        Some(Duration::from_millis(ttl))
+    } else {
+        None
+    };
+    match vida_util {
+        Some(vida_util) => bdd.guardar_valor_con_expiracion(clave, vida_util, valor),
+        None => bdd.guardar_valor(clave, valor),
+    }
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// Mueve la clave a otra instancia: la serializa, la recrea alli con RESTORE y, si la instancia
+/// destino la acepta, la elimina localmente salvo que se indique COPY. El RESTORE va precedido
+/// por ASKING para que lo acepte un nodo que esta importando el slot. La conexion se hace sin
+/// tener tomada la base de datos, por lo que una escritura concurrente sobre la clave
+/// mientras se migra puede perderse
+fn migrate(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let parametros = comando.tokens().to_vec();
+    if parametros.len() < 5 {
+        return ResultadoRedis::Error(
+            "ERR wrong number of arguments for 'migrate' command".to_string(),
+        );
+    }
+    let (host, puerto, clave) = (&parametros[0], &parametros[1], &parametros[2]);
+    let (db, timeout): (u64, u64) = match (parametros[3].parse(), parametros[4].parse()) {
+        (Ok(db), Ok(timeout)) => (db, timeout),
+        _ => {
+            return ResultadoRedis::Error("ERR value is not an integer or out of range".to_string())
+        }
+    };
+    if db != 0 {
+        return ResultadoRedis::Error("ERR DB index is out of range".to_string());
+    }
+    let opciones = match OpcionesParser::new()
+        .bandera("COPY")
+        .bandera("REPLACE")
+        .parsear(&parametros[5..])
+    {
+        Ok(o) => o,
+        Err(e) => return e.a_resultado(),
+    };
+
+    let (serializado, ttl) = match bdd.lock() {
+        Ok(bdd) => match bdd.obtener_valor(clave).and_then(serializar_valor) {
+            Some(s) => (s, bdd.obtener_expiracion_en_milisegundos(clave).max(0)),
+            None => return ResultadoRedis::StrSimple("NOKEY".to_string()),
+        },
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+
+    let mut restore = vec![
+        "RESTORE".to_string(),
+        clave.to_string(),
+        ttl.to_string(),
+        serializado,
+    ];
+    if opciones.tiene("REPLACE") {
+        restore.push("REPLACE".to_string());
+    }
+    let pedidos = vec![vec!["ASKING".to_string()], restore];
+    let respuesta = match enviar_a_instancia(host, puerto, timeout, &pedidos) {
+        Ok(r) => r,
+        Err(_) => {
+            return ResultadoRedis::Error(
+                "IOERR error or timeout reading to target instance".to_string(),
+            )
+        }
+    };
+    if let ResultadoRedis::Error(error) = respuesta {
+        return ResultadoRedis::Error(format!("ERR Target instance replied with error: {}", error));
+    }
+
+    if !opciones.tiene("COPY") {
+        match bdd.lock() {
+            Ok(mut bdd) => bdd.eliminar_clave(clave),
+            Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+        };
+    }
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+
+/// Envia los comandos a la instancia y devuelve la respuesta al ultimo
+fn enviar_a_instancia(
+    host: &str,
+    puerto: &str,
+    timeout: u64,
+    comandos: &[Vec<String>],
+) -> std::io::Result<ResultadoRedis> {
+    let timeout = Duration::from_millis(timeout.max(1));
+    let direccion = match format!("{}:{}", host, puerto).to_socket_addrs()?.next() {
+        Some(d) => d,
+        None => return Err(std::io::ErrorKind::NotFound.into()),
+    };
+    let mut stream = TcpStream::connect_timeout(&direccion, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    for tokens in comandos {
+        let pedido = ResultadoRedis::Vector(
+            tokens
+                .iter()
+                .map(|t| ResultadoRedis::BulkStr(t.to_string()))
+                .collect(),
+        );
+        stream.write_all(resp::codificar(&pedido).as_bytes())?;
+    }
+
+    let mut lector = BufReader::new(stream);
+    let mut respuesta = ResultadoRedis::Nil;
+    for _ in comandos {
+        respuesta = resp::leer(&mut lector)?;
+    }
+    Ok(respuesta)
+}
 
 fn recorrer_y_ejecutar(
     comando: &mut ComandoInfo,
@@ -177,21 +428,58 @@ fn expire(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoR
             )
         }
     };
+    let condicion = match condicion_de_expiracion(comando) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+
     match bdd.lock() {
-        Ok(mut bdd) => {
-            ResultadoRedis::Int(bdd.actualizar_valor_con_expiracion(clave, parametro) as isize)
-        }
+        Ok(mut bdd) => match bdd.ahora().checked_add(Duration::from_secs(parametro)) {
+            Some(instante) => ResultadoRedis::Int(
+                bdd.actualizar_expiracion_si(clave, instante, condicion) as isize,
+            ),
+            None => {
+                ResultadoRedis::Error("ERR invalid expire time in 'expire' command".to_string())
+            }
+        },
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
-/// Tiene el mismo efecto que EXPIRE, pero en lugar de indicar el número de segundos que representa el TTL (time to live), toma el tiempo absoluto en el timestamp de Unix (segundos desde el 1ro de enero de 1970)
+
+/// Parsea los modificadores NX, XX, GT y LT de EXPIRE y EXPIREAT
+fn condicion_de_expiracion(comando: &ComandoInfo) -> Result<CondicionExpiracion, ResultadoRedis> {
+    let opciones = OpcionesParser::new()
+        .bandera("NX")
+        .bandera("XX")
+        .bandera("GT")
+        .bandera("LT")
+        .excluyentes(&["NX", "XX", "GT", "LT"])
+        .parsear(comando.tokens().get(2..).unwrap_or_default())
+        .map_err(|e| e.a_resultado())?;
+
+    Ok(if opciones.tiene("NX") {
+        CondicionExpiracion::SinExpiracion
+    } else if opciones.tiene("XX") {
+        CondicionExpiracion::ConExpiracion
+    } else if opciones.tiene("GT") {
+        CondicionExpiracion::Mayor
+    } else if opciones.tiene("LT") {
+        CondicionExpiracion::Menor
+    } else {
+        CondicionExpiracion::Siempre
+    })
+}
+/// Tiene el mismo efecto que EXPIRE, pero en lugar de indicar el número de segundos que representa el TTL (time to live), toma el tiempo absoluto en el timestamp de Unix (segundos desde el 1ro de enero de 1970).
+/// PEXPIREAT es igual pero con el timestamp en milisegundos
 fn expireat(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let nombre = comando.get_nombre().to_lowercase();
     let clave = match comando.get_clave() {
         Some(c) => c,
         None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'expireat' command".to_string(),
-            )
+            return ResultadoRedis::Error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                nombre
+            ))
         }
     };
 
@@ -205,25 +493,35 @@ fn expireat(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
             }
         },
         None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'expireat' command".to_string(),
-            )
+            return ResultadoRedis::Error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                nombre
+            ))
         }
     };
 
-    let tiempo_desde_epoch = UNIX_EPOCH + Duration::from_secs(parametro);
-    let tiempo_a_esperar = match tiempo_desde_epoch.duration_since(SystemTime::now()) {
-        Ok(d) => d,
-        Err(_) => {
-            return ResultadoRedis::Error(
-                "TimeError the time since epoch has already happened".to_string(),
-            )
+    let condicion = match condicion_de_expiracion(comando) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+
+    let desde_epoch = match nombre.as_str() {
+        "pexpireat" => Duration::from_millis(parametro),
+        _ => Duration::from_secs(parametro),
+    };
+    let instante = match UNIX_EPOCH.checked_add(desde_epoch) {
+        Some(i) => i,
+        None => {
+            return ResultadoRedis::Error(format!(
+                "ERR invalid expire time in '{}' command",
+                nombre
+            ))
         }
     };
     match bdd.lock() {
-        Ok(mut bdd) => ResultadoRedis::Int(
-            bdd.actualizar_valor_con_expiracion(clave, tiempo_a_esperar.as_secs()) as isize,
-        ),
+        Ok(mut bdd) => {
+            ResultadoRedis::Int(bdd.actualizar_expiracion_si(clave, instante, condicion) as isize)
+        }
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
@@ -257,6 +555,21 @@ fn ttl(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedi
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
+/// Igual que TTL pero retorna el tiempo que le queda a la clave en milisegundos
+fn pttl(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let clave = match comando.get_clave() {
+        Some(c) => c,
+        None => {
+            return ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'pttl' command".to_string(),
+            )
+        }
+    };
+    match bdd.lock() {
+        Ok(bdd) => ResultadoRedis::Int(bdd.obtener_expiracion_en_milisegundos(&clave)),
+        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    }
+}
 /// Actualiza el valor de último acceso a la clave
 fn touch(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
     let mut acum = 0;
@@ -281,10 +594,11 @@ fn keys(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
         }
     };
 
-    let vector: Vec<String> = match bdd.lock() {
-        Ok(bdd) => bdd.claves(&re),
+    let (instantanea, ahora) = match bdd.lock() {
+        Ok(bdd) => (bdd.instantanea(), bdd.ahora()),
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
+    let vector = claves_que_coinciden(&instantanea, &re, ahora);
 
     ResultadoRedis::Vector(
         vector
@@ -294,6 +608,87 @@ fn keys(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
     )
 }
 
+/// Cantidad de claves que examina SCAN por llamada si no se envia COUNT
+const SCAN_COUNT: i64 = 10;
+
+/// Itera las claves de la base de datos de a partes. Cada llamada examina COUNT claves a partir del cursor
+/// y devuelve el cursor para la siguiente llamada junto con las claves examinadas que coinciden
+/// con el patron de MATCH y son del tipo de TYPE
+fn scan(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+    let cursor = match comando.get(0).map(|c| c.parse::<u64>()) {
+        Some(Ok(c)) => c,
+        Some(Err(_)) => return ResultadoRedis::Error("ERR invalid cursor".to_string()),
+        None => {
+            return ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'scan' command".to_string(),
+            )
+        }
+    };
+
+    let opciones = match OpcionesParser::new()
+        .con_texto("MATCH")
+        .con_entero("COUNT")
+        .con_texto("TYPE")
+        .parsear(&comando.tokens()[1..])
+    {
+        Ok(o) => o,
+        Err(e) => return e.a_resultado(),
+    };
+    let cantidad = match opciones.entero("COUNT").unwrap_or(SCAN_COUNT) {
+        c if c > 0 => c as usize,
+        _ => return ResultadoRedis::Error("ERR syntax error".to_string()),
+    };
+    let patron = match opciones
+        .texto("MATCH")
+        .map(|p| Regex::new(&glob_a_regex(p)))
+    {
+        Some(Ok(re)) => Some(re),
+        Some(Err(_)) => return ResultadoRedis::Error("ERR invalid pattern".to_string()),
+        None => None,
+    };
+    let tipo = opciones.texto("TYPE").map(|t| t.to_lowercase());
+
+    let bdd = match bdd.lock() {
+        Ok(bdd) => bdd,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    let (siguiente, examinadas) = bdd.recorrer_claves(cursor, cantidad);
+    let ahora = bdd.ahora();
+
+    let claves = examinadas
+        .into_iter()
+        .filter(|clave| {
+            match bdd
+                .tabla()
+                .get(clave.as_str())
+                .and_then(|v| v.get_al(ahora))
+            {
+                Some(valor) => {
+                    patron.as_ref().is_none_or(|re| re.is_match(clave))
+                        && tipo.as_ref().is_none_or(|t| t == nombre_de_tipo(valor))
+                }
+                None => false,
+            }
+        })
+        .map(ResultadoRedis::BulkStr)
+        .collect();
+
+    ResultadoRedis::Vector(vec![
+        ResultadoRedis::BulkStr(siguiente.to_string()),
+        ResultadoRedis::Vector(claves),
+    ])
+}
+
+/// Nombre del tipo de dato con el que se filtra en SCAN TYPE
+fn nombre_de_tipo(valor: &TipoRedis) -> &'static str {
+    match valor {
+        TipoRedis::Str(_) => "string",
+        TipoRedis::Lista(_) => "list",
+        TipoRedis::Set(_) => "set",
+        TipoRedis::Canal(_) => "channel",
+    }
+}
+
 fn es_parseable(num: &str) -> bool {
     num.parse::<i32>().is_ok()
 }
@@ -510,16 +905,9 @@ fn sort(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
     let valores = match bdd.lock() {
         Ok(bdd) => match bdd.obtener_valor(&parametros[0]) {
             Some(TipoRedis::Lista(lista)) => lista.clone(),
-            Some(TipoRedis::Set(set)) => Vec::from_iter(set)
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>(),
+            Some(TipoRedis::Set(set)) => set.iter().map(|x| x.to_string()).collect::<Vec<String>>(),
             None => return ResultadoRedis::Vector(vec![]),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+            _ => return ResultadoRedis::Error(WRONGTYPE.to_string()),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the databases".to_string()),
     };
@@ -539,14 +927,16 @@ fn sort(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
 mod tests {
     use super::*;
     use crate::base_de_datos::TipoRedis;
-    use std::collections::HashSet;
+    use crate::codificacion::LimitesDeCodificacion;
+    use crate::conjunto::Conjunto;
+    use crate::reloj::RelojSimulado;
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime};
 
     #[test]
     fn copy_copia_el_valor_de_una_clave_en_otra() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
 
         let ptr_arc = Arc::new(Mutex::new(data_base));
         let arc_clone = Arc::clone(&ptr_arc);
@@ -565,7 +955,7 @@ mod tests {
                 .unwrap()
                 .obtener_valor("otra_clave")
                 .unwrap(),
-            &TipoRedis::Str("valor".to_string())
+            &TipoRedis::Str("valor".into())
         );
     }
 
@@ -586,11 +976,11 @@ mod tests {
     #[test]
     fn del_elimina_las_claves_guardadas_en_la_base_de_datos() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".into()));
         data_base.guardar_valor("3".to_string(), TipoRedis::Lista(Vec::new()));
-        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".into()));
 
         let comando = vec![
             "del".to_string(),
@@ -609,11 +999,11 @@ mod tests {
     #[test]
     fn del_trata_de_elimina_las_claves_que_no_estan_guardadas_en_la_base_de_datos() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".into()));
         data_base.guardar_valor("3".to_string(), TipoRedis::Lista(Vec::new()));
-        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".into()));
 
         let comando = vec![
             "del".to_string(),
@@ -632,11 +1022,11 @@ mod tests {
     #[test]
     fn del_elimina_las_claves_repetidas_que_de_la_base_de_datos() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".into()));
         data_base.guardar_valor("3".to_string(), TipoRedis::Lista(Vec::new()));
-        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".into()));
 
         let comando = vec![
             "del".to_string(),
@@ -655,11 +1045,11 @@ mod tests {
     #[test]
     fn existis_chequea_las_claves_guardadas_en_la_base_de_datos() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".into()));
         data_base.guardar_valor("3".to_string(), TipoRedis::Lista(Vec::new()));
-        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".into()));
 
         let comando = vec![
             "del".to_string(),
@@ -678,11 +1068,11 @@ mod tests {
     #[test]
     fn existis_chequea_las_claves_repetidas_que_de_la_base_de_datos() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("1".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("2".to_string(), TipoRedis::Str("valor".into()));
         data_base.guardar_valor("3".to_string(), TipoRedis::Lista(Vec::new()));
-        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("4".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("5".to_string(), TipoRedis::Str("valor".into()));
 
         let comando = vec![
             "del".to_string(),
@@ -701,7 +1091,7 @@ mod tests {
     #[test]
     fn rename_cambia_modifica_la_clave_pedida() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
 
         let ptr_arc = Arc::new(Mutex::new(data_base));
         let arc_clone = Arc::clone(&ptr_arc);
@@ -721,7 +1111,7 @@ mod tests {
                 .unwrap()
                 .obtener_valor("otra_clave")
                 .unwrap(),
-            &TipoRedis::Str("valor".to_string())
+            &TipoRedis::Str("valor".into())
         );
         assert!(!arc_clone.lock().unwrap().existe_clave("clave"));
     }
@@ -729,9 +1119,9 @@ mod tests {
     #[test]
     fn tipo_devuelve_el_tipo_del_valor_almacenado_con_esa_clave() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("string".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("string".to_string(), TipoRedis::Str("valor".into()));
         data_base.guardar_valor("lista".to_string(), TipoRedis::Lista(Vec::new()));
-        data_base.guardar_valor("set".to_string(), TipoRedis::Set(HashSet::new()));
+        data_base.guardar_valor("set".to_string(), TipoRedis::Set(Conjunto::new()));
 
         let ptr1 = Arc::new(Mutex::new(data_base));
         let ptr2 = Arc::clone(&ptr1);
@@ -769,8 +1159,10 @@ mod tests {
     #[test]
     fn expire_cuando_se_crea_una_clave_no_expirable_y_se_la_pasa_a_volatil_esta_expira_correctamente(
     ) {
+        let reloj = Arc::new(RelojSimulado::new());
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.set_reloj(reloj.clone());
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -784,7 +1176,9 @@ mod tests {
             expire(&mut comando, Arc::clone(&ptr))
         );
 
-        thread::sleep(Duration::from_secs(2));
+        reloj.avanzar(Duration::from_millis(999));
+        assert!(ptr.lock().unwrap().existe_clave("clave"));
+        reloj.avanzar(Duration::from_millis(1));
 
         assert!(!ptr.lock().unwrap().existe_clave("clave"));
     }
@@ -792,9 +1186,9 @@ mod tests {
     #[test]
     fn keys_si_se_ingresa_la_siguiente_re_el_resultado_es_el_correcto() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("hello".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("hallo".to_string(), TipoRedis::Str("valor".to_string()));
-        data_base.guardar_valor("hillo".to_string(), TipoRedis::Str("valor".to_string()));
+        data_base.guardar_valor("hello".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("hallo".to_string(), TipoRedis::Str("valor".into()));
+        data_base.guardar_valor("hillo".to_string(), TipoRedis::Str("valor".into()));
 
         let mut comando = ComandoInfo::new(vec!["keys".to_string(), "h[ae]llo".to_string()]);
 
@@ -806,6 +1200,196 @@ mod tests {
         assert!(valor.contains(&ResultadoRedis::BulkStr("hello".to_string())));
     }
 
+    #[test]
+    fn expire_con_modificadores_solo_actualiza_si_se_cumple_la_condicion() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+        let bdd = Arc::new(Mutex::new(data_base));
+        let ejecutar = |tokens: &[&str]| {
+            let mut comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+            expire(&mut comando, Arc::clone(&bdd))
+        };
+
+        assert_eq!(
+            ResultadoRedis::Int(0),
+            ejecutar(&["expire", "clave", "10", "XX"])
+        );
+        assert_eq!(
+            ResultadoRedis::Int(1),
+            ejecutar(&["expire", "clave", "10", "nx"])
+        );
+        assert_eq!(
+            ResultadoRedis::Int(0),
+            ejecutar(&["expire", "clave", "5", "GT"])
+        );
+        assert_eq!(
+            ResultadoRedis::Int(1),
+            ejecutar(&["expire", "clave", "5", "LT"])
+        );
+        assert_eq!(
+            ResultadoRedis::Error(
+                "ERR syntax error, NX and XX options at the same time are not compatible"
+                    .to_string()
+            ),
+            ejecutar(&["expire", "clave", "10", "NX", "XX"])
+        );
+    }
+
+    #[test]
+    fn expire_con_un_tiempo_que_desborda_devuelve_error_sin_envenenar_la_base() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+        let bdd = Arc::new(Mutex::new(data_base));
+        let mut comando = ComandoInfo::new(vec![
+            "expire".to_string(),
+            "clave".to_string(),
+            "18446744073709551615".to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::Error("ERR invalid expire time in 'expire' command".to_string()),
+            expire(&mut comando, Arc::clone(&bdd))
+        );
+        assert!(!bdd.is_poisoned());
+        assert_eq!(-1, bdd.lock().unwrap().obtener_expiracion("clave"));
+    }
+
+    #[test]
+    fn pexpireat_toma_el_instante_en_milisegundos() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+        let bdd = Arc::new(Mutex::new(data_base));
+        let en_un_minuto = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        let mut comando = ComandoInfo::new(vec![
+            "pexpireat".to_string(),
+            "clave".to_string(),
+            en_un_minuto.to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::Int(1),
+            expireat(&mut comando, Arc::clone(&bdd))
+        );
+        let restante = bdd
+            .lock()
+            .unwrap()
+            .obtener_expiracion_en_milisegundos("clave");
+        assert!(restante > 59_000 && restante <= 60_000);
+    }
+
+    #[test]
+    fn scan_recorre_todas_las_claves_filtrando_por_patron_y_tipo() {
+        let mut data_base = BaseDeDatos::new();
+        for i in 0..15 {
+            data_base.guardar_valor(format!("str{}", i), TipoRedis::Str("valor".into()));
+            data_base.guardar_valor(format!("lista{}", i), TipoRedis::Lista(Vec::new()));
+        }
+        let bdd = Arc::new(Mutex::new(data_base));
+
+        let mut cursor = "0".to_string();
+        let mut claves = Vec::new();
+        loop {
+            let mut comando = ComandoInfo::new(vec![
+                "scan".to_string(),
+                cursor,
+                "MATCH".to_string(),
+                "*1*".to_string(),
+                "TYPE".to_string(),
+                "list".to_string(),
+                "COUNT".to_string(),
+                "4".to_string(),
+            ]);
+            let mut respuesta = match scan(&mut comando, Arc::clone(&bdd)) {
+                ResultadoRedis::Vector(v) => v,
+                r => panic!("respuesta inesperada {:?}", r),
+            };
+            match respuesta.pop() {
+                Some(ResultadoRedis::Vector(v)) => claves.extend(v),
+                r => panic!("respuesta inesperada {:?}", r),
+            }
+            cursor = match respuesta.pop() {
+                Some(ResultadoRedis::BulkStr(c)) => c,
+                r => panic!("respuesta inesperada {:?}", r),
+            };
+            if cursor == "0" {
+                break;
+            }
+        }
+
+        let mut esperadas: Vec<ResultadoRedis> = [1, 10, 11, 12, 13, 14]
+            .iter()
+            .map(|i| ResultadoRedis::BulkStr(format!("lista{}", i)))
+            .collect();
+        claves.sort_by_key(|c| format!("{:?}", c));
+        esperadas.sort_by_key(|c| format!("{:?}", c));
+        assert_eq!(esperadas, claves);
+    }
+
+    #[test]
+    fn scan_match_recibe_un_patron_glob() {
+        let mut data_base = BaseDeDatos::new();
+        for clave in &["hello", "hallo", "hillo", "hllo", "a.b"] {
+            data_base.guardar_valor(clave.to_string(), TipoRedis::Str("valor".into()));
+        }
+        let bdd = Arc::new(Mutex::new(data_base));
+        let coincidentes = |patron: &str| {
+            let mut comando = ComandoInfo::new(
+                ["scan", "0", "COUNT", "100", "MATCH", patron]
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+            );
+            let mut claves = match scan(&mut comando, Arc::clone(&bdd)) {
+                ResultadoRedis::Vector(mut v) => match v.pop() {
+                    Some(ResultadoRedis::Vector(claves)) => claves,
+                    r => panic!("respuesta inesperada {:?}", r),
+                },
+                r => panic!("respuesta inesperada {:?}", r),
+            };
+            claves.sort_by_key(|c| format!("{:?}", c));
+            claves
+        };
+        let bulk = |claves: &[&str]| -> Vec<ResultadoRedis> {
+            claves
+                .iter()
+                .map(|c| ResultadoRedis::BulkStr(c.to_string()))
+                .collect()
+        };
+
+        assert_eq!(5, coincidentes("*").len());
+        assert_eq!(bulk(&["hallo", "hello", "hillo"]), coincidentes("h?llo"));
+        assert_eq!(bulk(&["hallo", "hello"]), coincidentes("h[ae]llo"));
+        assert_eq!(bulk(&["hillo"]), coincidentes("h[^ae]llo"));
+        assert_eq!(bulk(&["a.b"]), coincidentes("a.b"));
+        assert_eq!(bulk(&["hllo"]), coincidentes("hl*"));
+    }
+
+    #[test]
+    fn scan_con_cursor_u_opciones_invalidas_devuelve_error() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let ejecutar = |tokens: &[&str]| {
+            let mut comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+            scan(&mut comando, Arc::clone(&bdd))
+        };
+
+        assert_eq!(
+            ResultadoRedis::Error("ERR invalid cursor".to_string()),
+            ejecutar(&["scan", "abc"])
+        );
+        assert_eq!(
+            ResultadoRedis::Error("ERR syntax error".to_string()),
+            ejecutar(&["scan", "0", "COUNT", "0"])
+        );
+        assert_eq!(
+            ResultadoRedis::Error("ERR syntax error".to_string()),
+            ejecutar(&["scan", "0", "LIMIT", "1"])
+        );
+    }
+
     #[test]
     fn sort_ordena_los_elementos_numericos_en_una_lista() {
         let mut data_base = BaseDeDatos::new();
@@ -1088,11 +1672,11 @@ mod tests {
     #[test]
     fn sort_ordena_los_elementos_en_una_lista_con_pesos_externos() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("peso_2".to_string(), TipoRedis::Str("2".to_string()));
-        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".to_string()));
-        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".to_string()));
-        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".to_string()));
-        data_base.guardar_valor("peso_6".to_string(), TipoRedis::Str("6".to_string()));
+        data_base.guardar_valor("peso_2".to_string(), TipoRedis::Str("2".into()));
+        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".into()));
+        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".into()));
+        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".into()));
+        data_base.guardar_valor("peso_6".to_string(), TipoRedis::Str("6".into()));
         data_base.guardar_valor(
             "mylist".to_string(),
             TipoRedis::Lista(vec![
@@ -1125,10 +1709,10 @@ mod tests {
     #[test]
     fn sort_ordena_los_elementos_en_una_lista_con_pesos_externos_faltantes() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".to_string()));
-        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".to_string()));
-        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".to_string()));
-        data_base.guardar_valor("peso_6".to_string(), TipoRedis::Str("6".to_string()));
+        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".into()));
+        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".into()));
+        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".into()));
+        data_base.guardar_valor("peso_6".to_string(), TipoRedis::Str("6".into()));
         data_base.guardar_valor(
             "mylist".to_string(),
             TipoRedis::Lista(vec![
@@ -1193,26 +1777,17 @@ mod tests {
     #[test]
     fn sort_ordena_los_elementos_y_devuelve_los_objetos_que_representan_las_ids() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("peso_1".to_string(), TipoRedis::Str("1".to_string()));
-        data_base.guardar_valor("peso_2".to_string(), TipoRedis::Str("2".to_string()));
-        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".to_string()));
-        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".to_string()));
-        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".to_string()));
-
-        data_base.guardar_valor(
-            "objeto_1".to_string(),
-            TipoRedis::Str("primero".to_string()),
-        );
-        data_base.guardar_valor(
-            "objeto_2".to_string(),
-            TipoRedis::Str("segundo".to_string()),
-        );
-        data_base.guardar_valor(
-            "objeto_3".to_string(),
-            TipoRedis::Str("tercero".to_string()),
-        );
-        data_base.guardar_valor("objeto_4".to_string(), TipoRedis::Str("cuarto".to_string()));
-        data_base.guardar_valor("objeto_5".to_string(), TipoRedis::Str("quinto".to_string()));
+        data_base.guardar_valor("peso_1".to_string(), TipoRedis::Str("1".into()));
+        data_base.guardar_valor("peso_2".to_string(), TipoRedis::Str("2".into()));
+        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".into()));
+        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".into()));
+        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".into()));
+
+        data_base.guardar_valor("objeto_1".to_string(), TipoRedis::Str("primero".into()));
+        data_base.guardar_valor("objeto_2".to_string(), TipoRedis::Str("segundo".into()));
+        data_base.guardar_valor("objeto_3".to_string(), TipoRedis::Str("tercero".into()));
+        data_base.guardar_valor("objeto_4".to_string(), TipoRedis::Str("cuarto".into()));
+        data_base.guardar_valor("objeto_5".to_string(), TipoRedis::Str("quinto".into()));
 
         data_base.guardar_valor(
             "mylist".to_string(),
@@ -1248,11 +1823,11 @@ mod tests {
     #[test]
     fn sort_ordena_por_pesos_externos_los_elementos_los_guarda_con_la_clave_dada() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("peso_1".to_string(), TipoRedis::Str("1".to_string()));
-        data_base.guardar_valor("peso_2".to_string(), TipoRedis::Str("2".to_string()));
-        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".to_string()));
-        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".to_string()));
-        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".to_string()));
+        data_base.guardar_valor("peso_1".to_string(), TipoRedis::Str("1".into()));
+        data_base.guardar_valor("peso_2".to_string(), TipoRedis::Str("2".into()));
+        data_base.guardar_valor("peso_3".to_string(), TipoRedis::Str("3".into()));
+        data_base.guardar_valor("peso_4".to_string(), TipoRedis::Str("4".into()));
+        data_base.guardar_valor("peso_5".to_string(), TipoRedis::Str("5".into()));
 
         data_base.guardar_valor(
             "mylist".to_string(),
@@ -1289,4 +1864,131 @@ mod tests {
             ptr_clone.lock().unwrap().obtener_valor("ordenados")
         );
     }
+
+    #[test]
+    fn object_encoding_informa_la_representacion_y_su_cambio_al_superar_los_limites() {
+        let mut data_base = BaseDeDatos::new();
+        data_base.set_limites(LimitesDeCodificacion {
+            set_max_listpack_entradas: 2,
+            set_max_listpack_valor: 64,
+            set_max_intset_entradas: 2,
+        });
+        data_base.guardar_valor("largo".to_string(), TipoRedis::Str("x".repeat(40).into()));
+        data_base.guardar_valor("texto".to_string(), TipoRedis::Str("hola".into()));
+        data_base.guardar_valor("lista".to_string(), TipoRedis::Lista(vec!["a".to_string()]));
+        let set: Conjunto = vec!["a".to_string(), "b".to_string()].into_iter().collect();
+        data_base.guardar_valor("set".to_string(), TipoRedis::Set(set));
+        let ptr = Arc::new(Mutex::new(data_base));
+
+        let encoding = |clave: &str| {
+            let mut comando = ComandoInfo::new(vec![
+                "OBJECT".to_string(),
+                "ENCODING".to_string(),
+                clave.to_string(),
+            ]);
+            object(&mut comando, Arc::clone(&ptr))
+        };
+        assert_eq!(
+            ResultadoRedis::BulkStr("raw".to_string()),
+            encoding("largo")
+        );
+        assert_eq!(
+            ResultadoRedis::BulkStr("embstr".to_string()),
+            encoding("texto")
+        );
+        assert_eq!(
+            ResultadoRedis::BulkStr("listpack".to_string()),
+            encoding("set")
+        );
+        assert_eq!(
+            ResultadoRedis::BulkStr("quicklist".to_string()),
+            encoding("lista")
+        );
+        assert_eq!(ResultadoRedis::Nil, encoding("inexistente"));
+
+        let set: Conjunto = vec!["a".to_string(), "b".to_string(), "c".to_string()]
+            .into_iter()
+            .collect();
+        ptr.lock()
+            .unwrap()
+            .actualizar_valor("set".to_string(), TipoRedis::Set(set));
+        assert_eq!(
+            ResultadoRedis::BulkStr("hashtable".to_string()),
+            encoding("set")
+        );
+    }
+
+    fn ejecutar(tokens: &[&str], bdd: &Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+        let comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+        Box::new(ComandoKeyHandler::new(comando)).ejecutar(Arc::clone(bdd))
+    }
+
+    #[test]
+    fn restore_recrea_el_valor_obtenido_con_dump() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        bdd.lock().unwrap().guardar_valor(
+            "lista".to_string(),
+            TipoRedis::Lista(vec!["a".to_string(), "b:c".to_string()]),
+        );
+
+        let serializado = match ejecutar(&["dump", "lista"], &bdd) {
+            ResultadoRedis::BulkStr(s) => s,
+            otro => panic!("{:?}", otro),
+        };
+        assert_eq!(
+            ResultadoRedis::Error("BUSYKEY Target key name already exists.".to_string()),
+            ejecutar(&["restore", "lista", "0", &serializado], &bdd)
+        );
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            ejecutar(&["restore", "copia", "10000", &serializado], &bdd)
+        );
+
+        let b = bdd.lock().unwrap();
+        assert_eq!(b.obtener_valor("lista"), b.obtener_valor("copia"));
+        assert!(b.obtener_expiracion("copia") > 0);
+        drop(b);
+        assert_eq!(ResultadoRedis::Nil, ejecutar(&["dump", "no_existe"], &bdd));
+    }
+
+    #[test]
+    fn migrate_envia_restore_a_la_instancia_destino_y_elimina_la_clave() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        bdd.lock()
+            .unwrap()
+            .guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let puerto = listener.local_addr().unwrap().port().to_string();
+        let destino = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut parser = crate::parser::Parser::new(stream.try_clone().unwrap());
+            assert_eq!("ASKING", parser.siguiente_comando().unwrap().get_nombre());
+            let comando = parser.siguiente_comando().unwrap();
+            stream.write_all(b"+OK\r\n+OK\r\n").unwrap();
+            comando
+        });
+
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            ejecutar(
+                &["migrate", "127.0.0.1", &puerto, "clave", "0", "1000"],
+                &bdd
+            )
+        );
+
+        let comando = destino.join().unwrap();
+        assert_eq!("RESTORE", comando.get_nombre());
+        assert_eq!(
+            Some(TipoRedis::Str("valor".into())),
+            deserializar_valor(&comando.get(2).unwrap())
+        );
+        assert!(!bdd.lock().unwrap().existe_clave("clave"));
+        assert_eq!(
+            ResultadoRedis::StrSimple("NOKEY".to_string()),
+            ejecutar(
+                &["migrate", "127.0.0.1", &puerto, "clave", "0", "1000"],
+                &bdd
+            )
+        );
+    }
 }
@@ -63,13 +63,10 @@ pub fn lindex(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Result
         }
     };
     let lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => return ResultadoRedis::Nil,
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -102,14 +99,10 @@ pub fn llen(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
     };
 
     ResultadoRedis::Int(match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.len(),
-            None => 0,
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.len(),
+            Ok(None) => 0,
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     } as isize)
@@ -144,14 +137,10 @@ fn pop(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>, reversed: bool)
         None => 1,
     };
     let mut lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => return ResultadoRedis::Nil,
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => return ResultadoRedis::Nil,
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -169,7 +158,7 @@ fn pop(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>, reversed: bool)
     match bdd.lock() {
         Ok(mut bdd) => {
             if !lista.is_empty() {
-                bdd.guardar_valor(clave, TipoRedis::Lista(lista));
+                bdd.actualizar_valor(clave, TipoRedis::Lista(lista));
             } else {
                 bdd.eliminar_clave(&clave);
             }
@@ -204,7 +193,7 @@ fn push(
     }
     let long = lista.len();
     match bdd.lock() {
-        Ok(mut bdd) => bdd.guardar_valor(clave, TipoRedis::Lista(lista)),
+        Ok(mut bdd) => bdd.actualizar_valor(clave, TipoRedis::Lista(lista)),
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
     ResultadoRedis::Int(long as isize)
@@ -220,14 +209,10 @@ pub fn lpush(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resulta
         }
     };
     let lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => Vec::new(),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => Vec::new(),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -245,14 +230,10 @@ pub fn lpushx(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Result
         }
     };
     let lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => return ResultadoRedis::Int(0),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => return ResultadoRedis::Int(0),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -269,14 +250,10 @@ pub fn rpush(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resulta
         }
     };
     let lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => Vec::new(),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => Vec::new(),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -293,14 +270,10 @@ pub fn rpushx(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Result
         }
     };
     let lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => return ResultadoRedis::Int(0),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => return ResultadoRedis::Int(0),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -316,31 +289,16 @@ pub fn lrange(comando: &mut ComandoInfo, base_de_datos: Arc<Mutex<BaseDeDatos>>)
             )
         }
     };
-    let inicio: i32 = match comando.get_parametro() {
-        Some(p) => match p.parse() {
-            Ok(i) => i,
-            Err(_) => {
-                return ResultadoRedis::Error(
-                    "ERR value is not an integer or out of range".to_string(),
-                )
-            }
-        },
-        None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'lrange' command".to_string(),
-            )
-        }
-    };
-    let fin: i32 = match comando.get_parametro() {
-        Some(p) => match p.parse() {
-            Ok(i) => i,
-            Err(_) => {
+    let (inicio, fin): (i32, i32) = match (comando.get(1), comando.get(2)) {
+        (Some(inicio), Some(fin)) => match (inicio.parse(), fin.parse()) {
+            (Ok(inicio), Ok(fin)) => (inicio, fin),
+            _ => {
                 return ResultadoRedis::Error(
                     "ERR value is not an integer or out of range".to_string(),
                 )
             }
         },
-        None => {
+        _ => {
             return ResultadoRedis::Error(
                 "ERR wrong number of arguments for 'lrange' command".to_string(),
             )
@@ -348,14 +306,10 @@ pub fn lrange(comando: &mut ComandoInfo, base_de_datos: Arc<Mutex<BaseDeDatos>>)
     };
 
     let lista = match base_de_datos.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => return ResultadoRedis::Vector(vec![]),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => return ResultadoRedis::Vector(vec![]),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -458,14 +412,10 @@ pub fn lrem(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
     };
 
     let mut lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => return ResultadoRedis::Int(0),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => return ResultadoRedis::Int(0),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -492,7 +442,7 @@ pub fn lrem(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
     match bdd.lock() {
         Ok(mut bdd) => {
             if !lista_filtrada.is_empty() {
-                bdd.guardar_valor(clave, TipoRedis::Lista(lista_filtrada));
+                bdd.actualizar_valor(clave, TipoRedis::Lista(lista_filtrada));
             } else {
                 bdd.eliminar_clave(&clave);
             }
@@ -503,49 +453,27 @@ pub fn lrem(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
 }
 /// Setea el elemento de la posición index de la lista con el elemento suministrado. Se retorna error si se indica un rango inválido
 pub fn lset(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
-    let clave = match comando.get_clave() {
-        Some(c) => c,
-        None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'lset' command".to_string(),
-            )
-        }
-    };
-
-    let parametro = match comando.get_parametro() {
-        Some(p) => p,
-        None => {
+    let (clave, indice, parametro) = match (comando.get(0), comando.get(1), comando.get(2)) {
+        (Some(c), Some(i), Some(p)) => (c, i, p),
+        _ => {
             return ResultadoRedis::Error(
                 "ERR wrong number of arguments for 'lset' command".to_string(),
             )
         }
     };
 
-    let indice: i32 = match parametro.parse() {
+    let indice: i32 = match indice.parse() {
         Ok(v) => v,
         Err(_) => {
             return ResultadoRedis::Error("ERR value is not an integer or out of range".to_string())
         }
     };
 
-    let parametro = match comando.get_parametro() {
-        Some(p) => p,
-        None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'lset' command".to_string(),
-            )
-        }
-    };
-
     let mut lista = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Lista(lista)) => lista.clone(),
-            None => Vec::new(),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_lista(&clave) {
+            Ok(Some(lista)) => lista.clone(),
+            Ok(None) => Vec::new(),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -561,7 +489,7 @@ pub fn lset(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
     }
 
     match bdd.lock() {
-        Ok(mut bdd) => bdd.guardar_valor(clave, TipoRedis::Lista(lista)),
+        Ok(mut bdd) => bdd.actualizar_valor(clave, TipoRedis::Lista(lista)),
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
     ResultadoRedis::StrSimple("OK".to_string())
@@ -647,7 +575,7 @@ mod tests {
     #[test]
     fn llen_si_se_llama_llen_a_un_string_se_devuelve_un_error_de_tipo() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("milista".to_string(), TipoRedis::Str("hola".to_string()));
+        data_base.guardar_valor("milista".to_string(), TipoRedis::Str("hola".into()));
 
         let ptr = Arc::new(Mutex::new(data_base));
 
@@ -715,7 +643,7 @@ mod tests {
     fn lpop_si_se_llama_sobre_un_tipo_distinto_a_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec!["lpop".to_string(), "clave".to_string()]);
@@ -775,7 +703,7 @@ mod tests {
     fn lpush_si_se_pushea_a_alguna_clave_existente_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -878,7 +806,7 @@ mod tests {
     fn lpushx_si_se_pushea_a_alguna_clave_existente_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -956,7 +884,7 @@ mod tests {
     fn lrange_si_se_lo_llama_sobre_algo_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -1090,7 +1018,7 @@ mod tests {
     #[test]
     fn lrem_si_se_pide_eliminar_de_una_clave_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -1197,7 +1125,7 @@ mod tests {
     fn lset_si_se_lo_llama_sobre_algo_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -1275,7 +1203,7 @@ mod tests {
     fn rpop_si_se_llama_sobre_un_tipo_distinto_a_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec!["rpop".to_string(), "clave".to_string()]);
@@ -1335,7 +1263,7 @@ mod tests {
     fn rpush_si_se_pushea_a_alguna_clave_existente_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
@@ -1438,7 +1366,7 @@ mod tests {
     fn rpushx_si_se_pushea_a_alguna_clave_existente_que_no_es_una_lista_devuelve_wrong_type() {
         let mut data_base = BaseDeDatos::new();
 
-        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".to_string()));
+        data_base.guardar_valor("clave".to_string(), TipoRedis::Str("".into()));
         let ptr = Arc::new(Mutex::new(data_base));
 
         let mut comando = ComandoInfo::new(vec![
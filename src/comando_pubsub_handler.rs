@@ -1,13 +1,15 @@
-use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, TipoRedis};
-use crate::canal::Canal;
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
 use crate::cliente::Cliente;
 use crate::comando::ComandoHandler;
 use crate::comando_info::ComandoInfo;
+use crate::subcomando::Subcomandos;
 use std::sync::{Arc, Mutex};
 
 pub type ComandoConCliente =
     Box<dyn FnOnce(&mut ComandoInfo, Cliente, Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis + 'static>;
 
+type FuncionPubSub = fn(&mut ComandoInfo, Cliente, Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis;
+
 pub struct ComandoPubSubHandler {
     cliente: Cliente,
     comando: ComandoInfo,
@@ -40,35 +42,60 @@ pub fn es_comando_pubsub(comando: &str) -> bool {
     let comandos = vec!["SUBSCRIBE", "UNSUBSCRIBE", "PUBLISH", "PUBSUB"];
     comandos.iter().any(|&c| c == comando)
 }
-/// Suscribe al cliente al canal especificado
+/// Comandos que puede enviar un cliente mientras esta suscripto a algun canal
+const COMANDOS_DE_SUSCRIPTOR: [&str; 4] = ["SUBSCRIBE", "UNSUBSCRIBE", "PING", "QUIT"];
+
+/// Valida que el cliente pueda ejecutar el comando, mientras este suscripto a algun canal
+/// solo puede ejecutar comandos de suscripcion, PING o QUIT
+pub fn validar_modo_suscriptor(comando: &str, cliente: &Cliente) -> Result<(), ResultadoRedis> {
+    if cliente.suscripciones() == 0 || COMANDOS_DE_SUSCRIPTOR.contains(&comando) {
+        return Ok(());
+    }
+    Err(ResultadoRedis::Error(format!(
+        "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+        comando.to_lowercase()
+    )))
+}
+
+/// Respuesta que confirma una suscripcion o desuscripcion con la cantidad de canales
+/// a los que queda suscripto el cliente
+fn confirmacion(tipo: &str, canal: Option<String>, suscripciones: usize) -> ResultadoRedis {
+    ResultadoRedis::Vector(vec![
+        ResultadoRedis::BulkStr(tipo.to_string()),
+        match canal {
+            Some(c) => ResultadoRedis::BulkStr(c),
+            None => ResultadoRedis::Nil,
+        },
+        ResultadoRedis::Int(suscripciones as isize),
+    ])
+}
+
+/// Suscribe al cliente a los canales especificados
 fn subscribe(
     comando: &mut ComandoInfo,
     cliente: Cliente,
     bdd: Arc<Mutex<BaseDeDatos>>,
 ) -> ResultadoRedis {
+    let mut confirmaciones = Vec::new();
     while let Some(clave) = comando.get_parametro() {
-        let mut canal = match bdd.lock() {
-            Ok(bdd) => match bdd.obtener_valor(&clave) {
-                Some(TipoRedis::Canal(c)) => c.clone(),
-                None => Canal::new(clave.clone()),
-                _ => {
-                    return ResultadoRedis::Error(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value"
-                            .to_string(),
-                    )
-                }
-            },
+        let mut bdd = match bdd.lock() {
+            Ok(bdd) => bdd,
             Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
         };
-
-        canal.suscribirse(cliente.clone());
-
-        match bdd.lock() {
-            Ok(mut bdd) => bdd.guardar_valor(clave, TipoRedis::Canal(canal)),
-            Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+        match bdd.suscribir_a_canal(clave.clone(), &cliente) {
+            Ok(true) => {
+                cliente.agregar_suscripcion();
+            }
+            Ok(false) => (),
+            Err(e) => return e.a_resultado(),
         }
+        confirmaciones.push(confirmacion(
+            "subscribe",
+            Some(clave),
+            cliente.suscripciones(),
+        ));
     }
-    ResultadoRedis::Vacio
+    ResultadoRedis::Varios(confirmaciones)
 }
 /// Desuscribe al cliente de los canales indicados, si no se indica ninguno, lo desuscribe de todos
 fn unsubscribe(
@@ -76,28 +103,38 @@ fn unsubscribe(
     cliente: Cliente,
     bdd: Arc<Mutex<BaseDeDatos>>,
 ) -> ResultadoRedis {
-    while let Some(clave) = comando.get_parametro() {
-        let mut canal = match bdd.lock() {
-            Ok(bdd) => match bdd.obtener_valor(&clave) {
-                Some(TipoRedis::Canal(c)) => c.clone(),
-                _ => {
-                    return ResultadoRedis::Error(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value"
-                            .to_string(),
-                    )
-                }
-            },
-            Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
-        };
+    let mut bdd = match bdd.lock() {
+        Ok(bdd) => bdd,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
 
-        canal.desuscribirse(cliente.clone());
+    let mut confirmaciones = Vec::new();
+    if comando.is_empty() {
+        for clave in bdd.desuscribir_de_todos(&cliente) {
+            let suscripciones = cliente.quitar_suscripcion();
+            confirmaciones.push(confirmacion("unsubscribe", Some(clave), suscripciones));
+        }
+        if confirmaciones.is_empty() {
+            confirmaciones.push(confirmacion("unsubscribe", None, cliente.suscripciones()));
+        }
+        return ResultadoRedis::Varios(confirmaciones);
+    }
 
-        match bdd.lock() {
-            Ok(mut bdd) => bdd.guardar_valor(clave, TipoRedis::Canal(canal)),
-            Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    while let Some(clave) = comando.get_parametro() {
+        match bdd.desuscribir_de_canal(clave.clone(), &cliente) {
+            Ok(true) => {
+                cliente.quitar_suscripcion();
+            }
+            Ok(false) => (),
+            Err(e) => return e.a_resultado(),
         }
+        confirmaciones.push(confirmacion(
+            "unsubscribe",
+            Some(clave),
+            cliente.suscripciones(),
+        ));
     }
-    ResultadoRedis::Vacio
+    ResultadoRedis::Varios(confirmaciones)
 }
 /// Envía (publica) un mensaje en un canal dado
 fn publish(
@@ -120,13 +157,10 @@ fn publish(
     };
 
     let mut canal = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Canal(c)) => c.clone(),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_canal(&clave) {
+            Ok(Some(c)) => c.clone(),
+            Ok(None) => return ResultadoRedis::Int(0),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -135,24 +169,28 @@ fn publish(
 /// es un comando de introspección que permite inspeccionar el estado del subsistema Pub / Sub. Está compuesto por subcomandos que se documentan por separado
 fn pubsub(
     comando: &mut ComandoInfo,
-    _cliente: Cliente,
+    cliente: Cliente,
     bdd: Arc<Mutex<BaseDeDatos>>,
 ) -> ResultadoRedis {
-    let clave = match comando.get_clave() {
-        Some(c) => c,
-        _ => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'pubsub' command".to_string(),
-            )
-        }
-    };
+    let subcomandos = Subcomandos::<FuncionPubSub>::new("PUBSUB")
+        .agregar(
+            "CHANNELS",
+            -2,
+            "CHANNELS [<pattern>]",
+            "Return the currently active channels matching a <pattern> (default: '*').",
+            channels,
+        )
+        .agregar(
+            "NUMSUB",
+            -2,
+            "NUMSUB [<channel> ...]",
+            "Return the number of subscribers for the specified channels.",
+            numsub,
+        );
 
-    match clave.as_str() {
-        "CHANNELS" => channels(comando, _cliente, bdd),
-        "NUMSUB" => numsub(comando, _cliente, bdd),
-        _ => {
-            ResultadoRedis::Error("ERR wrong number of arguments for 'pubsub' command".to_string())
-        }
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, cliente, bdd),
+        Err(respuesta) => respuesta,
     }
 }
 /// Muestra los canales activos actualmente . Un canal activo es un canal Pub / Sub con uno o más suscriptores (sin incluir los clientes suscritos a patrones)
@@ -188,20 +226,115 @@ fn numsub(
 ) -> ResultadoRedis {
     let mut cantidades = Vec::new();
     while let Some(clave) = comando.get_parametro() {
-        let canal = match bdd.lock() {
-            Ok(bdd) => match bdd.obtener_valor(&clave) {
-                Some(TipoRedis::Canal(c)) => c.clone(),
-                _ => {
-                    return ResultadoRedis::Error(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value"
-                            .to_string(),
-                    )
-                }
+        let cantidad = match bdd.lock() {
+            Ok(bdd) => match bdd.obtener_como_canal(&clave) {
+                Ok(Some(c)) => c.len(),
+                Ok(None) => 0,
+                Err(e) => return e.a_resultado(),
             },
             Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
         };
 
-        cantidades.push(canal.len() as isize);
+        cantidades.push(cantidad as isize);
     }
     ResultadoRedis::Vector(cantidades.iter().map(|i| ResultadoRedis::Int(*i)).collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cliente::Token;
+    use crate::cliente_redis::ClienteRedis;
+    use std::net::{TcpListener, TcpStream};
+
+    fn cliente() -> Cliente {
+        cliente_con_token(1)
+    }
+
+    fn cliente_con_token(token: Token) -> Cliente {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        Box::new(ClienteRedis::new(token, 0, stream))
+    }
+
+    fn ejecutar(
+        tokens: &[&str],
+        cliente: &Cliente,
+        bdd: &Arc<Mutex<BaseDeDatos>>,
+    ) -> ResultadoRedis {
+        let comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+        Box::new(ComandoPubSubHandler::new(comando, cliente.clone())).ejecutar(Arc::clone(bdd))
+    }
+
+    #[test]
+    fn las_confirmaciones_informan_la_cantidad_de_suscripciones_del_cliente() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let cliente = cliente();
+
+        assert_eq!(
+            ResultadoRedis::Varios(vec![
+                confirmacion("subscribe", Some("a".to_string()), 1),
+                confirmacion("subscribe", Some("b".to_string()), 2),
+                confirmacion("subscribe", Some("a".to_string()), 2),
+            ]),
+            ejecutar(&["SUBSCRIBE", "a", "b", "a"], &cliente, &bdd)
+        );
+        assert_eq!(
+            ResultadoRedis::Varios(vec![confirmacion("unsubscribe", Some("c".to_string()), 2)]),
+            ejecutar(&["UNSUBSCRIBE", "c"], &cliente, &bdd)
+        );
+
+        let todos = ejecutar(&["UNSUBSCRIBE"], &cliente, &bdd);
+        let cantidades: Vec<&ResultadoRedis> = match &todos {
+            ResultadoRedis::Varios(v) => v
+                .iter()
+                .map(|c| match c {
+                    ResultadoRedis::Vector(partes) => &partes[2],
+                    _ => panic!("confirmacion invalida"),
+                })
+                .collect(),
+            _ => panic!("se esperaban varias confirmaciones"),
+        };
+        assert_eq!(
+            vec![&ResultadoRedis::Int(1), &ResultadoRedis::Int(0)],
+            cantidades
+        );
+        assert_eq!(0, cliente.suscripciones());
+    }
+
+    #[test]
+    fn un_cliente_suscripto_solo_puede_ejecutar_comandos_de_suscripcion() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let cliente = cliente();
+        assert_eq!(Ok(()), validar_modo_suscriptor("GET", &cliente));
+
+        ejecutar(&["SUBSCRIBE", "a"], &cliente, &bdd);
+
+        assert_eq!(Ok(()), validar_modo_suscriptor("PING", &cliente));
+        assert_eq!(
+            Err(ResultadoRedis::Error("ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context".to_string())),
+            validar_modo_suscriptor("GET", &cliente)
+        );
+    }
+
+    #[test]
+    fn al_desconectarse_el_cliente_solo_se_lo_quita_de_sus_canales() {
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let (uno, otro) = (cliente_con_token(1), cliente_con_token(2));
+        ejecutar(&["SUBSCRIBE", "a", "b", "c"], &uno, &bdd);
+        ejecutar(&["SUBSCRIBE", "b"], &otro, &bdd);
+        ejecutar(&["UNSUBSCRIBE", "c"], &uno, &bdd);
+
+        let mut bdd = bdd.lock().unwrap();
+        assert_eq!(
+            vec!["a".to_string(), "b".to_string()],
+            bdd.desuscribir_de_todos(&uno)
+        );
+        assert!(bdd.desuscribir_de_todos(&uno).is_empty());
+        assert_eq!(
+            Some(1),
+            bdd.obtener_como_canal("b").unwrap().map(|c| c.len())
+        );
+        assert_eq!(vec!["b".to_string()], bdd.desuscribir_de_todos(&otro));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
+use crate::comando::ComandoHandler;
+use crate::comando_info::ComandoInfo;
+use crate::registro_comandos::{DefinicionComando, RegistroDeComandos};
+use crate::subcomando::Subcomandos;
+use std::sync::{Arc, Mutex};
+
+type FuncionRegistro = fn(&mut ComandoInfo, &RegistroDeComandos) -> ResultadoRedis;
+
+/// Manejador del comando COMMAND y de los comandos registrados por quienes embeben el servidor
+pub struct ComandoRegistroHandler {
+    comando: ComandoInfo,
+    registro: Arc<RegistroDeComandos>,
+}
+
+impl ComandoRegistroHandler {
+    pub fn new(comando: ComandoInfo, registro: Arc<RegistroDeComandos>) -> Self {
+        ComandoRegistroHandler { comando, registro }
+    }
+}
+
+impl ComandoHandler for ComandoRegistroHandler {
+    fn ejecutar(mut self: Box<Self>, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+        let nombre = self.comando.get_nombre();
+        if nombre == "COMMAND" {
+            return command(&mut self.comando, &self.registro);
+        }
+
+        let definicion = match self.registro.obtener(&nombre) {
+            Some(d) => d,
+            None => {
+                return ResultadoRedis::Error(format!(
+                    "ComandoError '{}' no existe",
+                    self.comando.descripcion()
+                ))
+            }
+        };
+        if let Err(error) = definicion.validar_aridad(&self.comando) {
+            return error;
+        }
+
+        match definicion.funcion() {
+            Some(funcion) => funcion(&mut self.comando, bdd),
+            None => ResultadoRedis::Error(format!(
+                "ComandoError '{}' no existe",
+                self.comando.descripcion()
+            )),
+        }
+    }
+}
+
+/// Se encarga de detectar si el comando es COMMAND o fue registrado por un usuario del servidor
+pub fn es_comando_registrado(comando: &str, registro: &RegistroDeComandos) -> bool {
+    comando == "COMMAND"
+        || registro
+            .obtener(comando)
+            .is_some_and(|d| d.funcion().is_some())
+}
+
+/// Devuelve la descripcion de todos los comandos que entiende el servidor
+fn command(comando: &mut ComandoInfo, registro: &RegistroDeComandos) -> ResultadoRedis {
+    if comando.is_empty() {
+        return ResultadoRedis::Vector(registro.definiciones().map(describir).collect());
+    }
+
+    let subcomandos = Subcomandos::<FuncionRegistro>::new("COMMAND")
+        .agregar(
+            "COUNT",
+            2,
+            "COUNT",
+            "Return the total number of commands in this server.",
+            command_count,
+        )
+        .agregar(
+            "INFO",
+            -3,
+            "INFO <command-name> [<command-name> ...]",
+            "Return details about the given commands.",
+            command_info,
+        );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, registro),
+        Err(respuesta) => respuesta,
+    }
+}
+/// Devuelve la cantidad de comandos que entiende el servidor
+fn command_count(_comando: &mut ComandoInfo, registro: &RegistroDeComandos) -> ResultadoRedis {
+    ResultadoRedis::Int(registro.definiciones().count() as isize)
+}
+/// Devuelve la descripcion de los comandos pedidos o nil si no existen
+fn command_info(comando: &mut ComandoInfo, registro: &RegistroDeComandos) -> ResultadoRedis {
+    let mut descripciones = Vec::new();
+    while let Some(nombre) = comando.get_parametro() {
+        descripciones.push(match registro.obtener(&nombre) {
+            Some(definicion) => describir(definicion),
+            None => ResultadoRedis::Nil,
+        });
+    }
+    ResultadoRedis::Vector(descripciones)
+}
+
+fn describir(definicion: &DefinicionComando) -> ResultadoRedis {
+    let (primera, ultima, paso) = definicion.posicion_claves();
+    ResultadoRedis::Vector(vec![
+        ResultadoRedis::BulkStr(definicion.nombre().to_lowercase()),
+        ResultadoRedis::Int(definicion.aridad()),
+        ResultadoRedis::Vector(
+            definicion
+                .banderas()
+                .iter()
+                .map(|b| ResultadoRedis::StrSimple(b.to_string()))
+                .collect(),
+        ),
+        ResultadoRedis::Int(primera),
+        ResultadoRedis::Int(ultima),
+        ResultadoRedis::Int(paso as isize),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registro() -> Arc<RegistroDeComandos> {
+        let mut registro = RegistroDeComandos::new();
+        registro
+            .registrar(
+                "hola",
+                2,
+                &["readonly"],
+                Arc::new(|comando, _bdd| {
+                    ResultadoRedis::BulkStr(format!("hola {}", comando.get(0).unwrap()))
+                }),
+            )
+            .unwrap();
+        Arc::new(registro)
+    }
+
+    fn ejecutar(tokens: &[&str]) -> ResultadoRedis {
+        let comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+        Box::new(ComandoRegistroHandler::new(comando, registro()))
+            .ejecutar(Arc::new(Mutex::new(BaseDeDatos::new())))
+    }
+
+    #[test]
+    fn un_comando_registrado_se_ejecuta_validando_su_aridad() {
+        assert_eq!(
+            ResultadoRedis::BulkStr("hola mundo".to_string()),
+            ejecutar(&["HOLA", "mundo"])
+        );
+        assert_eq!(
+            ResultadoRedis::Error("ERR wrong number of arguments for 'hola' command".to_string()),
+            ejecutar(&["HOLA"])
+        );
+    }
+
+    #[test]
+    fn command_info_describe_comandos_propios_y_registrados() {
+        assert_eq!(
+            ResultadoRedis::Vector(vec![
+                ResultadoRedis::Vector(vec![
+                    ResultadoRedis::BulkStr("hola".to_string()),
+                    ResultadoRedis::Int(2),
+                    ResultadoRedis::Vector(vec![ResultadoRedis::StrSimple("readonly".to_string())]),
+                    ResultadoRedis::Int(0),
+                    ResultadoRedis::Int(0),
+                    ResultadoRedis::Int(0),
+                ]),
+                ResultadoRedis::Vector(vec![
+                    ResultadoRedis::BulkStr("get".to_string()),
+                    ResultadoRedis::Int(-2),
+                    ResultadoRedis::Vector(vec![ResultadoRedis::StrSimple("readonly".to_string())]),
+                    ResultadoRedis::Int(1),
+                    ResultadoRedis::Int(1),
+                    ResultadoRedis::Int(1),
+                ]),
+                ResultadoRedis::Nil,
+            ]),
+            ejecutar(&["COMMAND", "INFO", "hola", "get", "inexistente"])
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, Rol};
+use crate::cliente::Cliente;
+use crate::comando::ComandoHandler;
+use crate::comando_info::ComandoInfo;
+use crate::config::Config;
+use crate::persistencia::serializar_tabla;
+use crate::replicacion::{codificar_comando, Replicacion, TOKEN_MAESTRO};
+use crate::temporizador::{Despertar, Espera, RuedaDeTemporizadores};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type FuncionReplicacion = fn(
+    &mut ComandoInfo,
+    Cliente,
+    Arc<Mutex<BaseDeDatos>>,
+    Arc<Replicacion>,
+    Arc<RuedaDeTemporizadores>,
+) -> ResultadoRedis;
+
+/// Manejador de los comandos que usan las replicas para sincronizarse con este servidor
+pub struct ComandoReplicacionHandler {
+    comando: ComandoInfo,
+    cliente: Cliente,
+    config: Arc<Mutex<Config>>,
+    a_ejecutar: FuncionReplicacion,
+}
+
+impl ComandoReplicacionHandler {
+    pub fn new(comando: ComandoInfo, cliente: Cliente, config: Arc<Mutex<Config>>) -> Self {
+        let a_ejecutar = match comando.get_nombre().as_str() {
+            "REPLCONF" => replconf,
+            "WAIT" => wait,
+            _ => psync,
+        };
+        ComandoReplicacionHandler {
+            comando,
+            cliente,
+            config,
+            a_ejecutar,
+        }
+    }
+}
+
+impl ComandoHandler for ComandoReplicacionHandler {
+    fn ejecutar(mut self: Box<Self>, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
+        let (replicacion, temporizadores) = match self.config.lock() {
+            Ok(c) => (c.replicacion(), c.temporizadores()),
+            Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+        };
+        (self.a_ejecutar)(
+            &mut self.comando,
+            self.cliente,
+            bdd,
+            replicacion,
+            temporizadores,
+        )
+    }
+}
+
+/// Se encarga de detectar si el comando corresponde a los de replicacion
+pub fn es_comando_replicacion(comando: &str) -> bool {
+    let comandos = ["SYNC", "PSYNC", "REPLCONF", "WAIT"];
+    comandos.contains(&comando)
+}
+
+/// Sincroniza a la replica enviandole la tabla completa, a partir de entonces recibe
+/// todas las escrituras por esta conexion. Como no se guarda un historial de escrituras
+/// la sincronizacion es siempre completa, sin importar el offset que pida la replica
+fn psync(
+    _comando: &mut ComandoInfo,
+    cliente: Cliente,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    replicacion: Arc<Replicacion>,
+    _temporizadores: Arc<RuedaDeTemporizadores>,
+) -> ResultadoRedis {
+    replicacion.sincronizar_replica(cliente, || match bdd.lock() {
+        Ok(bdd) => Some(serializar_tabla(bdd.tabla(), bdd.ahora())),
+        Err(_) => None,
+    })
+}
+
+/// Configuracion que envia la replica antes de sincronizarse y confirmaciones de offset.
+/// ACK lo envia la replica al maestro y no tiene respuesta, GETACK lo envia el maestro
+/// a la replica, que responde con su offset
+fn replconf(
+    comando: &mut ComandoInfo,
+    mut cliente: Cliente,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    replicacion: Arc<Replicacion>,
+    _temporizadores: Arc<RuedaDeTemporizadores>,
+) -> ResultadoRedis {
+    let opcion = comando.get_parametro().unwrap_or_default().to_lowercase();
+    let valor = comando.get_parametro();
+    match (opcion.as_str(), valor) {
+        ("listening-port", Some(puerto)) => {
+            replicacion.registrar_puerto(cliente.obtener_token(), puerto);
+            ResultadoRedis::StrSimple("OK".to_string())
+        }
+        ("capa", Some(_)) => ResultadoRedis::StrSimple("OK".to_string()),
+        ("ack", Some(offset)) => {
+            if let Ok(offset) = offset.parse() {
+                replicacion.confirmar(cliente.obtener_token(), offset);
+            }
+            ResultadoRedis::Varios(Vec::new())
+        }
+        ("getack", Some(_)) if cliente.obtener_token() == TOKEN_MAESTRO => {
+            cliente
+                .enviar_mensaje(confirmacion(replicacion.offset()))
+                .ok();
+            ResultadoRedis::Varios(Vec::new())
+        }
+        _ => ResultadoRedis::Error("ERR Unrecognized REPLCONF option".to_string()),
+    }
+}
+
+/// Comando con el que la replica confirma al maestro el offset que aplico
+pub fn confirmacion(offset: u64) -> String {
+    codificar_comando(&[
+        "REPLCONF".to_string(),
+        "ACK".to_string(),
+        offset.to_string(),
+    ])
+}
+
+/// Bloquea al cliente hasta que al menos numreplicas replicas confirmen todas las escrituras
+/// propagadas hasta el momento, o hasta que pasen timeout milisegundos (0 espera sin limite).
+/// La espera se revisa con cada confirmacion y la vence la rueda de temporizadores.
+/// Devuelve la cantidad de replicas que las confirmaron
+fn wait(
+    comando: &mut ComandoInfo,
+    _cliente: Cliente,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    replicacion: Arc<Replicacion>,
+    temporizadores: Arc<RuedaDeTemporizadores>,
+) -> ResultadoRedis {
+    let (cantidad, timeout): (usize, u64) = match (
+        comando.get(0).map(|c| c.parse()),
+        comando.get(1).map(|t| t.parse()),
+    ) {
+        (Some(Ok(c)), Some(Ok(t))) => (c, t),
+        _ => {
+            return ResultadoRedis::Error("ERR value is not an integer or out of range".to_string())
+        }
+    };
+    match bdd.lock() {
+        Ok(b) if b.rol() == Rol::Replica => {
+            return ResultadoRedis::Error(
+                "ERR WAIT cannot be used with replica instances.".to_string(),
+            )
+        }
+        Ok(_) => (),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+
+    let objetivo = replicacion.offset();
+    let espera = Arc::new(Espera::new());
+    replicacion.avisar_confirmaciones(&espera);
+    if timeout > 0 {
+        temporizadores.programar(&espera, Duration::from_millis(timeout));
+    }
+    let mut confirmaciones_pedidas = false;
+    loop {
+        let al_dia = replicacion.replicas_al_dia(objetivo);
+        if al_dia >= cantidad {
+            return ResultadoRedis::Int(al_dia as isize);
+        }
+        if !confirmaciones_pedidas {
+            replicacion.pedir_confirmaciones();
+            confirmaciones_pedidas = true;
+        }
+        if espera.esperar() == Despertar::Vencido {
+            return ResultadoRedis::Int(replicacion.replicas_al_dia(objetivo) as isize);
+        }
+    }
+}
@@ -1,14 +1,28 @@
-use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
+use crate::aof::Aof;
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, Rol};
+use crate::cluster::{slot_de_clave, CANTIDAD_SLOTS};
 use crate::comando::ComandoHandler;
 use crate::comando_info::ComandoInfo;
 use crate::config::Config;
+use crate::opciones_parser::OpcionesParser;
+use crate::perfilador::PERFILADOR;
+use crate::persistencia::{cargar_tabla, volcar_tabla};
+use crate::subcomando::Subcomandos;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Cantidad de claves que devuelve HOTKEYS si no se indica COUNT
+const HOTKEYS_COUNT: i64 = 10;
 
 pub type ComandoConConfig = Box<
     dyn FnOnce(&mut ComandoInfo, Arc<Mutex<BaseDeDatos>>, Arc<Mutex<Config>>) -> ResultadoRedis
         + 'static,
 >;
 
+type FuncionServer =
+    fn(&mut ComandoInfo, Arc<Mutex<BaseDeDatos>>, Arc<Mutex<Config>>) -> ResultadoRedis;
+
 pub struct ComandoServerHandler {
     comando: ComandoInfo,
     config: Arc<Mutex<Config>>,
@@ -18,11 +32,22 @@ pub struct ComandoServerHandler {
 impl ComandoServerHandler {
     pub fn new(comando: ComandoInfo, config: Arc<Mutex<Config>>) -> Self {
         let a_ejecutar = match comando.get_nombre().as_str() {
+            "BGREWRITEAOF" => bgrewriteaof,
+            "BGSAVE" => bgsave,
             "DBSIZE" => dbsize,
+            "CLUSTER" => cluster,
             "CONFIG" => fconfig,
+            "DEBUG" => debug,
+            "ECHO" => echo,
+            "HOTKEYS" => hotkeys,
             "INFO" => info,
+            "LATENCY" => latency,
+            "MEMORY" => memory,
             "MONITOR" => monitor,
             "PING" => ping,
+            "QUIT" => quit,
+            "REPLICAOF" | "SLAVEOF" => replicaof,
+            "SAVE" => save,
             _ => flushdb,
         };
         ComandoServerHandler {
@@ -40,7 +65,26 @@ impl ComandoHandler for ComandoServerHandler {
 }
 /// Se encarga de detectar si el comando corresponde a los implementados del tipo server
 pub fn es_comando_server(comando: &str) -> bool {
-    let comandos = vec!["FLUSHDB", "DBSIZE", "CONFIG", "INFO", "MONITOR", "PING"];
+    let comandos = vec![
+        "FLUSHDB",
+        "BGREWRITEAOF",
+        "BGSAVE",
+        "DBSIZE",
+        "CLUSTER",
+        "CONFIG",
+        "DEBUG",
+        "ECHO",
+        "HOTKEYS",
+        "INFO",
+        "LATENCY",
+        "MEMORY",
+        "MONITOR",
+        "PING",
+        "QUIT",
+        "REPLICAOF",
+        "SAVE",
+        "SLAVEOF",
+    ];
     comandos.iter().any(|&c| c == comando)
 }
 
@@ -51,6 +95,57 @@ fn ping(
 ) -> ResultadoRedis {
     ResultadoRedis::StrSimple("PONG".to_string())
 }
+/// Devuelve el mensaje recibido. El modo pipe lo usa para saber cuando recibio todas las respuestas
+fn echo(
+    comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    match comando.get_parametro() {
+        Some(mensaje) => ResultadoRedis::BulkStr(mensaje),
+        None => {
+            ResultadoRedis::Error("ERR wrong number of arguments for 'echo' command".to_string())
+        }
+    }
+}
+/// Le pide al servidor que cierre la conexion. La conexion se cierra despues de enviar la respuesta
+fn quit(
+    _comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// Devuelve las claves mas accedidas junto con la cantidad aproximada de accesos,
+/// de la mas accedida a la menos accedida. Permite detectar cargas concentradas en pocas claves
+fn hotkeys(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let opciones = match OpcionesParser::new()
+        .con_entero("COUNT")
+        .parsear(comando.tokens())
+    {
+        Ok(o) => o,
+        Err(e) => return e.a_resultado(),
+    };
+    let cantidad = match opciones.entero("COUNT").unwrap_or(HOTKEYS_COUNT) {
+        c if c > 0 => c as usize,
+        _ => return ResultadoRedis::Error("ERR syntax error".to_string()),
+    };
+
+    let estadisticas = match bdd.lock() {
+        Ok(b) => b.estadisticas(),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    let mut respuesta = Vec::new();
+    for (clave, accesos) in estadisticas.claves_calientes(cantidad) {
+        respuesta.push(ResultadoRedis::BulkStr(clave));
+        respuesta.push(ResultadoRedis::Int(accesos as isize));
+    }
+    ResultadoRedis::Vector(respuesta)
+}
 /// Borra todas las claves de la base de datos. Este comando nunca falla
 fn flushdb(
     _comando: &mut ComandoInfo,
@@ -63,6 +158,94 @@ fn flushdb(
     };
     ResultadoRedis::StrSimple("OK".to_string())
 }
+/// Error de los comandos que escriben el archivo de persistencia cuando el servidor
+/// se inicio con persistence no y solo guarda los datos en memoria
+fn persistencia_deshabilitada() -> ResultadoRedis {
+    ResultadoRedis::Error(
+        "ERR persistence is disabled (persistence no), the dataset is only kept in memory"
+            .to_string(),
+    )
+}
+/// Devuelve el archivo de persistencia, o el error a responder si la persistencia esta deshabilitada
+fn archivo_de_persistencia(config: &Mutex<Config>) -> Result<String, ResultadoRedis> {
+    match config.lock() {
+        Ok(c) if c.persistidor().is_some() => Ok(c.dbfilename()),
+        Ok(_) => Err(persistencia_deshabilitada()),
+        Err(_) => Err(ResultadoRedis::Error(
+            "ERR when accessing config".to_string(),
+        )),
+    }
+}
+/// El comando SAVE persiste sincronicamente la base de datos en el archivo de persistencia
+fn save(
+    _comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let archivo = match archivo_de_persistencia(&config) {
+        Ok(a) => a,
+        Err(error) => return error,
+    };
+    match bdd.lock() {
+        Ok(b) => {
+            if volcar_tabla(&archivo, b.tabla(), b.ahora()).is_err() {
+                return ResultadoRedis::Error("ERR Error trying to save the DB".to_string());
+            }
+        }
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// El comando BGSAVE persiste la base de datos en segundo plano, en el hilo de persistencia
+fn bgsave(
+    _comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let persistidor = match config.lock() {
+        Ok(c) => match c.persistidor() {
+            Some(p) => p,
+            None => return persistencia_deshabilitada(),
+        },
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    match bdd.lock() {
+        Ok(b) => persistidor.forzar(b.instantanea(), b.ahora()),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("Background saving started".to_string())
+}
+/// El comando BGREWRITEAOF compacta el AOF en segundo plano, reemplazandolo por la tabla actual
+/// seguida de los comandos que lleguen mientras se escribe
+fn bgrewriteaof(
+    _comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let aof = match config.lock() {
+        Ok(c) => match c.aof() {
+            Some(aof) => aof,
+            None => {
+                return ResultadoRedis::Error(
+                    "ERR the append only file is disabled (appendonly no)".to_string(),
+                )
+            }
+        },
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    let iniciada = match bdd.lock() {
+        Ok(b) => Aof::reescribir_en_segundo_plano(&aof, b.instantanea(), b.ahora()),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    match iniciada {
+        true => {
+            ResultadoRedis::StrSimple("Background append only file rewriting started".to_string())
+        }
+        false => ResultadoRedis::Error(
+            "ERR Background append only file rewriting already in progress".to_string(),
+        ),
+    }
+}
 /// Retorna el numero de claves en la base de datos
 fn dbsize(
     _comando: &mut ComandoInfo,
@@ -75,26 +258,411 @@ fn dbsize(
     };
     ResultadoRedis::Int(cantidad as isize)
 }
-/// Determina si el comando solicidado es config_set o config_get
-fn fconfig(
+/// Comandos de descubrimiento del cluster
+fn cluster(
     comando: &mut ComandoInfo,
     bdd: Arc<Mutex<BaseDeDatos>>,
     config: Arc<Mutex<Config>>,
 ) -> ResultadoRedis {
-    let parametro = match comando.get_parametro() {
-        Some(p) => p,
-        None => {
+    match config.lock() {
+        Ok(c) if !c.cluster().habilitado() => {
             return ResultadoRedis::Error(
-                "ERR wrong number of arguments for fconfig command".to_string(),
+                "ERR This instance has cluster support disabled".to_string(),
             )
         }
+        Ok(_) => (),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+
+    let subcomandos = Subcomandos::<FuncionServer>::new("CLUSTER")
+        .agregar(
+            "INFO",
+            2,
+            "INFO",
+            "Return information about the cluster.",
+            cluster_info,
+        )
+        .agregar("MYID", 2, "MYID", "Return the node id.", cluster_myid)
+        .agregar(
+            "SLOTS",
+            2,
+            "SLOTS",
+            "Return information about slots range mappings.",
+            cluster_slots,
+        )
+        .agregar(
+            "KEYSLOT",
+            3,
+            "KEYSLOT <key>",
+            "Return the hash slot for <key>.",
+            cluster_keyslot,
+        )
+        .agregar(
+            "COUNTKEYSINSLOT",
+            3,
+            "COUNTKEYSINSLOT <slot>",
+            "Return the number of keys in <slot>.",
+            cluster_countkeysinslot,
+        )
+        .agregar(
+            "GETKEYSINSLOT",
+            4,
+            "GETKEYSINSLOT <slot> <count>",
+            "Return key names stored by current node in a slot.",
+            cluster_getkeysinslot,
+        )
+        .agregar(
+            "SETSLOT",
+            -4,
+            "SETSLOT <slot> (IMPORTING <node-id>|MIGRATING <node-id>|STABLE|NODE <node-id>)",
+            "Set slot state.",
+            cluster_setslot,
+        );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, bdd, config),
+        Err(respuesta) => respuesta,
+    }
+}
+/// Devuelve el estado del cluster y la cantidad de slots asignados y nodos conocidos
+fn cluster_info(
+    _comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    match config.lock() {
+        Ok(c) => ResultadoRedis::BulkStr(c.cluster().info().join("\r\n") + "\r\n"),
+        Err(_) => ResultadoRedis::Error("ERR when accessing config".to_string()),
+    }
+}
+/// Devuelve el identificador de este nodo
+fn cluster_myid(
+    _comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    match config.lock() {
+        Ok(c) => ResultadoRedis::BulkStr(c.cluster().mi_id().to_string()),
+        Err(_) => ResultadoRedis::Error("ERR when accessing config".to_string()),
+    }
+}
+/// Devuelve cada rango de slots con la direccion y el identificador del nodo que es su duenio
+fn cluster_slots(
+    _comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let c = match config.lock() {
+        Ok(c) => c,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    ResultadoRedis::Vector(
+        c.cluster()
+            .slots()
+            .into_iter()
+            .map(|(inicio, fin, nodo)| {
+                ResultadoRedis::Vector(vec![
+                    ResultadoRedis::Int(inicio as isize),
+                    ResultadoRedis::Int(fin as isize),
+                    ResultadoRedis::Vector(vec![
+                        ResultadoRedis::BulkStr(nodo.host.clone()),
+                        ResultadoRedis::Int(nodo.puerto as isize),
+                        ResultadoRedis::BulkStr(nodo.id.clone()),
+                    ]),
+                ])
+            })
+            .collect(),
+    )
+}
+/// Devuelve el slot al que pertenece la clave
+fn cluster_keyslot(
+    comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    match comando.get_parametro() {
+        Some(clave) => ResultadoRedis::Int(slot_de_clave(&clave) as isize),
+        None => ResultadoRedis::Error(
+            "ERR wrong number of arguments for 'cluster|keyslot' command".to_string(),
+        ),
+    }
+}
+
+fn parsear_slot(slot: Option<String>) -> Result<u16, ResultadoRedis> {
+    match slot.and_then(|s| s.parse::<u16>().ok()) {
+        Some(slot) if (slot as usize) < CANTIDAD_SLOTS => Ok(slot),
+        _ => Err(ResultadoRedis::Error(
+            "ERR Invalid or out of range slot".to_string(),
+        )),
+    }
+}
+
+/// Claves almacenadas en este nodo que pertenecen al slot
+fn claves_en_slot(bdd: &BaseDeDatos, slot: u16) -> Vec<String> {
+    let ahora = bdd.ahora();
+    bdd.tabla()
+        .iter()
+        .filter(|(clave, valor)| !valor.esta_expirado_al(ahora) && slot_de_clave(clave) == slot)
+        .map(|(clave, _)| clave.to_string())
+        .collect()
+}
+
+/// Devuelve la cantidad de claves de este nodo que pertenecen al slot
+fn cluster_countkeysinslot(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let slot = match parsear_slot(comando.get_parametro()) {
+        Ok(slot) => slot,
+        Err(error) => return error,
+    };
+    match bdd.lock() {
+        Ok(bdd) => ResultadoRedis::Int(claves_en_slot(&bdd, slot).len() as isize),
+        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    }
+}
+
+/// Devuelve hasta count claves de este nodo que pertenecen al slot, para moverlas con MIGRATE
+fn cluster_getkeysinslot(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let slot = match parsear_slot(comando.get_parametro()) {
+        Ok(slot) => slot,
+        Err(error) => return error,
+    };
+    let cantidad: usize = match comando.get_parametro().map(|c| c.parse()) {
+        Some(Ok(c)) => c,
+        _ => return ResultadoRedis::Error("ERR Invalid number of keys".to_string()),
+    };
+    match bdd.lock() {
+        Ok(bdd) => {
+            let mut claves = claves_en_slot(&bdd, slot);
+            claves.sort();
+            claves.truncate(cantidad);
+            ResultadoRedis::Vector(claves.into_iter().map(ResultadoRedis::BulkStr).collect())
+        }
+        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    }
+}
+
+/// Cambia el estado de migracion de un slot o lo asigna a un nodo
+fn cluster_setslot(
+    comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let slot = match parsear_slot(comando.get_parametro()) {
+        Ok(slot) => slot,
+        Err(error) => return error,
+    };
+    let accion = comando.get_parametro().unwrap_or_default().to_uppercase();
+    let nodo = comando.get_parametro();
+
+    let mut c = match config.lock() {
+        Ok(c) => c,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    let cluster = c.cluster_mut();
+    let resultado = match (accion.as_str(), nodo) {
+        ("MIGRATING", Some(nodo)) => cluster.migrar_slot(slot, &nodo),
+        ("IMPORTING", Some(nodo)) => cluster.importar_slot(slot, &nodo),
+        ("NODE", Some(nodo)) => cluster.asignar_slot(slot, &nodo),
+        ("STABLE", None) => {
+            cluster.estabilizar_slot(slot);
+            Ok(())
+        }
+        _ => Err("ERR syntax error".to_string()),
+    };
+    match resultado {
+        Ok(()) => ResultadoRedis::StrSimple("OK".to_string()),
+        Err(error) => ResultadoRedis::Error(error),
+    }
+}
+/// Determina si el comando solicidado es config_set o config_get
+fn fconfig(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let subcomandos = Subcomandos::<FuncionServer>::new("CONFIG")
+        .agregar(
+            "GET",
+            3,
+            "GET <parameter>",
+            "Return the value of the configuration parameter.",
+            config_get,
+        )
+        .agregar(
+            "SET",
+            4,
+            "SET <parameter> <value>",
+            "Set the configuration parameter to the given value.",
+            config_set,
+        );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, bdd, config),
+        Err(respuesta) => respuesta,
+    }
+}
+/// Determina cual de los subcomandos de depuracion se solicito
+fn debug(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let subcomandos = Subcomandos::<FuncionServer>::new("DEBUG")
+        .agregar(
+            "RELOAD",
+            2,
+            "RELOAD",
+            "Save the dataset to disk and reload it back to memory.",
+            debug_reload,
+        )
+        .agregar(
+            "FLUSHALL",
+            2,
+            "FLUSHALL",
+            "Remove all keys without modifying the dump file.",
+            debug_flushall,
+        )
+        .agregar(
+            "POPULATE",
+            -3,
+            "POPULATE <count> [<prefix>] [<size>]",
+            "Create <count> string keys named key:<num>, or <prefix>:<num>. If <size> is specified the value is padded or truncated to that size.",
+            debug_populate,
+        )
+        .agregar(
+            "PROFILE",
+            3,
+            "PROFILE <seconds>",
+            "Sample for <seconds> where the server spends its time: parsing, lock-wait, execution, reply-write and persistence.",
+            debug_profile,
+        );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, bdd, config),
+        Err(respuesta) => respuesta,
+    }
+}
+/// DEBUG RELOAD persiste sincronicamente la base de datos y la vuelve a cargar desde el archivo,
+/// permitiendo detectar errores de serializacion. Si el archivo no se puede volver a cargar se
+/// responde un error y se conservan los datos en memoria
+fn debug_reload(
+    _comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let archivo = match archivo_de_persistencia(&config) {
+        Ok(a) => a,
+        Err(error) => return error,
     };
 
-    match parametro.to_uppercase().as_str() {
-        "GET" => config_get(comando, bdd, config),
-        "SET" => config_set(comando, bdd, config),
-        _ => ResultadoRedis::Error("ERR Opcion config not found".to_string()),
+    match bdd.lock() {
+        Ok(mut b) => {
+            let ahora = b.ahora();
+            if volcar_tabla(&archivo, b.tabla(), ahora).is_err() {
+                return ResultadoRedis::Error("ERR Error trying to save the DB".to_string());
+            }
+            match cargar_tabla(&archivo, ahora) {
+                Ok(tabla) => b.reemplazar_tabla(tabla),
+                Err(_) => {
+                    return ResultadoRedis::Error(
+                        "ERR Error trying to load the RDB dump".to_string(),
+                    )
+                }
+            }
+        }
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// DEBUG FLUSHALL borra todas las claves de la base de datos sin modificar el archivo de persistencia
+fn debug_flushall(
+    _comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    match bdd.lock() {
+        Ok(mut b) => b.reemplazar_tabla(HashMap::new()),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// DEBUG POPULATE genera claves de tipo string directamente en la tabla, sin pasar por el parser,
+/// para armar rapidamente conjuntos de datos de prueba. No reemplaza las claves que ya existen
+fn debug_populate(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    if comando.len() > 4 {
+        return ResultadoRedis::Error(
+            "ERR wrong number of arguments for 'debug|populate' command".to_string(),
+        );
     }
+    let cantidad: usize = match comando.get_parametro().map(|c| c.parse()) {
+        Some(Ok(c)) => c,
+        _ => return ResultadoRedis::Error("ERR count must be a positive integer".to_string()),
+    };
+    let prefijo = comando.get_parametro().unwrap_or_else(|| "key".to_string());
+    let tamanio = match comando.get_parametro().map(|t| t.parse::<usize>()) {
+        Some(Ok(t)) => Some(t),
+        Some(Err(_)) => {
+            return ResultadoRedis::Error("ERR size must be a positive integer".to_string())
+        }
+        None => None,
+    };
+    match bdd.lock() {
+        Ok(mut b) => b.poblar(cantidad, &prefijo, tamanio),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// DEBUG PROFILE mide durante los segundos indicados cuanto tiempo pasa el servidor en cada etapa
+/// de atender los comandos. Bloquea solo a la conexion que lo pide, que no cuenta en lo medido
+fn debug_profile(
+    comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let segundos: u64 = match comando.get_parametro().map(|s| s.parse()) {
+        Some(Ok(s)) if s > 0 => s,
+        _ => return ResultadoRedis::Error("ERR seconds must be a positive integer".to_string()),
+    };
+    let informe = match PERFILADOR.perfilar(Duration::from_secs(segundos)) {
+        Some(i) => i,
+        None => return ResultadoRedis::Error("ERR a profile is already running".to_string()),
+    };
+    let total: Duration = informe.iter().map(|e| e.total).sum();
+    ResultadoRedis::Vector(
+        informe
+            .into_iter()
+            .flat_map(|etapa| {
+                let porcentaje = match total.is_zero() {
+                    true => 0.0,
+                    false => etapa.total.as_secs_f64() * 100.0 / total.as_secs_f64(),
+                };
+                vec![
+                    ResultadoRedis::BulkStr(etapa.etapa.to_string()),
+                    ResultadoRedis::Vector(vec![
+                        ResultadoRedis::BulkStr("total_usec".to_string()),
+                        ResultadoRedis::Int(etapa.total.as_micros() as isize),
+                        ResultadoRedis::BulkStr("samples".to_string()),
+                        ResultadoRedis::Int(etapa.muestras as isize),
+                        ResultadoRedis::BulkStr("max_usec".to_string()),
+                        ResultadoRedis::Int(etapa.maximo.as_micros() as isize),
+                        ResultadoRedis::BulkStr("percentage".to_string()),
+                        ResultadoRedis::BulkStr(format!("{:.2}", porcentaje)),
+                    ]),
+                ]
+            })
+            .collect(),
+    )
 }
 /// El comando CONFIG GET se utiliza para leer los parámetros de configuración de un servidor en ejecución
 fn config_get(
@@ -126,7 +694,7 @@ fn config_get(
 /// El comando CONFIG SET se utiliza para reconfigurar un servidor en tiempo de ejecución sin necesidad de reiniciarlo
 fn config_set(
     comando: &mut ComandoInfo,
-    _bdd: Arc<Mutex<BaseDeDatos>>,
+    bdd: Arc<Mutex<BaseDeDatos>>,
     config: Arc<Mutex<Config>>,
 ) -> ResultadoRedis {
     let (parametro, valor) = match (comando.get_parametro(), comando.get_parametro()) {
@@ -138,27 +706,268 @@ fn config_set(
         }
     };
 
-    match config.lock() {
-        Ok(mut c) => c.set(parametro, valor),
+    let (limites, gracia) = match config.lock() {
+        Ok(mut c) => {
+            c.set(parametro, valor);
+            (c.limites_de_codificacion(), c.gracia_de_expiracion())
+        }
         Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
     };
+    match bdd.lock() {
+        Ok(mut b) => {
+            b.set_limites(limites);
+            b.set_gracia_de_expiracion(gracia);
+        }
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
 
     ResultadoRedis::StrSimple("Ok".to_string())
 }
-/// El comando INFO retorna información y estadísticas sobre el servidor en un formato fácil de parsear por computadores y fácil de leer por humanos
-fn info(
+/// Convierte al servidor en replica del maestro indicado, o en maestro con REPLICAOF NO ONE.
+/// Solo cambia la configuracion y el rol, la sincronizacion con el nuevo maestro y los ajustes
+/// de persistencia los hace el hilo que supervisa el rol
+fn replicaof(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let (host, puerto) = match (comando.get_parametro(), comando.get_parametro()) {
+        (Some(h), Some(p)) => (h, p),
+        _ => return ResultadoRedis::Error("ERR syntax error".to_string()),
+    };
+    let nuevo_maestro = if host.eq_ignore_ascii_case("no") && puerto.eq_ignore_ascii_case("one") {
+        None
+    } else if puerto.parse::<u16>().is_ok() {
+        Some(format!("{}:{}", host, puerto))
+    } else {
+        return ResultadoRedis::Error("ERR Invalid master port".to_string());
+    };
+
+    let mut c = match config.lock() {
+        Ok(c) => c,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    if c.cluster().habilitado() {
+        return ResultadoRedis::Error("ERR REPLICAOF not allowed in cluster mode.".to_string());
+    }
+    if nuevo_maestro.is_some() && c.maestro() == nuevo_maestro {
+        return ResultadoRedis::StrSimple("OK Already connected to specified master".to_string());
+    }
+    let rol = match nuevo_maestro {
+        Some(_) => {
+            c.set("replicaof".to_string(), format!("{} {}", host, puerto));
+            Rol::Replica
+        }
+        None => {
+            c.set("replicaof".to_string(), "no one".to_string());
+            Rol::Maestro
+        }
+    };
+    match bdd.lock() {
+        Ok(mut b) => b.set_rol(rol),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// Determina cual de los subcomandos del monitor de latencia se solicito
+fn latency(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let subcomandos = Subcomandos::<FuncionServer>::new("LATENCY")
+        .agregar(
+            "LATEST",
+            2,
+            "LATEST",
+            "Return the latest latency samples for all events.",
+            latency_latest,
+        )
+        .agregar(
+            "HISTORY",
+            3,
+            "HISTORY <event>",
+            "Return time-latency samples for the <event>.",
+            latency_history,
+        )
+        .agregar(
+            "RESET",
+            -2,
+            "RESET [<event> ...]",
+            "Reset latency data of one or more <event> classes (default: reset all data for all event classes).",
+            latency_reset,
+        );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, bdd, config),
+        Err(respuesta) => respuesta,
+    }
+}
+/// Devuelve por cada evento el instante y la duracion de su ultimo pico junto con el maximo registrado
+fn latency_latest(
+    _comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let latencia = match config.lock() {
+        Ok(c) => c.latencia(),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    ResultadoRedis::Vector(
+        latencia
+            .ultimas()
+            .into_iter()
+            .map(|(evento, muestra, maximo)| {
+                ResultadoRedis::Vector(vec![
+                    ResultadoRedis::BulkStr(evento),
+                    ResultadoRedis::Int(muestra.instante as isize),
+                    ResultadoRedis::Int(muestra.milisegundos as isize),
+                    ResultadoRedis::Int(maximo as isize),
+                ])
+            })
+            .collect(),
+    )
+}
+/// Devuelve los picos registrados del evento como pares de instante y duracion
+fn latency_history(
+    comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let evento = comando.get_parametro().unwrap_or_default();
+    let latencia = match config.lock() {
+        Ok(c) => c.latencia(),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    ResultadoRedis::Vector(
+        latencia
+            .historial(&evento)
+            .into_iter()
+            .map(|muestra| {
+                ResultadoRedis::Vector(vec![
+                    ResultadoRedis::Int(muestra.instante as isize),
+                    ResultadoRedis::Int(muestra.milisegundos as isize),
+                ])
+            })
+            .collect(),
+    )
+}
+/// Olvida los picos de los eventos indicados, o de todos, y devuelve cuantos eventos se reiniciaron
+fn latency_reset(
+    comando: &mut ComandoInfo,
+    _bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let mut eventos = Vec::new();
+    while let Some(evento) = comando.get_parametro() {
+        eventos.push(evento);
+    }
+    match config.lock() {
+        Ok(c) => ResultadoRedis::Int(c.latencia().reiniciar(&eventos) as isize),
+        Err(_) => ResultadoRedis::Error("ERR when accessing config".to_string()),
+    }
+}
+/// Determina cual de los subcomandos de memoria se solicito
+fn memory(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let subcomandos = Subcomandos::<FuncionServer>::new("MEMORY")
+        .agregar(
+            "PURGE",
+            2,
+            "PURGE",
+            "Shrink over-allocated containers to release the memory they are not using.",
+            memory_purge,
+        )
+        .agregar(
+            "STATS",
+            2,
+            "STATS",
+            "Return information about the memory reserved by the dataset.",
+            memory_stats,
+        );
+
+    match subcomandos.resolver(comando) {
+        Ok(subcomando) => subcomando(comando, bdd, config),
+        Err(respuesta) => respuesta,
+    }
+}
+/// Compacta la tabla y todas sus colecciones sin esperar a que lo haga la desfragmentacion
+/// en segundo plano
+fn memory_purge(
+    _comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    match bdd.lock() {
+        Ok(mut b) => b.desfragmentar(),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    ResultadoRedis::StrSimple("OK".to_string())
+}
+/// Devuelve la estimacion de la memoria en uso y reservada por los contenedores como pares
+/// de nombre y valor
+fn memory_stats(
     _comando: &mut ComandoInfo,
     bdd: Arc<Mutex<BaseDeDatos>>,
+    _config: Arc<Mutex<Config>>,
+) -> ResultadoRedis {
+    let (claves, fragmentacion) = match bdd.lock() {
+        Ok(b) => (b.cantidad_claves(), b.fragmentacion()),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    let estadisticas = vec![
+        ("keys.count", claves),
+        ("dataset.bytes", fragmentacion.en_uso),
+        ("reserved.bytes", fragmentacion.reservado),
+        ("fragmentation.bytes", fragmentacion.desperdicio()),
+        (
+            "fragmentation.percentage",
+            fragmentacion.porcentaje() as usize,
+        ),
+    ];
+    ResultadoRedis::Vector(
+        estadisticas
+            .into_iter()
+            .flat_map(|(nombre, valor)| {
+                vec![
+                    ResultadoRedis::BulkStr(nombre.to_string()),
+                    ResultadoRedis::Int(valor as isize),
+                ]
+            })
+            .collect(),
+    )
+}
+/// El comando INFO retorna información y estadísticas sobre el servidor en un formato fácil de parsear por computadores y fácil de leer por humanos.
+/// Con el nombre de una sección, por ejemplo INFO keyprefixes, retorna solo esa sección
+fn info(
+    comando: &mut ComandoInfo,
+    bdd: Arc<Mutex<BaseDeDatos>>,
     config: Arc<Mutex<Config>>,
 ) -> ResultadoRedis {
     let info = match (config.lock(), bdd.lock()) {
         (Ok(c), Ok(b)) => {
             let mut v = c.info();
+            v.append(&mut b.estadisticas().info());
+            v.append(&mut c.cluster().seccion_info());
+            v.append(&mut c.replicacion().seccion_info(b.rol(), c.maestro()));
+            v.append(&mut c.bloqueos().seccion_info());
+            v.append(&mut c.escrituras_por_prefijo().seccion_info());
+            v.append(&mut c.latencia().seccion_info());
+            v.append(&mut c.almacenes_externos().seccion_info());
+            v.append(&mut seccion_persistencia(&c));
+            v.append(&mut seccion_disponibilidad(&c, b.rol()));
             v.append(&mut b.info());
             v
         }
         _ => return ResultadoRedis::Error("ERR when accessing info".to_string()),
     };
+    let info = match comando.get_parametro() {
+        Some(seccion) => filtrar_seccion(info, &seccion),
+        None => info,
+    };
 
     ResultadoRedis::Vector(
         info.iter()
@@ -166,6 +975,47 @@ fn info(
             .collect(),
     )
 }
+/// Seccion Persistence de INFO, con el estado del AOF si esta habilitado
+fn seccion_persistencia(config: &Config) -> Vec<String> {
+    let mut info = vec!["# Persistence".to_string()];
+    match config.aof() {
+        Some(aof) => {
+            info.push("aof_enabled:1".to_string());
+            info.append(&mut aof.info());
+        }
+        None => info.push("aof_enabled:0".to_string()),
+    }
+    info.append(&mut config.estado_persistencia().info());
+    info.push("".to_string());
+    info
+}
+/// Seccion Readiness de INFO, que usa /readyz. El servidor esta listo si termino de cargar los
+/// datos, es decir si no es una replica que todavia no se sincronizo con su maestro, y si el
+/// ultimo guardado y la ultima escritura del AOF no fallaron
+fn seccion_disponibilidad(config: &Config, rol: Rol) -> Vec<String> {
+    let cargando = rol == Rol::Replica && !config.replicacion().maestro_conectado();
+    let persistencia_ok = !config.estado_persistencia().guardado_fallido()
+        && !config.aof().is_some_and(|aof| aof.escritura_fallida());
+    vec![
+        "# Readiness".to_string(),
+        format!("loading:{}", cargando as u8),
+        format!("persistence_ok:{}", persistencia_ok as u8),
+        format!("ready:{}", (!cargando && persistencia_ok) as u8),
+        "".to_string(),
+    ]
+}
+/// Devuelve solo las lineas de la seccion de INFO cuyo titulo coincide con el nombre, sin distinguir mayusculas
+fn filtrar_seccion(info: Vec<String>, seccion: &str) -> Vec<String> {
+    let mut en_seccion = false;
+    info.into_iter()
+        .filter(|linea| {
+            if let Some(titulo) = linea.strip_prefix("# ") {
+                en_seccion = titulo.eq_ignore_ascii_case(seccion);
+            }
+            en_seccion
+        })
+        .collect()
+}
 /// Es un comando de depuración que imprime al cliente cada comando procesado por el servidor. Puede ayudar entender qué está sucediendo en la base de datos
 fn monitor(
     _comando: &mut ComandoInfo,
@@ -178,3 +1028,84 @@ fn monitor(
     };
     ResultadoRedis::StrSimple("Ok".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::TipoRedis;
+    use crate::persistencia::Persistidor;
+    use std::sync::mpsc::channel;
+    use std::time::SystemTime;
+
+    fn config_con_persistencia(archivo: &str) -> Arc<Mutex<Config>> {
+        let mut config = Config::new();
+        config.set("dbfilename".to_string(), archivo.to_string());
+        config.set_persistidor(Persistidor::new(channel().0));
+        Arc::new(Mutex::new(config))
+    }
+
+    fn ejecutar(
+        tokens: &[&str],
+        bdd: &Arc<Mutex<BaseDeDatos>>,
+        config: &Arc<Mutex<Config>>,
+    ) -> ResultadoRedis {
+        let comando = ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+        Box::new(ComandoServerHandler::new(comando, Arc::clone(config))).ejecutar(Arc::clone(bdd))
+    }
+
+    #[test]
+    fn debug_reload_conserva_los_datos_y_sus_expiraciones() {
+        let archivo = std::env::temp_dir().join("debug_reload_conserva.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+        let config = config_con_persistencia(&archivo);
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        {
+            let mut b = bdd.lock().unwrap();
+            b.guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+            b.guardar_valor_con_expiracion(
+                "temporal".to_string(),
+                Duration::from_secs(100),
+                TipoRedis::Str("otro".into()),
+            );
+        }
+
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            ejecutar(&["DEBUG", "RELOAD"], &bdd, &config)
+        );
+
+        let b = bdd.lock().unwrap();
+        assert_eq!(Ok(Some("valor")), b.obtener_como_str("clave"));
+        assert_eq!(Ok(Some("otro")), b.obtener_como_str("temporal"));
+        assert!((1..=100).contains(&b.obtener_expiracion("temporal")));
+        std::fs::remove_file(&archivo).ok();
+    }
+
+    #[test]
+    fn debug_flushall_no_toca_el_archivo_y_el_siguiente_reload_persiste_la_base_vacia() {
+        let archivo = std::env::temp_dir().join("debug_flushall_persiste.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+        let config = config_con_persistencia(&archivo);
+        let bdd = Arc::new(Mutex::new(BaseDeDatos::new()));
+        bdd.lock()
+            .unwrap()
+            .guardar_valor("clave".to_string(), TipoRedis::Str("valor".into()));
+        ejecutar(&["DEBUG", "RELOAD"], &bdd, &config);
+
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            ejecutar(&["DEBUG", "FLUSHALL"], &bdd, &config)
+        );
+        assert!(bdd.lock().unwrap().tabla().is_empty());
+        assert!(cargar_tabla(&archivo, SystemTime::now())
+            .unwrap()
+            .contains_key("clave"));
+
+        ejecutar(&["DEBUG", "RELOAD"], &bdd, &config);
+        assert!(bdd.lock().unwrap().tabla().is_empty());
+        assert!(cargar_tabla(&archivo, SystemTime::now())
+            .unwrap()
+            .is_empty());
+        std::fs::remove_file(&archivo).ok();
+    }
+}
@@ -1,7 +1,7 @@
 use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, TipoRedis};
 use crate::comando::{Comando, ComandoHandler};
 use crate::comando_info::ComandoInfo;
-use std::collections::HashSet;
+use crate::conjunto::Conjunto;
 use std::sync::{Arc, Mutex};
 
 pub struct ComandoSetHandler {
@@ -47,27 +47,19 @@ fn sadd(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
     };
     match bdd.lock() {
         Ok(mut bdd) => {
-            let (a_agregar, cantidad_ingresada) = match bdd.obtener_valor(&clave) {
-                Some(TipoRedis::Set(set)) => aggregar_al_set(comando, &mut set.clone()),
-                None => aggregar_al_set(comando, &mut HashSet::new()),
-                _ => {
-                    return ResultadoRedis::Error(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value"
-                            .to_string(),
-                    )
-                }
+            let (a_agregar, cantidad_ingresada) = match bdd.obtener_como_set(&clave) {
+                Ok(Some(set)) => aggregar_al_set(comando, &mut set.clone()),
+                Ok(None) => aggregar_al_set(comando, &mut Conjunto::new()),
+                Err(e) => return e.a_resultado(),
             };
-            bdd.guardar_valor(clave, TipoRedis::Set(a_agregar));
+            bdd.actualizar_valor(clave, TipoRedis::Set(a_agregar));
             ResultadoRedis::Int(cantidad_ingresada as isize)
         }
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
 
-fn aggregar_al_set(
-    comando: &mut ComandoInfo,
-    set: &mut HashSet<String>,
-) -> (HashSet<String>, usize) {
+fn aggregar_al_set(comando: &mut ComandoInfo, set: &mut Conjunto) -> (Conjunto, usize) {
     let mut cantidad_ingresada = 0;
 
     while let Some(parametro) = comando.get_parametro() {
@@ -90,12 +82,10 @@ fn scard(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRe
     };
 
     match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Set(set)) => ResultadoRedis::Int(set.len() as isize),
-            None => ResultadoRedis::Int(0),
-            _ => ResultadoRedis::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-            ),
+        Ok(bdd) => match bdd.obtener_como_set(&clave) {
+            Ok(Some(set)) => ResultadoRedis::Int(set.len() as isize),
+            Ok(None) => ResultadoRedis::Int(0),
+            Err(e) => e.a_resultado(),
         },
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
@@ -106,29 +96,26 @@ fn sismember(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resulta
         Some(clave) => clave,
         None => {
             return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'scard' command".to_string(),
+                "ERR wrong number of arguments for 'sismember' command".to_string(),
             )
         }
     };
 
     match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Set(set)) => {
+        Ok(bdd) => match bdd.obtener_como_set(&clave) {
+            Ok(Some(set)) => {
                 let parametro = match comando.get_parametro() {
                     Some(parametro) => parametro,
                     None => {
                         return ResultadoRedis::Error(
-                            "WRONGTYPE Operation against a key holding the wrong kind of value"
-                                .to_string(),
+                            "ERR wrong number of arguments for 'sismember' command".to_string(),
                         )
                     }
                 };
                 ResultadoRedis::Int(if set.contains(&parametro) { 1 } else { 0 })
             }
-            None => ResultadoRedis::Int(0),
-            _ => ResultadoRedis::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-            ),
+            Ok(None) => ResultadoRedis::Int(0),
+            Err(e) => e.a_resultado(),
         },
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
@@ -145,18 +132,16 @@ fn smembers(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> Resultad
     };
 
     match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Set(set)) => {
+        Ok(bdd) => match bdd.obtener_como_set(&clave) {
+            Ok(Some(set)) => {
                 let mut vector = vec![];
                 for valor in set.iter() {
                     vector.push(ResultadoRedis::BulkStr(valor.clone()));
                 }
                 ResultadoRedis::Vector(vector)
             }
-            None => ResultadoRedis::Vector(vec![]),
-            _ => ResultadoRedis::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-            ),
+            Ok(None) => ResultadoRedis::Vector(vec![]),
+            Err(e) => e.a_resultado(),
         },
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
@@ -174,27 +159,19 @@ fn srem(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
 
     match bdd.lock() {
         Ok(mut bdd) => {
-            let (a_agregar, cantidad_eliminada) = match bdd.obtener_valor(&clave) {
-                Some(TipoRedis::Set(set)) => eliminar_del_set(comando, &mut set.clone()),
-                None => return ResultadoRedis::Int(0),
-                _ => {
-                    return ResultadoRedis::Error(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value"
-                            .to_string(),
-                    )
-                }
+            let (a_agregar, cantidad_eliminada) = match bdd.obtener_como_set(&clave) {
+                Ok(Some(set)) => eliminar_del_set(comando, &mut set.clone()),
+                Ok(None) => return ResultadoRedis::Int(0),
+                Err(e) => return e.a_resultado(),
             };
-            bdd.guardar_valor(clave, TipoRedis::Set(a_agregar));
+            bdd.actualizar_valor(clave, TipoRedis::Set(a_agregar));
             ResultadoRedis::Int(cantidad_eliminada as isize)
         }
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
 }
 
-fn eliminar_del_set(
-    comando: &mut ComandoInfo,
-    set: &mut HashSet<String>,
-) -> (HashSet<String>, usize) {
+fn eliminar_del_set(comando: &mut ComandoInfo, set: &mut Conjunto) -> (Conjunto, usize) {
     let mut cantidad_eliminada = 0;
 
     while let Some(parametro) = comando.get_parametro() {
@@ -226,7 +203,7 @@ mod tests {
 
         assert_eq!(ResultadoRedis::Int(1), resultado,);
 
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("miValor".to_string());
         assert_eq!(
             h.lock()
@@ -257,10 +234,7 @@ mod tests {
     #[test]
     fn sadd_cuando_se_envia_una_clave_invalida_se_envia_el_error_adecuado() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor(
-            "miClave".to_string(),
-            TipoRedis::Str("unString".to_string()),
-        );
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("unString".into()));
         let vector = vec![
             "SADD".to_string(),
             "miClave".to_string(),
@@ -295,7 +269,7 @@ mod tests {
     #[test]
     fn scard_cuando_se_envia_una_clave_que_posee_dos_elementos_se_devuelve_2_de_cardinalidad() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("miValor".to_string());
         set.insert("otroValor".to_string());
 
@@ -312,10 +286,7 @@ mod tests {
     #[test]
     fn scard_cuando_se_envia_una_clave_invalida_se_envia_el_error_adecuado() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor(
-            "miClave".to_string(),
-            TipoRedis::Str("unString".to_string()),
-        );
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("unString".into()));
         let vector = vec!["SCARD".to_string(), "miClave".to_string()];
 
         let h = Arc::new(Mutex::new(bdd));
@@ -351,7 +322,7 @@ mod tests {
     fn sismember_cuando_se_envia_una_clave_que_posee_dos_elementos_y_se_pregunta_si_uno_de_ellos_pertenece_se_devuelve_1_de_true(
     ) {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("miValor".to_string());
         set.insert("otroValor".to_string());
 
@@ -372,10 +343,7 @@ mod tests {
     #[test]
     fn sismember_cuando_se_envia_una_clave_invalida_se_envia_el_error_adecuado() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor(
-            "miClave".to_string(),
-            TipoRedis::Str("unString".to_string()),
-        );
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("unString".into()));
         let vector = vec![
             "SISMEMBER".to_string(),
             "miClave".to_string(),
@@ -409,10 +377,7 @@ mod tests {
     #[test]
     fn smembers_cuando_se_envia_una_clave_invalida_se_envia_el_error_adecuado() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor(
-            "miClave".to_string(),
-            TipoRedis::Str("unString".to_string()),
-        );
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("unString".into()));
         let vector = vec!["SMEMBERS".to_string(), "miClave".to_string()];
 
         let h = Arc::new(Mutex::new(bdd));
@@ -449,7 +414,7 @@ mod tests {
     ) {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
 
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("miValor".to_string());
         set.insert("otroValor".to_string());
 
@@ -466,7 +431,7 @@ mod tests {
 
         assert_eq!(ResultadoRedis::Int(1), resultado,);
 
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("otroValor".to_string());
         assert_eq!(
             h.lock()
@@ -482,7 +447,7 @@ mod tests {
     ) {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
 
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("miValor".to_string());
         set.insert("otroValor".to_string());
 
@@ -500,7 +465,7 @@ mod tests {
 
         assert_eq!(ResultadoRedis::Int(1), resultado,);
 
-        let mut set = HashSet::new();
+        let mut set = Conjunto::new();
         set.insert("otroValor".to_string());
         assert_eq!(
             h.lock()
@@ -514,10 +479,7 @@ mod tests {
     #[test]
     fn srem_cuando_se_envia_una_clave_invalida_se_envia_el_error_adecuado() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor(
-            "miClave".to_string(),
-            TipoRedis::Str("unString".to_string()),
-        );
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("unString".into()));
         let vector = vec![
             "SREM".to_string(),
             "miClave".to_string(),
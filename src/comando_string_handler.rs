@@ -1,7 +1,9 @@
 use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, TipoRedis};
 use crate::comando::{Comando, ComandoHandler};
 use crate::comando_info::ComandoInfo;
+use crate::opciones_parser::OpcionesParser;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub struct ComandoStringHandler {
     comando: ComandoInfo,
@@ -41,7 +43,11 @@ pub fn es_comando_string(comando: &str) -> bool {
     ];
     comandos.iter().any(|&c| c == comando)
 }
-/// Devuelve el valor de una clave, si la clave no existe, se retorna el valor especial nil. Se retorna un error si el valor almacenado en esa clave no es un string, porque GET maneja solamente strings
+/// Devuelve el valor de una clave, si la clave no existe, se retorna el valor especial nil. Se retorna un error si el valor almacenado en esa clave no es un string, porque GET maneja solamente strings.
+/// Con el modificador STALE tambien devuelve el valor de una clave que expiro hace menos que la opcion stale-while-expired,
+/// para que los clientes de un cache sigan leyendo mientras uno solo la refresca.
+/// Sin STALE la respuesta es un bulk string con el valor. Con STALE es un arreglo de dos elementos: el bulk string con el valor
+/// y el entero 1 si ya expiro o 0 si no. En ambos casos es nil si la clave no existe
 fn get(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
     let clave = match comando.get_clave() {
         Some(c) => c,
@@ -51,84 +57,99 @@ fn get(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedi
             )
         }
     };
+    let opciones = match OpcionesParser::new()
+        .bandera("STALE")
+        .parsear(&comando.tokens()[1..])
+    {
+        Ok(o) => o,
+        Err(e) => return e.a_resultado(),
+    };
 
-    match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Str(valor)) => ResultadoRedis::BulkStr(valor.to_string()),
-            None => ResultadoRedis::Nil,
-            _ => ResultadoRedis::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-            ),
-        },
-        Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    let bdd = match bdd.lock() {
+        Ok(bdd) => bdd,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    if !opciones.tiene("STALE") {
+        return match bdd.obtener_como_str(&clave) {
+            Ok(Some(valor)) => ResultadoRedis::BulkStr(valor.to_string()),
+            Ok(None) => ResultadoRedis::Nil,
+            Err(e) => e.a_resultado(),
+        };
     }
-}
-
-fn obtener_tiempo_expiracion(parametros: Vec<String>, support: &str) -> Option<u64> {
-    match parametros.rsplit(|p| p == &support.to_string()).next() {
-        Some(c) => match c[0].clone().parse::<u64>() {
-            Ok(num) => Some(num),
-            Err(_) => None,
-        },
-        None => None,
+    match bdd.obtener_como_str_aunque_expiro(&clave) {
+        Ok(Some((valor, viejo))) => ResultadoRedis::Vector(vec![
+            ResultadoRedis::BulkStr(valor.to_string()),
+            ResultadoRedis::Int(viejo as isize),
+        ]),
+        Ok(None) => ResultadoRedis::Nil,
+        Err(e) => e.a_resultado(),
     }
 }
-/// Setea que la clave especificada almacene el valor especificado de tipo string. Si la clave contiene un valor previo, la clave es sobreescrita, independientemente del tipo de dato contenido (descartando también el valor previo de TTL)
+
+/// Setea que la clave especificada almacene el valor especificado de tipo string. Si la clave contiene un valor previo, la clave es sobreescrita, independientemente del tipo de dato contenido (descartando también el valor previo de TTL).
+/// Acepta los modificadores EX/PX para indicar la expiracion, PXAT para indicar el instante en el que expira en milisegundos desde epoch
+/// y NX/XX para setear solo si la clave no existe o si ya existe
 fn set(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
-    let clave = match comando.get_clave() {
-        Some(c) => c,
-        None => {
+    let (clave, valor) = match (comando.get(0), comando.get(1)) {
+        (Some(c), Some(v)) => (c, v),
+        _ => {
             return ResultadoRedis::Error(
                 "ERR wrong number of arguments for 'set' command".to_string(),
             )
         }
     };
 
-    let parametros = match comando.get_parametros() {
-        Some(p) => p,
-        None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for 'set' command".to_string(),
-            )
-        }
+    let opciones = match OpcionesParser::new()
+        .bandera("NX")
+        .bandera("XX")
+        .con_entero("EX")
+        .con_entero("PX")
+        .con_entero("PXAT")
+        .excluyentes(&["NX", "XX"])
+        .excluyentes(&["EX", "PX", "PXAT"])
+        .parsear(&comando.tokens()[2..])
+    {
+        Ok(o) => o,
+        Err(e) => return e.a_resultado(),
     };
-    if parametros.len() == 1 {
-        return ResultadoRedis::Error(
-            "ERR wrong number of arguments for 'set' command".to_string(),
-        );
+
+    let expiracion = match (opciones.entero("EX"), opciones.entero("PX")) {
+        (Some(ex), _) if ex > 0 => Some(Duration::from_secs(ex as u64)),
+        (_, Some(px)) if px > 0 => Some(Duration::from_millis(px as u64)),
+        (None, None) => None,
+        _ => return ResultadoRedis::Error("ERR invalid expire time in 'set' command".to_string()),
+    };
+    if let Some(e) = expiracion {
+        if SystemTime::now().checked_add(e).is_none() {
+            return ResultadoRedis::Error("ERR invalid expire time in 'set' command".to_string());
+        }
     }
+    let expira_en = match opciones.entero("PXAT") {
+        None => None,
+        Some(ms) => match UNIX_EPOCH.checked_add(Duration::from_millis(ms.max(0) as u64)) {
+            Some(instante) if ms > 0 => Some(instante),
+            _ => {
+                return ResultadoRedis::Error(
+                    "ERR invalid expire time in 'set' command".to_string(),
+                )
+            }
+        },
+    };
+
     match bdd.lock() {
         Ok(mut bdd) => {
-            if parametros.contains(&"EX".to_string()) {
-                let expiracion = match obtener_tiempo_expiracion(parametros.clone(), "EX") {
-                    Some(e) => e,
-                    None => {
-                        return ResultadoRedis::Error(
-                            "ERR value is not an integer or out of range".to_string(),
-                        )
-                    }
-                };
-                bdd.guardar_valor_con_expiracion(
-                    clave,
-                    expiracion,
-                    TipoRedis::Str(parametros[1].clone()),
-                )
-            } else if parametros.contains(&"PX".to_string()) {
-                let expiracion = match obtener_tiempo_expiracion(parametros.clone(), "PX") {
-                    Some(e) => e,
-                    None => {
-                        return ResultadoRedis::Error(
-                            "ERR value is not an integer or out of range".to_string(),
-                        )
-                    }
-                };
-                bdd.guardar_valor_con_expiracion(
-                    clave,
-                    expiracion / 1000,
-                    TipoRedis::Str(parametros[1].clone()),
-                )
-            } else {
-                bdd.guardar_valor(clave, TipoRedis::Str(parametros[1].clone()))
+            let existe = bdd.existe_clave(&clave);
+            if (opciones.tiene("NX") && existe) || (opciones.tiene("XX") && !existe) {
+                return ResultadoRedis::Nil;
+            }
+
+            let valor = TipoRedis::Str(valor.into());
+            match (expiracion, expira_en) {
+                (Some(e), _) => bdd.guardar_valor_con_expiracion(clave, e, valor),
+                (_, Some(instante)) => {
+                    bdd.guardar_valor_con_expiracion_absoluta(clave, instante, valor)
+                }
+                _ => bdd.guardar_valor(clave, valor),
             }
         }
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
@@ -155,12 +176,10 @@ fn getset(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoR
     };
 
     match bdd.lock() {
-        Ok(mut bdd) => match bdd.intercambiar_valor(clave, TipoRedis::Str(parametro)) {
-            Some(TipoRedis::Str(valor_enterior)) => ResultadoRedis::StrSimple(valor_enterior),
-            None => ResultadoRedis::Nil,
-            _ => ResultadoRedis::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-            ),
+        Ok(mut bdd) => match bdd.intercambiar_valor(clave, parametro) {
+            Ok(Some(valor_anterior)) => ResultadoRedis::StrSimple(valor_anterior),
+            Ok(None) => ResultadoRedis::Nil,
+            Err(e) => e.a_resultado(),
         },
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
@@ -186,18 +205,15 @@ fn append(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoR
     match bdd.lock() {
         Ok(mut bdd) => {
             if bdd.existe_clave(&clave) {
-                valor = match bdd.obtener_valor(&clave) {
-                    Some(TipoRedis::Str(v)) => v.to_string() + &valor,
-                    _ => {
-                        return ResultadoRedis::Error(
-                            "WRONGTYPE Operation against a key holding the wrong kind of value"
-                                .to_string(),
-                        )
-                    }
+                valor = match bdd.obtener_como_str(&clave) {
+                    Ok(Some(v)) => v.to_string() + &valor,
+                    Ok(None) => valor,
+                    Err(e) => return e.a_resultado(),
                 };
             };
-            bdd.guardar_valor(clave, TipoRedis::Str(valor.to_string()));
-            ResultadoRedis::Int(valor.len() as isize)
+            let largo = valor.len();
+            bdd.actualizar_valor(clave, TipoRedis::Str(valor.into()));
+            ResultadoRedis::Int(largo as isize)
         }
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
@@ -232,11 +248,10 @@ fn strlen(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoR
         None => return ResultadoRedis::Int(0),
     };
     match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Str(valor)) => ResultadoRedis::Int(valor.len() as isize),
-            _ => ResultadoRedis::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-            ),
+        Ok(bdd) => match bdd.obtener_como_str(&clave) {
+            Ok(Some(valor)) => ResultadoRedis::Int(valor.len() as isize),
+            Ok(None) => ResultadoRedis::Int(0),
+            Err(e) => e.a_resultado(),
         },
         Err(_) => ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
@@ -253,14 +268,10 @@ fn operar_sobre_int(
     };
 
     let valor = match bdd.lock() {
-        Ok(bdd) => match bdd.obtener_valor(&clave) {
-            Some(TipoRedis::Str(valor)) => valor.clone(),
-            None => "0".to_string(),
-            _ => {
-                return ResultadoRedis::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
-                )
-            }
+        Ok(bdd) => match bdd.obtener_como_str(&clave) {
+            Ok(Some(valor)) => valor.to_string(),
+            Ok(None) => "0".to_string(),
+            Err(e) => return e.a_resultado(),
         },
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
@@ -286,7 +297,7 @@ fn operar_sobre_int(
 
     num = f(num, param);
     match bdd.lock() {
-        Ok(mut bdd) => bdd.guardar_valor(clave, TipoRedis::Str(num.to_string())),
+        Ok(mut bdd) => bdd.actualizar_valor(clave, TipoRedis::Str(num.to_string().into())),
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     }
     ResultadoRedis::BulkStr(num.to_string())
@@ -301,51 +312,34 @@ fn incrby(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoR
 }
 /// Retorna el valor de todas las claves especificadas. Para las claves que no contienen valor o el valor no es un string, se retorna el tipo especial nil
 fn mget(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
-    let mut valores = vec![];
-    let mut quedan_valores = true;
-
-    while quedan_valores {
-        let param = comando.get_parametro();
-        match param {
-            Some(p) => match bdd.lock() {
-                Ok(bdd) => match bdd.obtener_valor(&p) {
-                    Some(TipoRedis::Str(valor)) => {
-                        valores.push(ResultadoRedis::BulkStr(valor.to_string()))
-                    }
-                    _ => valores.push(ResultadoRedis::Nil),
-                },
-                Err(_) => {
-                    return ResultadoRedis::Error("ERR when accessing the database".to_string())
-                }
-            },
-            None => {
-                quedan_valores = false;
-            }
-        }
-    }
-
-    if valores.is_empty() {
+    if comando.is_empty() {
         return ResultadoRedis::Error("ERR wrong number of arguments for mget command".to_string());
     }
-    ResultadoRedis::Vector(valores)
+
+    let bdd = match bdd.lock() {
+        Ok(bdd) => bdd,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+
+    ResultadoRedis::Vector(
+        comando
+            .tokens()
+            .iter()
+            .map(|clave| match bdd.obtener_valor(clave) {
+                Some(TipoRedis::Str(valor)) => ResultadoRedis::BulkStr(valor.to_string()),
+                _ => ResultadoRedis::Nil,
+            })
+            .collect(),
+    )
 }
 /// Setea las claves data a sus respectivos valores, reemplazando los valores existentes con los nuevos valores como SET. MSET es atómica, de modo que todas las claves son actualizadas a la vez. No es posible para los clientes ver que algunas claves del conjunto fueron modificadas, mientras otras no
 fn mset(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis {
-    let parametros = match comando.get_parametros() {
-        Some(p) => p,
-        None => {
-            return ResultadoRedis::Error(
-                "ERR wrong number of arguments for mset command".to_string(),
-            )
-        }
-    };
-
-    if parametros.len() % 2 != 0 {
+    if comando.is_empty() || !comando.len().is_multiple_of(2) {
         return ResultadoRedis::Error("ERR wrong number of arguments for mset command".to_string());
     }
 
     match bdd.lock() {
-        Ok(mut bdd) => bdd.guardar_valores(parametros),
+        Ok(mut bdd) => bdd.guardar_valores(comando.tokens()),
         Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
     };
     ResultadoRedis::StrSimple("OK".to_string())
@@ -354,12 +348,13 @@ fn mset(comando: &mut ComandoInfo, bdd: Arc<Mutex<BaseDeDatos>>) -> ResultadoRed
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashSet;
+    use crate::conjunto::Conjunto;
+    use crate::reloj::RelojSimulado;
 
     #[test]
     fn get_devuelve_el_valor_almacenado_en_el_hash() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".into()));
         let mut comando = ComandoInfo::new(vec!["get".to_string(), "miClave".to_string()]);
 
         assert_eq!(
@@ -397,10 +392,130 @@ mod tests {
         );
     }
 
+    #[test]
+    fn set_con_nx_no_sobreescribe_una_clave_existente() {
+        let mut bdd: BaseDeDatos = BaseDeDatos::new();
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".into()));
+        let ptr_hash = Arc::new(Mutex::new(bdd));
+
+        let mut comando = ComandoInfo::new(vec![
+            "set".to_string(),
+            "miClave".to_string(),
+            "otroValor".to_string(),
+            "nx".to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::Nil,
+            set(&mut comando, Arc::clone(&ptr_hash))
+        );
+        assert_eq!(
+            Some(&TipoRedis::Str("miValor".into())),
+            ptr_hash.lock().unwrap().obtener_valor("miClave")
+        );
+    }
+
+    #[test]
+    fn set_con_xx_no_crea_una_clave_inexistente() {
+        let ptr_hash = Arc::new(Mutex::new(BaseDeDatos::new()));
+
+        let mut comando = ComandoInfo::new(vec![
+            "set".to_string(),
+            "miClave".to_string(),
+            "miValor".to_string(),
+            "XX".to_string(),
+            "EX".to_string(),
+            "10".to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::Nil,
+            set(&mut comando, Arc::clone(&ptr_hash))
+        );
+        assert!(!ptr_hash.lock().unwrap().existe_clave("miClave"));
+    }
+
+    #[test]
+    fn set_con_una_expiracion_que_desborda_devuelve_error_sin_envenenar_la_base() {
+        let ptr_hash = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let mut comando = ComandoInfo::new(vec![
+            "set".to_string(),
+            "miClave".to_string(),
+            "miValor".to_string(),
+            "EX".to_string(),
+            "9223372036854775807".to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::Error("ERR invalid expire time in 'set' command".to_string()),
+            set(&mut comando, Arc::clone(&ptr_hash))
+        );
+        assert!(!ptr_hash.is_poisoned());
+        assert!(!ptr_hash.lock().unwrap().existe_clave("miClave"));
+    }
+
+    #[test]
+    fn set_con_pxat_expira_en_el_instante_absoluto_indicado() {
+        let ptr_hash = Arc::new(Mutex::new(BaseDeDatos::new()));
+        let en_un_minuto = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        let mut comando = ComandoInfo::new(vec![
+            "set".to_string(),
+            "miClave".to_string(),
+            "miValor".to_string(),
+            "PXAT".to_string(),
+            en_un_minuto.to_string(),
+        ]);
+        let mut vencido = ComandoInfo::new(vec![
+            "set".to_string(),
+            "otraClave".to_string(),
+            "miValor".to_string(),
+            "pxat".to_string(),
+            "1000".to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            set(&mut comando, Arc::clone(&ptr_hash))
+        );
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            set(&mut vencido, Arc::clone(&ptr_hash))
+        );
+        let mut bdd = ptr_hash.lock().unwrap();
+        assert!(bdd.obtener_expiracion_en_milisegundos("miClave") > 59_000);
+        assert!(!bdd.existe_clave("otraClave"));
+    }
+
+    #[test]
+    fn set_con_opciones_incompatibles_devuelve_error_de_sintaxis() {
+        let bdd: BaseDeDatos = BaseDeDatos::new();
+        let mut comando = ComandoInfo::new(vec![
+            "set".to_string(),
+            "miClave".to_string(),
+            "miValor".to_string(),
+            "EX".to_string(),
+            "10".to_string(),
+            "PX".to_string(),
+            "100".to_string(),
+        ]);
+
+        assert_eq!(
+            ResultadoRedis::Error(
+                "ERR syntax error, EX and PX options at the same time are not compatible"
+                    .to_string()
+            ),
+            set(&mut comando, Arc::new(Mutex::new(bdd)))
+        );
+    }
+
     #[test]
     fn append_agrega_el_string_enviado_al_final_del_string_guardado_con_la_misma_clave() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".into()));
         let ptr_hash = Arc::new(Mutex::new(bdd));
         let ptr_hash1 = Arc::clone(&ptr_hash);
 
@@ -411,6 +526,7 @@ mod tests {
         ]);
 
         assert_eq!(ResultadoRedis::Int(24), append(&mut comando, ptr_hash1));
+        let mut comando = ComandoInfo::new(vec!["GET".to_string(), "miClave".to_string()]);
         assert_eq!(
             ResultadoRedis::BulkStr("miValorconAlgoAppendeado".to_string()),
             get(&mut comando, ptr_hash)
@@ -430,6 +546,7 @@ mod tests {
         ]);
 
         assert_eq!(ResultadoRedis::Int(17), append(&mut comando, ptr_hash1));
+        let mut comando = ComandoInfo::new(vec!["GET".to_string(), "miClave".to_string()]);
         assert_eq!(
             ResultadoRedis::BulkStr("conAlgoAppendeado".to_string()),
             get(&mut comando, ptr_hash)
@@ -457,7 +574,7 @@ mod tests {
     #[test]
     fn getdel_devuelve_el_valor_almacenado_en_el_hash() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".into()));
 
         let ptr_hash = Arc::new(Mutex::new(bdd));
         let ptr_hash_clone = Arc::clone(&ptr_hash);
@@ -485,10 +602,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn get_stale_devuelve_el_valor_junto_con_si_es_viejo() {
+        let mut bdd: BaseDeDatos = BaseDeDatos::new();
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".into()));
+        let ptr_hash = Arc::new(Mutex::new(bdd));
+        let comando =
+            |tokens: &[&str]| ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+
+        assert_eq!(
+            ResultadoRedis::Vector(vec![
+                ResultadoRedis::BulkStr("miValor".to_string()),
+                ResultadoRedis::Int(0)
+            ]),
+            get(
+                &mut comando(&["GET", "miClave", "stale"]),
+                Arc::clone(&ptr_hash)
+            )
+        );
+        assert_eq!(
+            ResultadoRedis::Nil,
+            get(
+                &mut comando(&["GET", "otra", "STALE"]),
+                Arc::clone(&ptr_hash)
+            )
+        );
+        assert_eq!(
+            ResultadoRedis::Error("ERR syntax error".to_string()),
+            get(&mut comando(&["GET", "miClave", "VIEJO"]), ptr_hash)
+        );
+    }
+
+    #[test]
+    fn get_sin_stale_devuelve_un_bulk_string_aunque_la_clave_siga_en_gracia() {
+        let reloj = Arc::new(RelojSimulado::new());
+        let mut bdd: BaseDeDatos = BaseDeDatos::new();
+        bdd.set_reloj(reloj.clone());
+        bdd.set_gracia_de_expiracion(Duration::from_secs(10));
+        bdd.guardar_valor_con_expiracion(
+            "miClave".to_string(),
+            Duration::from_secs(1),
+            TipoRedis::Str("miValor".into()),
+        );
+        let ptr_hash = Arc::new(Mutex::new(bdd));
+        let comando =
+            |tokens: &[&str]| ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+
+        assert_eq!(
+            ResultadoRedis::BulkStr("miValor".to_string()),
+            get(&mut comando(&["GET", "miClave"]), Arc::clone(&ptr_hash))
+        );
+
+        reloj.avanzar(Duration::from_secs(1));
+        assert_eq!(
+            ResultadoRedis::Nil,
+            get(&mut comando(&["GET", "miClave"]), Arc::clone(&ptr_hash))
+        );
+        assert_eq!(
+            ResultadoRedis::Vector(vec![
+                ResultadoRedis::BulkStr("miValor".to_string()),
+                ResultadoRedis::Int(1)
+            ]),
+            get(&mut comando(&["GET", "miClave", "STALE"]), ptr_hash)
+        );
+    }
+
     #[test]
     fn strlen_devuelve_el_valor_almacenado_en_el_hash() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("miValor".into()));
         let mut comando = ComandoInfo::new(vec!["get".to_string(), "miClave".to_string()]);
 
         assert_eq!(
@@ -514,7 +696,7 @@ mod tests {
     #[test]
     fn decrby_resta_correcatemente_un_valor_entero_a_una_clave_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".into()));
         let mut comando = ComandoInfo::new(vec![
             "decrby".to_string(),
             "miClave".to_string(),
@@ -530,7 +712,7 @@ mod tests {
     #[test]
     fn decrby_resta_correcatemente_un_valor_entero_a_una_clave_negativa_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("-10".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("-10".into()));
         let mut comando = ComandoInfo::new(vec![
             "decrby".to_string(),
             "miClave".to_string(),
@@ -546,7 +728,7 @@ mod tests {
     #[test]
     fn decrby_resta_correcatemente_un_valor_negativo_a_una_clave_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("10".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("10".into()));
         let mut comando = ComandoInfo::new(vec![
             "decrby".to_string(),
             "miClave".to_string(),
@@ -595,7 +777,7 @@ mod tests {
     #[test]
     fn decrby_devuelve_error_un_valor_erroneo_a_una_clave_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".into()));
         let mut comando = ComandoInfo::new(vec![
             "decrby".to_string(),
             "miClave".to_string(),
@@ -611,7 +793,7 @@ mod tests {
     #[test]
     fn incrby_resta_correcatemente_un_valor_entero_a_una_clave_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".into()));
         let mut comando = ComandoInfo::new(vec![
             "incrby".to_string(),
             "miClave".to_string(),
@@ -627,7 +809,7 @@ mod tests {
     #[test]
     fn incrby_resta_correcatemente_un_valor_entero_a_una_clave_negativa_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("-10".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("-10".into()));
         let mut comando = ComandoInfo::new(vec![
             "incrby".to_string(),
             "miClave".to_string(),
@@ -643,7 +825,7 @@ mod tests {
     #[test]
     fn incrby_resta_correcatemente_un_valor_negativo_a_una_clave_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("10".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("10".into()));
         let mut comando = ComandoInfo::new(vec![
             "incrby".to_string(),
             "miClave".to_string(),
@@ -692,7 +874,7 @@ mod tests {
     #[test]
     fn incrby_devuelve_error_un_valor_erroneo_a_una_clave_parseable() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".to_string()));
+        bdd.guardar_valor("miClave".to_string(), TipoRedis::Str("1".into()));
         let mut comando = ComandoInfo::new(vec![
             "incrby".to_string(),
             "miClave".to_string(),
@@ -708,10 +890,10 @@ mod tests {
     #[test]
     fn mget_devuelve_una_lista_con_todos_los_valores_de_las_claves() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".to_string()));
-        bdd.guardar_valor("clave2".to_string(), TipoRedis::Str("2".to_string()));
-        bdd.guardar_valor("clave3".to_string(), TipoRedis::Str("3".to_string()));
-        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".to_string()));
+        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".into()));
+        bdd.guardar_valor("clave2".to_string(), TipoRedis::Str("2".into()));
+        bdd.guardar_valor("clave3".to_string(), TipoRedis::Str("3".into()));
+        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".into()));
 
         let mut comando = ComandoInfo::new(vec![
             "mget".to_string(),
@@ -736,10 +918,10 @@ mod tests {
     fn mget_devuelve_una_lista_con_todos_los_valores_de_las_claves_y_si_la_clave_no_existe_devuelve_nil(
     ) {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".to_string()));
-        bdd.guardar_valor("clave2".to_string(), TipoRedis::Str("2".to_string()));
-        bdd.guardar_valor("clave3".to_string(), TipoRedis::Str("3".to_string()));
-        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".to_string()));
+        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".into()));
+        bdd.guardar_valor("clave2".to_string(), TipoRedis::Str("2".into()));
+        bdd.guardar_valor("clave3".to_string(), TipoRedis::Str("3".into()));
+        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".into()));
 
         let mut comando = ComandoInfo::new(vec![
             "mget".to_string(),
@@ -763,10 +945,10 @@ mod tests {
     #[test]
     fn mget_devuelve_una_lista_con_todos_nil_si_la_clave_no_existen() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".to_string()));
-        bdd.guardar_valor("clave2".to_string(), TipoRedis::Str("2".to_string()));
-        bdd.guardar_valor("clave3".to_string(), TipoRedis::Str("3".to_string()));
-        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".to_string()));
+        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".into()));
+        bdd.guardar_valor("clave2".to_string(), TipoRedis::Str("2".into()));
+        bdd.guardar_valor("clave3".to_string(), TipoRedis::Str("3".into()));
+        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".into()));
 
         let mut comando = ComandoInfo::new(vec![
             "mget".to_string(),
@@ -790,10 +972,10 @@ mod tests {
     #[test]
     fn mget_devuelve_una_lista_con_todos_nil_si_la_clave_no_es_de_tipo_str() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".to_string()));
+        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".into()));
         bdd.guardar_valor("clave2".to_string(), TipoRedis::Lista(Vec::new()));
-        bdd.guardar_valor("clave3".to_string(), TipoRedis::Set(HashSet::new()));
-        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".to_string()));
+        bdd.guardar_valor("clave3".to_string(), TipoRedis::Set(Conjunto::new()));
+        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".into()));
 
         let mut comando = ComandoInfo::new(vec![
             "mget".to_string(),
@@ -817,10 +999,10 @@ mod tests {
     #[test]
     fn mget_devuelve_una_error_con_si_no_hay_parametro() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
-        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".to_string()));
+        bdd.guardar_valor("clave1".to_string(), TipoRedis::Str("1".into()));
         bdd.guardar_valor("clave2".to_string(), TipoRedis::Lista(Vec::new()));
-        bdd.guardar_valor("clave3".to_string(), TipoRedis::Set(HashSet::new()));
-        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".to_string()));
+        bdd.guardar_valor("clave3".to_string(), TipoRedis::Set(Conjunto::new()));
+        bdd.guardar_valor("clave4".to_string(), TipoRedis::Str("4".into()));
 
         let mut comando = ComandoInfo::new(vec!["mget".to_string()]);
 
@@ -852,15 +1034,15 @@ mod tests {
         );
 
         assert_eq!(
-            Some(&TipoRedis::Str("1".to_string())),
+            Some(&TipoRedis::Str("1".into())),
             ptr_hash.lock().unwrap().obtener_valor("clave1")
         );
         assert_eq!(
-            Some(&TipoRedis::Str("2".to_string())),
+            Some(&TipoRedis::Str("2".into())),
             ptr_hash.lock().unwrap().obtener_valor("clave2")
         );
         assert_eq!(
-            Some(&TipoRedis::Str("3".to_string())),
+            Some(&TipoRedis::Str("3".into())),
             ptr_hash.lock().unwrap().obtener_valor("clave3")
         );
     }
@@ -894,7 +1076,7 @@ mod tests {
     fn getset_devuelve_el_antiguo_valor_almacenado() {
         let mut bdd: BaseDeDatos = BaseDeDatos::new();
 
-        bdd.guardar_valor("clave".to_string(), TipoRedis::Str("clave".to_string()));
+        bdd.guardar_valor("clave".to_string(), TipoRedis::Str("clave".into()));
         let ptr_hash = Arc::new(Mutex::new(bdd));
         let ptr_hash1 = Arc::clone(&ptr_hash);
 
@@ -910,7 +1092,7 @@ mod tests {
         );
 
         assert_eq!(
-            Some(&TipoRedis::Str("nueva_clave".to_string())),
+            Some(&TipoRedis::Str("nueva_clave".into())),
             ptr_hash.lock().unwrap().obtener_valor("clave")
         );
     }
@@ -931,7 +1113,7 @@ mod tests {
         assert_eq!(ResultadoRedis::Nil, getset(&mut comando, ptr_hash1));
 
         assert_eq!(
-            Some(&TipoRedis::Str("nueva_clave".to_string())),
+            Some(&TipoRedis::Str("nueva_clave".into())),
             ptr_hash.lock().unwrap().obtener_valor("clave")
         );
     }
@@ -1,13 +1,37 @@
-use crate::cliente::Cliente;
+use crate::almacen_externo::AlmacenesExternos;
+use crate::aof::{Aof, PoliticaFsync};
+use crate::bloqueo_claves::BloqueoPorClave;
+use crate::cliente::{Cliente, Token};
+use crate::cluster::EstadoCluster;
+use crate::codificacion::LimitesDeCodificacion;
+use crate::cola_de_salida::LimiteDeSalida;
+use crate::demonio::Supervision;
+use crate::escrituras_por_prefijo::EscriturasPorPrefijo;
+use crate::latencia::MonitorDeLatencia;
 use crate::log_handler::Logger;
-use crate::persistencia::Persistidor;
-use std::collections::HashMap;
+use crate::notificaciones_claves::NotificacionesDeClaves;
+use crate::permisos_de_claves::PermisosDeClaves;
+use crate::persistencia::{EstadoPersistencia, Persistidor};
+use crate::replicacion::Replicacion;
+use crate::temporizador::RuedaDeTemporizadores;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
 
 use regex::Regex;
 
+/// Porcentaje de memoria desperdiciada a partir del cual se desfragmenta, como en redis
+const UMBRAL_DESFRAGMENTACION: u64 = 10;
+
+/// Bytes desperdiciados por debajo de los cuales no se desfragmenta, 100mb como en redis
+const BYTES_IGNORADOS_DESFRAGMENTACION: usize = 100 * 1024 * 1024;
+
+/// Milisegundos que se espera al almacen externo si no esta configurado external-store-timeout
+const ESPERA_ALMACEN_EXTERNO: u64 = 100;
+
 /// Representa un error al leer el archivo de configuracion
 pub enum ArchivoError {
     ArchivoInexistenteError,
@@ -19,6 +43,24 @@ pub struct Config {
     mapa_config: HashMap<String, String>,
     persistidor: Option<Persistidor>,
     monitorear_ultimo_cliente: bool,
+    cluster: EstadoCluster,
+    replicacion: Arc<Replicacion>,
+    /// Clientes que se autenticaron con AUTH, solo se usa si esta configurado requirepass
+    autenticados: HashSet<Token>,
+    latencia: Arc<MonitorDeLatencia>,
+    bloqueos: Arc<BloqueoPorClave>,
+    temporizadores: Arc<RuedaDeTemporizadores>,
+    notificaciones: Arc<NotificacionesDeClaves>,
+    escrituras: Arc<EscriturasPorPrefijo>,
+    /// Reglas ~patron de la opcion user
+    permisos: PermisosDeClaves,
+    /// Registrados por quien embebe el servidor
+    almacenes_externos: Arc<AlmacenesExternos>,
+    /// Ninguno si no esta configurado appendonly yes
+    aof: Option<Arc<Aof>>,
+    estado_persistencia: Arc<EstadoPersistencia>,
+    /// Archivo del que se leyo la configuracion, para volver a leerlo con SIGHUP
+    archivo: Option<String>,
 }
 
 impl Config {
@@ -35,6 +77,19 @@ impl Config {
             mapa_config,
             persistidor: None,
             monitorear_ultimo_cliente: false,
+            cluster: EstadoCluster::default(),
+            replicacion: Arc::new(Replicacion::new()),
+            autenticados: HashSet::new(),
+            latencia: Arc::new(MonitorDeLatencia::new()),
+            bloqueos: Arc::new(BloqueoPorClave::new()),
+            temporizadores: Arc::new(RuedaDeTemporizadores::new()),
+            notificaciones: Arc::new(NotificacionesDeClaves::new()),
+            escrituras: Arc::new(EscriturasPorPrefijo::new()),
+            permisos: PermisosDeClaves::new(),
+            almacenes_externos: Arc::new(AlmacenesExternos::new()),
+            aof: None,
+            estado_persistencia: Arc::new(EstadoPersistencia::default()),
+            archivo: None,
         }
     }
 
@@ -59,6 +114,14 @@ impl Config {
         }
     }
 
+    /// Limite de mensajes de pub/sub pendientes de cada cliente y que hacer al superarlo
+    pub fn limite_de_salida(&self) -> LimiteDeSalida {
+        LimiteDeSalida::desde(
+            self.mapa_config.get("pubsub-max-pending"),
+            self.mapa_config.get("pubsub-overflow-policy"),
+        )
+    }
+
     pub fn dbfilename(&self) -> String {
         match self.mapa_config.get("dbfilename") {
             Some(d) => d.to_string(),
@@ -83,6 +146,162 @@ impl Config {
         }
     }
 
+    /// Indica si el servidor se configuro como replica de otro con la opcion replicaof
+    pub fn es_replica(&self) -> bool {
+        match self.mapa_config.get("replicaof") {
+            Some(r) => !r.trim().is_empty() && r.trim().to_lowercase() != "no one",
+            None => false,
+        }
+    }
+
+    /// Direccion host:puerto del maestro configurado con replicaof, si el servidor es replica
+    pub fn maestro(&self) -> Option<String> {
+        if !self.es_replica() {
+            return None;
+        }
+        let replicaof = self.mapa_config.get("replicaof")?;
+        let mut partes = replicaof.split_whitespace();
+        match (partes.next(), partes.next()) {
+            (Some(host), Some(puerto)) => Some(format!("{}:{}", host, puerto)),
+            _ => None,
+        }
+    }
+
+    pub fn puerto(&self) -> String {
+        match self.mapa_config.get("port") {
+            Some(p) => p.to_string(),
+            None => "8080".to_string(),
+        }
+    }
+
+    /// Contrasenia que deben enviar los clientes con AUTH, si esta configurada
+    pub fn requirepass(&self) -> Option<String> {
+        match self.mapa_config.get("requirepass") {
+            Some(p) if !p.is_empty() => Some(p.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Contrasenia con la que la replica se autentica ante su maestro
+    pub fn masterauth(&self) -> Option<String> {
+        match self.mapa_config.get("masterauth") {
+            Some(p) if !p.is_empty() => Some(p.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Indica si el cliente puede ejecutar comandos, siempre es asi si no hay requirepass
+    pub fn esta_autenticado(&self, cliente: Token) -> bool {
+        self.requirepass().is_none() || self.autenticados.contains(&cliente)
+    }
+
+    pub fn autenticar(&mut self, cliente: Token) {
+        self.autenticados.insert(cliente);
+    }
+
+    pub fn olvidar_autenticacion(&mut self, cliente: Token) {
+        self.autenticados.remove(&cliente);
+    }
+
+    /// Milisegundos que se conservan las claves despues de expirar para que GET ... STALE las
+    /// pueda seguir devolviendo mientras se refrescan, opcion stale-while-expired. 0 lo desactiva
+    pub fn gracia_de_expiracion(&self) -> Duration {
+        match self.mapa_config.get("stale-while-expired") {
+            Some(g) => Duration::from_millis(g.trim().parse().unwrap_or(0)),
+            None => Duration::from_millis(0),
+        }
+    }
+
+    /// Umbrales de la desfragmentacion en segundo plano, activada con activedefrag yes: el
+    /// porcentaje de memoria desperdiciada (active-defrag-threshold-lower) y los bytes
+    /// desperdiciados (active-defrag-ignore-bytes) que se deben superar para desfragmentar
+    pub fn umbrales_de_desfragmentacion(&self) -> Option<(u64, usize)> {
+        match self.mapa_config.get("activedefrag") {
+            Some(a) if a.trim().to_lowercase() == "yes" => (),
+            _ => return None,
+        };
+        let porcentaje = match self.mapa_config.get("active-defrag-threshold-lower") {
+            Some(p) => p.parse().unwrap_or(UMBRAL_DESFRAGMENTACION),
+            None => UMBRAL_DESFRAGMENTACION,
+        };
+        let bytes = match self.mapa_config.get("active-defrag-ignore-bytes") {
+            Some(b) => b.parse().unwrap_or(BYTES_IGNORADOS_DESFRAGMENTACION),
+            None => BYTES_IGNORADOS_DESFRAGMENTACION,
+        };
+        Some((porcentaje, bytes))
+    }
+
+    pub fn almacenes_externos(&self) -> Arc<AlmacenesExternos> {
+        Arc::clone(&self.almacenes_externos)
+    }
+
+    /// Espera maxima de cada operacion sobre un almacen externo, opcion external-store-timeout
+    /// en milisegundos
+    pub fn espera_almacen_externo(&self) -> Duration {
+        let milisegundos = match self.mapa_config.get("external-store-timeout") {
+            Some(e) => e.trim().parse().unwrap_or(ESPERA_ALMACEN_EXTERNO),
+            None => ESPERA_ALMACEN_EXTERNO,
+        };
+        Duration::from_millis(milisegundos)
+    }
+
+    pub fn latencia(&self) -> Arc<MonitorDeLatencia> {
+        Arc::clone(&self.latencia)
+    }
+
+    /// Bloqueos por clave que toman los comandos mientras se ejecutan
+    pub fn bloqueos(&self) -> Arc<BloqueoPorClave> {
+        Arc::clone(&self.bloqueos)
+    }
+
+    /// Temporizadores compartidos por los comandos bloqueantes para vencer sus esperas
+    pub fn temporizadores(&self) -> Arc<RuedaDeTemporizadores> {
+        Arc::clone(&self.temporizadores)
+    }
+
+    /// Notificaciones de espacio de claves, configuradas con notify-keyspace-events
+    pub fn notificaciones_de_claves(&self) -> Arc<NotificacionesDeClaves> {
+        Arc::clone(&self.notificaciones)
+    }
+
+    /// Escrituras contadas por prefijo de clave, configurado con key-prefix-buckets
+    pub fn escrituras_por_prefijo(&self) -> Arc<EscriturasPorPrefijo> {
+        Arc::clone(&self.escrituras)
+    }
+
+    /// Claves a las que pueden acceder los clientes segun las reglas ~patron de la opcion user
+    pub fn permisos_de_claves(&self) -> &PermisosDeClaves {
+        &self.permisos
+    }
+
+    pub fn replicacion(&self) -> Arc<Replicacion> {
+        Arc::clone(&self.replicacion)
+    }
+
+    /// Limites de las representaciones compactas de las colecciones,
+    /// los parametros que no estan configurados toman los valores predeterminados de redis
+    pub fn limites_de_codificacion(&self) -> LimitesDeCodificacion {
+        let predeterminados = LimitesDeCodificacion::default();
+        let limite = |clave: &str, predeterminado: usize| match self.mapa_config.get(clave) {
+            Some(l) => l.parse().unwrap_or(predeterminado),
+            None => predeterminado,
+        };
+        LimitesDeCodificacion {
+            set_max_listpack_entradas: limite(
+                "set-max-listpack-entries",
+                predeterminados.set_max_listpack_entradas,
+            ),
+            set_max_listpack_valor: limite(
+                "set-max-listpack-value",
+                predeterminados.set_max_listpack_valor,
+            ),
+            set_max_intset_entradas: limite(
+                "set-max-intset-entries",
+                predeterminados.set_max_intset_entradas,
+            ),
+        }
+    }
+
     /// Obtiene los items de la configuracion que matchean la expresion regular
     pub fn get(&self, re: &str) -> Vec<String> {
         let regex = match Regex::new(re) {
@@ -102,9 +321,28 @@ impl Config {
 
     /// Setea un parametro de la configuracion
     pub fn set(&mut self, parametro: String, valor: String) {
+        self.configurar_suscriptor(&parametro, &valor);
         self.mapa_config.insert(parametro, valor);
     }
 
+    /// Aplica el parametro a los suscriptores de la base de datos que se pueden
+    /// reconfigurar sin reiniciar el servidor
+    fn configurar_suscriptor(&self, parametro: &str, valor: &str) {
+        match parametro {
+            "notify-keyspace-events" => self.notificaciones.configurar(valor),
+            "key-prefix-buckets" => self.escrituras.configurar(valor),
+            "user" => self.permisos.configurar(valor),
+            "replica-max-pending" => self.replicacion.configurar_limite(valor),
+            "latency-monitor-threshold" => self.latencia.configurar(valor),
+            "appendfsync" => {
+                if let Some(aof) = &self.aof {
+                    aof.configurar(valor)
+                }
+            }
+            _ => (),
+        }
+    }
+
     /// Devuelve un vector con las configuraciones del servidor
     pub fn info(&self) -> Vec<String> {
         let mut info = vec!["# Config".to_string(), "".to_string()];
@@ -134,6 +372,48 @@ impl Config {
         logger.archivo(self.logfile());
     }
 
+    /// Indica si la base de datos se persiste en disco. Con persistence no el servidor guarda
+    /// los datos solo en memoria, como un cache, y no lee ni escribe el archivo de persistencia.
+    /// Solo se tiene en cuenta al iniciar el servidor
+    pub fn persistencia_habilitada(&self) -> bool {
+        match self.mapa_config.get("persistence") {
+            Some(p) => p.trim().to_lowercase() != "no",
+            None => true,
+        }
+    }
+
+    /// Indica si las escrituras se agregan al AOF, con appendonly yes. Requiere que la
+    /// persistencia este habilitada y solo se tiene en cuenta al iniciar el servidor
+    pub fn aof_habilitado(&self) -> bool {
+        self.persistencia_habilitada()
+            && match self.mapa_config.get("appendonly") {
+                Some(a) => a.trim().to_lowercase() == "yes",
+                None => false,
+            }
+    }
+
+    pub fn appendfilename(&self) -> String {
+        match self.mapa_config.get("appendfilename") {
+            Some(a) => a.to_string(),
+            None => "appendonly.aof".to_string(),
+        }
+    }
+
+    pub fn appendfsync(&self) -> PoliticaFsync {
+        match self.mapa_config.get("appendfsync") {
+            Some(a) => PoliticaFsync::desde(a),
+            None => PoliticaFsync::CadaSegundo,
+        }
+    }
+
+    pub fn set_aof(&mut self, aof: Arc<Aof>) {
+        self.aof = Some(aof);
+    }
+
+    pub fn aof(&self) -> Option<Arc<Aof>> {
+        self.aof.clone()
+    }
+
     pub fn actualizar_persistencia(&self) {
         match &self.persistidor {
             Some(p) => p.cambiar_archivo(self.dbfilename()),
@@ -144,10 +424,122 @@ impl Config {
     pub fn set_persistidor(&mut self, p: Persistidor) {
         self.persistidor = Some(p);
     }
+
+    pub fn persistidor(&self) -> Option<Persistidor> {
+        self.persistidor.clone()
+    }
+
+    /// Resultado de los guardados y reaperturas del log, para INFO
+    pub fn estado_persistencia(&self) -> Arc<EstadoPersistencia> {
+        Arc::clone(&self.estado_persistencia)
+    }
+
+    /// Indica si el servidor debe ejecutarse en segundo plano, opcion daemonize
+    pub fn daemonize(&self) -> bool {
+        match self.mapa_config.get("daemonize") {
+            Some(d) => d.trim().to_lowercase() == "yes",
+            None => false,
+        }
+    }
+
+    /// Indica si el servidor no debe iniciar cuando el archivo de persistencia esta corrupto,
+    /// opcion refuse-start-on-corrupt-dump. Si no, lo inicia con lo que se pudo recuperar
+    pub fn rechazar_dump_corrupto(&self) -> bool {
+        match self.mapa_config.get("refuse-start-on-corrupt-dump") {
+            Some(r) => r.trim().to_lowercase() == "yes",
+            None => false,
+        }
+    }
+
+    /// Archivo donde se escribe el pid al empezar a escuchar, ninguno si no se configuro
+    pub fn pidfile(&self) -> Option<String> {
+        self.mapa_config
+            .get("pidfile")
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| p.trim().to_string())
+    }
+
+    /// Como avisarle al sistema de inicio que el servidor esta listo, opcion supervised
+    pub fn supervised(&self) -> Supervision {
+        match self.mapa_config.get("supervised") {
+            Some(s) => Supervision::desde(s),
+            None => Supervision::Ninguna,
+        }
+    }
+
+    /// Arma el estado del cluster con las opciones cluster-enabled y cluster-nodes
+    pub fn configurar_cluster(&mut self) {
+        let habilitado = match self.mapa_config.get("cluster-enabled") {
+            Some(h) => h.trim().to_lowercase() == "yes",
+            None => false,
+        };
+        let nodos = match self.mapa_config.get("cluster-nodes") {
+            Some(n) => n.to_string(),
+            None => String::new(),
+        };
+        self.cluster = EstadoCluster::new(habilitado, &self.direccion(), &nodos);
+    }
+
+    pub fn cluster(&self) -> &EstadoCluster {
+        &self.cluster
+    }
+
+    pub fn cluster_mut(&mut self) -> &mut EstadoCluster {
+        &mut self.cluster
+    }
+
+    /// Vuelve a leer el archivo de configuracion y aplica sus opciones como CONFIG SET.
+    /// Las opciones que solo se leen al iniciar, como el puerto, no cambian hasta reiniciar.
+    /// Devuelve la cantidad de opciones leidas
+    pub fn recargar(&mut self) -> Result<usize, ArchivoError> {
+        let opciones = match &self.archivo {
+            Some(ruta) => leer_opciones(ruta)?,
+            None => return Err(ArchivoError::ArchivoInexistenteError),
+        };
+        let cantidad = opciones.len();
+        for (parametro, valor) in opciones {
+            self.set(parametro, valor);
+        }
+        Ok(cantidad)
+    }
 }
 
 /// Lee un archivo de configuracion y devuelve la configuracion leida
 pub fn obtener_configuracion(ruta_archivo: String) -> Result<Config, ArchivoError> {
+    let mapa = leer_opciones(&ruta_archivo)?;
+
+    if mapa.is_empty() {
+        let mut config = Config::new();
+        config.archivo = Some(ruta_archivo);
+        Ok(config)
+    } else {
+        let config = Config {
+            mapa_config: mapa,
+            persistidor: None,
+            monitorear_ultimo_cliente: false,
+            cluster: EstadoCluster::default(),
+            replicacion: Arc::new(Replicacion::new()),
+            autenticados: HashSet::new(),
+            latencia: Arc::new(MonitorDeLatencia::new()),
+            bloqueos: Arc::new(BloqueoPorClave::new()),
+            temporizadores: Arc::new(RuedaDeTemporizadores::new()),
+            notificaciones: Arc::new(NotificacionesDeClaves::new()),
+            escrituras: Arc::new(EscriturasPorPrefijo::new()),
+            permisos: PermisosDeClaves::new(),
+            almacenes_externos: Arc::new(AlmacenesExternos::new()),
+            aof: None,
+            estado_persistencia: Arc::new(EstadoPersistencia::default()),
+            archivo: Some(ruta_archivo),
+        };
+        for (parametro, valor) in &config.mapa_config {
+            config.configurar_suscriptor(parametro, valor);
+        }
+        Ok(config)
+    }
+}
+
+/// Lee las opciones del archivo de configuracion, una por linea con la forma `opcion: valor`
+fn leer_opciones(ruta_archivo: &str) -> Result<HashMap<String, String>, ArchivoError> {
     let archivo = match File::open(ruta_archivo) {
         Ok(archivo) => archivo,
         Err(_) => return Err(ArchivoError::ArchivoInexistenteError),
@@ -158,17 +550,9 @@ pub fn obtener_configuracion(ruta_archivo: String) -> Result<Config, ArchivoErro
     let mut mapa = HashMap::new();
 
     while let Some(Ok(linea)) = lineas.next() {
-        let argumento: Vec<&str> = linea.split(": ").collect();
-        mapa.insert(argumento[0].to_string(), argumento[1].to_string());
-    }
-
-    if mapa.is_empty() {
-        Ok(Config::new())
-    } else {
-        Ok(Config {
-            mapa_config: mapa,
-            persistidor: None,
-            monitorear_ultimo_cliente: false,
-        })
+        if let Some((opcion, valor)) = linea.split_once(": ") {
+            mapa.insert(opcion.to_string(), valor.to_string());
+        }
     }
+    Ok(mapa)
 }
@@ -0,0 +1,163 @@
+use crate::codificacion::LimitesDeCodificacion;
+
+use std::collections::HashSet;
+use std::iter::FromIterator;
+
+/// Representacion de los sets de la base de datos. Mientras el set es chico se guarda en un
+/// vector ordenado (analogo a listpack e intset de redis) y, cuando supera los limites
+/// de codificacion, se pasa a una tabla de hash. El cambio es transparente para los comandos
+/// y nunca se vuelve a la representacion compacta
+#[derive(Debug, Clone)]
+pub enum Conjunto {
+    Compacto(Vec<String>),
+    Tabla(HashSet<String>),
+}
+
+impl Conjunto {
+    /// Instancia un set vacio con la representacion compacta
+    pub fn new() -> Self {
+        Conjunto::Compacto(Vec::new())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Conjunto::Compacto(elementos) => elementos.len(),
+            Conjunto::Tabla(elementos) => elementos.len(),
+        }
+    }
+
+    pub fn contains(&self, elemento: &str) -> bool {
+        match self {
+            Conjunto::Compacto(elementos) => elementos
+                .binary_search_by(|e| e.as_str().cmp(elemento))
+                .is_ok(),
+            Conjunto::Tabla(elementos) => elementos.contains(elemento),
+        }
+    }
+
+    /// Agrega el elemento, devuelve false si ya pertenecia al set
+    pub fn insert(&mut self, elemento: String) -> bool {
+        match self {
+            Conjunto::Compacto(elementos) => match elementos.binary_search(&elemento) {
+                Ok(_) => false,
+                Err(posicion) => {
+                    elementos.insert(posicion, elemento);
+                    true
+                }
+            },
+            Conjunto::Tabla(elementos) => elementos.insert(elemento),
+        }
+    }
+
+    /// Quita el elemento, devuelve false si no pertenecia al set
+    pub fn remove(&mut self, elemento: &str) -> bool {
+        match self {
+            Conjunto::Compacto(elementos) => {
+                match elementos.binary_search_by(|e| e.as_str().cmp(elemento)) {
+                    Ok(posicion) => {
+                        elementos.remove(posicion);
+                        true
+                    }
+                    Err(_) => false,
+                }
+            }
+            Conjunto::Tabla(elementos) => elementos.remove(elemento),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &String> + '_> {
+        match self {
+            Conjunto::Compacto(elementos) => Box::new(elementos.iter()),
+            Conjunto::Tabla(elementos) => Box::new(elementos.iter()),
+        }
+    }
+
+    /// Pasa el set a la tabla de hash si su representacion compacta supera los limites
+    pub fn ajustar(&mut self, limites: &LimitesDeCodificacion) {
+        if let Conjunto::Compacto(elementos) = self {
+            if !limites.admite_set_compacto(elementos) {
+                *self = Conjunto::Tabla(elementos.drain(..).collect());
+            }
+        }
+    }
+
+    /// Nombre de la codificacion del set como lo informa OBJECT ENCODING
+    pub fn codificacion(&self) -> &'static str {
+        match self {
+            Conjunto::Compacto(_) => "listpack",
+            Conjunto::Tabla(_) => "hashtable",
+        }
+    }
+}
+
+/// Indica si todos los elementos se pueden representar como enteros
+pub fn son_enteros(elementos: &[String]) -> bool {
+    elementos.iter().all(|e| e.parse::<i64>().is_ok())
+}
+
+impl Default for Conjunto {
+    fn default() -> Self {
+        Conjunto::new()
+    }
+}
+
+/// Dos sets son iguales si tienen los mismos elementos, sin importar su representacion
+impl PartialEq for Conjunto {
+    fn eq(&self, otro: &Self) -> bool {
+        self.len() == otro.len() && self.iter().all(|e| otro.contains(e))
+    }
+}
+
+impl FromIterator<String> for Conjunto {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        let mut elementos: Vec<String> = iter.into_iter().collect();
+        elementos.sort_unstable();
+        elementos.dedup();
+        Conjunto::Compacto(elementos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limites(entradas: usize) -> LimitesDeCodificacion {
+        LimitesDeCodificacion {
+            set_max_listpack_entradas: entradas,
+            set_max_listpack_valor: 64,
+            set_max_intset_entradas: entradas,
+        }
+    }
+
+    #[test]
+    fn un_set_chico_conserva_la_representacion_compacta() {
+        let mut set = Conjunto::new();
+        assert!(set.insert("b".to_string()));
+        assert!(set.insert("a".to_string()));
+        assert!(!set.insert("a".to_string()));
+        set.ajustar(&limites(2));
+
+        assert_eq!(
+            Conjunto::Compacto(vec!["a".to_string(), "b".to_string()]),
+            set
+        );
+        assert_eq!("listpack", set.codificacion());
+        assert!(set.contains("a"));
+        assert!(set.remove("a"));
+        assert!(!set.contains("a"));
+    }
+
+    #[test]
+    fn un_set_que_supera_los_limites_pasa_a_ser_una_tabla_de_hash() {
+        let mut set: Conjunto = vec!["1".to_string(), "2".to_string()].into_iter().collect();
+        assert_eq!("listpack", set.codificacion());
+
+        set.insert("3".to_string());
+        set.ajustar(&limites(2));
+
+        assert!(matches!(set, Conjunto::Tabla(_)));
+        assert_eq!("hashtable", set.codificacion());
+        assert_eq!(3, set.len());
+        assert!(set.contains("3"));
+    }
+}
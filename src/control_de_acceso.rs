@@ -0,0 +1,127 @@
+use crate::base_de_datos::ResultadoRedis;
+use crate::cliente::Token;
+use crate::comando_info::ComandoInfo;
+use crate::config::Config;
+use crate::interceptor::{Interceptor, Peticion};
+
+use std::sync::{Arc, Mutex};
+
+/// Atiende AUTH y rechaza los comandos de los clientes que no se autenticaron cuando esta
+/// configurado requirepass, o que acceden a claves que no permiten las reglas ~patron de user
+pub struct ControlDeAcceso {
+    config: Arc<Mutex<Config>>,
+}
+
+impl ControlDeAcceso {
+    pub fn new(config: Arc<Mutex<Config>>) -> Self {
+        ControlDeAcceso { config }
+    }
+}
+
+impl Interceptor for ControlDeAcceso {
+    fn antes(&self, peticion: &Peticion) -> Option<ResultadoRedis> {
+        let token = peticion.token();
+        if peticion.comando.get_nombre() == "AUTH" {
+            return Some(autenticar(&self.config, token, &peticion.comando));
+        }
+        let c = match self.config.lock() {
+            Ok(c) => c,
+            Err(_) => {
+                return Some(ResultadoRedis::Error(
+                    "ERR when accessing config".to_string(),
+                ))
+            }
+        };
+        if !c.esta_autenticado(token) && peticion.comando.get_nombre() != "QUIT" {
+            return Some(ResultadoRedis::Error(
+                "NOAUTH Authentication required.".to_string(),
+            ));
+        }
+        if c.permisos_de_claves()
+            .primera_no_permitida(&peticion.claves)
+            .is_some()
+        {
+            return Some(ResultadoRedis::Error(
+                "NOPERM No permissions to access a key".to_string(),
+            ));
+        }
+        None
+    }
+}
+
+/// Autentica al cliente con la contrasenia de requirepass. Como no hay otros usuarios, el unico
+/// nombre de usuario aceptado es default
+fn autenticar(config: &Mutex<Config>, token: Token, entrada: &ComandoInfo) -> ResultadoRedis {
+    let (usuario, contrasenia) = match entrada.tokens() {
+        [contrasenia] => ("default", contrasenia),
+        [usuario, contrasenia] => (usuario.as_str(), contrasenia),
+        _ => return ResultadoRedis::Error("ERR syntax error".to_string()),
+    };
+    let mut c = match config.lock() {
+        Ok(c) => c,
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    match c.requirepass() {
+        None => ResultadoRedis::Error(
+            "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?"
+                .to_string(),
+        ),
+        Some(requerida) if usuario == "default" && contrasenia == &requerida => {
+            c.autenticar(token);
+            ResultadoRedis::StrSimple("OK".to_string())
+        }
+        Some(_) => ResultadoRedis::Error(
+            "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replicacion::codificar_comando;
+    use crate::resp;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::{BufReader, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn los_clientes_deben_autenticarse_y_solo_acceden_a_las_claves_permitidas() {
+        let mut config = Config::new();
+        config.set("requirepass".to_string(), "secreto".to_string());
+        config.set("user".to_string(), "default ~app:*".to_string());
+        let servidor = ServidorDePrueba::iniciar_con(config).unwrap();
+        let mut conexion = TcpStream::connect(servidor.direccion()).unwrap();
+        let mut lector = BufReader::new(conexion.try_clone().unwrap());
+        let mut enviar = |tokens: &[&str]| {
+            let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+            conexion
+                .write_all(codificar_comando(&tokens).as_bytes())
+                .unwrap();
+            resp::leer(&mut lector).unwrap()
+        };
+
+        assert_eq!(
+            ResultadoRedis::Error("NOAUTH Authentication required.".to_string()),
+            enviar(&["SET", "app:1", "a"])
+        );
+        assert_eq!(
+            ResultadoRedis::Error(
+                "WRONGPASS invalid username-password pair or user is disabled.".to_string()
+            ),
+            enviar(&["AUTH", "otra"])
+        );
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            enviar(&["AUTH", "secreto"])
+        );
+        assert_eq!(
+            ResultadoRedis::StrSimple("OK".to_string()),
+            enviar(&["SET", "app:1", "a"])
+        );
+        assert_eq!(
+            ResultadoRedis::Error("NOPERM No permissions to access a key".to_string()),
+            enviar(&["GET", "otra"])
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command, Stdio};
+
+/// Variable de entorno con la que el proceso lanzado en segundo plano sabe que ya es el demonio
+const VARIABLE_DEMONIO: &str = "REDIS_SERVER_DEMONIO";
+
+/// Lanza el servidor en segundo plano con los mismos argumentos, en su propio grupo de procesos y
+/// sin entrada ni salida estandar, para que no dependa de la terminal. Devuelve verdadero en el
+/// proceso que lo lanzo, que debe terminar, y falso en el demonio, que debe seguir ejecutando
+pub fn demonizar() -> io::Result<bool> {
+    if env::var_os(VARIABLE_DEMONIO).is_some() {
+        return Ok(false);
+    }
+    Command::new(env::current_exe()?)
+        .args(env::args_os().skip(1))
+        .env(VARIABLE_DEMONIO, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+    Ok(true)
+}
+
+/// Archivo con el pid del servidor, se borra al liberarlo
+#[derive(Debug)]
+pub struct ArchivoPid {
+    ruta: String,
+}
+
+impl ArchivoPid {
+    /// Escribe el pid del proceso en la ruta indicada
+    pub fn crear(ruta: &str) -> io::Result<Self> {
+        fs::write(ruta, format!("{}\n", process::id()))?;
+        Ok(ArchivoPid {
+            ruta: ruta.to_string(),
+        })
+    }
+}
+
+impl Drop for ArchivoPid {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.ruta);
+    }
+}
+
+/// Como avisarle al sistema de inicio en que estado esta el servidor, opcion supervised
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Supervision {
+    Ninguna,
+    Systemd,
+    /// Systemd si el proceso se lanzo con NOTIFY_SOCKET
+    Automatica,
+}
+
+impl Supervision {
+    pub fn desde(valor: &str) -> Self {
+        match valor.trim().to_lowercase().as_str() {
+            "systemd" => Supervision::Systemd,
+            "auto" => Supervision::Automatica,
+            _ => Supervision::Ninguna,
+        }
+    }
+
+    /// Envia el estado al socket de NOTIFY_SOCKET, como sd_notify, por ejemplo `READY=1`.
+    /// Devuelve falso si no hay que avisar o el proceso no se lanzo desde systemd
+    pub fn notificar(&self, estado: &str) -> io::Result<bool> {
+        if *self == Supervision::Ninguna {
+            return Ok(false);
+        }
+        match env::var("NOTIFY_SOCKET") {
+            Ok(ruta) => notificar_systemd(&ruta, estado).map(|_| true),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// Los sockets que empiezan con @ estan en el espacio de nombres abstracto de Linux
+fn notificar_systemd(ruta: &str, estado: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    match ruta.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(nombre) => {
+            use std::os::linux::net::SocketAddrExt;
+            let direccion = std::os::unix::net::SocketAddr::from_abstract_name(nombre)?;
+            socket.send_to_addr(estado.as_bytes(), &direccion)?;
+        }
+        _ => {
+            socket.send_to(estado.as_bytes(), ruta)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn el_archivo_pid_tiene_el_pid_del_proceso_y_se_borra_al_liberarlo() {
+        let ruta = env::temp_dir().join(format!("redis-{}.pid", process::id()));
+        let ruta = ruta.to_string_lossy().to_string();
+
+        let archivo = ArchivoPid::crear(&ruta).unwrap();
+        assert_eq!(
+            format!("{}\n", process::id()),
+            fs::read_to_string(&ruta).unwrap()
+        );
+
+        drop(archivo);
+        assert!(!Path::new(&ruta).exists());
+    }
+
+    #[test]
+    fn el_estado_se_envia_al_socket_de_systemd() {
+        let ruta = env::temp_dir().join(format!("notify-{}.sock", process::id()));
+        let _ = fs::remove_file(&ruta);
+        let systemd = UnixDatagram::bind(&ruta).unwrap();
+
+        notificar_systemd(&ruta.to_string_lossy(), "READY=1").unwrap();
+
+        let mut recibido = [0; 16];
+        let leidos = systemd.recv(&mut recibido).unwrap();
+        assert_eq!(b"READY=1", &recibido[..leidos]);
+        assert!(!Supervision::desde("no").notificar("READY=1").unwrap());
+        fs::remove_file(&ruta).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use crate::base_de_datos::TipoRedis;
+use crate::cadena::Cadena;
+use crate::conjunto::Conjunto;
+use crate::valor::Valor;
+
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::time::SystemTime;
+
+/// Estimacion de la memoria que ocupan los contenedores de la base de datos: los bytes que
+/// estan en uso y los que estan reservados. Los Vec y las tablas de hash no devuelven memoria
+/// al achicarse, por lo que tras borrar muchos elementos la diferencia puede ser grande
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Fragmentacion {
+    pub en_uso: usize,
+    pub reservado: usize,
+}
+
+impl Fragmentacion {
+    /// Bytes reservados que no se estan usando
+    pub fn desperdicio(&self) -> usize {
+        self.reservado.saturating_sub(self.en_uso)
+    }
+
+    /// Porcentaje de la memoria reservada que no se esta usando
+    pub fn porcentaje(&self) -> u64 {
+        if self.reservado == 0 {
+            return 0;
+        }
+        (self.desperdicio() * 100 / self.reservado) as u64
+    }
+
+    /// Indica si vale la pena desfragmentar: el desperdicio supera el porcentaje indicado y
+    /// ademas la cantidad minima de bytes, para no recorrer la base por unos pocos bytes
+    pub fn supera(&self, porcentaje: u64, bytes_minimos: usize) -> bool {
+        self.desperdicio() > bytes_minimos && self.porcentaje() > porcentaje
+    }
+
+    fn sumar<T>(&mut self, largo: usize, capacidad: usize) {
+        self.en_uso += largo * size_of::<T>();
+        self.reservado += capacidad * size_of::<T>();
+    }
+}
+
+/// Estima la fragmentacion de la tabla y de las colecciones que guarda, sin contar las que ya
+/// expiraron en el instante indicado. Se calcula sobre una instantanea, por lo que no necesita
+/// bloquear a la base de datos
+pub fn estimar(tabla: &HashMap<Cadena, Valor>, ahora: SystemTime) -> Fragmentacion {
+    let mut fragmentacion = Fragmentacion::default();
+    fragmentacion.sumar::<(Cadena, Valor)>(tabla.len(), tabla.capacity());
+    for valor in tabla.values().filter_map(|v| v.get_al(ahora)) {
+        match valor {
+            TipoRedis::Lista(elementos) => {
+                fragmentacion.sumar::<String>(elementos.len(), elementos.capacity());
+                for elemento in elementos {
+                    fragmentacion.sumar::<u8>(elemento.len(), elemento.capacity());
+                }
+            }
+            TipoRedis::Set(Conjunto::Compacto(elementos)) => {
+                fragmentacion.sumar::<String>(elementos.len(), elementos.capacity())
+            }
+            TipoRedis::Set(Conjunto::Tabla(elementos)) => {
+                fragmentacion.sumar::<String>(elementos.len(), elementos.capacity())
+            }
+            TipoRedis::Str(_) | TipoRedis::Canal(_) => (),
+        }
+    }
+    fragmentacion
+}
+
+/// Libera la capacidad sobrante de las colecciones del valor
+pub fn compactar(valor: &mut TipoRedis) {
+    match valor {
+        TipoRedis::Lista(elementos) => {
+            elementos.shrink_to_fit();
+            for elemento in elementos.iter_mut() {
+                elemento.shrink_to_fit();
+            }
+        }
+        TipoRedis::Set(Conjunto::Compacto(elementos)) => elementos.shrink_to_fit(),
+        TipoRedis::Set(Conjunto::Tabla(elementos)) => elementos.shrink_to_fit(),
+        TipoRedis::Str(_) | TipoRedis::Canal(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compactar_una_lista_vaciada_elimina_el_desperdicio() {
+        let mut elementos: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        elementos.truncate(10);
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            Cadena::from("lista"),
+            Valor::no_expirable(TipoRedis::Lista(elementos)),
+        );
+
+        let antes = estimar(&tabla, SystemTime::now());
+        assert!(antes.supera(50, 0));
+
+        for valor in tabla.values_mut() {
+            valor.compactar();
+        }
+        tabla.shrink_to_fit();
+        let despues = estimar(&tabla, SystemTime::now());
+        assert_eq!(antes.en_uso, despues.en_uso);
+        assert!(despues.desperdicio() < antes.desperdicio());
+        assert!(!despues.supera(50, 0));
+    }
+
+    #[test]
+    fn no_supera_el_umbral_si_el_desperdicio_es_menor_a_los_bytes_minimos() {
+        let fragmentacion = Fragmentacion {
+            en_uso: 10,
+            reservado: 100,
+        };
+        assert_eq!(90, fragmentacion.porcentaje());
+        assert!(fragmentacion.supera(50, 0));
+        assert!(!fragmentacion.supera(50, 1000));
+        assert!(!fragmentacion.supera(95, 0));
+    }
+}
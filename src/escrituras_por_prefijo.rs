@@ -0,0 +1,134 @@
+use crate::base_de_datos::Instantanea;
+use crate::eventos::{Evento, Suscriptor};
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+#[derive(Debug, Default)]
+struct Contadores {
+    /// Patron configurado, su prefijo sin el * final y las escrituras contadas
+    prefijos: Vec<(String, String, u64)>,
+    /// Escrituras de claves que no empiezan con ninguno de los prefijos
+    otras: u64,
+}
+
+/// Cuenta las escrituras de claves agrupadas por prefijo, configurado con key-prefix-buckets
+/// como una lista de patrones separados por espacios, por ejemplo `session:* cart:*`. Cada
+/// escritura se cuenta en el primer patron cuyo prefijo coincide, o en otras. Sirve para
+/// estimar como se repartiria la carga de escrituras al dividir las claves entre nodos
+#[derive(Debug, Default)]
+pub struct EscriturasPorPrefijo {
+    contadores: Mutex<Contadores>,
+}
+
+impl EscriturasPorPrefijo {
+    pub fn new() -> Self {
+        EscriturasPorPrefijo::default()
+    }
+
+    fn contadores(&self) -> MutexGuard<'_, Contadores> {
+        match self.contadores.lock() {
+            Ok(c) => c,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    /// Reemplaza los patrones por los de la lista, reiniciando todos los contadores
+    pub fn configurar(&self, patrones: &str) {
+        let mut contadores = self.contadores();
+        contadores.prefijos = patrones
+            .split_whitespace()
+            .map(|p| (p.to_string(), p.trim_end_matches('*').to_string(), 0))
+            .collect();
+        contadores.otras = 0;
+    }
+
+    fn registrar(&self, claves: &[&str]) {
+        let mut contadores = self.contadores();
+        for clave in claves {
+            match contadores
+                .prefijos
+                .iter_mut()
+                .find(|(_, prefijo, _)| clave.starts_with(prefijo.as_str()))
+            {
+                Some((_, _, escrituras)) => *escrituras += 1,
+                None => contadores.otras += 1,
+            }
+        }
+    }
+
+    /// Seccion Keyprefixes de INFO con las escrituras de cada patron y las de las demas claves
+    pub fn seccion_info(&self) -> Vec<String> {
+        let contadores = self.contadores();
+        let mut info = vec!["# Keyprefixes".to_string()];
+        for (i, (patron, _, escrituras)) in contadores.prefijos.iter().enumerate() {
+            info.push(format!(
+                "prefix{}:pattern={},writes={}",
+                i, patron, escrituras
+            ));
+        }
+        info.push(format!("prefix_other:writes={}", contadores.otras));
+        info.push("".to_string());
+        info
+    }
+}
+
+/// Cuenta como escritura cada clave escrita o eliminada, las expiraciones no se cuentan
+impl Suscriptor for EscriturasPorPrefijo {
+    fn recibir(&self, eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+        let claves: Vec<&str> = eventos
+            .iter()
+            .filter_map(|evento| match evento {
+                Evento::ClaveEscrita(clave) | Evento::ClaveEliminada(clave) => Some(clave.as_str()),
+                _ => None,
+            })
+            .collect();
+        if !claves.is_empty() {
+            self.registrar(&claves);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn las_escrituras_se_cuentan_en_el_primer_prefijo_que_coincide() {
+        let escrituras = EscriturasPorPrefijo::new();
+        escrituras.configurar("session:* cart:* session:admin:*");
+        let tabla = Arc::new(HashMap::new());
+
+        escrituras.recibir(
+            &[
+                Evento::ClaveEscrita("session:1".to_string()),
+                Evento::ClaveEliminada("session:admin:2".to_string()),
+                Evento::ClaveEscrita("cart:1".to_string()),
+                Evento::ClaveExpirada("cart:2".to_string()),
+                Evento::ClaveEscrita("usuario:1".to_string()),
+            ],
+            &tabla,
+            SystemTime::now(),
+        );
+
+        assert_eq!(
+            vec![
+                "# Keyprefixes",
+                "prefix0:pattern=session:*,writes=2",
+                "prefix1:pattern=cart:*,writes=1",
+                "prefix2:pattern=session:admin:*,writes=0",
+                "prefix_other:writes=1",
+                "",
+            ],
+            escrituras.seccion_info()
+        );
+
+        escrituras.configurar("cart:*");
+        assert_eq!(
+            "prefix0:pattern=cart:*,writes=0",
+            escrituras.seccion_info()[1]
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use crate::claves_calientes::ClavesCalientes;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Cantidad de claves mas accedidas que se informan en INFO
+const CLAVES_CALIENTES_INFO: usize = 5;
+
+/// Estadisticas del servidor compartidas entre los hilos de los clientes, la base de datos
+/// y la expiracion de claves. Se actualizan sin tomar locks y se informan en INFO
+#[derive(Debug)]
+pub struct Estadisticas {
+    inicio: Instant,
+    conexiones_recibidas: AtomicU64,
+    conexiones_rechazadas: AtomicU64,
+    comandos_procesados: AtomicU64,
+    aciertos: AtomicU64,
+    fallos: AtomicU64,
+    claves_expiradas: AtomicU64,
+    suscriptores_en_panico: AtomicU64,
+    claves_calientes: ClavesCalientes,
+}
+
+impl Estadisticas {
+    pub fn new() -> Self {
+        Estadisticas {
+            inicio: Instant::now(),
+            conexiones_recibidas: AtomicU64::new(0),
+            conexiones_rechazadas: AtomicU64::new(0),
+            comandos_procesados: AtomicU64::new(0),
+            aciertos: AtomicU64::new(0),
+            fallos: AtomicU64::new(0),
+            claves_expiradas: AtomicU64::new(0),
+            suscriptores_en_panico: AtomicU64::new(0),
+            claves_calientes: ClavesCalientes::new(),
+        }
+    }
+
+    /// Registra una conexion aceptada por el servidor
+    pub fn conexion_recibida(&self) {
+        self.conexiones_recibidas.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra una conexion que el servidor no pudo atender
+    pub fn conexion_rechazada(&self) {
+        self.conexiones_rechazadas.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra un comando ejecutado
+    pub fn comando_procesado(&self) {
+        self.comandos_procesados.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra la busqueda de una clave, segun si se encontro o no
+    pub fn busqueda(&self, encontrada: bool) {
+        let contador = if encontrada {
+            &self.aciertos
+        } else {
+            &self.fallos
+        };
+        contador.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra claves eliminadas por expirar
+    pub fn claves_expiradas(&self, cantidad: usize) {
+        self.claves_expiradas
+            .fetch_add(cantidad as u64, Ordering::Relaxed);
+    }
+
+    /// Registra un suscriptor del bus de eventos que entro en panico
+    pub fn suscriptor_en_panico(&self) {
+        self.suscriptores_en_panico.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Registra el acceso de un comando a sus claves para detectar las mas accedidas
+    pub fn accesos(&self, claves: &[String]) {
+        self.claves_calientes.registrar(claves);
+    }
+
+    /// Devuelve las claves mas accedidas con su cantidad aproximada de accesos
+    pub fn claves_calientes(&self, cantidad: usize) -> Vec<(String, u64)> {
+        self.claves_calientes.mas_accedidas(cantidad)
+    }
+
+    /// Tiempo transcurrido desde que se inicio el servidor
+    pub fn tiempo_activo(&self) -> Duration {
+        self.inicio.elapsed()
+    }
+
+    /// Devuelve las estadisticas con el formato de las secciones Server y Stats de INFO
+    pub fn info(&self) -> Vec<String> {
+        vec![
+            "# Server".to_string(),
+            format!("uptime_in_seconds:{}", self.tiempo_activo().as_secs()),
+            "".to_string(),
+            "# Stats".to_string(),
+            format!(
+                "total_connections_received:{}",
+                self.conexiones_recibidas.load(Ordering::Relaxed)
+            ),
+            format!(
+                "total_commands_processed:{}",
+                self.comandos_procesados.load(Ordering::Relaxed)
+            ),
+            format!(
+                "rejected_connections:{}",
+                self.conexiones_rechazadas.load(Ordering::Relaxed)
+            ),
+            format!(
+                "expired_keys:{}",
+                self.claves_expiradas.load(Ordering::Relaxed)
+            ),
+            format!(
+                "event_subscriber_panics:{}",
+                self.suscriptores_en_panico.load(Ordering::Relaxed)
+            ),
+            format!("keyspace_hits:{}", self.aciertos.load(Ordering::Relaxed)),
+            format!("keyspace_misses:{}", self.fallos.load(Ordering::Relaxed)),
+            format!(
+                "hotkeys:{}",
+                self.claves_calientes(CLAVES_CALIENTES_INFO)
+                    .iter()
+                    .map(|(clave, accesos)| format!("{}={}", clave, accesos))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+            "".to_string(),
+        ]
+    }
+}
+
+impl Default for Estadisticas {
+    fn default() -> Self {
+        Estadisticas::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_de_datos::ResultadoRedis;
+    use crate::replicacion::codificar_comando;
+    use crate::resp;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::{BufReader, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn las_estadisticas_se_actualizan_desde_varios_hilos() {
+        let estadisticas = Arc::new(Estadisticas::new());
+        let hilos: Vec<_> = (0..4)
+            .map(|_| {
+                let clon = Arc::clone(&estadisticas);
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        clon.comando_procesado();
+                        clon.busqueda(true);
+                    }
+                    clon.conexion_recibida();
+                })
+            })
+            .collect();
+        for hilo in hilos {
+            hilo.join().unwrap();
+        }
+        estadisticas.busqueda(false);
+        estadisticas.claves_expiradas(3);
+        estadisticas.accesos(&["a".to_string(), "b".to_string(), "a".to_string()]);
+
+        let info = estadisticas.info();
+        assert!(info.contains(&"total_connections_received:4".to_string()));
+        assert!(info.contains(&"total_commands_processed:400".to_string()));
+        assert!(info.contains(&"keyspace_hits:400".to_string()));
+        assert!(info.contains(&"keyspace_misses:1".to_string()));
+        assert!(info.contains(&"expired_keys:3".to_string()));
+        assert!(info.contains(&"rejected_connections:0".to_string()));
+        assert!(info.contains(&"hotkeys:a=2,b=1".to_string()));
+    }
+
+    #[test]
+    fn solo_las_lecturas_cuentan_aciertos_y_fallos() {
+        let servidor = ServidorDePrueba::iniciar().unwrap();
+        let mut conexion = TcpStream::connect(servidor.direccion()).unwrap();
+        let mut lector = BufReader::new(conexion.try_clone().unwrap());
+        let mut enviar = |tokens: &[&str]| {
+            let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+            conexion
+                .write_all(codificar_comando(&tokens).as_bytes())
+                .unwrap();
+            resp::leer(&mut lector).unwrap()
+        };
+
+        enviar(&["SET", "a", "1"]);
+        enviar(&["APPEND", "a", "2"]);
+        enviar(&["DEL", "b"]);
+        enviar(&["GET", "a"]);
+        enviar(&["MGET", "a", "b", "c"]);
+
+        let info = match enviar(&["INFO"]) {
+            ResultadoRedis::Vector(info) => info,
+            otro => panic!("respuesta inesperada {:?}", otro),
+        };
+        assert!(info.contains(&ResultadoRedis::BulkStr("keyspace_hits:2".to_string())));
+        assert!(info.contains(&ResultadoRedis::BulkStr("keyspace_misses:2".to_string())));
+    }
+}
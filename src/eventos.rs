@@ -0,0 +1,331 @@
+use crate::base_de_datos::Instantanea;
+use crate::cliente::Token;
+
+use std::any::{self, Any};
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Identifica a un suscriptor del bus para poder quitarlo
+pub type IdSuscripcion = u64;
+
+thread_local! {
+    static EMITIENDO: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Predicado que indica si el hilo actual esta entregando eventos a los suscriptores, es decir
+/// si tiene la base de datos tomada y no debe bloquearse esperando a un cliente
+pub fn emitiendo() -> bool {
+    EMITIENDO.with(|e| e.get())
+}
+
+/// Evento que publica la base de datos en su bus interno
+#[derive(Debug, Clone, PartialEq)]
+pub enum Evento {
+    /// Se guardo o modifico el valor de la clave
+    ClaveEscrita(String),
+    /// Se elimino la clave, por un borrado explicito o al renombrarla
+    ClaveEliminada(String),
+    /// Se elimino la clave porque vencio su expiracion
+    ClaveExpirada(String),
+    /// Se reemplazo la tabla completa, por ejemplo con FLUSHALL o al sincronizarse con el maestro
+    BaseReemplazada,
+    /// Termino de ejecutarse un comando de un cliente
+    ComandoEjecutado {
+        cliente: Token,
+        /// Nombre del comando seguido de sus argumentos
+        comando: Vec<String>,
+        /// Claves a las que accedio el comando segun el registro de comandos
+        claves: Vec<String>,
+        escritura: bool,
+        exitoso: bool,
+    },
+}
+
+impl Evento {
+    /// Indica si el evento cambia el contenido de la tabla
+    pub fn modifica_la_tabla(&self) -> bool {
+        !matches!(self, Evento::ComandoEjecutado { .. })
+    }
+}
+
+/// Interesado en los eventos de la base de datos. Los eventos que produce una misma operacion
+/// se reciben juntos con la instantanea de la tabla luego de aplicarla y la hora del reloj de
+/// la base de datos, con la que se decide que claves de la instantanea expiraron. Se recibe con
+/// la base de datos bloqueada, por lo que no se la debe volver a bloquear y el trabajo costoso
+/// se debe delegar a otro hilo
+pub trait Suscriptor {
+    fn recibir(&self, eventos: &[Evento], tabla: &Instantanea, ahora: SystemTime);
+
+    /// Nombre con el que se identifica al suscriptor en el log, por defecto el de su tipo
+    fn nombre(&self) -> &str {
+        any::type_name::<Self>()
+    }
+
+    /// Indica si sin este suscriptor se perderian escrituras, como con la persistencia o la
+    /// replicacion. Un suscriptor critico no se quita del bus aunque entre en panico
+    fn es_critico(&self) -> bool {
+        false
+    }
+}
+
+/// Permite suscribir un componente compartido conservando otra referencia para configurarlo
+impl<T: Suscriptor> Suscriptor for Arc<T> {
+    fn recibir(&self, eventos: &[Evento], tabla: &Instantanea, ahora: SystemTime) {
+        self.as_ref().recibir(eventos, tabla, ahora);
+    }
+
+    fn nombre(&self) -> &str {
+        self.as_ref().nombre()
+    }
+
+    fn es_critico(&self) -> bool {
+        self.as_ref().es_critico()
+    }
+}
+
+/// Suscriptor que entro en panico al recibir eventos
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanicoDeSuscriptor {
+    pub nombre: String,
+    /// Mensaje con el que entro en panico, si era un texto
+    pub mensaje: String,
+    /// Si se lo quito del bus, lo que no ocurre con los suscriptores criticos
+    pub quitado: bool,
+}
+
+/// Bus de eventos de la base de datos. Reemplaza al observador que solo usaba la persistencia:
+/// la persistencia, el AOF, la propagacion a las replicas, las notificaciones de claves y las
+/// escrituras por prefijo se suscriben a los eventos que les interesan.
+/// Los suscriptores reciben los eventos en el orden en que se suscribieron. Un suscriptor que
+/// entra en panico no interrumpe la escritura ni a los demas suscriptores: se quita del bus,
+/// salvo que sea critico, y se informa a quien emitio los eventos para que lo registre
+#[derive(Default)]
+pub struct BusDeEventos {
+    suscriptores: Vec<(IdSuscripcion, Box<dyn Suscriptor + Send>)>,
+    siguiente_id: IdSuscripcion,
+}
+
+impl BusDeEventos {
+    pub fn new() -> Self {
+        BusDeEventos::default()
+    }
+
+    /// Agrega un suscriptor despues de los que ya estaban, devuelve su identificador
+    pub fn suscribir(&mut self, suscriptor: Box<dyn Suscriptor + Send>) -> IdSuscripcion {
+        self.siguiente_id += 1;
+        self.suscriptores.push((self.siguiente_id, suscriptor));
+        self.siguiente_id
+    }
+
+    /// Quita al suscriptor, devuelve falso si no estaba suscripto
+    pub fn desuscribir(&mut self, id: IdSuscripcion) -> bool {
+        let cantidad = self.suscriptores.len();
+        self.suscriptores.retain(|(actual, _)| *actual != id);
+        cantidad != self.suscriptores.len()
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.suscriptores.len()
+    }
+
+    /// Entrega los eventos a todos los suscriptores y devuelve los que entraron en panico.
+    /// Se quita a los que no son criticos, ya que su estado puede haber quedado inconsistente.
+    /// A los criticos no se los quita ni se propaga el panico, que dejaria envenenada a la base
+    /// de datos, sino que se los sigue intentando en cada emision
+    pub fn emitir(
+        &mut self,
+        eventos: &[Evento],
+        tabla: &Instantanea,
+        ahora: SystemTime,
+    ) -> Vec<PanicoDeSuscriptor> {
+        let mut panicos = Vec::new();
+        if eventos.is_empty() {
+            return panicos;
+        }
+        let anterior = EMITIENDO.with(|e| e.replace(true));
+        self.suscriptores.retain(|(_, suscriptor)| {
+            let error = match panic::catch_unwind(AssertUnwindSafe(|| {
+                suscriptor.recibir(eventos, tabla, ahora)
+            })) {
+                Ok(()) => return true,
+                Err(error) => error,
+            };
+            let quitado = !suscriptor.es_critico();
+            panicos.push(PanicoDeSuscriptor {
+                nombre: suscriptor.nombre().to_string(),
+                mensaje: mensaje_de_panico(error.as_ref()),
+                quitado,
+            });
+            !quitado
+        });
+        EMITIENDO.with(|e| e.set(anterior));
+        panicos
+    }
+}
+
+fn mensaje_de_panico(error: &(dyn Any + Send)) -> String {
+    match (error.downcast_ref::<&str>(), error.downcast_ref::<String>()) {
+        (Some(mensaje), _) => mensaje.to_string(),
+        (_, Some(mensaje)) => mensaje.to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    struct Registro(Arc<Mutex<Vec<Evento>>>);
+
+    impl Suscriptor for Registro {
+        fn recibir(&self, eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            self.0.lock().unwrap().extend_from_slice(eventos);
+        }
+    }
+
+    struct Defectuoso;
+
+    impl Suscriptor for Defectuoso {
+        fn recibir(&self, _eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            panic!("suscriptor defectuoso");
+        }
+    }
+
+    struct Ordenado(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Suscriptor for Ordenado {
+        fn recibir(&self, _eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    #[test]
+    fn todos_los_suscriptores_reciben_los_eventos_emitidos() {
+        let primero = Arc::new(Mutex::new(Vec::new()));
+        let segundo = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = BusDeEventos::new();
+        bus.suscribir(Box::new(Registro(Arc::clone(&primero))));
+        bus.suscribir(Box::new(Registro(Arc::clone(&segundo))));
+
+        let tabla = Arc::new(HashMap::new());
+        bus.emitir(&[], &tabla, SystemTime::now());
+        bus.emitir(
+            &[
+                Evento::ClaveEscrita("a".to_string()),
+                Evento::ClaveEliminada("b".to_string()),
+            ],
+            &tabla,
+            SystemTime::now(),
+        );
+
+        for recibidos in &[primero, segundo] {
+            assert_eq!(
+                vec![
+                    Evento::ClaveEscrita("a".to_string()),
+                    Evento::ClaveEliminada("b".to_string())
+                ],
+                *recibidos.lock().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn los_suscriptores_reciben_en_orden_de_suscripcion_y_se_pueden_quitar() {
+        let orden = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = BusDeEventos::new();
+        let persistencia = bus.suscribir(Box::new(Ordenado("persistencia", Arc::clone(&orden))));
+        bus.suscribir(Box::new(Ordenado("metricas", Arc::clone(&orden))));
+        bus.suscribir(Box::new(Ordenado("replicacion", Arc::clone(&orden))));
+        let tabla = Arc::new(HashMap::new());
+
+        bus.emitir(&[Evento::BaseReemplazada], &tabla, SystemTime::now());
+        assert!(bus.desuscribir(persistencia));
+        assert!(!bus.desuscribir(persistencia));
+        bus.emitir(&[Evento::BaseReemplazada], &tabla, SystemTime::now());
+
+        assert_eq!(
+            vec![
+                "persistencia",
+                "metricas",
+                "replicacion",
+                "metricas",
+                "replicacion"
+            ],
+            *orden.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn un_suscriptor_que_entra_en_panico_se_quita_sin_afectar_a_los_demas() {
+        let recibidos = Arc::new(Mutex::new(Vec::new()));
+        let mut bus = BusDeEventos::new();
+        bus.suscribir(Box::new(Defectuoso));
+        bus.suscribir(Box::new(Registro(Arc::clone(&recibidos))));
+        let tabla = Arc::new(HashMap::new());
+
+        let panicos = bus.emitir(
+            &[Evento::ClaveEscrita("a".to_string())],
+            &tabla,
+            SystemTime::now(),
+        );
+        assert_eq!(
+            vec![PanicoDeSuscriptor {
+                nombre: any::type_name::<Defectuoso>().to_string(),
+                mensaje: "suscriptor defectuoso".to_string(),
+                quitado: true,
+            }],
+            panicos
+        );
+        assert!(bus
+            .emitir(
+                &[Evento::ClaveEscrita("b".to_string())],
+                &tabla,
+                SystemTime::now()
+            )
+            .is_empty());
+
+        assert_eq!(1, bus.len());
+        assert_eq!(2, recibidos.lock().unwrap().len());
+    }
+
+    struct Critico;
+
+    impl Suscriptor for Critico {
+        fn recibir(&self, _eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+            panic!("sin disco");
+        }
+
+        fn nombre(&self) -> &str {
+            "aof"
+        }
+
+        fn es_critico(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn un_suscriptor_critico_que_entra_en_panico_sigue_suscripto() {
+        let mut bus = BusDeEventos::new();
+        bus.suscribir(Box::new(Arc::new(Critico)));
+        let tabla = Arc::new(HashMap::new());
+
+        for _ in 0..2 {
+            let panicos = bus.emitir(&[Evento::BaseReemplazada], &tabla, SystemTime::now());
+            assert_eq!(
+                vec![PanicoDeSuscriptor {
+                    nombre: "aof".to_string(),
+                    mensaje: "sin disco".to_string(),
+                    quitado: false,
+                }],
+                panicos
+            );
+        }
+        assert_eq!(1, bus.len());
+    }
+}
@@ -0,0 +1,60 @@
+/// Base del hash FNV-1a de 64 bits
+const BASE: u64 = 0xcbf2_9ce4_8422_2325;
+/// Primo del hash FNV-1a de 64 bits
+const PRIMO: u64 = 0x0100_0000_01b3;
+
+/// Hash FNV-1a de 64 bits que se calcula a medida que llegan los bytes, para sumas de control
+/// de contenidos que no se tienen completos en memoria
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub fn new() -> Self {
+        Fnv1a(BASE)
+    }
+
+    /// Instancia un hash con la base alterada por la semilla, para obtener hashes
+    /// independientes de un mismo contenido
+    pub fn con_semilla(semilla: u64) -> Self {
+        Fnv1a(BASE ^ semilla)
+    }
+
+    pub fn agregar(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |suma, byte| {
+            (suma ^ *byte as u64).wrapping_mul(PRIMO)
+        });
+    }
+
+    pub fn suma(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a::new()
+    }
+}
+
+/// Hash FNV-1a de 64 bits de los bytes
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = Fnv1a::new();
+    hash.agregar(bytes);
+    hash.suma()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calcular_por_partes_da_la_misma_suma_que_de_una_vez() {
+        let mut hash = Fnv1a::new();
+        hash.agregar(b"hola ");
+        hash.agregar(b"mundo");
+
+        assert_eq!(fnv1a(b"hola mundo"), hash.suma());
+        assert_eq!(0xcbf2_9ce4_8422_2325, fnv1a(b""));
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::cliente::Token;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Entidad encargada de repartir los Token de los clientes.
+/// Los Token son unicos y crecientes durante toda la vida del servidor,
+/// por lo que un cliente que se reconecta recibe uno nuevo y nunca se reutiliza uno ya entregado
+#[derive(Debug)]
+pub struct GeneradorDeTokens {
+    siguiente: AtomicI64,
+}
+
+impl GeneradorDeTokens {
+    /// Instancia un generador que comienza a repartir desde el Token 0
+    pub fn new() -> Self {
+        GeneradorDeTokens {
+            siguiente: AtomicI64::new(0),
+        }
+    }
+
+    /// Devuelve un Token nunca antes entregado,
+    /// o ninguno si ya se agotaron los Token disponibles
+    pub fn siguiente(&self) -> Option<Token> {
+        self.siguiente
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |t| t.checked_add(1))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn el_generador_entrega_tokens_crecientes() {
+        let generador = GeneradorDeTokens::new();
+
+        assert_eq!(Some(0), generador.siguiente());
+        assert_eq!(Some(1), generador.siguiente());
+        assert_eq!(Some(2), generador.siguiente());
+    }
+
+    #[test]
+    fn el_generador_no_repite_tokens_entre_hilos() {
+        let generador = Arc::new(GeneradorDeTokens::new());
+
+        let hilos: Vec<_> = (0..4)
+            .map(|_| {
+                let generador = Arc::clone(&generador);
+                thread::spawn(move || {
+                    (0..100)
+                        .filter_map(|_| generador.siguiente())
+                        .collect::<Vec<Token>>()
+                })
+            })
+            .collect();
+
+        let mut tokens = HashSet::new();
+        for hilo in hilos {
+            for token in hilo.join().unwrap() {
+                assert!(tokens.insert(token));
+            }
+        }
+        assert_eq!(400, tokens.len());
+    }
+
+    #[test]
+    fn cuando_se_agotan_los_tokens_no_se_entrega_ninguno_mas() {
+        let generador = GeneradorDeTokens {
+            siguiente: AtomicI64::new(Token::MAX - 1),
+        };
+
+        assert_eq!(Some(Token::MAX - 1), generador.siguiente());
+        assert_eq!(None, generador.siguiente());
+        assert_eq!(None, generador.siguiente());
+    }
+}
@@ -0,0 +1,97 @@
+/// Traduce un patron glob como los de SCAN MATCH o las reglas `~patron` de ACL a una expresion
+/// regular que debe coincidir con todo el texto. Entiende `*`, `?`, las clases `[abc]`, `[a-z]`
+/// y `[^a]`, y `\` para tomar literalmente el caracter que le sigue. Un `[` sin cerrar se toma
+/// literalmente
+pub fn glob_a_regex(patron: &str) -> String {
+    let caracteres: Vec<char> = patron.chars().collect();
+    let mut regex = String::from("^");
+    let mut i = 0;
+    while i < caracteres.len() {
+        match caracteres[i] {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '\\' if i + 1 < caracteres.len() => {
+                i += 1;
+                regex.push_str(&literal(caracteres[i]));
+            }
+            '[' => match caracteres[i + 1..].iter().position(|&c| c == ']') {
+                Some(largo) => {
+                    regex.push_str(&clase(&caracteres[i + 1..i + 1 + largo]));
+                    i += largo + 1;
+                }
+                None => regex.push_str(&literal('[')),
+            },
+            otro => regex.push_str(&literal(otro)),
+        }
+        i += 1;
+    }
+    regex.push('$');
+    regex
+}
+
+/// Clase de caracteres con el contenido de los corchetes, respetando los rangos y la negacion
+fn clase(contenido: &[char]) -> String {
+    let (negada, contenido) = match contenido.first() {
+        Some('^') => (true, &contenido[1..]),
+        _ => (false, contenido),
+    };
+    if contenido.is_empty() {
+        // Una clase vacia no coincide con ningun caracter, o con cualquiera si esta negada
+        return String::from(if negada { "[\\s\\S]" } else { "[^\\s\\S]" });
+    }
+    let mut clase = String::from(if negada { "[^" } else { "[" });
+    for (i, &caracter) in contenido.iter().enumerate() {
+        match caracter {
+            '-' if i > 0 && i + 1 < contenido.len() => clase.push('-'),
+            otro => clase.push_str(&literal(otro)),
+        }
+    }
+    clase.push(']');
+    clase
+}
+
+fn literal(caracter: char) -> String {
+    regex::escape(&caracter.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn coincide(patron: &str, texto: &str) -> bool {
+        Regex::new(&glob_a_regex(patron)).unwrap().is_match(texto)
+    }
+
+    #[test]
+    fn el_asterisco_y_el_signo_de_pregunta_coinciden_con_varios_y_un_caracter() {
+        assert!(coincide("*", ""));
+        assert!(coincide("*", "cualquier.clave"));
+        assert!(coincide("usuario:*", "usuario:1"));
+        assert!(!coincide("usuario:*", "xusuario:1"));
+        assert!(coincide("h?llo", "hallo"));
+        assert!(!coincide("h?llo", "hllo"));
+        assert!(coincide("a.b", "a.b"));
+        assert!(!coincide("a.b", "axb"));
+    }
+
+    #[test]
+    fn los_corchetes_coinciden_con_una_clase_de_caracteres() {
+        assert!(coincide("h[ae]llo", "hello"));
+        assert!(!coincide("h[ae]llo", "hillo"));
+        assert!(coincide("h[^e]llo", "hallo"));
+        assert!(!coincide("h[^e]llo", "hello"));
+        assert!(coincide("clave[0-9]", "clave7"));
+        assert!(!coincide("clave[0-9]", "clavex"));
+        assert!(coincide("a[-]b", "a-b"));
+        assert!(coincide("a[b", "a[b"));
+    }
+
+    #[test]
+    fn la_barra_invertida_toma_literalmente_al_caracter_siguiente() {
+        assert!(coincide("a\\*b", "a*b"));
+        assert!(!coincide("a\\*b", "axb"));
+        assert!(coincide("a\\?", "a?"));
+        assert!(coincide("\\[x]", "[x]"));
+    }
+}
@@ -61,7 +61,11 @@ pub fn parsear_respuesta(res: &ResultadoRedis) -> String {
         ),
         ResultadoRedis::Nil => "(nil)".to_string(),
         ResultadoRedis::Error(e) => format!("(error) {}", e),
-        ResultadoRedis::Vacio => String::new(),
+        ResultadoRedis::Varios(vec) => vec
+            .iter()
+            .map(parsear_respuesta)
+            .collect::<Vec<String>>()
+            .join("\n"),
     }
 }
 
@@ -0,0 +1,230 @@
+use crate::base_de_datos::ResultadoRedis;
+use crate::cliente::{Cliente, Token};
+use crate::comando_info::ComandoInfo;
+use crate::estadisticas::Estadisticas;
+use crate::registro_comandos::RegistroDeComandos;
+
+use std::cell::Cell;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Comando de un cliente junto con las claves y banderas que le asigna el registro de comandos,
+/// es lo que reciben los interceptores y el manejador de comandos
+pub struct Peticion<'a> {
+    pub cliente: &'a Cliente,
+    pub comando: ComandoInfo,
+    pub claves: Vec<String>,
+    pub banderas: Vec<String>,
+    tiempos: Cell<Option<(Duration, Duration)>>,
+}
+
+impl<'a> Peticion<'a> {
+    pub fn new(cliente: &'a Cliente, comando: ComandoInfo, registro: &RegistroDeComandos) -> Self {
+        let (claves, banderas) = match registro.obtener(&comando.get_nombre()) {
+            Some(definicion) => (definicion.claves(&comando), definicion.banderas().to_vec()),
+            None => (Vec::new(), Vec::new()),
+        };
+        Peticion {
+            cliente,
+            comando,
+            claves,
+            banderas,
+            tiempos: Cell::new(None),
+        }
+    }
+
+    pub fn token(&self) -> Token {
+        self.cliente.obtener_token()
+    }
+
+    pub fn es_escritura(&self) -> bool {
+        self.banderas.iter().any(|b| b == "write")
+    }
+
+    pub fn es_lectura(&self) -> bool {
+        self.banderas.iter().any(|b| b == "readonly")
+    }
+
+    /// Anota cuanto espero el comando para tomar los bloqueos de sus claves y cuanto tardo en
+    /// ejecutarse una vez tomados
+    pub fn registrar_tiempos(&self, espera_lock: Duration, ejecucion: Duration) {
+        self.tiempos.set(Some((espera_lock, ejecucion)));
+    }
+
+    /// Espera de los bloqueos y duracion de la ejecucion, ninguna si el comando se rechazo
+    /// antes de ejecutarse
+    pub fn tiempos(&self) -> Option<(Duration, Duration)> {
+        self.tiempos.get()
+    }
+}
+
+/// Interfaz de los hooks que se ejecutan alrededor de cada comando, por ejemplo
+/// para loggear, chequear permisos, medir estadisticas o propagar comandos
+pub trait Interceptor {
+    /// Se ejecuta antes del comando. Si devuelve algun resultado el comando no se ejecuta
+    /// y ese resultado es el que se le envia al cliente
+    fn antes(&self, _peticion: &Peticion) -> Option<ResultadoRedis> {
+        None
+    }
+
+    /// Se ejecuta despues del comando con el resultado obtenido y el tiempo que tardo en ejecutarse
+    fn despues(&self, _peticion: &Peticion, _resultado: &ResultadoRedis, _duracion: Duration) {}
+}
+
+/// Cuenta cada comando que ejecuta el servidor
+impl Interceptor for Arc<Estadisticas> {
+    fn despues(&self, _peticion: &Peticion, _resultado: &ResultadoRedis, _duracion: Duration) {
+        self.comando_procesado();
+    }
+}
+
+/// Cadena de interceptores que se registran al iniciar el servidor y envuelven la ejecucion de
+/// los comandos de los clientes. Los comandos del AOF y del maestro no pasan por ella
+#[derive(Default)]
+pub struct CadenaDeInterceptores {
+    interceptores: Vec<Box<dyn Interceptor + Send + Sync>>,
+}
+
+impl CadenaDeInterceptores {
+    pub fn new() -> Self {
+        CadenaDeInterceptores::default()
+    }
+
+    /// Agrega un interceptor al final de la cadena
+    pub fn agregar(&mut self, interceptor: Box<dyn Interceptor + Send + Sync>) {
+        self.interceptores.push(interceptor);
+    }
+
+    /// Ejecuta el comando pasando por la cadena. Los `antes` se ejecutan en el orden de registro
+    /// y los `despues` en el orden inverso, solo para los interceptores cuyo `antes` se ejecuto
+    pub fn ejecutar<F>(&self, peticion: &Peticion, ejecutar: F) -> ResultadoRedis
+    where
+        F: FnOnce(&Peticion) -> ResultadoRedis,
+    {
+        let inicio = Instant::now();
+        let mut ejecutados = 0;
+        let mut resultado = None;
+
+        for interceptor in &self.interceptores {
+            ejecutados += 1;
+            resultado = interceptor.antes(peticion);
+            if resultado.is_some() {
+                break;
+            }
+        }
+
+        let resultado = match resultado {
+            Some(r) => r,
+            None => ejecutar(peticion),
+        };
+
+        let duracion = inicio.elapsed();
+        for interceptor in self.interceptores[..ejecutados].iter().rev() {
+            interceptor.despues(peticion, &resultado, duracion);
+        }
+        resultado
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cliente_redis::ClienteRedis;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Mutex;
+
+    struct Registro {
+        nombre: &'static str,
+        eventos: Arc<Mutex<Vec<String>>>,
+        rechazar: bool,
+    }
+
+    impl Interceptor for Registro {
+        fn antes(&self, peticion: &Peticion) -> Option<ResultadoRedis> {
+            self.eventos.lock().unwrap().push(format!(
+                "antes {} {}",
+                self.nombre,
+                peticion.comando.get_nombre()
+            ));
+            if self.rechazar {
+                return Some(ResultadoRedis::Error("NOPERM".to_string()));
+            }
+            None
+        }
+
+        fn despues(&self, _peticion: &Peticion, resultado: &ResultadoRedis, _duracion: Duration) {
+            self.eventos
+                .lock()
+                .unwrap()
+                .push(format!("despues {} {:?}", self.nombre, resultado));
+        }
+    }
+
+    fn cliente() -> Cliente {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        Box::new(ClienteRedis::new(1, 0, stream))
+    }
+
+    fn registro(
+        nombre: &'static str,
+        eventos: &Arc<Mutex<Vec<String>>>,
+        rechazar: bool,
+    ) -> Box<Registro> {
+        Box::new(Registro {
+            nombre,
+            eventos: Arc::clone(eventos),
+            rechazar,
+        })
+    }
+
+    #[test]
+    fn los_interceptores_envuelven_al_comando_en_orden() {
+        let eventos = Arc::new(Mutex::new(Vec::new()));
+        let mut cadena = CadenaDeInterceptores::new();
+        cadena.agregar(registro("a", &eventos, false));
+        cadena.agregar(registro("b", &eventos, false));
+
+        let cliente = cliente();
+        let peticion = Peticion::new(
+            &cliente,
+            ComandoInfo::new(vec!["ping".to_string()]),
+            &RegistroDeComandos::new(),
+        );
+        let resultado =
+            cadena.ejecutar(&peticion, |_| ResultadoRedis::StrSimple("PONG".to_string()));
+
+        assert_eq!(ResultadoRedis::StrSimple("PONG".to_string()), resultado);
+        assert_eq!(
+            vec![
+                "antes a PING",
+                "antes b PING",
+                "despues b StrSimple(\"PONG\")",
+                "despues a StrSimple(\"PONG\")",
+            ],
+            *eventos.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn un_interceptor_puede_cortar_la_ejecucion_del_comando() {
+        let eventos = Arc::new(Mutex::new(Vec::new()));
+        let mut cadena = CadenaDeInterceptores::new();
+        cadena.agregar(registro("a", &eventos, true));
+        cadena.agregar(registro("b", &eventos, false));
+
+        let cliente = cliente();
+        let peticion = Peticion::new(
+            &cliente,
+            ComandoInfo::new(vec!["ping".to_string()]),
+            &RegistroDeComandos::new(),
+        );
+        let resultado = cadena.ejecutar(&peticion, |_| panic!("el comando no se debe ejecutar"));
+
+        assert_eq!(ResultadoRedis::Error("NOPERM".to_string()), resultado);
+        assert_eq!(
+            vec!["antes a PING", "despues a Error(\"NOPERM\")"],
+            *eventos.lock().unwrap()
+        );
+    }
+}
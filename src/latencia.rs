@@ -0,0 +1,374 @@
+use crate::base_de_datos::ResultadoRedis;
+use crate::comando_info::ComandoInfo;
+use crate::interceptor::{Interceptor, Peticion};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cantidad de muestras que se guardan de cada evento, igual que redis
+const MUESTRAS_POR_EVENTO: usize = 160;
+
+/// Evento que registra el tiempo que un comando espera para tomar los bloqueos de sus claves,
+/// que mantiene durante toda su ejecucion
+pub const EVENTO_ESPERA_LOCK: &str = "lock-wait";
+
+/// Evento que registra el tiempo de ejecucion de un comando una vez tomados los bloqueos de sus
+/// claves, incluidos sus accesos a la tabla
+pub const EVENTO_COMANDO: &str = "command";
+
+/// Valores del histograma que se guardan exactos, los mayores se agrupan por potencia de dos
+const CUBETAS_EXACTAS: u64 = 16;
+
+/// Cubetas en que se divide cada potencia de dos, la precision relativa es de 1/8
+const SUBCUBETAS_BITS: u32 = 3;
+
+/// Cubetas necesarias para representar cualquier duracion en microsegundos de un u64
+const CANTIDAD_CUBETAS: usize = 16 + (64 - 4) * (1 << SUBCUBETAS_BITS);
+
+/// Percentiles de cada comando que se muestran en INFO latencystats
+const PERCENTILES: [(&str, f64); 3] = [("p50", 50.0), ("p99", 99.0), ("p99.9", 99.9)];
+
+/// Pico de latencia de un evento, en segundos desde UNIX_EPOCH y milisegundos de duracion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Muestra {
+    pub instante: u64,
+    pub milisegundos: u64,
+}
+
+#[derive(Debug, Default)]
+struct HistorialDeEvento {
+    muestras: VecDeque<Muestra>,
+    maximo: u64,
+}
+
+/// Histograma de duraciones en microsegundos con cubetas logaritmicas, como HdrHistogram:
+/// registrar una duracion es incrementar un contador y el error de cada percentil es menor
+/// al 12,5% sin importar la magnitud
+#[derive(Debug)]
+struct Histograma {
+    cubetas: Vec<u64>,
+    total: u64,
+}
+
+impl Default for Histograma {
+    fn default() -> Self {
+        Histograma {
+            cubetas: vec![0; CANTIDAD_CUBETAS],
+            total: 0,
+        }
+    }
+}
+
+impl Histograma {
+    fn registrar(&mut self, microsegundos: u64) {
+        self.cubetas[cubeta(microsegundos)] += 1;
+        self.total += 1;
+    }
+
+    /// Mayor duracion de la cubeta en la que cae el percentil indicado
+    fn percentil(&self, percentil: f64) -> u64 {
+        let objetivo = ((percentil / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut acumulado = 0;
+        for (indice, cantidad) in self.cubetas.iter().enumerate() {
+            acumulado += cantidad;
+            if acumulado >= objetivo {
+                return maximo_de_cubeta(indice);
+            }
+        }
+        0
+    }
+}
+
+/// Indice de la cubeta de la duracion: las menores a CUBETAS_EXACTAS tienen una cada una y las
+/// demas se reparten en 2^SUBCUBETAS_BITS cubetas por potencia de dos
+fn cubeta(microsegundos: u64) -> usize {
+    if microsegundos < CUBETAS_EXACTAS {
+        return microsegundos as usize;
+    }
+    let exponente = 63 - microsegundos.leading_zeros();
+    let subcubeta = (microsegundos >> (exponente - SUBCUBETAS_BITS)) & ((1 << SUBCUBETAS_BITS) - 1);
+    CUBETAS_EXACTAS as usize + ((exponente - 4) << SUBCUBETAS_BITS) as usize + subcubeta as usize
+}
+
+fn maximo_de_cubeta(indice: usize) -> u64 {
+    if indice < CUBETAS_EXACTAS as usize {
+        return indice as u64;
+    }
+    let relativo = indice - CUBETAS_EXACTAS as usize;
+    let exponente = (relativo >> SUBCUBETAS_BITS) as u32 + 4;
+    let subcubeta = (relativo & ((1 << SUBCUBETAS_BITS) - 1)) as u64;
+    let ancho = 1u64 << (exponente - SUBCUBETAS_BITS);
+    (1u64 << exponente).saturating_add((subcubeta + 1) * ancho - 1)
+}
+
+/// Monitor de latencia, guarda los picos de cada evento que superan el umbral configurado con
+/// latency-monitor-threshold para consultarlos con LATENCY. Separar la espera de los locks de
+/// la ejecucion permite distinguir si la lentitud se debe a la contencion o al comando en si.
+/// Ademas lleva un histograma del tiempo de ejecucion de cada comando, que a diferencia de los
+/// picos incluye todas las ejecuciones, para la seccion Latencystats de INFO
+#[derive(Debug, Default)]
+pub struct MonitorDeLatencia {
+    eventos: Mutex<HashMap<String, HistorialDeEvento>>,
+    por_comando: Mutex<HashMap<String, Histograma>>,
+    /// Umbral en milisegundos de latency-monitor-threshold, 0 desactiva el monitor
+    umbral: AtomicU64,
+}
+
+impl MonitorDeLatencia {
+    pub fn new() -> Self {
+        MonitorDeLatencia::default()
+    }
+
+    /// Cambia el umbral a partir del cual se registran los picos, con latency-monitor-threshold
+    pub fn configurar(&self, valor: &str) {
+        self.umbral
+            .store(valor.trim().parse().unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Registra la duracion del evento si alcanza el umbral en milisegundos, un umbral de 0
+    /// desactiva el monitor. Los picos de un mismo segundo se guardan como una unica muestra
+    pub fn registrar(&self, evento: &str, duracion: Duration) {
+        let umbral = self.umbral.load(Ordering::Relaxed);
+        let milisegundos = duracion.as_millis() as u64;
+        if umbral == 0 || milisegundos < umbral {
+            return;
+        }
+        let instante = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut eventos = match self.eventos.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        let historial = eventos.entry(evento.to_string()).or_default();
+        historial.maximo = historial.maximo.max(milisegundos);
+        match historial.muestras.back_mut() {
+            Some(ultima) if ultima.instante == instante => {
+                ultima.milisegundos = ultima.milisegundos.max(milisegundos)
+            }
+            _ => {
+                if historial.muestras.len() == MUESTRAS_POR_EVENTO {
+                    historial.muestras.pop_front();
+                }
+                historial.muestras.push_back(Muestra {
+                    instante,
+                    milisegundos,
+                });
+            }
+        }
+    }
+
+    /// Agrega la duracion de la ejecucion al histograma del comando
+    pub fn registrar_comando(&self, comando: &str, duracion: Duration) {
+        let mut por_comando = match self.por_comando.lock() {
+            Ok(p) => p,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        let microsegundos = duracion.as_micros().min(u64::MAX as u128) as u64;
+        match por_comando.get_mut(comando) {
+            Some(histograma) => histograma.registrar(microsegundos),
+            None => {
+                let mut histograma = Histograma::default();
+                histograma.registrar(microsegundos);
+                por_comando.insert(comando.to_string(), histograma);
+            }
+        }
+    }
+
+    /// Seccion Latencystats de INFO con los percentiles del tiempo de ejecucion de cada comando,
+    /// en microsegundos, en el formato de redis
+    pub fn seccion_info(&self) -> Vec<String> {
+        let por_comando = match self.por_comando.lock() {
+            Ok(p) => p,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        let mut comandos: Vec<&String> = por_comando.keys().collect();
+        comandos.sort();
+        let mut info = vec!["# Latencystats".to_string()];
+        for comando in comandos {
+            let percentiles: Vec<String> = PERCENTILES
+                .iter()
+                .map(|(nombre, percentil)| {
+                    format!(
+                        "{}={}.000",
+                        nombre,
+                        por_comando[comando].percentil(*percentil)
+                    )
+                })
+                .collect();
+            info.push(format!(
+                "latency_percentiles_usec_{}:{}",
+                comando.to_lowercase(),
+                percentiles.join(",")
+            ));
+        }
+        info.push("".to_string());
+        info
+    }
+
+    /// Ultima muestra y maximo historico de cada evento, ordenados por nombre
+    pub fn ultimas(&self) -> Vec<(String, Muestra, u64)> {
+        let eventos = match self.eventos.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        let mut ultimas: Vec<(String, Muestra, u64)> = eventos
+            .iter()
+            .filter_map(|(evento, historial)| {
+                historial
+                    .muestras
+                    .back()
+                    .map(|m| (evento.to_string(), *m, historial.maximo))
+            })
+            .collect();
+        ultimas.sort_by(|a, b| a.0.cmp(&b.0));
+        ultimas
+    }
+
+    /// Muestras guardadas del evento, de la mas antigua a la mas reciente
+    pub fn historial(&self, evento: &str) -> Vec<Muestra> {
+        let eventos = match self.eventos.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        match eventos.get(evento) {
+            Some(historial) => historial.muestras.iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Olvida las muestras de los eventos indicados, o de todos si no se indica ninguno.
+    /// Devuelve la cantidad de eventos reiniciados
+    pub fn reiniciar(&self, eventos: &[String]) -> usize {
+        let mut registrados = match self.eventos.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        if eventos.is_empty() {
+            let cantidad = registrados.len();
+            registrados.clear();
+            return cantidad;
+        }
+        eventos
+            .iter()
+            .filter(|e| registrados.remove(e.as_str()).is_some())
+            .count()
+    }
+}
+
+/// Registra en el monitor de latencia la espera de los bloqueos y la ejecucion de cada comando
+pub struct MedicionDeLatencia {
+    latencia: Arc<MonitorDeLatencia>,
+}
+
+impl MedicionDeLatencia {
+    pub fn new(latencia: Arc<MonitorDeLatencia>) -> Self {
+        MedicionDeLatencia { latencia }
+    }
+}
+
+impl Interceptor for MedicionDeLatencia {
+    fn despues(&self, peticion: &Peticion, _resultado: &ResultadoRedis, _duracion: Duration) {
+        let (espera, ejecucion) = match peticion.tiempos() {
+            Some(t) => t,
+            None => return,
+        };
+        self.latencia.registrar(EVENTO_ESPERA_LOCK, espera);
+        if !es_bloqueante(&peticion.comando) {
+            self.latencia.registrar(EVENTO_COMANDO, ejecucion);
+            self.latencia
+                .registrar_comando(&peticion.comando.get_nombre(), ejecucion);
+        }
+    }
+}
+
+/// Predicado que indica si el comando bloquea a proposito: WAIT esperando a las replicas y
+/// DEBUG PROFILE mientras perfila. Su duracion no es latencia del servidor
+pub fn es_bloqueante(comando: &ComandoInfo) -> bool {
+    comando.get_nombre() == "WAIT"
+        || (comando.get_nombre() == "DEBUG"
+            && comando
+                .tokens()
+                .first()
+                .is_some_and(|s| s.eq_ignore_ascii_case("PROFILE")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solo_se_registran_los_picos_que_alcanzan_el_umbral() {
+        let monitor = MonitorDeLatencia::new();
+        monitor.registrar(EVENTO_COMANDO, Duration::from_millis(50));
+        monitor.configurar("10");
+        monitor.registrar(EVENTO_COMANDO, Duration::from_millis(5));
+        assert!(monitor.ultimas().is_empty());
+
+        monitor.registrar(EVENTO_ESPERA_LOCK, Duration::from_millis(30));
+        monitor.registrar(EVENTO_ESPERA_LOCK, Duration::from_millis(20));
+        let ultimas = monitor.ultimas();
+        assert_eq!(1, ultimas.len());
+        assert_eq!(EVENTO_ESPERA_LOCK, ultimas[0].0);
+        assert_eq!(30, ultimas[0].2);
+
+        let historial = monitor.historial(EVENTO_ESPERA_LOCK);
+        assert!(!historial.is_empty() && historial.len() <= 2);
+        assert_eq!(30, historial.iter().map(|m| m.milisegundos).max().unwrap());
+    }
+
+    #[test]
+    fn cada_duracion_cae_en_una_cubeta_que_la_contiene_con_error_acotado() {
+        let mut anterior = 0;
+        for microsegundos in (0..100_000).chain([u64::MAX / 3, u64::MAX]) {
+            let indice = cubeta(microsegundos);
+            assert!(indice >= anterior && indice < CANTIDAD_CUBETAS);
+            anterior = indice;
+            let maximo = maximo_de_cubeta(indice);
+            assert!(maximo >= microsegundos);
+            assert!(maximo - microsegundos <= microsegundos / 8);
+        }
+    }
+
+    #[test]
+    fn latencystats_informa_los_percentiles_de_cada_comando() {
+        let monitor = MonitorDeLatencia::new();
+        for _ in 0..990 {
+            monitor.registrar_comando("GET", Duration::from_micros(10));
+        }
+        for _ in 0..10 {
+            monitor.registrar_comando("GET", Duration::from_millis(2));
+        }
+        monitor.registrar_comando("SET", Duration::from_micros(3));
+
+        assert_eq!(
+            vec![
+                "# Latencystats".to_string(),
+                "latency_percentiles_usec_get:p50=10.000,p99=10.000,p99.9=2047.000".to_string(),
+                "latency_percentiles_usec_set:p50=3.000,p99=3.000,p99.9=3.000".to_string(),
+                "".to_string(),
+            ],
+            monitor.seccion_info()
+        );
+    }
+
+    #[test]
+    fn reiniciar_olvida_los_eventos_indicados_o_todos() {
+        let monitor = MonitorDeLatencia::new();
+        monitor.configurar("10");
+        monitor.registrar(EVENTO_COMANDO, Duration::from_millis(20));
+        monitor.registrar(EVENTO_ESPERA_LOCK, Duration::from_millis(20));
+
+        assert_eq!(
+            1,
+            monitor.reiniciar(&[EVENTO_COMANDO.to_string(), "otro".to_string()])
+        );
+        assert!(monitor.historial(EVENTO_COMANDO).is_empty());
+        assert_eq!(1, monitor.reiniciar(&[]));
+        assert!(monitor.ultimas().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+//! Servidor redis embebible. El binario redis-server lo ejecuta con la configuracion de un
+//! archivo, y quien lo embebe puede ademas registrar sus propios comandos y suscribirse a los
+//! eventos de la base de datos o respaldar claves en un almacen externo
+
+mod almacen_externo;
+mod aof;
+mod base_de_datos;
+mod bloqueo_claves;
+mod cadena;
+mod canal;
+mod claves_calientes;
+mod cliente;
+mod cliente_http;
+mod cliente_redis;
+mod cluster;
+mod codificacion;
+mod cola_de_salida;
+mod comando;
+mod comando_client_handler;
+mod comando_http;
+mod comando_info;
+mod comando_key_handler;
+mod comando_list_handler;
+mod comando_nulo_handler;
+mod comando_pubsub_handler;
+mod comando_registro_handler;
+mod comando_replicacion_handler;
+mod comando_server_handler;
+mod comando_set_handler;
+mod comando_string_handler;
+mod config;
+mod conjunto;
+mod control_de_acceso;
+mod demonio;
+mod desfragmentacion;
+mod escrituras_por_prefijo;
+mod estadisticas;
+mod eventos;
+mod fnv;
+mod generador_tokens;
+mod glob;
+mod http_parser;
+mod interceptor;
+mod latencia;
+mod log_handler;
+pub mod modo_pipe;
+pub mod modo_replay;
+mod notificaciones_claves;
+mod opciones_parser;
+mod parser;
+mod perfilador;
+mod permisos_de_claves;
+mod persistencia;
+mod redis;
+mod redis_error;
+mod registro_comandos;
+mod reloj;
+mod replicacion;
+mod resp;
+mod seguimiento_claves;
+mod senales;
+mod servidor_de_prueba;
+mod subcomando;
+mod temporizador;
+mod valor;
+
+pub use crate::almacen_externo::AlmacenExterno;
+pub use crate::base_de_datos::{BaseDeDatos, Instantanea, ResultadoRedis};
+pub use crate::comando_info::ComandoInfo;
+pub use crate::config::{obtener_configuracion, Config};
+pub use crate::demonio::demonizar;
+pub use crate::eventos::{Evento, IdSuscripcion, Suscriptor};
+pub use crate::persistencia::verificar_dump;
+pub use crate::redis::{Detencion, Redis};
+pub use crate::redis_error::RedisError;
+pub use crate::registro_comandos::{FuncionComando, RegistroError};
+pub use crate::servidor_de_prueba::ServidorDePrueba;
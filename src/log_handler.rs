@@ -1,6 +1,8 @@
+use crate::base_de_datos::ResultadoRedis;
 use crate::canal::Canal;
 use crate::cliente::Cliente;
 use crate::comando_info::ComandoInfo;
+use crate::interceptor::{Interceptor, Peticion};
 use crate::redis_error::RedisError;
 
 use std::fs::OpenOptions;
@@ -52,7 +54,7 @@ impl LogHandler {
 
     /// Ejecuta al manejador esperando mensajes
     ///
-    /// ```no_run
+    /// ```ignore
     /// let (tx_log, rx_log) = channel();
     ///
     /// let mut log_handler: LogHandler =
@@ -206,3 +208,11 @@ impl Logger {
         if self.log.send(Mensaje::ArchivoALogear(ruta_nueva)).is_ok() {}
     }
 }
+
+/// El Logger registra cada comando antes de que se ejecute
+impl Interceptor for Logger {
+    fn antes(&self, peticion: &Peticion) -> Option<ResultadoRedis> {
+        self.log_comando(peticion.cliente.obtener_addr(), peticion.comando.clone());
+        None
+    }
+}
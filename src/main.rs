@@ -1,35 +1,33 @@
-mod base_de_datos;
-mod canal;
-mod cliente;
-mod cliente_http;
-mod cliente_redis;
-mod comando;
-mod comando_http;
-mod comando_info;
-mod comando_key_handler;
-mod comando_list_handler;
-mod comando_nulo_handler;
-mod comando_pubsub_handler;
-mod comando_server_handler;
-mod comando_set_handler;
-mod comando_string_handler;
-mod config;
-mod http_parser;
-mod log_handler;
-mod observer;
-mod parser;
-mod persistencia;
-mod redis;
-mod redis_error;
-mod valor;
-
 use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
 
-use crate::config::{obtener_configuracion, Config};
-use crate::redis::Redis;
+use proyecto_taller_1::{
+    demonizar, modo_pipe, modo_replay, obtener_configuracion, verificar_dump, Config, Redis,
+};
 
-/// Ejecuta el servidor redis
+/// Ejecuta el servidor redis. Con `--check-dump <archivo>` solo verifica el archivo de persistencia
+/// y con `--pipe [host:puerto] [archivo]` envia los comandos del archivo a un servidor en ejecucion.
+/// Con `--replay <archivo> [host:puerto] [velocidad]` vuelve a ejecutar los comandos de un log
 fn main() {
+    let argumentos: Vec<String> = env::args().collect();
+    if let Some(posicion) = argumentos.iter().position(|a| a == "--check-dump") {
+        chequear_dump(argumentos.get(posicion + 1));
+    }
+
+    if let Some(posicion) = argumentos.iter().position(|a| a == "--pipe") {
+        modo_pipe(argumentos.get(posicion + 1), argumentos.get(posicion + 2));
+    }
+
+    if let Some(posicion) = argumentos.iter().position(|a| a == "--replay") {
+        modo_replay(
+            argumentos.get(posicion + 1),
+            argumentos.get(posicion + 2),
+            argumentos.get(posicion + 3),
+        );
+    }
+
     let config = match env::args().last() {
         Some(ruta) => match obtener_configuracion(ruta) {
             Ok(config) => config,
@@ -38,9 +36,126 @@ fn main() {
         None => Config::new(),
     };
 
+    if config.daemonize() {
+        match demonizar() {
+            Ok(true) => process::exit(0),
+            Ok(false) => (),
+            Err(e) => {
+                eprintln!("No se pudo iniciar el servidor en segundo plano: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     let mut redis: Redis = Redis::new(config);
-    match redis.iniciar() {
-        Ok(_) => (),
-        Err(_) => println!("Error al iniciar"),
+    let resultado = redis.iniciar();
+    // Se libera antes de terminar el proceso para que se escriba lo que quedo en el log
+    drop(redis);
+    if let Err(e) = resultado {
+        eprintln!("Error al iniciar: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Imprime el informe del archivo de persistencia y termina el proceso,
+/// con codigo de salida distinto de cero si el archivo no es valido
+fn chequear_dump(archivo: Option<&String>) -> ! {
+    let archivo = match archivo {
+        Some(a) => a,
+        None => {
+            eprintln!("Uso: redis-server --check-dump <archivo>");
+            process::exit(2);
+        }
+    };
+    match verificar_dump(archivo) {
+        Ok(informe) => {
+            for linea in informe.lineas() {
+                println!("{}", linea);
+            }
+            process::exit(if informe.es_valido() { 0 } else { 1 });
+        }
+        Err(e) => {
+            eprintln!("No se pudo leer {}: {}", archivo, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Envia al servidor los comandos en RESP del archivo, o de la entrada estandar si no se indica,
+/// y termina el proceso con codigo de salida distinto de cero si algun comando fallo
+fn modo_pipe(direccion: Option<&String>, archivo: Option<&String>) -> ! {
+    let direccion = match direccion {
+        Some(d) => d.to_string(),
+        None => Config::new().direccion(),
+    };
+    let resultado = match archivo {
+        Some(archivo) => match File::open(archivo) {
+            Ok(a) => modo_pipe::enviar(&direccion, a),
+            Err(e) => {
+                eprintln!("No se pudo leer {}: {}", archivo, e);
+                process::exit(1);
+            }
+        },
+        None => modo_pipe::enviar(&direccion, io::stdin()),
+    };
+    match resultado {
+        Ok(resumen) => {
+            println!("Se recibio la ultima respuesta del servidor.");
+            println!(
+                "errores: {}, respuestas: {}",
+                resumen.errores, resumen.respuestas
+            );
+            process::exit(if resumen.errores == 0 { 0 } else { 1 });
+        }
+        Err(e) => {
+            eprintln!("Error en el modo pipe con {}: {}", direccion, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Vuelve a ejecutar en el servidor los comandos de un log de MONITOR o del logfile.
+/// La velocidad multiplica el ritmo original, 1 si no se indica y 0 para enviarlos sin pausas
+fn modo_replay(
+    archivo: Option<&String>,
+    direccion: Option<&String>,
+    velocidad: Option<&String>,
+) -> ! {
+    let uso = || -> ! {
+        eprintln!("Uso: redis-server --replay <archivo> [host:puerto] [velocidad]");
+        process::exit(2);
+    };
+    let archivo = match archivo {
+        Some(a) => a,
+        None => uso(),
+    };
+    let direccion = match direccion {
+        Some(d) => d.to_string(),
+        None => Config::new().direccion(),
+    };
+    let velocidad: f64 = match velocidad.map(|v| v.parse()) {
+        Some(Ok(v)) if v >= 0.0 => v,
+        Some(_) => uso(),
+        None => 1.0,
+    };
+    let entrada = match File::open(archivo) {
+        Ok(a) => BufReader::new(a),
+        Err(e) => {
+            eprintln!("No se pudo leer {}: {}", archivo, e);
+            process::exit(1);
+        }
     };
+    match modo_replay::reproducir(&direccion, entrada, velocidad) {
+        Ok(resumen) => {
+            println!(
+                "errores: {}, respuestas: {}",
+                resumen.errores, resumen.respuestas
+            );
+            process::exit(if resumen.errores == 0 { 0 } else { 1 });
+        }
+        Err(e) => {
+            eprintln!("Error al reproducir {} en {}: {}", archivo, direccion, e);
+            process::exit(1);
+        }
+    }
 }
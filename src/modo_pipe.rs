@@ -0,0 +1,97 @@
+use crate::base_de_datos::ResultadoRedis;
+use crate::resp;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Resultado de enviar un flujo de comandos con el modo pipe
+#[derive(Debug, Default, PartialEq)]
+pub struct ResumenPipe {
+    pub respuestas: usize,
+    pub errores: usize,
+}
+
+/// Envia al servidor un flujo de comandos ya codificados en RESP sin esperar la respuesta de cada
+/// uno, para cargar grandes volumenes de datos. Las respuestas se leen en otro hilo a medida que
+/// llegan. Al terminar la entrada se envia un ECHO con una marca aleatoria: cuando vuelve la marca
+/// ya se recibieron las respuestas de todos los comandos anteriores
+pub fn enviar<R: Read>(direccion: &str, mut entrada: R) -> io::Result<ResumenPipe> {
+    enviar_con(direccion, |conexion| {
+        io::copy(&mut entrada, conexion).map(|_| ())
+    })
+}
+
+/// Igual que enviar pero los comandos los escribe `escribir` en la conexion, por ejemplo
+/// para espaciarlos en el tiempo. Las respuestas se cuentan mientras se escriben
+pub fn enviar_con<F>(direccion: &str, escribir: F) -> io::Result<ResumenPipe>
+where
+    F: FnOnce(&mut TcpStream) -> io::Result<()>,
+{
+    let mut conexion = TcpStream::connect(direccion)?;
+    let lector = BufReader::new(conexion.try_clone()?);
+    let marca = generar_marca();
+    let marca_esperada = marca.clone().into_bytes();
+    let hilo_lector = thread::spawn(move || contar_respuestas(lector, &marca_esperada));
+
+    escribir(&mut conexion)?;
+    conexion
+        .write_all(format!("*2\r\n$4\r\nECHO\r\n${}\r\n{}\r\n", marca.len(), marca).as_bytes())?;
+    eprintln!("Se enviaron todos los datos, esperando la ultima respuesta...");
+    match hilo_lector.join() {
+        Ok(resumen) => resumen,
+        Err(_) => Err(io::Error::other("fallo el hilo que lee las respuestas")),
+    }
+}
+
+/// Lee respuestas hasta encontrar la marca, informando cada error por la salida de errores
+fn contar_respuestas<R: BufRead>(mut lector: R, marca: &[u8]) -> io::Result<ResumenPipe> {
+    let mut resumen = ResumenPipe::default();
+    loop {
+        match resp::leer(&mut lector)? {
+            ResultadoRedis::BulkStr(contenido) if contenido.as_bytes() == marca => {
+                return Ok(resumen)
+            }
+            ResultadoRedis::Error(error) => {
+                eprintln!("{}", error);
+                resumen.errores += 1;
+            }
+            _ => (),
+        }
+        resumen.respuestas += 1;
+    }
+}
+
+/// Marca de 40 caracteres hexadecimales que no deberia aparecer en las respuestas de los datos
+fn generar_marca() -> String {
+    let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos(),
+        Err(_) => 0,
+    };
+    format!("{:032x}{:08x}", nanos, process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn se_cuentan_las_respuestas_y_los_errores_hasta_la_marca() {
+        let respuestas = "+OK\r\n:3\r\n-ERR wrong number of arguments\r\n$-1\r\n\
+            *2\r\n$1\r\na\r\n*1\r\n:1\r\n$4\r\nMARC\r\n+OK\r\n";
+
+        let resumen = contar_respuestas(Cursor::new(respuestas), b"MARC").unwrap();
+
+        assert_eq!(
+            ResumenPipe {
+                respuestas: 5,
+                errores: 1
+            },
+            resumen
+        );
+        assert!(contar_respuestas(Cursor::new("+OK\r\n"), b"MARC").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::modo_pipe::{self, ResumenPipe};
+use crate::registro_comandos::RegistroDeComandos;
+use crate::replicacion::codificar_comando;
+
+use std::io::{self, BufRead, BufWriter, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Comandos que cambian el modo de la conexion y dejarian de contestar los que siguen
+const COMANDOS_NO_REPRODUCIBLES: &[&str] = &["MONITOR", "SYNC", "PSYNC", "QUIT"];
+
+/// Comando leido de una linea del log, con el instante en que se ejecuto si el formato lo indica
+#[derive(Debug, PartialEq)]
+struct ComandoRegistrado {
+    instante: Option<f64>,
+    tokens: Vec<String>,
+}
+
+/// Vuelve a ejecutar contra un servidor los comandos de un log, para reproducir problemas o
+/// precalentar una cache. Entiende dos formatos de linea:
+///
+/// * el de MONITOR de redis, `1339518083.107412 [0 127.0.0.1:60866] "set" "clave" "valor"`,
+///   que indica el instante de cada comando
+/// * el del logfile y el MONITOR de este servidor, `127.0.0.1:60866 SET clave valor`, sin instantes
+///   y con los parametros separados por espacios
+///
+/// Las lineas que no son comandos, como las conexiones o los errores, se ignoran.
+/// Con una velocidad mayor a cero los comandos con instante se espacian como en el original,
+/// dividiendo las pausas por la velocidad; con velocidad cero se envian sin pausas
+pub fn reproducir<R: BufRead>(
+    direccion: &str,
+    entrada: R,
+    velocidad: f64,
+) -> io::Result<ResumenPipe> {
+    let registro = RegistroDeComandos::new();
+    modo_pipe::enviar_con(direccion, |conexion| {
+        let mut escritor = BufWriter::new(conexion);
+        let mut origen: Option<(f64, Instant)> = None;
+        for linea in entrada.lines() {
+            let comando = match parsear_linea(&linea?, &registro) {
+                Some(c) => c,
+                None => continue,
+            };
+            if let (Some(instante), true) = (comando.instante, velocidad > 0.0) {
+                let (primero, inicio) = *origen.get_or_insert((instante, Instant::now()));
+                let objetivo = Duration::from_secs_f64(((instante - primero) / velocidad).max(0.0));
+                if let Some(espera) = objetivo.checked_sub(inicio.elapsed()) {
+                    escritor.flush()?;
+                    thread::sleep(espera);
+                }
+            }
+            escritor.write_all(codificar_comando(&comando.tokens).as_bytes())?;
+        }
+        escritor.flush()
+    })
+}
+
+fn parsear_linea(linea: &str, registro: &RegistroDeComandos) -> Option<ComandoRegistrado> {
+    let comando = parsear_linea_monitor(linea).or_else(|| parsear_linea_log(linea))?;
+    let definicion = registro.obtener(comando.tokens.first()?)?;
+    if COMANDOS_NO_REPRODUCIBLES.contains(&definicion.nombre())
+        || definicion.banderas().iter().any(|b| b == "pubsub")
+    {
+        return None;
+    }
+    Some(comando)
+}
+
+/// Linea del MONITOR de redis: instante, base y cliente entre corchetes y los tokens entre comillas
+fn parsear_linea_monitor(linea: &str) -> Option<ComandoRegistrado> {
+    let (instante, resto) = linea.split_once(' ')?;
+    let instante: f64 = instante.parse().ok()?;
+    let resto = resto.strip_prefix('[')?;
+    let (_, argumentos) = resto.split_once("] ")?;
+    Some(ComandoRegistrado {
+        instante: Some(instante),
+        tokens: parsear_entre_comillas(argumentos)?,
+    })
+}
+
+/// Linea del logfile de este servidor: direccion del cliente seguida del comando
+fn parsear_linea_log(linea: &str) -> Option<ComandoRegistrado> {
+    let mut palabras = linea.split_whitespace();
+    if !palabras.next()?.contains(':') {
+        return None;
+    }
+    let tokens: Vec<String> = palabras.map(str::to_string).collect();
+    if tokens.is_empty() {
+        return None;
+    }
+    Some(ComandoRegistrado {
+        instante: None,
+        tokens,
+    })
+}
+
+/// Separa los tokens entre comillas dobles, interpretando los escapes que usa MONITOR
+fn parsear_entre_comillas(argumentos: &str) -> Option<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut caracteres = argumentos.chars().peekable();
+    loop {
+        while caracteres.next_if(|c| *c == ' ').is_some() {}
+        match caracteres.next() {
+            None => return Some(tokens),
+            Some('"') => (),
+            Some(_) => return None,
+        }
+        let mut token = Vec::new();
+        loop {
+            match caracteres.next()? {
+                '"' => break,
+                '\\' => match caracteres.next()? {
+                    'n' => token.push(b'\n'),
+                    'r' => token.push(b'\r'),
+                    't' => token.push(b'\t'),
+                    'a' => token.push(0x07),
+                    'b' => token.push(0x08),
+                    'x' => {
+                        let hexa: String =
+                            [caracteres.next()?, caracteres.next()?].iter().collect();
+                        token.push(u8::from_str_radix(&hexa, 16).ok()?);
+                    }
+                    otro => token.extend(otro.to_string().as_bytes()),
+                },
+                c => token.extend(c.to_string().as_bytes()),
+            }
+        }
+        tokens.push(String::from_utf8_lossy(&token).to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::{Cursor, Read};
+    use std::net::TcpStream;
+
+    #[test]
+    fn se_entienden_los_dos_formatos_y_se_ignoran_las_lineas_que_no_son_comandos() {
+        let registro = RegistroDeComandos::new();
+        let comando = |linea| parsear_linea(linea, &registro);
+
+        assert_eq!(
+            Some(ComandoRegistrado {
+                instante: Some(1339518083.5),
+                tokens: vec!["set".into(), "una clave".into(), "a\"b\\\n\u{1}".into()],
+            }),
+            comando(r#"1339518083.5 [0 127.0.0.1:60866] "set" "una clave" "a\"b\\\n\x01""#)
+        );
+        assert_eq!(
+            Some(ComandoRegistrado {
+                instante: None,
+                tokens: vec!["SET".into(), "clave".into(), "valor".into()],
+            }),
+            comando("127.0.0.1:60866 SET clave valor")
+        );
+        assert_eq!(None, comando("127.0.0.1:60866 Se conecto usario"));
+        assert_eq!(None, comando("127.0.0.1:60866 SUBSCRIBE canal"));
+        assert_eq!(None, comando(r#"1339518083.5 [0 lua] "monitor""#));
+        assert_eq!(None, comando(r#"1339518083.5 [0 lua] "get" "sin cerrar"#));
+    }
+
+    #[test]
+    fn los_comandos_del_log_se_ejecutan_en_el_servidor() {
+        let servidor = ServidorDePrueba::iniciar().unwrap();
+        let log = "127.0.0.1:5000 Se conecto usario\n\
+                   127.0.0.1:5000 SET clave valor\n\
+                   1.5 [0 127.0.0.1:5000] \"append\" \"clave\" \" con espacios\"\n\
+                   1.51 [0 127.0.0.1:5000] \"incrby\" \"clave\" \"1\"\n";
+
+        let resumen = reproducir(&servidor.direccion().to_string(), Cursor::new(log), 1.0).unwrap();
+
+        assert_eq!(
+            ResumenPipe {
+                respuestas: 3,
+                errores: 1
+            },
+            resumen
+        );
+        let mut conexion = TcpStream::connect(servidor.direccion()).unwrap();
+        conexion
+            .write_all(b"*2\r\n$3\r\nGET\r\n$5\r\nclave\r\n")
+            .unwrap();
+        let mut respuesta = vec![0; 25];
+        conexion.read_exact(&mut respuesta).unwrap();
+        assert_eq!(b"$18\r\nvalor con espacios\r\n".to_vec(), respuesta);
+    }
+}
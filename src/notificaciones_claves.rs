@@ -0,0 +1,187 @@
+use crate::base_de_datos::{Instantanea, TipoRedis};
+use crate::eventos::{Evento, Suscriptor};
+
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Comandos cuyos eventos son genericos aunque la clave guarde un tipo de dato en particular
+const COMANDOS_GENERICOS: [&str; 12] = [
+    "del",
+    "unlink",
+    "expire",
+    "pexpire",
+    "expireat",
+    "pexpireat",
+    "persist",
+    "rename",
+    "renamenx",
+    "copy",
+    "migrate",
+    "restore",
+];
+
+/// Clases de eventos habilitadas con notify-keyspace-events, con las mismas letras que redis
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Clases {
+    /// K: se publica en __keyspace@0__:<clave> con el evento como mensaje
+    keyspace: bool,
+    /// E: se publica en __keyevent@0__:<evento> con la clave como mensaje
+    keyevent: bool,
+    /// g: comandos genericos como DEL, EXPIRE o RENAME
+    genericos: bool,
+    /// $: comandos sobre strings
+    strings: bool,
+    /// l: comandos sobre listas
+    listas: bool,
+    /// s: comandos sobre sets
+    sets: bool,
+    /// x: claves eliminadas al expirar
+    expirados: bool,
+}
+
+impl Clases {
+    /// Interpreta las letras de notify-keyspace-events, A equivale a g$lsx. Si no se habilita
+    /// K o E, o ninguna clase de eventos, no se envian notificaciones
+    fn desde(letras: &str) -> Self {
+        let mut clases = Clases::default();
+        for letra in letras.chars() {
+            match letra {
+                'K' => clases.keyspace = true,
+                'E' => clases.keyevent = true,
+                'g' => clases.genericos = true,
+                '$' => clases.strings = true,
+                'l' => clases.listas = true,
+                's' => clases.sets = true,
+                'x' => clases.expirados = true,
+                'A' => {
+                    clases.genericos = true;
+                    clases.strings = true;
+                    clases.listas = true;
+                    clases.sets = true;
+                    clases.expirados = true;
+                }
+                _ => (),
+            }
+        }
+        clases
+    }
+
+    fn habilitadas(&self) -> bool {
+        (self.keyspace || self.keyevent)
+            && (self.genericos || self.strings || self.listas || self.sets || self.expirados)
+    }
+}
+
+/// Notificaciones de espacio de claves: publica en canales de pub/sub las escrituras exitosas
+/// y las expiraciones. El evento es el nombre del comando en minusculas, o expired para las
+/// claves expiradas. La clase de un comando que no es generico se deduce del tipo de dato
+/// que guarda la clave luego de ejecutarlo
+#[derive(Debug, Default)]
+pub struct NotificacionesDeClaves {
+    clases: Mutex<Clases>,
+}
+
+impl NotificacionesDeClaves {
+    pub fn new() -> Self {
+        NotificacionesDeClaves::default()
+    }
+
+    /// Cambia las clases de eventos que se notifican segun notify-keyspace-events
+    pub fn configurar(&self, letras: &str) {
+        match self.clases.lock() {
+            Ok(mut c) => *c = Clases::desde(letras),
+            Err(envenenado) => *envenenado.into_inner() = Clases::desde(letras),
+        }
+    }
+
+    fn clases(&self) -> Clases {
+        match self.clases.lock() {
+            Ok(c) => *c,
+            Err(envenenado) => *envenenado.into_inner(),
+        }
+    }
+}
+
+impl Suscriptor for NotificacionesDeClaves {
+    fn recibir(&self, eventos: &[Evento], tabla: &Instantanea, ahora: SystemTime) {
+        let clases = self.clases();
+        if !clases.habilitadas() {
+            return;
+        }
+        for evento in eventos {
+            match evento {
+                Evento::ClaveExpirada(clave) if clases.expirados => {
+                    notificar(&clases, tabla, ahora, "expired", clave)
+                }
+                Evento::ComandoEjecutado {
+                    comando,
+                    claves,
+                    escritura: true,
+                    exitoso: true,
+                    ..
+                } => {
+                    let nombre = comando[0].to_lowercase();
+                    for clave in claves {
+                        if habilitado(&clases, &nombre, tabla, ahora, clave) {
+                            notificar(&clases, tabla, ahora, &nombre, clave);
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Indica si la clase del evento que produjo el comando sobre la clave esta habilitada
+fn habilitado(
+    clases: &Clases,
+    nombre: &str,
+    tabla: &Instantanea,
+    ahora: SystemTime,
+    clave: &str,
+) -> bool {
+    if COMANDOS_GENERICOS.contains(&nombre) {
+        return clases.genericos;
+    }
+    match tabla.get(clave).and_then(|v| v.get_al(ahora)) {
+        Some(TipoRedis::Str(_)) => clases.strings,
+        Some(TipoRedis::Lista(_)) => clases.listas,
+        Some(TipoRedis::Set(_)) => clases.sets,
+        Some(TipoRedis::Canal(_)) => false,
+        None => clases.genericos,
+    }
+}
+
+fn notificar(clases: &Clases, tabla: &Instantanea, ahora: SystemTime, evento: &str, clave: &str) {
+    if clases.keyspace {
+        publicar(tabla, ahora, &format!("__keyspace@0__:{}", clave), evento);
+    }
+    if clases.keyevent {
+        publicar(tabla, ahora, &format!("__keyevent@0__:{}", evento), clave);
+    }
+}
+
+fn publicar(tabla: &Instantanea, ahora: SystemTime, canal: &str, mensaje: &str) {
+    if let Some(TipoRedis::Canal(canal)) = tabla.get(canal).and_then(|v| v.get_al(ahora)) {
+        canal.clone().publicar(mensaje.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn las_clases_requieren_un_tipo_de_canal_y_un_tipo_de_evento() {
+        assert!(!Clases::desde("").habilitadas());
+        assert!(!Clases::desde("KE").habilitadas());
+        assert!(!Clases::desde("A").habilitadas());
+        assert!(Clases::desde("Kx").habilitadas());
+
+        let todas = Clases::desde("EA");
+        assert!(todas.keyevent && !todas.keyspace);
+        assert!(todas.genericos && todas.strings && todas.listas && todas.sets);
+        assert!(todas.expirados);
+    }
+}
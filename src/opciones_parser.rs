@@ -0,0 +1,256 @@
+use crate::base_de_datos::ResultadoRedis;
+use std::collections::HashMap;
+
+/// Errores que pueden ocurrir al parsear los modificadores de un comando
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpcionesError {
+    /// Modificador desconocido, repetido o al que le faltan argumentos
+    Sintaxis,
+    /// El argumento de un modificador no es un entero valido
+    Entero,
+    /// Se enviaron dos modificadores que no pueden usarse juntos
+    Excluyentes(String, String),
+}
+
+impl OpcionesError {
+    /// Devuelve el error con el mensaje estandar de redis
+    pub fn a_resultado(&self) -> ResultadoRedis {
+        match self {
+            OpcionesError::Sintaxis => ResultadoRedis::Error("ERR syntax error".to_string()),
+            OpcionesError::Entero => {
+                ResultadoRedis::Error("ERR value is not an integer or out of range".to_string())
+            }
+            OpcionesError::Excluyentes(a, b) => ResultadoRedis::Error(format!(
+                "ERR syntax error, {} and {} options at the same time are not compatible",
+                a, b
+            )),
+        }
+    }
+}
+
+/// Modificadores ya parseados de un comando
+#[derive(Debug, Default, PartialEq)]
+pub struct Opciones {
+    banderas: Vec<String>,
+    valores: HashMap<String, i64>,
+    textos: HashMap<String, String>,
+}
+
+impl Opciones {
+    /// Predicado que indica si se envio la bandera
+    pub fn tiene(&self, bandera: &str) -> bool {
+        self.banderas.iter().any(|b| b == bandera)
+            || self.valores.contains_key(bandera)
+            || self.textos.contains_key(bandera)
+    }
+
+    /// Devuelve el argumento de texto del modificador si fue enviado
+    pub fn texto(&self, opcion: &str) -> Option<&str> {
+        self.textos.get(opcion).map(|t| t.as_str())
+    }
+
+    /// Devuelve el argumento entero del modificador si fue enviado
+    pub fn entero(&self, opcion: &str) -> Option<i64> {
+        self.valores.get(opcion).copied()
+    }
+}
+
+/// Parser reutilizable de los modificadores (NX, XX, EX, LIMIT, ...) que aceptan los comandos.
+/// Se configura con las opciones validas y luego se aplica sobre la cola de parametros
+///
+/// # Ejemplo
+/// ```ignore
+/// let opciones = match OpcionesParser::new()
+///     .bandera("NX")
+///     .bandera("XX")
+///     .con_entero("EX")
+///     .excluyentes(&["NX", "XX"])
+///     .parsear(&comando.tokens()[2..])
+/// {
+///     Ok(o) => o,
+///     Err(e) => return e.a_resultado(),
+/// };
+/// ```
+#[derive(Debug, Default)]
+pub struct OpcionesParser {
+    banderas: Vec<&'static str>,
+    con_entero: Vec<&'static str>,
+    con_texto: Vec<&'static str>,
+    excluyentes: Vec<Vec<&'static str>>,
+}
+
+impl OpcionesParser {
+    pub fn new() -> Self {
+        OpcionesParser::default()
+    }
+
+    /// Agrega un modificador que no recibe argumentos, por ejemplo WITHSCORES
+    pub fn bandera(mut self, nombre: &'static str) -> Self {
+        self.banderas.push(nombre);
+        self
+    }
+
+    /// Agrega un modificador seguido de un argumento entero, por ejemplo EX seconds
+    pub fn con_entero(mut self, nombre: &'static str) -> Self {
+        self.con_entero.push(nombre);
+        self
+    }
+
+    /// Agrega un modificador seguido de un argumento de texto, por ejemplo MATCH pattern
+    pub fn con_texto(mut self, nombre: &'static str) -> Self {
+        self.con_texto.push(nombre);
+        self
+    }
+
+    /// Declara un grupo de modificadores de los cuales se puede enviar uno solo
+    pub fn excluyentes(mut self, grupo: &[&'static str]) -> Self {
+        self.excluyentes.push(grupo.to_vec());
+        self
+    }
+
+    /// Parsea los parametros sin importar mayusculas, validando repeticiones,
+    /// argumentos faltantes y modificadores excluyentes
+    pub fn parsear(&self, parametros: &[String]) -> Result<Opciones, OpcionesError> {
+        let mut opciones = Opciones::default();
+        let mut restantes = parametros.iter();
+
+        while let Some(parametro) = restantes.next() {
+            let nombre = parametro.to_uppercase();
+            if opciones.tiene(&nombre) {
+                return Err(OpcionesError::Sintaxis);
+            }
+
+            if let Some(bandera) = self.banderas.iter().find(|b| **b == nombre) {
+                opciones.banderas.push(bandera.to_string());
+            } else if self.con_entero.contains(&nombre.as_str()) {
+                match restantes.next().map(|a| a.parse::<i64>()) {
+                    Some(Ok(entero)) => opciones.valores.insert(nombre, entero),
+                    Some(Err(_)) => return Err(OpcionesError::Entero),
+                    None => return Err(OpcionesError::Sintaxis),
+                };
+            } else if self.con_texto.contains(&nombre.as_str()) {
+                match restantes.next() {
+                    Some(texto) => opciones.textos.insert(nombre, texto.to_string()),
+                    None => return Err(OpcionesError::Sintaxis),
+                };
+            } else {
+                return Err(OpcionesError::Sintaxis);
+            }
+        }
+
+        for grupo in &self.excluyentes {
+            let enviadas: Vec<&&str> = grupo.iter().filter(|o| opciones.tiene(o)).collect();
+            if enviadas.len() > 1 {
+                return Err(OpcionesError::Excluyentes(
+                    enviadas[0].to_string(),
+                    enviadas[1].to_string(),
+                ));
+            }
+        }
+
+        Ok(opciones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parametros(valores: &[&str]) -> Vec<String> {
+        valores.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn parser_de_set() -> OpcionesParser {
+        OpcionesParser::new()
+            .bandera("NX")
+            .bandera("XX")
+            .con_entero("EX")
+            .con_entero("PX")
+            .excluyentes(&["NX", "XX"])
+            .excluyentes(&["EX", "PX"])
+    }
+
+    #[test]
+    fn el_parser_reconoce_banderas_y_argumentos_enteros_sin_importar_mayusculas() {
+        let opciones = parser_de_set()
+            .parsear(&parametros(&["nx", "Ex", "10"]))
+            .unwrap();
+
+        assert!(opciones.tiene("NX"));
+        assert!(!opciones.tiene("XX"));
+        assert_eq!(Some(10), opciones.entero("EX"));
+        assert_eq!(None, opciones.entero("PX"));
+    }
+
+    #[test]
+    fn el_parser_devuelve_error_si_se_envian_opciones_excluyentes() {
+        let error = parser_de_set()
+            .parsear(&parametros(&["NX", "XX"]))
+            .unwrap_err();
+
+        assert_eq!(
+            OpcionesError::Excluyentes("NX".to_string(), "XX".to_string()),
+            error
+        );
+    }
+
+    #[test]
+    fn el_parser_devuelve_error_si_falta_el_argumento_o_no_es_entero() {
+        let parser = parser_de_set();
+
+        assert_eq!(
+            OpcionesError::Sintaxis,
+            parser.parsear(&parametros(&["EX"])).unwrap_err()
+        );
+        assert_eq!(
+            OpcionesError::Entero,
+            parser.parsear(&parametros(&["PX", "diez"])).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn el_parser_devuelve_error_de_sintaxis_ante_opciones_desconocidas_o_repetidas() {
+        let parser = parser_de_set();
+
+        assert_eq!(
+            ResultadoRedis::Error("ERR syntax error".to_string()),
+            parser
+                .parsear(&parametros(&["KEEPTTL"]))
+                .unwrap_err()
+                .a_resultado()
+        );
+        assert_eq!(
+            OpcionesError::Sintaxis,
+            parser.parsear(&parametros(&["NX", "NX"])).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn el_parser_acepta_opciones_con_argumentos_de_texto() {
+        let parser = OpcionesParser::new().con_texto("MATCH").con_entero("COUNT");
+        let opciones = parser
+            .parsear(&parametros(&["match", "clave*", "COUNT", "5"]))
+            .unwrap();
+
+        assert_eq!(Some("clave*"), opciones.texto("MATCH"));
+        assert_eq!(Some(5), opciones.entero("COUNT"));
+        assert_eq!(
+            OpcionesError::Sintaxis,
+            parser.parsear(&parametros(&["MATCH"])).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn el_argumento_de_un_modificador_entero_debe_ser_un_entero() {
+        let parser = parser_de_set();
+
+        assert_eq!(
+            OpcionesError::Sintaxis,
+            parser.parsear(&parametros(&["EX"])).unwrap_err()
+        );
+        assert_eq!(
+            OpcionesError::Entero,
+            parser.parsear(&parametros(&["EX", "diez"])).unwrap_err()
+        );
+    }
+}
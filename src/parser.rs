@@ -1,19 +1,92 @@
 use crate::base_de_datos::ResultadoRedis;
 use crate::comando_info::ComandoInfo;
-use std::io::{BufRead, BufReader, Read};
+use crate::perfilador::{Etapa, PERFILADOR};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind, Read};
 
 /// Errores que pueden ocurrir en la ejecucion del Parser
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug)]
 pub enum ParserError {
-    /// Error en como esta formateado el Comando de Redis
+    /// Error en como esta formateado el Comando de Redis del que no se puede recuperar la conexion,
+    /// porque no se sabe donde termina la trama
     RedisSyntaxError,
+    /// Trama mal formada que el parser ya descarto hasta el comienzo de la siguiente,
+    /// por lo que se puede responder el error y seguir atendiendo la conexion
+    Protocolo(String),
     /// Se esperaba una cadena pero estaba vacia
     MensajeVacioError,
+    /// No se pudo leer del stream
+    Lectura(io::Error),
 }
 
-/// Entidad encargada de parsear stream que cumplen con la sintaxis de Redis
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParserError::RedisSyntaxError => write!(f, "sintaxis invalida"),
+            ParserError::Protocolo(mensaje) => write!(f, "{}", mensaje),
+            ParserError::MensajeVacioError => write!(f, "mensaje vacio"),
+            ParserError::Lectura(error) => write!(f, "error de lectura ({})", error),
+        }
+    }
+}
+
+impl Error for ParserError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParserError::Lectura(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Los errores de lectura se comparan por su tipo, ya que io::Error no se puede comparar
+impl PartialEq for ParserError {
+    fn eq(&self, otro: &Self) -> bool {
+        match (self, otro) {
+            (ParserError::RedisSyntaxError, ParserError::RedisSyntaxError) => true,
+            (ParserError::Protocolo(a), ParserError::Protocolo(b)) => a == b,
+            (ParserError::MensajeVacioError, ParserError::MensajeVacioError) => true,
+            (ParserError::Lectura(a), ParserError::Lectura(b)) => a.kind() == b.kind(),
+            _ => false,
+        }
+    }
+}
+
+impl ParserError {
+    /// Indica si la conexion puede seguir usandose despues del error
+    pub fn es_recuperable(&self) -> bool {
+        matches!(self, ParserError::Protocolo(_))
+    }
+
+    /// Devuelve el error que se le informa al cliente
+    pub fn a_resultado(&self) -> ResultadoRedis {
+        match self {
+            ParserError::Protocolo(mensaje) => ResultadoRedis::Error(mensaje.to_string()),
+            _ => ResultadoRedis::Error("ERR Protocol error: invalid request".to_string()),
+        }
+    }
+}
+
+/// Cantidad de bytes que se piden al stream en cada lectura
+const TAMANIO_LECTURA: usize = 4096;
+/// Largo maximo de un argumento, como proto-max-bulk-len de redis
+const MAXIMO_BULK: usize = 512 * 1024 * 1024;
+
+/// Entidad encargada de parsear stream que cumplen con la sintaxis de Redis.
+/// Conserva lo leido del stream que todavia no se parseo, por lo que los comandos
+/// que llegan juntos en una misma lectura se procesan sin volver a leer del stream.
+/// Recuerda hasta donde parseo el buffer, asi cada byte se parsea una sola vez aunque el
+/// comando llegue en varias lecturas
 pub struct Parser<R> {
-    lector: BufReader<R>,
+    lector: R,
+    buffer: Vec<u8>,
+    /// Bytes del principio del buffer que ya se parsearon
+    consumidos: usize,
+    /// Cantidad de argumentos y argumentos ya parseados del comando que todavia no llego completo
+    parcial: Option<(usize, Vec<String>)>,
+    /// Siguiente comando, o error, que ya esta completo en el buffer
+    siguiente: Option<Result<ComandoInfo, ParserError>>,
 }
 
 impl<R: Read> Parser<R> {
@@ -21,74 +94,172 @@ impl<R: Read> Parser<R> {
     /// a partir de el se encargara de parsear
     pub fn new(stream: R) -> Self {
         Parser {
-            lector: BufReader::new(stream),
+            lector: stream,
+            buffer: Vec::new(),
+            consumidos: 0,
+            parcial: None,
+            siguiente: None,
         }
     }
 
-    /// Parsea el stream obteniendo un Comando o un Error
-    pub fn parsear_stream(self) -> Result<ComandoInfo, ParserError> {
-        let mut lineas = self.lector.lines();
-
-        let primer_valor = match lineas.next() {
-            Some(Ok(valor)) => valor,
-            _ => return Err(ParserError::MensajeVacioError),
-        };
+    /// Parsea el siguiente comando. Primero se consumen los comandos completos que quedaron en el buffer
+    /// y solo se lee del stream cuando el buffer no tiene un comando completo
+    /// Si la trama esta mal formada pero se puede ubicar el comienzo de la siguiente, se descarta
+    /// y se devuelve un error recuperable
+    pub fn siguiente_comando(&mut self) -> Result<ComandoInfo, ParserError> {
+        loop {
+            if let Some(resultado) = self.siguiente.take() {
+                self.adelantar();
+                return resultado;
+            }
+            self.leer()?;
+            self.adelantar();
+        }
+    }
 
-        let capacidad = match parsear_int(primer_valor) {
-            Some(valor) => valor,
-            None => return Err(ParserError::RedisSyntaxError),
-        };
+    /// Predicado que indica si el buffer tiene un comando completo (o un error de sintaxis)
+    /// que se puede procesar sin volver a leer del stream
+    pub fn hay_comando_completo(&self) -> bool {
+        self.siguiente.is_some()
+    }
 
-        let mut comando = Vec::with_capacity(capacidad as usize);
+    /// Parsea lo que falta del buffer hasta completar el siguiente comando, si ya llego
+    fn adelantar(&mut self) {
+        match PERFILADOR.medir(Etapa::Parseo, || self.parsear_comando()) {
+            Ok(Some(comando)) => self.siguiente = Some(Ok(comando)),
+            Ok(None) => {}
+            Err(error) => {
+                self.parcial = None;
+                if error.es_recuperable() {
+                    self.resincronizar();
+                }
+                self.siguiente = Some(Err(error));
+            }
+        }
+    }
 
-        while let Some(Ok(longitud_str)) = lineas.next() {
-            if let Some(Ok(argumento)) = lineas.next() {
-                let longitud = match parsear_int(longitud_str) {
-                    Some(valor) => valor as usize,
-                    None => return Err(ParserError::RedisSyntaxError),
-                };
+    /// Continua el comando que quedo a medias o parsea uno nuevo desde donde se dejo el buffer.
+    /// Devuelve ninguno si todavia no llego completo o un error si no respeta el protocolo
+    fn parsear_comando(&mut self) -> Result<Option<ComandoInfo>, ParserError> {
+        let (cantidad, mut argumentos) = match self.parcial.take() {
+            Some(parcial) => parcial,
+            None => match parsear_encabezado(&self.buffer[self.consumidos..], b'*')? {
+                Some((Some(cantidad), bytes)) if cantidad > 0 => {
+                    self.consumidos += bytes;
+                    (cantidad, Vec::new())
+                }
+                Some(_) => return Err(protocolo("invalid multibulk length")),
+                None => return Ok(None),
+            },
+        };
 
-                if longitud != argumento.len() {
-                    return Err(ParserError::RedisSyntaxError);
+        while argumentos.len() < cantidad {
+            match parsear_argumento(&self.buffer[self.consumidos..])? {
+                Some((argumento, bytes)) => {
+                    argumentos.push(argumento);
+                    self.consumidos += bytes;
                 }
-                comando.push(argumento);
-                if comando.len() == capacidad as usize {
-                    return Ok(ComandoInfo::new(comando));
+                None => {
+                    self.parcial = Some((cantidad, argumentos));
+                    return Ok(None);
                 }
             }
         }
-        println!("{:?}", comando);
-        Ok(ComandoInfo::new(comando))
+        Ok(Some(ComandoInfo::new(argumentos)))
+    }
+
+    /// Descarta la trama invalida hasta la siguiente linea que empieza con `*`. Si no llego
+    /// ninguna se descartan las lineas completas y se conserva la ultima linea incompleta, que
+    /// puede ser el comienzo del proximo comando
+    fn resincronizar(&mut self) {
+        let pendiente = &self.buffer[self.consumidos..];
+        let siguiente = pendiente
+            .windows(3)
+            .position(|w| w == b"\r\n*")
+            .map(|p| p + 2);
+        let descartar = match siguiente {
+            Some(p) => p,
+            None => match pendiente.windows(2).rposition(|w| w == b"\r\n") {
+                Some(p) => p + 2,
+                None => pendiente.len(),
+            },
+        };
+        self.consumidos += descartar;
+    }
+
+    /// Agrega al buffer los datos disponibles en el stream, descartando antes lo ya parseado
+    fn leer(&mut self) -> Result<(), ParserError> {
+        let mut bloque = [0; TAMANIO_LECTURA];
+        let leidos = loop {
+            match self.lector.read(&mut bloque) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ParserError::Lectura(e)),
+            }
+        };
+
+        if leidos == 0 {
+            return Err(ParserError::MensajeVacioError);
+        }
+        self.buffer.drain(..self.consumidos);
+        self.consumidos = 0;
+        self.buffer.extend_from_slice(&bloque[..leidos]);
+        Ok(())
     }
 }
 
-/// Parsea la respuesta para que cumpla con el protocolo Redis
-pub fn parsear_respuesta(res: &ResultadoRedis) -> String {
-    match res {
-        ResultadoRedis::StrSimple(cad) => format!("+{}\r\n", cad),
-        ResultadoRedis::BulkStr(cad) => format!("${}\r\n{}\r\n", cad.len(), cad),
-        ResultadoRedis::Int(ent) => format!(":{}\r\n", ent),
-        ResultadoRedis::Vector(vec) => format!(
-            "*{}\r\n{}",
-            vec.len(),
-            vec.iter()
-                .map(|r| parsear_respuesta(r))
-                .collect::<Vec<String>>()
-                .join("")
-        ),
-        ResultadoRedis::Nil => "$-1\r\n".to_string(),
-        ResultadoRedis::Error(e) => format!("-{}\r\n", e),
-        ResultadoRedis::Vacio => String::new(),
+/// Parsea el argumento que se encuentra al principio del buffer. Devuelve el argumento junto con
+/// la cantidad de bytes que ocupa, ninguno si todavia no llego completo o un error si no respeta
+/// el protocolo
+fn parsear_argumento(buffer: &[u8]) -> Result<Option<(String, usize)>, ParserError> {
+    let (longitud, inicio) = match parsear_encabezado(buffer, b'$')? {
+        Some((Some(longitud), _)) if longitud > MAXIMO_BULK => {
+            return Err(ParserError::RedisSyntaxError)
+        }
+        Some((Some(longitud), bytes)) => (longitud, bytes),
+        Some((None, _)) => return Err(protocolo("invalid bulk length")),
+        None => return Ok(None),
+    };
+    let fin = inicio + longitud;
+    if buffer.len() < fin + 2 {
+        return Ok(None);
+    }
+    if &buffer[fin..fin + 2] != b"\r\n" {
+        return Err(ParserError::RedisSyntaxError);
+    }
+
+    match String::from_utf8(buffer[inicio..fin].to_vec()) {
+        Ok(argumento) => Ok(Some((argumento, fin + 2))),
+        Err(_) => Err(protocolo("invalid UTF-8 in bulk string")),
     }
 }
 
-pub fn parsear_int(cadena: String) -> Option<u32> {
-    let mut chars = cadena.chars();
-    chars.next();
-    match chars.as_str().parse::<u32>() {
-        Ok(int) => Some(int),
-        Err(_) => None,
+/// Parsea una linea de la forma `<prefijo><numero>\r\n`. Devuelve el numero, o ninguno si no es
+/// un numero valido, y la cantidad de bytes de la linea. Si la linea no llego completa devuelve ninguno
+fn parsear_encabezado(
+    buffer: &[u8],
+    prefijo: u8,
+) -> Result<Option<(Option<usize>, usize)>, ParserError> {
+    let fin = match buffer.windows(2).position(|w| w == b"\r\n") {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    if buffer[0] != prefijo {
+        return Err(protocolo(&format!(
+            "expected '{}', got '{}'",
+            prefijo as char, buffer[0] as char
+        )));
     }
+
+    let numero = std::str::from_utf8(&buffer[1..fin])
+        .ok()
+        .and_then(|n| n.parse::<usize>().ok());
+    Ok(Some((numero, fin + 2)))
+}
+
+/// Error de protocolo recuperable con el mensaje que se le informa al cliente
+pub fn protocolo(detalle: &str) -> ParserError {
+    ParserError::Protocolo(format!("ERR Protocol error: {}", detalle))
 }
 
 #[cfg(test)]
@@ -98,8 +269,8 @@ mod tests {
     #[test]
     fn cuando_se_recibe_un_mensaje_de_ping_este_se_parsea_y_se_devuelve_el_comando_correcto() {
         let stream = "*1\r\n$4\r\nPING\r\n".as_bytes();
-        let parser = Parser::new(stream);
-        let mut resultado = parser.parsear_stream().unwrap();
+        let mut parser = Parser::new(stream);
+        let mut resultado = parser.siguiente_comando().unwrap();
         assert_eq!(resultado.get_nombre(), "PING".to_string());
         assert_eq!(resultado.get_clave(), None);
         assert_eq!(resultado.get_parametro(), None);
@@ -108,8 +279,8 @@ mod tests {
     #[test]
     fn cuando_se_recibe_un_mensaje_de_llen_este_se_parsea_y_se_devuelve_el_comando_correcto() {
         let stream = "*2\r\n$4\r\nLLEN\r\n$6\r\nmylist\r\n".as_bytes();
-        let parser = Parser::new(stream);
-        let mut resultado = parser.parsear_stream().unwrap();
+        let mut parser = Parser::new(stream);
+        let mut resultado = parser.siguiente_comando().unwrap();
         assert_eq!(resultado.get_nombre(), "LLEN".to_string());
         assert_eq!(resultado.get_clave(), Some("mylist".to_string()));
         assert_eq!(resultado.get_parametro(), None);
@@ -118,8 +289,8 @@ mod tests {
     #[test]
     fn cuando_se_recibe_un_mensaje_de_sort_este_se_parsea_y_se_devuelve_el_comando_correcto() {
         let stream = "*7\r\n$4\r\nSORT\r\n$6\r\nmylist\r\n$5\r\nLIMIT\r\n$1\r\n0\r\n$1\r\n5\r\n$5\r\nALPHA\r\n$4\r\nDESC\r\n".as_bytes();
-        let parser = Parser::new(stream);
-        let mut resultado = parser.parsear_stream().unwrap();
+        let mut parser = Parser::new(stream);
+        let mut resultado = parser.siguiente_comando().unwrap();
 
         assert_eq!(resultado.get_nombre(), "SORT".to_string());
         assert_eq!(resultado.get_clave(), Some("mylist".to_string()));
@@ -133,64 +304,120 @@ mod tests {
     #[test]
     fn cuando_se_manda_un_mensaje_vacio_se_lanza_un_parser_error_de_tipo_mensaje_vacio() {
         let stream = "".as_bytes();
-        let parser = Parser::new(stream);
-        let error = parser.parsear_stream().unwrap_err();
+        let mut parser = Parser::new(stream);
+        let error = parser.siguiente_comando().unwrap_err();
         assert_eq!(error, ParserError::MensajeVacioError);
     }
 
     #[test]
-    fn cuando_se_manda_un_mensaje_con_un_error_de_sintaxis_se_lanza_un_redis_syntax_error() {
+    fn cuando_se_manda_un_mensaje_con_un_error_de_sintaxis_se_lanza_un_error_de_protocolo() {
         let stream = "++\r\n$4\r\n".as_bytes();
-        let parser = Parser::new(stream);
-        let error = parser.parsear_stream().unwrap_err();
-        assert_eq!(error, ParserError::RedisSyntaxError);
+        let mut parser = Parser::new(stream);
+        let error = parser.siguiente_comando().unwrap_err();
+        assert_eq!(
+            error,
+            ParserError::Protocolo("ERR Protocol error: expected '*', got '+'".to_string())
+        );
     }
 
     #[test]
-    fn cuando_se_envia_un_resultado_redis_simple_string_envia_un_string_correcto() {
-        let resultado = ResultadoRedis::StrSimple("Ok".to_string());
-        assert_eq!(parsear_respuesta(&resultado), "+Ok\r\n");
-    }
+    fn despues_de_una_trama_invalida_se_sigue_parseando_desde_la_siguiente() {
+        let stream = "*1\r\n#4\r\nPING\r\n*x\r\n*1\r\n$4\r\nPING\r\n".as_bytes();
+        let mut parser = Parser::new(stream);
 
-    #[test]
-    fn cuando_se_envia_un_resultado_redis_bulk_strings_se_parsea_correctamente() {
-        let resultado = ResultadoRedis::BulkStr("foo".to_string());
-        assert_eq!(parsear_respuesta(&resultado), "$3\r\nfoo\r\n");
+        let error = parser.siguiente_comando().unwrap_err();
+        assert!(error.es_recuperable());
+        assert_eq!(
+            error,
+            ParserError::Protocolo("ERR Protocol error: expected '$', got '#'".to_string())
+        );
+        assert_eq!(
+            parser.siguiente_comando().unwrap_err(),
+            ParserError::Protocolo("ERR Protocol error: invalid multibulk length".to_string())
+        );
+        assert_eq!(parser.siguiente_comando().unwrap().get_nombre(), "PING");
     }
 
     #[test]
-    fn cuando_se_envia_un_resultado_redis_int_se_parsea_correctamente() {
-        let resultado = ResultadoRedis::Int(55);
-        assert_eq!(parsear_respuesta(&resultado), ":55\r\n");
+    fn una_trama_con_un_argumento_mal_delimitado_no_es_recuperable() {
+        let stream = "*1\r\n$2\r\nPING\r\n".as_bytes();
+        let mut parser = Parser::new(stream);
+        let error = parser.siguiente_comando().unwrap_err();
+
+        assert_eq!(error, ParserError::RedisSyntaxError);
+        assert!(!error.es_recuperable());
     }
 
     #[test]
-    fn cuando_se_envia_un_resultado_redis_vector_de_ints_se_parsea_correctamente() {
-        let resultado =
-            ResultadoRedis::Vector(vec![ResultadoRedis::Int(1), ResultadoRedis::Int(2)]);
-        assert_eq!(parsear_respuesta(&resultado), "*2\r\n:1\r\n:2\r\n");
+    fn los_comandos_que_llegan_juntos_se_parsean_de_a_uno_sin_perderse() {
+        let stream = "*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nLLEN\r\n$6\r\nmylist\r\n".as_bytes();
+        let mut parser = Parser::new(stream);
+
+        assert_eq!(parser.siguiente_comando().unwrap().get_nombre(), "PING");
+        assert!(parser.hay_comando_completo());
+        assert_eq!(parser.siguiente_comando().unwrap().get_nombre(), "LLEN");
+        assert!(!parser.hay_comando_completo());
     }
 
     #[test]
-    fn cuando_se_envia_un_resultado_redis_vector_de_resultados_se_parsea_correctamente() {
-        let resultado = ResultadoRedis::Vector(vec![
-            ResultadoRedis::Int(1),
-            ResultadoRedis::Int(2),
-            ResultadoRedis::Int(3),
-            ResultadoRedis::Int(4),
-            ResultadoRedis::BulkStr("foobar".to_string()),
-        ]);
+    fn un_comando_que_llega_en_varias_lecturas_se_parsea_completo() {
+        let primera: &[u8] = b"*2\r\n$3\r\nGE";
+        let segunda: &[u8] = b"T\r\n$5\r\nho\r\nl\r\n";
+        let mut parser = Parser::new(primera.chain(segunda));
+        let mut comando = parser.siguiente_comando().unwrap();
+
+        assert_eq!(comando.get_nombre(), "GET");
+        assert_eq!(comando.get_clave(), Some("ho\r\nl".to_string()));
+        assert!(!parser.hay_comando_completo());
         assert_eq!(
-            parsear_respuesta(&resultado),
-            "*5\r\n:1\r\n:2\r\n:3\r\n:4\r\n$6\r\nfoobar\r\n"
+            parser.siguiente_comando().unwrap_err(),
+            ParserError::MensajeVacioError
         );
     }
 
+    #[test]
+    fn los_argumentos_ya_parseados_no_se_vuelven_a_parsear_en_la_siguiente_lectura() {
+        let primera: &[u8] = b"*2\r\n$3\r\nGET\r\n$6\r\nho";
+        let segunda: &[u8] = b"la\r\n\r\n";
+        let mut parser = Parser::new(primera.chain(segunda));
+
+        parser.leer().unwrap();
+        parser.adelantar();
+        assert!(!parser.hay_comando_completo());
+        assert_eq!(Some((2, vec!["GET".to_string()])), parser.parcial);
+        assert_eq!(primera.len() - 6, parser.consumidos);
+
+        let mut comando = parser.siguiente_comando().unwrap();
+        assert_eq!(comando.get_nombre(), "GET");
+        assert_eq!(comando.get_clave(), Some("hola\r\n".to_string()));
+    }
+
+    struct LectorQueFalla;
+
+    impl Read for LectorQueFalla {
+        fn read(&mut self, _buffer: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(ErrorKind::PermissionDenied, "sin permisos"))
+        }
+    }
+
+    #[test]
+    fn un_error_de_lectura_conserva_el_error_original() {
+        let mut parser = Parser::new(LectorQueFalla);
+        let error = parser.siguiente_comando().unwrap_err();
+
+        assert_eq!("error de lectura (sin permisos)", error.to_string());
+        assert!(error.source().is_some());
+        match error {
+            ParserError::Lectura(e) => assert_eq!(ErrorKind::PermissionDenied, e.kind()),
+            otro => panic!("error inesperado {:?}", otro),
+        }
+    }
+
     #[test]
     fn a() {
         let stream = "*3\r\n$3\r\nSET\r\n$7\r\ncatedra\r\n$18\r\nTallerProgramacion\r\n".as_bytes();
-        let parser = Parser::new(stream);
-        let mut resultado = parser.parsear_stream().unwrap();
+        let mut parser = Parser::new(stream);
+        let mut resultado = parser.siguiente_comando().unwrap();
         assert_eq!(resultado.get_nombre(), "SET".to_string());
         assert_eq!(resultado.get_clave(), Some("catedra".to_string()));
         assert_eq!(
@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Etapas en las que se reparte el tiempo de atender los comandos
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Etapa {
+    /// Interpretar los comandos que ya estan en el buffer, sin contar la lectura del socket
+    Parseo,
+    /// Esperar los locks de las claves y de la base de datos
+    EsperaLock,
+    Ejecucion,
+    /// Escribir las respuestas en el socket
+    Respuesta,
+    /// Agregar al AOF y volcar la tabla al archivo de persistencia
+    Persistencia,
+}
+
+const ETAPAS: [Etapa; 5] = [
+    Etapa::Parseo,
+    Etapa::EsperaLock,
+    Etapa::Ejecucion,
+    Etapa::Respuesta,
+    Etapa::Persistencia,
+];
+
+impl Etapa {
+    fn nombre(self) -> &'static str {
+        match self {
+            Etapa::Parseo => "parsing",
+            Etapa::EsperaLock => "lock-wait",
+            Etapa::Ejecucion => "execution",
+            Etapa::Respuesta => "reply-write",
+            Etapa::Persistencia => "persistence",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Acumulado {
+    nanosegundos: AtomicU64,
+    muestras: AtomicU64,
+    maximo: AtomicU64,
+}
+
+impl Acumulado {
+    const fn new() -> Self {
+        Acumulado {
+            nanosegundos: AtomicU64::new(0),
+            muestras: AtomicU64::new(0),
+            maximo: AtomicU64::new(0),
+        }
+    }
+
+    fn reiniciar(&self) {
+        self.nanosegundos.store(0, Ordering::SeqCst);
+        self.muestras.store(0, Ordering::SeqCst);
+        self.maximo.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Tiempo acumulado en una etapa mientras duro el perfilado
+#[derive(Debug, Clone, PartialEq)]
+pub struct InformeDeEtapa {
+    pub etapa: &'static str,
+    pub total: Duration,
+    pub muestras: u64,
+    pub maximo: Duration,
+}
+
+/// Mide en que etapas pasa el tiempo el servidor durante un intervalo, para diagnosticar una
+/// instancia en produccion sin herramientas externas. Es unico en el proceso porque las etapas
+/// se miden en lugares que no tienen acceso a la configuracion, como el parser de cada cliente.
+/// Mientras no se esta perfilando, medir una etapa solo cuesta leer un booleano
+#[derive(Debug)]
+pub struct Perfilador {
+    /// Lo toma quien esta perfilando, para que no se superpongan dos perfilados
+    en_curso: Mutex<()>,
+    activo: AtomicBool,
+    etapas: [Acumulado; 5],
+}
+
+pub static PERFILADOR: Perfilador = Perfilador::new();
+
+impl Perfilador {
+    const fn new() -> Self {
+        Perfilador {
+            en_curso: Mutex::new(()),
+            activo: AtomicBool::new(false),
+            etapas: [
+                Acumulado::new(),
+                Acumulado::new(),
+                Acumulado::new(),
+                Acumulado::new(),
+                Acumulado::new(),
+            ],
+        }
+    }
+
+    /// Suma la duracion a la etapa si se esta perfilando
+    pub fn registrar(&self, etapa: Etapa, duracion: Duration) {
+        if !self.activo.load(Ordering::Relaxed) {
+            return;
+        }
+        let nanosegundos = duracion.as_nanos().min(u64::MAX as u128) as u64;
+        let acumulado = &self.etapas[etapa as usize];
+        acumulado
+            .nanosegundos
+            .fetch_add(nanosegundos, Ordering::Relaxed);
+        acumulado.muestras.fetch_add(1, Ordering::Relaxed);
+        acumulado.maximo.fetch_max(nanosegundos, Ordering::Relaxed);
+    }
+
+    /// Ejecuta la funcion y, si se esta perfilando, suma lo que tardo a la etapa
+    pub fn medir<T, F: FnOnce() -> T>(&self, etapa: Etapa, funcion: F) -> T {
+        if !self.activo.load(Ordering::Relaxed) {
+            return funcion();
+        }
+        let inicio = Instant::now();
+        let resultado = funcion();
+        self.registrar(etapa, inicio.elapsed());
+        resultado
+    }
+
+    /// Perfila durante la duracion indicada bloqueando al hilo que lo pide y devuelve lo
+    /// acumulado en cada etapa. Devuelve ninguno si ya hay otro perfilado en curso
+    pub fn perfilar(&self, duracion: Duration) -> Option<Vec<InformeDeEtapa>> {
+        let _en_curso = self.en_curso.try_lock().ok()?;
+        self.etapas.iter().for_each(Acumulado::reiniciar);
+        self.activo.store(true, Ordering::SeqCst);
+        thread::sleep(duracion);
+        self.activo.store(false, Ordering::SeqCst);
+
+        Some(
+            ETAPAS
+                .iter()
+                .map(|etapa| {
+                    let acumulado = &self.etapas[*etapa as usize];
+                    InformeDeEtapa {
+                        etapa: etapa.nombre(),
+                        total: Duration::from_nanos(acumulado.nanosegundos.load(Ordering::SeqCst)),
+                        muestras: acumulado.muestras.load(Ordering::SeqCst),
+                        maximo: Duration::from_nanos(acumulado.maximo.load(Ordering::SeqCst)),
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solo_se_acumula_mientras_se_perfila_y_de_a_un_perfilado() {
+        let perfilador = Perfilador::new();
+        perfilador.registrar(Etapa::Ejecucion, Duration::from_millis(5));
+
+        let informe = thread::scope(|s| {
+            let perfilado = s.spawn(|| perfilador.perfilar(Duration::from_millis(300)));
+            while !perfilador.activo.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+            assert_eq!(None, perfilador.perfilar(Duration::from_millis(1)));
+            perfilador.registrar(Etapa::Ejecucion, Duration::from_millis(2));
+            perfilador.registrar(Etapa::Ejecucion, Duration::from_millis(3));
+            perfilador.medir(Etapa::Parseo, || ());
+            perfilado.join().unwrap().unwrap()
+        });
+
+        let nombres: Vec<&str> = informe.iter().map(|e| e.etapa).collect();
+        assert_eq!(
+            vec![
+                "parsing",
+                "lock-wait",
+                "execution",
+                "reply-write",
+                "persistence"
+            ],
+            nombres
+        );
+        assert_eq!(1, informe[0].muestras);
+        assert_eq!(0, informe[1].muestras);
+        assert_eq!(2, informe[2].muestras);
+        assert_eq!(Duration::from_millis(5), informe[2].total);
+        assert_eq!(Duration::from_millis(3), informe[2].maximo);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::glob::glob_a_regex;
+
+use regex::Regex;
+use std::sync::{RwLock, RwLockReadGuard};
+
+/// Claves a las que pueden acceder los clientes, configuradas con las reglas `~patron` de ACL en
+/// la opcion user, por ejemplo `user: default on ~app1:* ~cache:* +@all`. Como el unico usuario
+/// es default, las reglas de otros usuarios se ignoran. Los patrones son globs como los de SCAN MATCH.
+/// Sin reglas de claves se puede acceder a todas, `allkeys` equivale a `~*` y `resetkeys` quita
+/// los patrones anteriores de la regla
+#[derive(Debug, Default)]
+pub struct PermisosDeClaves {
+    /// Ninguno si se puede acceder a todas las claves
+    patrones: RwLock<Option<Vec<Regex>>>,
+}
+
+impl PermisosDeClaves {
+    pub fn new() -> Self {
+        PermisosDeClaves::default()
+    }
+
+    fn patrones(&self) -> RwLockReadGuard<'_, Option<Vec<Regex>>> {
+        match self.patrones.read() {
+            Ok(p) => p,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    /// Reemplaza los patrones por los de la regla del usuario default
+    pub fn configurar(&self, regla: &str) {
+        let mut tokens = regla.split_whitespace();
+        if tokens.next() != Some("default") {
+            return;
+        }
+        let mut patrones: Option<Vec<Regex>> = None;
+        for token in tokens {
+            match token {
+                "allkeys" | "~*" => patrones = None,
+                "resetkeys" => patrones = Some(Vec::new()),
+                _ => {
+                    if let Some(patron) = token.strip_prefix('~') {
+                        if let Ok(regex) = Regex::new(&glob_a_regex(patron)) {
+                            patrones.get_or_insert_with(Vec::new).push(regex);
+                        }
+                    }
+                }
+            }
+        }
+        match self.patrones.write() {
+            Ok(mut p) => *p = patrones,
+            Err(envenenado) => *envenenado.into_inner() = patrones,
+        }
+    }
+
+    /// Devuelve la primera clave del comando que no coincide con ningun patron, si la hay
+    pub fn primera_no_permitida<'a>(&self, claves: &'a [String]) -> Option<&'a String> {
+        match &*self.patrones() {
+            None => None,
+            Some(patrones) => claves
+                .iter()
+                .find(|clave| !patrones.iter().any(|regex| regex.is_match(clave))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solo_se_permiten_las_claves_que_coinciden_con_algun_patron() {
+        let permisos = PermisosDeClaves::new();
+        let claves = vec!["app1:usuario".to_string(), "app2.x".to_string()];
+        assert_eq!(None, permisos.primera_no_permitida(&claves));
+
+        permisos.configurar("default on >clave ~app1:* ~app?.x +@all");
+        assert_eq!(None, permisos.primera_no_permitida(&claves));
+
+        permisos.configurar("default ~app1:*");
+        assert_eq!(Some(&claves[1]), permisos.primera_no_permitida(&claves));
+        assert_eq!(
+            Some(&"app1".to_string()),
+            permisos.primera_no_permitida(&["app1".to_string()])
+        );
+
+        permisos.configurar("otro ~nada");
+        assert_eq!(Some(&claves[1]), permisos.primera_no_permitida(&claves));
+        permisos.configurar("default ~app1:* resetkeys");
+        assert_eq!(Some(&claves[0]), permisos.primera_no_permitida(&claves));
+        permisos.configurar("default ~app1:* allkeys");
+        assert_eq!(None, permisos.primera_no_permitida(&claves));
+    }
+}
@@ -1,27 +1,44 @@
-use crate::observer::Observer;
-use std::collections::{HashMap, HashSet};
+use crate::eventos::{Evento, Suscriptor};
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Result, Write};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Result, Write};
 use std::iter::FromIterator;
 
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
-use std::time::{Duration, Instant};
-
-use crate::base_de_datos::TipoRedis;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::base_de_datos::{Instantanea, TipoRedis};
+use crate::cadena::Cadena;
+use crate::conjunto::Conjunto;
+use crate::fnv::{fnv1a, Fnv1a};
+use crate::log_handler::Logger;
+use crate::perfilador::{Etapa, PERFILADOR};
+use crate::redis_error::RedisError;
 use crate::valor::Valor;
 
 const STRING: &str = "STRING";
 const LIST: &str = "LIST";
 const SET: &str = "SET";
 const EX: &str = "EX";
+const PXAT: &str = "PXAT";
+const CHECKSUM: &str = "CHECKSUM";
 const SEPARADOR: &str = ":";
 
 /// Representa un mensaje que puede enviar el Persistidor al PersistidorHandler
 pub enum MensajePersistencia {
-    /// Encapsula la tabla a persistir
-    Info(HashMap<String, Valor>),
+    /// Encapsula la tabla a persistir y la hora de la base de datos con la que se descartan las
+    /// claves expiradas
+    Info(Instantanea, SystemTime),
     /// Encapsula el Archivo donde se debe persistir la base de datos
     ArchivoAPersistir(String),
+    /// Deja de persistir los cambios hasta recibir Reanudar, por ejemplo durante una sincronizacion completa
+    Pausar,
+    /// Vuelve a persistir los cambios luego de Pausar
+    Reanudar,
+    /// Persiste la tabla en el momento, aunque este pausado o no haya pasado el intervalo
+    Forzar(Instantanea, SystemTime),
     /// Cierra el hilo donde se esta ejecutando el PersistidorHandler
     Cerrar,
 }
@@ -32,6 +49,9 @@ pub struct PersistidorHandler {
     intervalo: Duration,
     instante: Instant,
     receptor: Receiver<MensajePersistencia>,
+    pausado: bool,
+    estado: Arc<EstadoPersistencia>,
+    logger: Option<Logger>,
 }
 
 impl PersistidorHandler {
@@ -48,12 +68,25 @@ impl PersistidorHandler {
             receptor,
             instante: Instant::now(),
             intervalo: Duration::from_secs(intervalo),
+            pausado: false,
+            estado: Arc::new(EstadoPersistencia::default()),
+            logger: None,
         }
     }
 
+    /// Registra los guardados en el estado compartido, para informarlos en INFO
+    pub fn set_estado(&mut self, estado: Arc<EstadoPersistencia>) {
+        self.estado = estado;
+    }
+
+    /// Loguea los guardados que fallan
+    pub fn set_logger(&mut self, logger: Logger) {
+        self.logger = Some(logger);
+    }
+
     /// Ejecuta al manejador esperando mensajes
     ///
-    /// ```no_run
+    /// ```ignore
     /// let (tx_pers, rx_pers) = channel();
     /// let mut pers_handler = PersistidorHandler::new(config.dbfilename(), 1, rx_pers);
     ///
@@ -64,31 +97,115 @@ impl PersistidorHandler {
     pub fn persistir(&mut self) {
         while let Ok(mensaje) = self.receptor.recv() {
             match mensaje {
-                MensajePersistencia::Info(a_persistir) => {
-                    if self.instante.elapsed() >= self.intervalo {
-                        //persisto
-                        let mut vector: Vec<String> = vec![];
-                        for (key, val) in a_persistir.iter() {
-                            vector.push(guardar_clave_valor(
-                                key.to_string(),
-                                val.get(),
-                                val.get_tiempo(),
-                            ));
-                        }
-                        match guardar_en_archivo(&self.archivo, vector) {
-                            Ok(_) => (),
-                            Err(_) => break,
-                        };
+                MensajePersistencia::Info(a_persistir, ahora) => {
+                    if !self.pausado && self.instante.elapsed() >= self.intervalo {
+                        self.volcar(&a_persistir, ahora);
                         self.instante = Instant::now();
                     }
                 }
 
                 MensajePersistencia::ArchivoAPersistir(a) => self.archivo = a,
 
+                MensajePersistencia::Pausar => self.pausado = true,
+
+                MensajePersistencia::Reanudar => self.pausado = false,
+
+                MensajePersistencia::Forzar(a_persistir, ahora) => {
+                    self.volcar(&a_persistir, ahora);
+                    self.instante = Instant::now();
+                }
+
                 MensajePersistencia::Cerrar => break,
             };
         }
     }
+
+    /// Guarda la tabla en el archivo. Si falla se loguea el error y se vuelve a intentar en el
+    /// proximo intervalo, mientras tanto INFO informa que el ultimo guardado fallo
+    fn volcar(&self, tabla: &HashMap<Cadena, Valor>, ahora: SystemTime) {
+        self.estado.guardando.store(true, Ordering::SeqCst);
+        let resultado = PERFILADOR.medir(Etapa::Persistencia, || {
+            volcar_tabla(&self.archivo, tabla, ahora)
+        });
+        self.estado.registrar_guardado(resultado.is_ok());
+        if let (Err(e), Some(logger)) = (resultado, &self.logger) {
+            logger.log_error(self.archivo.clone(), RedisError::Io(e));
+        }
+    }
+}
+
+/// Resultado de los guardados y de las reaperturas del archivo de log, que se comparte entre el
+/// hilo de persistencia, el que atiende las señales y la seccion Persistence de INFO
+#[derive(Debug, Default)]
+pub struct EstadoPersistencia {
+    guardando: AtomicBool,
+    guardados: AtomicU64,
+    ultimo_guardado: AtomicU64,
+    ultimo_guardado_fallido: AtomicBool,
+    reaperturas_log: AtomicU64,
+    ultima_reapertura_log: AtomicU64,
+}
+
+impl EstadoPersistencia {
+    fn registrar_guardado(&self, exitoso: bool) {
+        if exitoso {
+            self.guardados.fetch_add(1, Ordering::SeqCst);
+            self.ultimo_guardado
+                .store(segundos_unix(), Ordering::SeqCst);
+        }
+        self.ultimo_guardado_fallido
+            .store(!exitoso, Ordering::SeqCst);
+        self.guardando.store(false, Ordering::SeqCst);
+    }
+
+    /// Indica si fallo el ultimo guardado de la base de datos
+    pub fn guardado_fallido(&self) -> bool {
+        self.ultimo_guardado_fallido.load(Ordering::SeqCst)
+    }
+
+    /// Anota que se volvio a abrir el archivo de log, por ejemplo despues de que logrotate lo movio
+    pub fn registrar_reapertura_log(&self) {
+        self.reaperturas_log.fetch_add(1, Ordering::SeqCst);
+        self.ultima_reapertura_log
+            .store(segundos_unix(), Ordering::SeqCst);
+    }
+
+    /// Campos de los guardados y del log para la seccion Persistence de INFO
+    pub fn info(&self) -> Vec<String> {
+        vec![
+            format!(
+                "rdb_bgsave_in_progress:{}",
+                self.guardando.load(Ordering::SeqCst) as u8
+            ),
+            format!("rdb_saves:{}", self.guardados.load(Ordering::SeqCst)),
+            format!(
+                "rdb_last_save_time:{}",
+                self.ultimo_guardado.load(Ordering::SeqCst)
+            ),
+            format!(
+                "rdb_last_bgsave_status:{}",
+                match self.guardado_fallido() {
+                    true => "err",
+                    false => "ok",
+                }
+            ),
+            format!(
+                "log_reopens:{}",
+                self.reaperturas_log.load(Ordering::SeqCst)
+            ),
+            format!(
+                "log_last_reopen_time:{}",
+                self.ultima_reapertura_log.load(Ordering::SeqCst)
+            ),
+        ]
+    }
+}
+
+fn segundos_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 /// Representa al mensajero que se comunica con el manejador para persistir la base de datos
@@ -107,10 +224,10 @@ impl Persistidor {
         Persistidor { persistidor }
     }
 
-    pub fn persistir(&self, base_de_datos: HashMap<String, Valor>) {
+    pub fn persistir(&self, base_de_datos: Instantanea, ahora: SystemTime) {
         if self
             .persistidor
-            .send(MensajePersistencia::Info(base_de_datos))
+            .send(MensajePersistencia::Info(base_de_datos, ahora))
             .is_ok()
         {}
     }
@@ -123,42 +240,74 @@ impl Persistidor {
             .is_ok()
         {}
     }
+
+    /// Suspende la persistencia de los cambios hasta llamar a reanudar
+    pub fn pausar(&self) {
+        if self.persistidor.send(MensajePersistencia::Pausar).is_ok() {}
+    }
+
+    pub fn reanudar(&self) {
+        if self.persistidor.send(MensajePersistencia::Reanudar).is_ok() {}
+    }
+
+    /// Persiste la base de datos sin esperar al intervalo, aunque la persistencia este pausada
+    pub fn forzar(&self, base_de_datos: Instantanea, ahora: SystemTime) {
+        if self
+            .persistidor
+            .send(MensajePersistencia::Forzar(base_de_datos, ahora))
+            .is_ok()
+        {}
+    }
 }
 
-/// El persistidor es un observador que espera a que la base de datos notifique cuando se produjo un cambio importante
-impl Observer for Persistidor {
-    /// Al actualizarse envia la nueva base de datos a persistir
-    fn actualizar(&self, bdd: HashMap<String, Valor>) {
-        self.persistir(bdd);
+/// El persistidor se suscribe a los eventos de la base de datos para enterarse de los cambios en la tabla
+impl Suscriptor for Persistidor {
+    /// Si la operacion modifico la tabla envia la nueva base de datos a persistir
+    fn recibir(&self, eventos: &[Evento], tabla: &Instantanea, ahora: SystemTime) {
+        if eventos.iter().any(Evento::modifica_la_tabla) {
+            self.persistir(Arc::clone(tabla), ahora);
+        }
+    }
+
+    fn nombre(&self) -> &str {
+        "persistencia"
+    }
+
+    fn es_critico(&self) -> bool {
+        true
     }
 }
 
 /// Crea una cadena con una codificacion especifica para persistir a partir de una clave y un valor
-fn guardar_clave_valor(clave: String, valor: Option<&TipoRedis>, time: Option<Duration>) -> String {
-    match (valor, time) {
-        (Some(TipoRedis::Str(valor)), Some(duration)) => {
+fn guardar_clave_valor(
+    clave: String,
+    valor: Option<&TipoRedis>,
+    expira_en: Option<SystemTime>,
+) -> String {
+    match (valor, expira_en) {
+        (Some(TipoRedis::Str(valor)), Some(instante)) => {
             STRING.to_string()
                 + SEPARADOR
                 + &clave
                 + SEPARADOR
                 + valor
                 + SEPARADOR
-                + EX
+                + PXAT
                 + SEPARADOR
-                + &(duration.as_secs().to_string())
+                + &milisegundos_desde_epoch(instante)
         }
 
         (Some(TipoRedis::Str(valor)), None) => {
             STRING.to_string() + SEPARADOR + &clave + SEPARADOR + valor
         }
 
-        (Some(TipoRedis::Lista(lista)), Some(duration)) => {
+        (Some(TipoRedis::Lista(lista)), Some(instante)) => {
             let mut persistencia_lista = LIST.to_string() + SEPARADOR + &clave;
             for valor in lista.iter() {
                 persistencia_lista += &(SEPARADOR.to_string() + valor);
             }
             persistencia_lista +=
-                &(SEPARADOR.to_string() + EX + SEPARADOR + &(duration.as_secs().to_string()));
+                &(SEPARADOR.to_string() + PXAT + SEPARADOR + &milisegundos_desde_epoch(instante));
             persistencia_lista
         }
 
@@ -170,13 +319,13 @@ fn guardar_clave_valor(clave: String, valor: Option<&TipoRedis>, time: Option<Du
             persistencia_lista
         }
 
-        (Some(TipoRedis::Set(set)), Some(duration)) => {
+        (Some(TipoRedis::Set(set)), Some(instante)) => {
             let mut persistencia_set = SET.to_string() + SEPARADOR + &clave;
             for valor in set.iter() {
                 persistencia_set += &(SEPARADOR.to_string() + valor);
             }
             persistencia_set +=
-                &(SEPARADOR.to_string() + EX + SEPARADOR + &(duration.as_secs().to_string()));
+                &(SEPARADOR.to_string() + PXAT + SEPARADOR + &milisegundos_desde_epoch(instante));
             persistencia_set
         }
         (Some(TipoRedis::Set(set)), None) => {
@@ -190,109 +339,335 @@ fn guardar_clave_valor(clave: String, valor: Option<&TipoRedis>, time: Option<Du
     }
 }
 
-fn guardar_en_archivo(archivo: &str, instrucciones: Vec<String>) -> Result<()> {
-    let mut archivo = match OpenOptions::new().write(true).create(true).open(archivo) {
-        Ok(a) => a,
-        Err(e) => return Err(e),
+/// Escribe sincronicamente la tabla en el archivo de persistencia, reemplazando su contenido.
+/// Los registros se escriben a medida que se serializan, sin armar el volcado completo en memoria.
+/// Las claves que ya expiraron en el instante indicado no se escriben
+pub fn volcar_tabla(
+    archivo: &str,
+    tabla: &HashMap<Cadena, Valor>,
+    ahora: SystemTime,
+) -> Result<()> {
+    let archivo = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(archivo)?;
+    let mut destino = BufWriter::new(archivo);
+    escribir_tabla(&mut destino, tabla, ahora)?;
+    destino.flush()
+}
+
+/// Serializa la tabla en el formato del archivo de persistencia: un registro por linea
+/// seguido de una linea con la suma de control del contenido, que permite detectar
+/// archivos truncados o modificados. Es tambien lo que recibe una replica al sincronizarse
+pub fn serializar_tabla(tabla: &HashMap<Cadena, Valor>, ahora: SystemTime) -> String {
+    let mut contenido = Vec::new();
+    match escribir_tabla(&mut contenido, tabla, ahora) {
+        Ok(()) => String::from_utf8(contenido).unwrap_or_default(),
+        Err(_) => String::new(),
+    }
+}
+
+fn escribir_tabla<W: Write>(
+    destino: &mut W,
+    tabla: &HashMap<Cadena, Valor>,
+    ahora: SystemTime,
+) -> Result<()> {
+    let mut suma = Fnv1a::new();
+    for (key, val) in tabla.iter() {
+        let registro =
+            guardar_clave_valor(key.to_string(), val.get_al(ahora), val.expira_en()) + "\n";
+        suma.agregar(registro.as_bytes());
+        destino.write_all(registro.as_bytes())?;
+    }
+    writeln!(destino, "{}{}{:016x}", CHECKSUM, SEPARADOR, suma.suma())
+}
+
+/// Serializa un valor en el formato que devuelve DUMP y recibe RESTORE: el tipo seguido de cada
+/// elemento precedido por su longitud en bytes, y al final la suma de control del contenido.
+/// Como los elementos llevan su longitud pueden contener el separador
+pub fn serializar_valor(valor: &TipoRedis) -> Option<String> {
+    let (tipo, elementos): (&str, Vec<&str>) = match valor {
+        TipoRedis::Str(s) => (STRING, vec![s.as_str()]),
+        TipoRedis::Lista(lista) => (LIST, lista.iter().map(String::as_str).collect()),
+        TipoRedis::Set(set) => (SET, set.iter().map(String::as_str).collect()),
+        TipoRedis::Canal(_) => return None,
     };
+    let mut contenido = tipo.to_string();
+    for elemento in elementos {
+        contenido += &format!("{}{}{}{}", SEPARADOR, elemento.len(), SEPARADOR, elemento);
+    }
+    let suma = fnv1a(contenido.as_bytes());
+    Some(format!("{}{}{:016x}", contenido, SEPARADOR, suma))
+}
 
-    for instruccion in instrucciones.iter() {
-        if let Err(e) = writeln!(archivo, "{}", instruccion) {
-            println!("{:?}", e);
+/// Reconstruye un valor serializado con serializar_valor. Devuelve ninguno si el contenido
+/// no corresponde a un valor o la suma de control no coincide
+pub fn deserializar_valor(serializado: &str) -> Option<TipoRedis> {
+    let (contenido, suma) = serializado.rsplit_once(SEPARADOR)?;
+    if u64::from_str_radix(suma, 16).ok()? != fnv1a(contenido.as_bytes()) {
+        return None;
+    }
+    let (tipo, mut resto) = match contenido.split_once(SEPARADOR) {
+        Some((tipo, resto)) => (tipo, resto),
+        None => (contenido, ""),
+    };
+
+    let mut elementos = Vec::new();
+    while !resto.is_empty() {
+        let (longitud, cola) = resto.split_once(SEPARADOR)?;
+        let longitud: usize = longitud.parse().ok()?;
+        elementos.push(cola.get(..longitud)?.to_string());
+        resto = cola.get(longitud..)?;
+        if !resto.is_empty() {
+            resto = resto.strip_prefix(SEPARADOR)?;
         }
     }
-    Ok(())
-}
 
-/// Lee el archivo de persistencia y crea una nuevo hashmap a partir de el
-pub fn levantar_tabla(archivo_persistencia: String) -> HashMap<String, Valor> {
-    let mut hashmap = HashMap::<String, Valor>::new();
+    match tipo {
+        STRING if elementos.len() == 1 => Some(TipoRedis::Str(elementos.remove(0).into())),
+        LIST => Some(TipoRedis::Lista(elementos)),
+        SET => Some(TipoRedis::Set(Conjunto::from_iter(elementos))),
+        _ => None,
+    }
+}
 
+/// Lee el archivo de persistencia y crea una nuevo hashmap a partir de el, descartando las
+/// claves que ya expiraron en el instante indicado
+pub fn levantar_tabla(archivo_persistencia: String, ahora: SystemTime) -> HashMap<Cadena, Valor> {
     let archivo = match File::open(archivo_persistencia) {
         Ok(archivo) => archivo,
-        Err(_) => return hashmap,
+        Err(_) => return HashMap::new(),
     };
 
     let reader = BufReader::new(archivo);
-    let mut lineas = reader.lines();
-    while let Some(Ok(line)) = lineas.next() {
-        if line.is_empty() {
-            continue;
+    levantar_registros(reader.lines().map_while(Result::ok), ahora)
+}
+
+/// Igual que levantar_tabla pero devuelve un error si el archivo no se puede leer, tiene
+/// registros corruptos o su suma de control no coincide, en lugar de una tabla vacia o parcial
+pub fn cargar_tabla(
+    archivo_persistencia: &str,
+    ahora: SystemTime,
+) -> Result<HashMap<Cadena, Valor>> {
+    if verificar_dump(archivo_persistencia)?.esta_corrupto() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "el archivo de persistencia {} esta corrupto",
+                archivo_persistencia
+            ),
+        ));
+    }
+    Ok(levantar_tabla(archivo_persistencia.to_string(), ahora))
+}
+
+/// Crea la tabla a partir del contenido serializado con serializar_tabla
+pub fn deserializar_tabla(contenido: &str, ahora: SystemTime) -> HashMap<Cadena, Valor> {
+    levantar_registros(contenido.lines().map(|l| l.to_string()), ahora)
+}
+
+fn levantar_registros(
+    lineas: impl Iterator<Item = String>,
+    ahora: SystemTime,
+) -> HashMap<Cadena, Valor> {
+    let mut hashmap = HashMap::<Cadena, Valor>::new();
+    for line in lineas {
+        let (clave, tipo_redis, expira_en) = match parsear_registro(&line, ahora) {
+            Some(registro) => registro,
+            None => continue,
+        };
+
+        let valor = match expira_en {
+            Some(instante) => Valor::expirable_en(tipo_redis, instante),
+            None => Valor::no_expirable(tipo_redis),
+        };
+        if !valor.esta_expirado_al(ahora) {
+            hashmap.insert(clave.into(), valor);
         }
-        let mut elemento: Vec<&str> = line.split(':').collect();
+    }
+    hashmap
+}
 
-        if elemento.contains(&"STRING") {
-            let mut valor = Valor::no_expirable(TipoRedis::Str(elemento[2].to_string()));
+/// Interpreta una linea del archivo de persistencia. Devuelve ninguno si la linea
+/// esta vacia, es la suma de control o no corresponde a un registro valido
+fn parsear_registro(
+    linea: &str,
+    ahora: SystemTime,
+) -> Option<(String, TipoRedis, Option<SystemTime>)> {
+    let mut elemento: Vec<&str> = linea.split(':').collect();
+    if elemento.len() < 2 {
+        return None;
+    }
+    let expira_en = separar_expiracion(&mut elemento, ahora);
+    let tipo = elemento.remove(0);
+    let clave = elemento.remove(0).to_string();
+
+    let tipo_redis = match tipo {
+        STRING => TipoRedis::Str(elemento.join(SEPARADOR).into()),
+        LIST => TipoRedis::Lista(elemento.iter().map(|x| x.to_string()).collect()),
+        SET => TipoRedis::Set(Conjunto::from_iter(elemento.iter().map(|x| x.to_string()))),
+        _ => return None,
+    };
+    Some((clave, tipo_redis, expira_en))
+}
 
-            if es_expirable(elemento.clone()) {
-                let tiempo = obtener_tiempo_expiracion(elemento.clone(), "EX").unwrap_or(0);
-                valor = Valor::expirable(TipoRedis::Str(elemento[2].to_string()), tiempo);
-            }
-            hashmap.insert(elemento[1].to_string(), valor);
-        } else if elemento.contains(&"LIST") {
-            elemento.remove(0);
-            let clave = elemento.remove(0).to_string();
-            let mut valor = Valor::no_expirable(TipoRedis::Lista(
-                elemento.iter().map(|x| x.to_string()).collect(),
-            ));
+/// Estado de la suma de control de un archivo de persistencia
+#[derive(Debug, PartialEq)]
+pub enum EstadoSumaDeControl {
+    Correcta,
+    Incorrecta,
+    /// El archivo fue escrito por una version anterior o se trunco antes de terminar de escribirse
+    Ausente,
+}
 
-            if es_expirable(elemento.clone()) {
-                let tiempo = obtener_tiempo_expiracion(elemento.clone(), "EX").unwrap_or(0);
+/// Resultado de verificar un archivo de persistencia sin levantar el servidor
+#[derive(Debug, PartialEq)]
+pub struct InformeDump {
+    pub strings: usize,
+    pub listas: usize,
+    pub sets: usize,
+    pub con_expiracion: usize,
+    /// Posicion en bytes de la primera linea que no se pudo interpretar
+    pub primer_error: Option<usize>,
+    pub suma_de_control: EstadoSumaDeControl,
+}
 
-                valor = Valor::expirable(
-                    TipoRedis::Lista(elemento.iter().map(|x| x.to_string()).collect()),
-                    tiempo,
-                );
-            }
-            hashmap.insert(clave, valor);
-        } else if elemento.contains(&"SET") {
-            elemento.remove(0);
-            let clave = elemento.remove(0).to_string();
-            let mut valor = Valor::no_expirable(TipoRedis::Set(HashSet::from_iter(
-                elemento
-                    .iter()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>(),
-            )));
-            if es_expirable(elemento.clone()) {
-                let tiempo = obtener_tiempo_expiracion(elemento.clone(), "EX").unwrap_or(0);
-
-                valor = Valor::expirable(
-                    TipoRedis::Set(HashSet::from_iter(
-                        elemento
-                            .iter()
-                            .map(|x| x.to_string())
-                            .collect::<Vec<String>>(),
-                    )),
-                    tiempo,
-                );
+impl InformeDump {
+    /// Un archivo es valido si no tiene registros corruptos y su suma de control coincide
+    pub fn es_valido(&self) -> bool {
+        self.primer_error.is_none() && self.suma_de_control == EstadoSumaDeControl::Correcta
+    }
+
+    /// El archivo tiene registros que no se pudieron interpretar o su suma de control no
+    /// coincide. Los archivos sin suma de control de versiones anteriores no estan corruptos
+    pub fn esta_corrupto(&self) -> bool {
+        self.primer_error.is_some() || self.suma_de_control == EstadoSumaDeControl::Incorrecta
+    }
+
+    /// Devuelve el informe en el formato en el que se imprime
+    pub fn lineas(&self) -> Vec<String> {
+        vec![
+            format!("strings:{}", self.strings),
+            format!("lists:{}", self.listas),
+            format!("sets:{}", self.sets),
+            format!("keys_with_expire:{}", self.con_expiracion),
+            match self.primer_error {
+                Some(posicion) => format!("first_corrupt_offset:{}", posicion),
+                None => "first_corrupt_offset:none".to_string(),
+            },
+            format!(
+                "checksum:{}",
+                match self.suma_de_control {
+                    EstadoSumaDeControl::Correcta => "ok",
+                    EstadoSumaDeControl::Incorrecta => "mismatch",
+                    EstadoSumaDeControl::Ausente => "missing",
+                }
+            ),
+        ]
+    }
+}
+
+/// Recorre el archivo de persistencia contando los registros de cada tipo hasta el primero
+/// que no se pueda interpretar, y verifica la suma de control del final del archivo
+pub fn verificar_dump(archivo: &str) -> Result<InformeDump> {
+    let contenido = std::fs::read(archivo)?;
+    let mut informe = InformeDump {
+        strings: 0,
+        listas: 0,
+        sets: 0,
+        con_expiracion: 0,
+        primer_error: None,
+        suma_de_control: EstadoSumaDeControl::Ausente,
+    };
+
+    let mut posicion = 0;
+    for linea in contenido.split(|b| *b == b'\n') {
+        let inicio = posicion;
+        posicion += linea.len() + 1;
+        if linea.is_empty() {
+            continue;
+        }
+        let registro = match std::str::from_utf8(linea) {
+            // la suma de control es siempre la ultima linea del archivo
+            Ok(l) if informe.suma_de_control == EstadoSumaDeControl::Ausente => l,
+            _ => {
+                informe.primer_error = Some(inicio);
+                break;
             }
-            hashmap.insert(clave, valor);
-        } else {
+        };
+
+        if let Some(suma) = registro.strip_prefix(&format!("{}{}", CHECKSUM, SEPARADOR)) {
+            let esperada = format!("{:016x}", fnv1a(&contenido[..inicio]));
+            informe.suma_de_control = if suma == esperada {
+                EstadoSumaDeControl::Correcta
+            } else {
+                EstadoSumaDeControl::Incorrecta
+            };
             continue;
         }
+
+        match parsear_registro(registro, SystemTime::now()) {
+            Some((_, tipo, expira_en)) => {
+                match tipo {
+                    TipoRedis::Str(_) => informe.strings += 1,
+                    TipoRedis::Lista(_) => informe.listas += 1,
+                    _ => informe.sets += 1,
+                }
+                if expira_en.is_some() {
+                    informe.con_expiracion += 1;
+                }
+            }
+            None => {
+                informe.primer_error = Some(inicio);
+                break;
+            }
+        }
     }
-    hashmap
+    Ok(informe)
 }
 
-fn es_expirable(parametros: Vec<&str>) -> bool {
-    parametros.contains(&"EX")
+/// Si el archivo de persistencia esta corrupto lo renombra a `<archivo>.corrupt-<instante>`,
+/// para que el persistidor no lo sobreescriba con los pocos registros que se pudieron levantar.
+/// Devuelve la ruta nueva, o ninguna si el archivo no existe o esta sano
+pub fn poner_en_cuarentena(archivo: &str) -> Result<Option<String>> {
+    if !std::path::Path::new(archivo).exists() || !verificar_dump(archivo)?.esta_corrupto() {
+        return Ok(None);
+    }
+    let copia = format!("{}.corrupt-{}", archivo, segundos_unix());
+    std::fs::rename(archivo, &copia)?;
+    Ok(Some(copia))
 }
 
-fn obtener_tiempo_expiracion(parametros: Vec<&str>, support: &str) -> Option<u64> {
-    match parametros.rsplit(|p| p == &support.to_string()).next() {
-        Some(c) => match c[0].parse::<u64>() {
-            Ok(num) => Some(num),
-            Err(_) => None,
-        },
-        None => None,
+/// Quita de la linea el sufijo de expiracion, si lo tiene, y devuelve el instante en el que expira.
+/// Los archivos con el formato anterior guardaban con EX los segundos restantes desde ahora
+fn separar_expiracion(elemento: &mut Vec<&str>, ahora: SystemTime) -> Option<SystemTime> {
+    let largo = elemento.len();
+    if largo < 4 {
+        return None;
     }
+    let instante = match (elemento[largo - 2], elemento[largo - 1].parse::<u64>()) {
+        (PXAT, Ok(ms)) => UNIX_EPOCH + Duration::from_millis(ms),
+        (EX, Ok(segundos)) => ahora.checked_add(Duration::from_secs(segundos))?,
+        _ => return None,
+    };
+    elemento.truncate(largo - 2);
+    Some(instante)
+}
+
+fn milisegundos_desde_epoch(instante: SystemTime) -> String {
+    instante
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+        .to_string()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashMap;
+    use std::sync::Arc;
 
     #[test]
     fn inserto_varios_strings_en_hash_map_y_guardar_clave_valor_devuelve_el_mensaje_para_volver_a_cargarlos(
@@ -300,23 +675,23 @@ mod tests {
         let mut map = HashMap::new();
         map.insert(
             "UnaClave1",
-            Valor::no_expirable(TipoRedis::Str("UnValor".to_string())),
+            Valor::no_expirable(TipoRedis::Str("UnValor".into())),
         );
         map.insert(
             "UnaClave2",
-            Valor::no_expirable(TipoRedis::Str("UnValor".to_string())),
+            Valor::no_expirable(TipoRedis::Str("UnValor".into())),
         );
         map.insert(
             "UnaClave3",
-            Valor::no_expirable(TipoRedis::Str("UnValor".to_string())),
+            Valor::no_expirable(TipoRedis::Str("UnValor".into())),
         );
 
         let mut vector: Vec<String> = vec![];
         for (key, val) in map.iter() {
             vector.push(guardar_clave_valor(
                 key.to_string(),
-                val.get(),
-                val.get_tiempo(),
+                val.get_al(SystemTime::now()),
+                val.expira_en(),
             ));
         }
         assert!(vector.contains(&"STRING:UnaClave1:UnValor".to_string()));
@@ -330,11 +705,11 @@ mod tests {
         let mut map = HashMap::new();
         map.insert(
             "UnaClave1",
-            Valor::no_expirable(TipoRedis::Str("UnValor".to_string())),
+            Valor::no_expirable(TipoRedis::Str("UnValor".into())),
         );
         map.insert(
             "UnaClave2",
-            Valor::no_expirable(TipoRedis::Str("UnValor".to_string())),
+            Valor::no_expirable(TipoRedis::Str("UnValor".into())),
         );
 
         let mut lista = TipoRedis::Lista(Vec::new());
@@ -354,8 +729,8 @@ mod tests {
         for (key, val) in map.iter() {
             vector.push(guardar_clave_valor(
                 key.to_string(),
-                val.get(),
-                val.get_tiempo(),
+                val.get_al(SystemTime::now()),
+                val.expira_en(),
             ));
         }
         assert!(vector.contains(&"STRING:UnaClave1:UnValor".to_string()));
@@ -368,18 +743,19 @@ mod tests {
     #[test]
     fn inserto_varios_strings_con_persistencia_en_hash_map_y_guardar_clave_valor_devuelve_el_mensaje_para_volver_a_cargarlos(
     ) {
+        let expira_en = UNIX_EPOCH + Duration::from_millis(4_000_000_000_000);
         let mut map = HashMap::new();
         map.insert(
             "UnaClave1",
-            Valor::expirable(TipoRedis::Str("UnValor".to_string()), 3000),
+            Valor::expirable_en(TipoRedis::Str("UnValor".into()), expira_en),
         );
         map.insert(
             "UnaClave2",
-            Valor::expirable(TipoRedis::Str("UnValor".to_string()), 3000),
+            Valor::expirable_en(TipoRedis::Str("UnValor".into()), expira_en),
         );
         map.insert(
             "UnaClave3",
-            Valor::expirable(TipoRedis::Str("UnValor".to_string()), 3000),
+            Valor::expirable_en(TipoRedis::Str("UnValor".into()), expira_en),
         );
 
         let mut lista = TipoRedis::Lista(Vec::new());
@@ -393,20 +769,252 @@ mod tests {
             _ => {}
         }
 
-        map.insert("milista", Valor::expirable(lista, 4500));
+        map.insert("milista", Valor::expirable_en(lista, expira_en));
 
         let mut vector: Vec<String> = vec![];
         for (key, val) in map.iter() {
             vector.push(guardar_clave_valor(
                 key.to_string(),
-                val.get(),
-                val.get_tiempo(),
+                val.get_al(SystemTime::now()),
+                val.expira_en(),
             ));
         }
-        assert!(vector.contains(&"STRING:UnaClave1:UnValor:EX:3000".to_string()));
-        assert!(vector.contains(&"STRING:UnaClave2:UnValor:EX:3000".to_string()));
-        assert!(vector.contains(&"STRING:UnaClave3:UnValor:EX:3000".to_string()));
-        assert!(vector
-            .contains(&"LIST:milista:PRIMER_VALOR:SEGUNDO_VALOR:TERCER_VALOR:EX:4500".to_string()));
+        assert!(vector.contains(&"STRING:UnaClave1:UnValor:PXAT:4000000000000".to_string()));
+        assert!(vector.contains(&"STRING:UnaClave2:UnValor:PXAT:4000000000000".to_string()));
+        assert!(vector.contains(&"STRING:UnaClave3:UnValor:PXAT:4000000000000".to_string()));
+        assert!(vector.contains(
+            &"LIST:milista:PRIMER_VALOR:SEGUNDO_VALOR:TERCER_VALOR:PXAT:4000000000000".to_string()
+        ));
+    }
+
+    #[test]
+    fn separar_expiracion_entiende_el_formato_absoluto_y_el_anterior_con_segundos_restantes() {
+        let mut absoluta = vec!["LIST", "milista", "a", "b", "PXAT", "4000000000000"];
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_millis(4_000_000_000_000)),
+            separar_expiracion(&mut absoluta, SystemTime::now())
+        );
+        assert_eq!(vec!["LIST", "milista", "a", "b"], absoluta);
+
+        let mut relativa = vec!["STRING", "clave", "valor", "EX", "100"];
+        let expira_en = separar_expiracion(&mut relativa, SystemTime::now()).unwrap();
+        assert!(expira_en > SystemTime::now() + Duration::from_secs(90));
+        assert_eq!(vec!["STRING", "clave", "valor"], relativa);
+
+        let mut sin_expiracion = vec!["SET", "miset", "EX", "PXAT"];
+        assert_eq!(
+            None,
+            separar_expiracion(&mut sin_expiracion, SystemTime::now())
+        );
+        assert_eq!(4, sin_expiracion.len());
+    }
+
+    #[test]
+    fn volcar_tabla_y_levantar_tabla_recuperan_los_mismos_valores() {
+        let archivo = std::env::temp_dir().join("persistencia_volcar_y_levantar.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            "clave".into(),
+            Valor::no_expirable(TipoRedis::Str("valor".into())),
+        );
+        tabla.insert(
+            "lista".into(),
+            Valor::expirable_en(
+                TipoRedis::Lista(vec!["a".to_string(), "b".to_string()]),
+                SystemTime::now() + Duration::from_secs(100),
+            ),
+        );
+        volcar_tabla(&archivo, &tabla, SystemTime::now()).unwrap();
+
+        tabla.remove("lista");
+        volcar_tabla(&archivo, &tabla, SystemTime::now()).unwrap();
+        let levantada = levantar_tabla(archivo.clone(), SystemTime::now());
+        std::fs::remove_file(&archivo).ok();
+
+        assert_eq!(1, levantada.len());
+        assert_eq!(
+            Some(&TipoRedis::Str("valor".into())),
+            levantada["clave"].get_al(SystemTime::now())
+        );
+    }
+
+    #[test]
+    fn volcar_tabla_devuelve_el_error_de_escritura() {
+        let mut tabla: HashMap<Cadena, Valor> = HashMap::new();
+        tabla.insert(
+            "clave".into(),
+            Valor::no_expirable(TipoRedis::Str("valor".into())),
+        );
+
+        let error = volcar_tabla("/dev/full", &tabla, SystemTime::now()).unwrap_err();
+
+        assert_eq!(std::io::ErrorKind::StorageFull, error.kind());
+    }
+
+    #[test]
+    fn mientras_esta_pausado_solo_se_persisten_las_tablas_forzadas() {
+        let archivo = std::env::temp_dir().join("persistencia_pausada.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+        std::fs::remove_file(&archivo).ok();
+        let tabla_con = |clave: &str| {
+            let mut tabla = HashMap::new();
+            tabla.insert(
+                clave.into(),
+                Valor::no_expirable(TipoRedis::Str("valor".into())),
+            );
+            Arc::new(tabla)
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut handler = PersistidorHandler::new(archivo.clone(), 0, rx);
+        let estado = Arc::new(EstadoPersistencia::default());
+        handler.set_estado(Arc::clone(&estado));
+        let persistidor = Persistidor::new(tx);
+        persistidor.pausar();
+        persistidor.persistir(tabla_con("descartada"), SystemTime::now());
+        persistidor.forzar(tabla_con("forzada"), SystemTime::now());
+        persistidor.persistir(tabla_con("descartada"), SystemTime::now());
+        persistidor
+            .persistidor
+            .send(MensajePersistencia::Cerrar)
+            .unwrap();
+        handler.persistir();
+        let levantada = levantar_tabla(archivo.clone(), SystemTime::now());
+        std::fs::remove_file(&archivo).ok();
+
+        assert_eq!(1, levantada.len());
+        assert!(levantada.contains_key("forzada"));
+        let info = estado.info();
+        assert!(info.contains(&"rdb_bgsave_in_progress:0".to_string()));
+        assert!(info.contains(&"rdb_saves:1".to_string()));
+        assert!(info.contains(&"rdb_last_bgsave_status:ok".to_string()));
+        assert!(!info.contains(&"rdb_last_save_time:0".to_string()));
+    }
+
+    #[test]
+    fn los_archivos_corruptos_se_renombran_y_los_sanos_quedan_donde_estan() {
+        let archivo = std::env::temp_dir().join("persistencia_cuarentena.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            "clave".into(),
+            Valor::no_expirable(TipoRedis::Str("valor".into())),
+        );
+        volcar_tabla(&archivo, &tabla, SystemTime::now()).unwrap();
+
+        assert_eq!(None, poner_en_cuarentena(&archivo).unwrap());
+        assert!(std::path::Path::new(&archivo).exists());
+
+        let mut contenido = std::fs::read_to_string(&archivo).unwrap();
+        contenido.insert_str(0, "basura\n");
+        std::fs::write(&archivo, &contenido).unwrap();
+        let copia = poner_en_cuarentena(&archivo).unwrap().unwrap();
+
+        assert!(copia.starts_with(&format!("{}.corrupt-", archivo)));
+        assert!(!std::path::Path::new(&archivo).exists());
+        assert_eq!(contenido, std::fs::read_to_string(&copia).unwrap());
+        assert_eq!(1, levantar_tabla(copia.clone(), SystemTime::now()).len());
+        std::fs::remove_file(&copia).ok();
+    }
+
+    #[test]
+    fn cargar_tabla_falla_si_el_archivo_no_existe_o_esta_corrupto() {
+        let archivo = std::env::temp_dir().join("persistencia_cargar_tabla.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+        std::fs::remove_file(&archivo).ok();
+        assert!(cargar_tabla(&archivo, SystemTime::now()).is_err());
+
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            "clave".into(),
+            Valor::no_expirable(TipoRedis::Str("valor".into())),
+        );
+        volcar_tabla(&archivo, &tabla, SystemTime::now()).unwrap();
+        assert_eq!(1, cargar_tabla(&archivo, SystemTime::now()).unwrap().len());
+
+        let contenido = std::fs::read_to_string(&archivo).unwrap();
+        std::fs::write(&archivo, contenido.replace("valor", "otro!")).unwrap();
+        let error = cargar_tabla(&archivo, SystemTime::now()).err().unwrap();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+        std::fs::remove_file(&archivo).ok();
+    }
+
+    #[test]
+    fn verificar_dump_cuenta_los_registros_y_detecta_corrupcion() {
+        let archivo = std::env::temp_dir().join("persistencia_verificar_dump.rb");
+        let archivo = archivo.to_str().unwrap().to_string();
+
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            "clave".into(),
+            Valor::no_expirable(TipoRedis::Str("valor".into())),
+        );
+        tabla.insert(
+            "lista".into(),
+            Valor::expirable_en(
+                TipoRedis::Lista(vec!["a".to_string()]),
+                SystemTime::now() + Duration::from_secs(100),
+            ),
+        );
+        volcar_tabla(&archivo, &tabla, SystemTime::now()).unwrap();
+        let informe = verificar_dump(&archivo).unwrap();
+        assert_eq!(1, informe.strings);
+        assert_eq!(1, informe.listas);
+        assert_eq!(1, informe.con_expiracion);
+        assert!(informe.es_valido());
+
+        let contenido = std::fs::read_to_string(&archivo).unwrap();
+        let primera = contenido.lines().next().unwrap().len() + 1;
+        let corrupto = contenido.replacen("\n", "\nBASURA\n", 1);
+        std::fs::write(&archivo, corrupto).unwrap();
+        let informe = verificar_dump(&archivo).unwrap();
+        assert_eq!(Some(primera), informe.primer_error);
+        assert_eq!(EstadoSumaDeControl::Ausente, informe.suma_de_control);
+        assert!(!informe.es_valido());
+
+        std::fs::write(&archivo, contenido.replace("valor", "otro!")).unwrap();
+        let informe = verificar_dump(&archivo).unwrap();
+        std::fs::remove_file(&archivo).ok();
+
+        assert_eq!(None, informe.primer_error);
+        assert_eq!(EstadoSumaDeControl::Incorrecta, informe.suma_de_control);
+        assert!(!informe.es_valido());
+    }
+
+    #[test]
+    fn un_valor_serializado_se_reconstruye_aunque_contenga_el_separador() {
+        let lista = TipoRedis::Lista(vec!["a:b".to_string(), "".to_string(), "ñ".to_string()]);
+        let serializado = serializar_valor(&lista).unwrap();
+        assert_eq!(Some(lista), deserializar_valor(&serializado));
+
+        let string = TipoRedis::Str("1:2".into());
+        let serializado = serializar_valor(&string).unwrap();
+        assert_eq!(Some(string), deserializar_valor(&serializado));
+
+        let adulterado = serializado.replacen("1:2", "1:3", 1);
+        assert_eq!(None, deserializar_valor(&adulterado));
+    }
+
+    #[test]
+    fn la_expiracion_al_serializar_y_al_levantar_se_decide_con_el_instante_indicado() {
+        let inicio = UNIX_EPOCH + Duration::from_millis(4_000_000_000_000);
+        let mut tabla = HashMap::new();
+        tabla.insert(
+            "volatil".into(),
+            Valor::expirable_en(
+                TipoRedis::Str("valor".into()),
+                inicio + Duration::from_secs(10),
+            ),
+        );
+
+        let contenido = serializar_tabla(&tabla, inicio);
+        assert!(contenido.contains("STRING:volatil:valor"));
+        assert_eq!(1, deserializar_tabla(&contenido, inicio).len());
+        assert!(deserializar_tabla(&contenido, inicio + Duration::from_secs(10)).is_empty());
+
+        let vencida = serializar_tabla(&tabla, inicio + Duration::from_secs(10));
+        assert!(!vencida.contains("volatil"));
     }
 }
@@ -1,30 +1,104 @@
-use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
+use crate::almacen_externo::{AlmacenExterno, RespaldoExterno};
+use crate::aof::{self, Aof, ClienteInterno};
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis, Rol};
+use crate::cadena::Cadena;
 use crate::cliente::{crear_cliente, Cliente, Token};
+use crate::cliente_redis::ClienteRedis;
+use crate::cluster::RedireccionDeCluster;
 use crate::comando::crear_comando_handler;
-use crate::comando_info::ComandoInfo;
+use crate::comando_pubsub_handler::validar_modo_suscriptor;
+use crate::comando_replicacion_handler::confirmacion;
+use crate::control_de_acceso::ControlDeAcceso;
+use crate::demonio::ArchivoPid;
+use crate::desfragmentacion;
+use crate::estadisticas::Estadisticas;
+use crate::eventos::{Evento, IdSuscripcion, Suscriptor};
+use crate::generador_tokens::GeneradorDeTokens;
+use crate::interceptor::{CadenaDeInterceptores, Peticion};
+use crate::latencia::{es_bloqueante, MedicionDeLatencia};
 use crate::log_handler::{LogHandler, Logger, Mensaje};
-use crate::observer::Observable;
-use crate::persistencia::{levantar_tabla, MensajePersistencia, Persistidor, PersistidorHandler};
+use crate::perfilador::{Etapa, PERFILADOR};
+use crate::persistencia::{
+    deserializar_tabla, levantar_tabla, poner_en_cuarentena, MensajePersistencia, Persistidor,
+    PersistidorHandler,
+};
 use crate::redis_error::RedisError;
+use crate::registro_comandos::{FuncionComando, RegistroDeComandos, RegistroError};
+use crate::replicacion::{
+    se_propaga, sincronizar_con_maestro, SincronizacionCompleta, TOKEN_MAESTRO,
+};
+use crate::senales::{self, Senal};
+use crate::valor::Valor;
 use crate::Config;
 
-use std::net::TcpListener;
+use std::collections::HashMap;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 extern crate redis;
 
+/// Cada cuanto el hilo que supervisa el rol revisa si cambio el maestro configurado
+const ESPERA_CAMBIO_DE_ROL: Duration = Duration::from_millis(100);
+
+/// Cada cuanto el hilo de desfragmentacion estima el desperdicio de memoria
+const INTERVALO_DESFRAGMENTACION: Duration = Duration::from_secs(10);
+
 /// Entidad principal del serividor Redis, se encarga de manejar conexiones y procesar comandos enviados por los usuarios
 pub struct Redis {
     config: Arc<Mutex<Config>>,
     bdd: Arc<Mutex<BaseDeDatos>>,
-    siguiente_id: Token,
+    tokens: GeneradorDeTokens,
+    interceptores: Arc<CadenaDeInterceptores>,
+    registro: Arc<RegistroDeComandos>,
+    estadisticas: Arc<Estadisticas>,
     tx_log: Sender<Mensaje>,
     hilo_log: Option<JoinHandle<()>>,
-    tx_pers: Sender<MensajePersistencia>,
+    /// Ninguno si la persistencia esta deshabilitada
+    tx_pers: Option<Sender<MensajePersistencia>>,
     hilo_pers: Option<JoinHandle<()>>,
     hilos_clientes: Vec<Option<JoinHandle<()>>>,
+    /// Se activa con Detencion para dejar de aceptar conexiones y terminar los hilos de fondo
+    detenido: Arc<AtomicBool>,
+    /// Sockets de las conexiones abiertas, para cerrarlas al terminar el servidor
+    conexiones: Arc<Mutex<HashMap<Token, TcpStream>>>,
+    /// Se borra al liberar el servidor
+    archivo_pid: Option<ArchivoPid>,
+    /// Copia del archivo de persistencia que estaba corrupto al iniciar
+    dump_corrupto: Option<String>,
+}
+
+/// Permite detener desde otro hilo a un servidor que esta atendiendo conexiones
+#[derive(Debug, Clone)]
+pub struct Detencion {
+    detenido: Arc<AtomicBool>,
+    direccion: SocketAddr,
+    conexiones: Arc<Mutex<HashMap<Token, TcpStream>>>,
+}
+
+impl Detencion {
+    /// Marca al servidor como detenido y se conecta para destrabar la espera de conexiones,
+    /// el servidor deja de aceptar conexiones y termina de atender las que tenia abiertas
+    pub fn detener(&self) {
+        self.detenido.store(true, Ordering::SeqCst);
+        if TcpStream::connect(self.direccion).is_ok() {}
+    }
+
+    /// Detiene al servidor cerrando las conexiones abiertas en lugar de esperar a que terminen
+    pub fn terminar(&self) {
+        self.detener();
+        let conexiones = match self.conexiones.lock() {
+            Ok(c) => c,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        for socket in conexiones.values() {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+    }
 }
 
 impl Redis {
@@ -36,6 +110,7 @@ impl Redis {
     ///
     /// # Ejemplo de ejecucion
     /// ```no_run
+    /// # use proyecto_taller_1::{Config, Redis};
     /// let mut redis: Redis = Redis::new(Config::new());
     /// match redis.iniciar() {
     ///     Ok(_) => (),
@@ -52,60 +127,459 @@ impl Redis {
             log_handler.logear();
         });
 
-        let (tx_pers, rx_pers) = channel();
-        let mut pers_handler = PersistidorHandler::new(config.dbfilename(), 1, rx_pers);
+        let (tx_pers, hilo_pers) = match config.persistencia_habilitada() {
+            true => {
+                let (tx_pers, rx_pers) = channel();
+                let mut pers_handler = PersistidorHandler::new(config.dbfilename(), 1, rx_pers);
+                pers_handler.set_estado(config.estado_persistencia());
+                pers_handler.set_logger(Logger::new(tx_log.clone()));
+                let hilo_pers = thread::spawn(move || {
+                    pers_handler.persistir();
+                });
+                (Some(tx_pers), Some(hilo_pers))
+            }
+            false => (None, None),
+        };
 
-        let hilo_pers = thread::spawn(move || {
-            pers_handler.persistir();
-        });
+        // Si hay AOF la tabla se reconstruye con sus comandos en lugar de leer el volcado
+        let cargar_aof = config.aof_habilitado() && Path::new(&config.appendfilename()).exists();
+        let mut bdd = BaseDeDatos::new();
+        let dump_corrupto = match tx_pers {
+            Some(_) if !cargar_aof => {
+                let logger = Logger::new(tx_log.clone());
+                let (tabla, copia) = levantar_dump(config.dbfilename(), bdd.ahora(), &logger);
+                bdd.reemplazar_tabla(tabla);
+                copia
+            }
+            _ => None,
+        };
+        bdd.set_limites(config.limites_de_codificacion());
+        bdd.set_gracia_de_expiracion(config.gracia_de_expiracion());
+        bdd.set_logger(Logger::new(tx_log.clone()));
+        if let Some(tx_pers) = &tx_pers {
+            config.set_persistidor(Persistidor::new(tx_pers.clone()));
+        }
+        if config.es_replica() {
+            bdd.set_rol(Rol::Replica);
+        }
+        config.configurar_cluster();
 
-        let mut bdd = BaseDeDatos::new_con(levantar_tabla(config.dbfilename()));
-        bdd.agregar_observador(Box::new(Persistidor::new(tx_pers.clone())));
-        config.set_persistidor(Persistidor::new(tx_pers.clone()));
+        let estadisticas = bdd.estadisticas();
+        let latencia = config.latencia();
+        let config = Arc::new(Mutex::new(config));
+        let bdd = Arc::new(Mutex::new(bdd));
+        let mut interceptores = CadenaDeInterceptores::new();
+        interceptores.agregar(Box::new(Logger::new(tx_log.clone())));
+        interceptores.agregar(Box::new(Arc::clone(&estadisticas)));
+        interceptores.agregar(Box::new(MedicionDeLatencia::new(latencia)));
+        interceptores.agregar(Box::new(ControlDeAcceso::new(Arc::clone(&config))));
+        interceptores.agregar(Box::new(RedireccionDeCluster::new(
+            Arc::clone(&config),
+            Arc::clone(&bdd),
+        )));
+        interceptores.agregar(Box::new(RespaldoExterno::new(
+            Arc::clone(&config),
+            Arc::clone(&bdd),
+        )));
 
-        Redis {
-            config: Arc::new(Mutex::new(config)),
-            bdd: Arc::new(Mutex::new(bdd)),
-            siguiente_id: 0,
+        let redis = Redis {
+            config,
+            bdd,
+            tokens: GeneradorDeTokens::new(),
+            interceptores: Arc::new(interceptores),
+            registro: Arc::new(RegistroDeComandos::new()),
+            estadisticas,
             tx_log,
             hilo_log: Some(hilo_log),
             tx_pers,
-            hilo_pers: Some(hilo_pers),
+            hilo_pers,
             hilos_clientes: Vec::new(),
+            detenido: Arc::new(AtomicBool::new(false)),
+            conexiones: Arc::new(Mutex::new(HashMap::new())),
+            archivo_pid: None,
+            dump_corrupto,
+        };
+        let aof = match cargar_aof {
+            true => redis.cargar_aof(),
+            false => redis.abrir_aof(),
+        };
+        redis.suscribir_componentes(aof);
+        redis
+    }
+
+    /// Levanta la tabla del preambulo del AOF, si lo tiene, y vuelve a ejecutar sus comandos para
+    /// reconstruir la tabla. Luego lo abre para seguir agregando. Se ejecutan antes de suscribir a
+    /// los componentes de la base de datos, de modo que no se vuelvan a agregar al archivo ni se
+    /// propaguen
+    fn cargar_aof(&self) -> Option<Arc<Aof>> {
+        let logger = Logger::new(self.tx_log.clone());
+        let ruta = match self.config.lock() {
+            Ok(c) => c.appendfilename(),
+            Err(_) => return None,
+        };
+        let ahora = match self.bdd.lock() {
+            Ok(bdd) => bdd.ahora(),
+            Err(_) => return None,
+        };
+        let preambulo = |tabla| {
+            if let Ok(mut bdd) = self.bdd.lock() {
+                bdd.reemplazar_tabla(tabla);
+            }
+        };
+        let cliente: Cliente = Box::new(ClienteInterno::new("AOF"));
+        let cargados = aof::cargar(&ruta, ahora, preambulo, |comando| {
+            manejar_comando(
+                &Peticion::new(&cliente, comando, &self.registro),
+                Arc::clone(&self.bdd),
+                Arc::clone(&self.config),
+                Arc::clone(&self.registro),
+            );
+        });
+        match cargados {
+            Ok(cantidad) => logger.log_coneccion(
+                "Servidor".to_string(),
+                format!("AOF cargado: {} comandos", cantidad),
+            ),
+            Err(e) => logger.log_coneccion(
+                "Servidor".to_string(),
+                format!("No se pudo cargar el AOF completo: {}", e),
+            ),
+        }
+        self.abrir_aof()
+    }
+
+    /// Abre el AOF si esta configurado appendonly yes e inicia su hilo de fsync
+    fn abrir_aof(&self) -> Option<Arc<Aof>> {
+        let logger = Logger::new(self.tx_log.clone());
+        let mut c = self.config.lock().ok()?;
+        if !c.aof_habilitado() {
+            return None;
+        }
+        match Aof::abrir(&c.appendfilename(), c.appendfsync()) {
+            Ok(aof) => {
+                let aof = Arc::new(aof);
+                Aof::iniciar_fsync(&aof, logger);
+                c.set_aof(Arc::clone(&aof));
+                Some(aof)
+            }
+            Err(e) => {
+                logger.log_coneccion(
+                    "Servidor".to_string(),
+                    format!("No se pudo abrir el AOF: {}", e),
+                );
+                None
+            }
+        }
+    }
+
+    /// Suscribe a la base de datos los componentes que reaccionan a sus eventos, en el orden
+    /// en el que los reciben
+    fn suscribir_componentes(&self, aof: Option<Arc<Aof>>) {
+        let (persistidor, replicacion, notificaciones, escrituras) = match self.config.lock() {
+            Ok(c) => (
+                c.persistidor(),
+                c.replicacion(),
+                c.notificaciones_de_claves(),
+                c.escrituras_por_prefijo(),
+            ),
+            Err(_) => return,
+        };
+        if let Ok(mut bdd) = self.bdd.lock() {
+            if let Some(persistidor) = persistidor {
+                bdd.suscribir(Box::new(persistidor));
+            }
+            if let Some(aof) = aof {
+                bdd.suscribir(Box::new(aof));
+            }
+            bdd.suscribir(Box::new(replicacion));
+            bdd.suscribir(Box::new(notificaciones));
+            bdd.suscribir(Box::new(escrituras));
+        }
+    }
+
+    /// Registra un comando propio de quien embebe el servidor. El comando aparece en la salida de COMMAND
+    /// y se valida su aridad antes de ejecutarlo, igual que los comandos provistos por el servidor.
+    /// Se debe registrar antes de iniciar el servidor
+    ///
+    /// # Argumentos
+    ///
+    /// * `nombre` - nombre del comando, no distingue mayusculas
+    /// * `aridad` - cantidad de elementos del comando contando su nombre, negativa si es la cantidad minima
+    /// * `banderas` - banderas informativas como readonly o write
+    /// * `funcion` - funcion que ejecuta el comando
+    ///
+    /// # Ejemplo
+    /// ```no_run
+    /// # use proyecto_taller_1::{Config, Redis, ResultadoRedis};
+    /// # use std::sync::Arc;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut redis: Redis = Redis::new(Config::new());
+    /// redis.registrar_comando("HOLA", 2, &["readonly"], Arc::new(|comando, _bdd| {
+    ///     ResultadoRedis::BulkStr(format!("hola {}", comando.get(0).unwrap_or_default()))
+    /// }))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn registrar_comando(
+        &mut self,
+        nombre: &str,
+        aridad: isize,
+        banderas: &[&str],
+        funcion: FuncionComando,
+    ) -> Result<(), RegistroError> {
+        match Arc::get_mut(&mut self.registro) {
+            Some(registro) => registro.registrar(nombre, aridad, banderas, funcion),
+            None => Err(RegistroError::ServidorIniciado),
+        }
+    }
+
+    /// Suscribe a los eventos de la base de datos un componente de quien embebe el servidor, por
+    /// ejemplo un recolector de metricas. Recibe los eventos despues de la persistencia, la
+    /// replicacion y las notificaciones de claves. Devuelve el identificador para quitarlo
+    ///
+    /// # Ejemplo
+    /// ```no_run
+    /// # use proyecto_taller_1::{Config, Evento, Instantanea, Redis, Suscriptor};
+    /// # use std::time::SystemTime;
+    /// # struct MisMetricas;
+    /// # impl MisMetricas { fn new() -> Self { MisMetricas } }
+    /// # impl Suscriptor for MisMetricas { fn recibir(&self, _: &[Evento], _: &Instantanea, _: SystemTime) {} }
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut redis: Redis = Redis::new(Config::new());
+    /// let id = redis.suscribir(Box::new(MisMetricas::new()))?;
+    /// redis.desuscribir(id)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn suscribir(
+        &self,
+        suscriptor: Box<dyn Suscriptor + Send>,
+    ) -> Result<IdSuscripcion, RedisError> {
+        match self.bdd.lock() {
+            Ok(mut bdd) => Ok(bdd.suscribir(suscriptor)),
+            Err(_) => Err(RedisError::Server),
+        }
+    }
+
+    /// Quita un suscriptor agregado con suscribir, devuelve falso si ya no estaba suscripto
+    pub fn desuscribir(&self, id: IdSuscripcion) -> Result<bool, RedisError> {
+        match self.bdd.lock() {
+            Ok(mut bdd) => Ok(bdd.desuscribir(id)),
+            Err(_) => Err(RedisError::Server),
+        }
+    }
+
+    /// Respalda las claves string que empiezan con el prefijo en un almacen externo de quien
+    /// embebe el servidor: las lecturas de claves que no estan las traen del almacen y las
+    /// escrituras se le envian. Cada operacion espera como maximo external-store-timeout
+    ///
+    /// # Ejemplo
+    /// ```no_run
+    /// # use proyecto_taller_1::{AlmacenExterno, Config, Redis};
+    /// # use std::io;
+    /// # use std::sync::Arc;
+    /// # struct MiBaseDeDatos;
+    /// # impl MiBaseDeDatos { fn conectar() -> io::Result<Self> { Ok(MiBaseDeDatos) } }
+    /// # impl AlmacenExterno for MiBaseDeDatos {
+    /// #     fn leer(&self, _: &str) -> io::Result<Option<String>> { Ok(None) }
+    /// #     fn escribir(&self, _: &str, _: &str) -> io::Result<()> { Ok(()) }
+    /// #     fn borrar(&self, _: &str) -> io::Result<()> { Ok(()) }
+    /// # }
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut redis: Redis = Redis::new(Config::new());
+    /// redis.registrar_almacen_externo("user:", Arc::new(MiBaseDeDatos::conectar()?))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn registrar_almacen_externo(
+        &self,
+        prefijo: &str,
+        almacen: Arc<dyn AlmacenExterno>,
+    ) -> Result<(), RedisError> {
+        match self.config.lock() {
+            Ok(c) => {
+                c.almacenes_externos().registrar(prefijo, almacen);
+                Ok(())
+            }
+            Err(_) => Err(RedisError::Server),
         }
     }
 
     /// Comienza a ejecutar al servidor esperando conexiones en el puerto indicado en Config,
     /// devuelve un Error redis en caso de no poder iniciarse
+    /// Una vez abierto el puerto escribe el pidfile, avisa al sistema de inicio que esta listo y
+    /// empieza a atender las señales del proceso
     pub fn iniciar(&mut self) -> Result<(), RedisError> {
+        self.verificar_dump_corrupto()?;
+        let listener = self.escuchar()?;
+        self.anunciar_inicio();
+        self.atender_senales(self.detencion(&listener)?);
+        self.atender(listener)
+    }
+
+    /// Con SIGTERM o SIGINT deja de aceptar conexiones y cierra las abiertas para que el servidor
+    /// termine ordenadamente, con SIGHUP vuelve a leer el archivo de configuracion y con SIGUSR1
+    /// guarda la base de datos y reabre el log
+    fn atender_senales(&self, detencion: Detencion) {
+        let logger = Logger::new(self.tx_log.clone());
+        let receptor = match senales::escuchar() {
+            Ok(r) => r,
+            Err(e) => {
+                logger.log_coneccion(
+                    "Servidor".to_string(),
+                    format!("No se pudieron atender las senales del proceso: {}", e),
+                );
+                return;
+            }
+        };
+        let config = Arc::clone(&self.config);
+        let tabla = Arc::clone(&self.bdd);
+        thread::spawn(move || {
+            for senal in receptor {
+                match senal {
+                    Senal::Terminar => {
+                        logger.log_coneccion(
+                            "Servidor".to_string(),
+                            "Se recibio la senal para terminar, cerrando el servidor".to_string(),
+                        );
+                        detencion.terminar();
+                        break;
+                    }
+                    Senal::Recargar => recargar_configuracion(&config, &tabla, &logger),
+                    Senal::Guardar => guardar_y_reabrir_log(&config, &tabla, &logger),
+                }
+            }
+        });
+    }
+
+    /// Con refuse-start-on-corrupt-dump no inicia si el archivo de persistencia estaba corrupto
+    fn verificar_dump_corrupto(&self) -> Result<(), RedisError> {
+        let rechazar = match self.config.lock() {
+            Ok(c) => c.rechazar_dump_corrupto(),
+            Err(_) => return Err(RedisError::Server),
+        };
+        match &self.dump_corrupto {
+            Some(copia) if rechazar => {
+                Logger::new(self.tx_log.clone()).log_coneccion(
+                    "Servidor".to_string(),
+                    format!(
+                        "ATENCION: no se inicia el servidor porque el archivo de persistencia esta corrupto (refuse-start-on-corrupt-dump yes), revisar {}",
+                        copia
+                    ),
+                );
+                Err(RedisError::DumpCorrupto(copia.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn anunciar_inicio(&mut self) {
+        let logger = Logger::new(self.tx_log.clone());
+        let (pidfile, supervision) = match self.config.lock() {
+            Ok(c) => (c.pidfile(), c.supervised()),
+            Err(_) => return,
+        };
+        if let Some(ruta) = pidfile {
+            match ArchivoPid::crear(&ruta) {
+                Ok(archivo) => self.archivo_pid = Some(archivo),
+                Err(e) => logger.log_coneccion(
+                    "Servidor".to_string(),
+                    format!("No se pudo escribir el pidfile {}: {}", ruta, e),
+                ),
+            }
+        }
+        if let Err(e) = supervision.notificar("READY=1") {
+            logger.log_coneccion(
+                "Servidor".to_string(),
+                format!(
+                    "No se pudo avisar a systemd que el servidor esta listo: {}",
+                    e
+                ),
+            );
+        }
+    }
+
+    /// Abre el puerto indicado en Config sin empezar a aceptar conexiones. Con el puerto 0 el
+    /// sistema operativo asigna uno libre, que se puede consultar en el listener
+    pub fn escuchar(&self) -> Result<TcpListener, RedisError> {
         let direccion = match self.config.lock() {
             Ok(c) => c.direccion(),
             Err(_) => return Err(RedisError::Server),
         };
+        match TcpListener::bind(direccion) {
+            Ok(l) => Ok(l),
+            Err(_) => Err(RedisError::Inicializacion),
+        }
+    }
 
-        let listener = match TcpListener::bind(direccion) {
-            Ok(l) => l,
-            Err(_) => return Err(RedisError::Inicializacion),
-        };
+    /// Devuelve con que detener al servidor que atiende las conexiones del listener
+    pub fn detencion(&self, listener: &TcpListener) -> Result<Detencion, RedisError> {
+        match listener.local_addr() {
+            Ok(direccion) => Ok(Detencion {
+                detenido: Arc::clone(&self.detenido),
+                direccion,
+                conexiones: Arc::clone(&self.conexiones),
+            }),
+            Err(_) => Err(RedisError::Inicializacion),
+        }
+    }
+
+    /// Atiende las conexiones del listener hasta que se lo detenga con Detencion
+    pub fn atender(&mut self, listener: TcpListener) -> Result<(), RedisError> {
+        self.iniciar_replicacion();
+        self.iniciar_desfragmentacion();
 
         for stream in listener.incoming().flatten() {
+            if self.detenido.load(Ordering::SeqCst) {
+                break;
+            }
             let clon_tabla = Arc::clone(&self.bdd);
+            let tabla = Arc::clone(&self.bdd);
             let clon_config = Arc::clone(&self.config);
+            let config = Arc::clone(&self.config);
+            let interceptores = Arc::clone(&self.interceptores);
+            let registro = Arc::clone(&self.registro);
             let logger = Logger::new(self.tx_log.clone());
-            let timeout = match self.config.lock() {
-                Ok(c) => c.timeout(),
+            let (timeout, limite) = match self.config.lock() {
+                Ok(c) => (c.timeout(), c.limite_de_salida()),
                 Err(_) => continue,
             };
 
-            let mut cliente = crear_cliente(self.siguiente_id, timeout, stream);
-            self.siguiente_id += 1;
+            let id = match self.tokens.siguiente() {
+                Some(id) => id,
+                None => {
+                    self.estadisticas.conexion_rechazada();
+                    logger.log_coneccion(
+                        "Servidor".to_string(),
+                        "No hay tokens disponibles, se rechaza la conexion".to_string(),
+                    );
+                    continue;
+                }
+            };
+            self.estadisticas.conexion_recibida();
+            let conexiones = Arc::clone(&self.conexiones);
+            if let (Ok(socket), Ok(mut c)) = (stream.try_clone(), conexiones.lock()) {
+                c.insert(id, socket);
+            }
+            let mut cliente = crear_cliente(id, timeout, limite, stream);
 
             let handle = thread::spawn(move || {
                 logger.log_coneccion(cliente.obtener_addr(), "Se conecto usario".to_string());
-                match manejar_cliente(&mut cliente, clon_tabla, clon_config, &logger) {
+                match manejar_cliente(
+                    &mut cliente,
+                    clon_tabla,
+                    clon_config,
+                    &interceptores,
+                    registro,
+                    &logger,
+                ) {
                     Ok(()) => (),
                     Err(e) => manejar_error(&logger, e, cliente.obtener_addr()),
                 };
+                desconectar_cliente(&mut cliente, &tabla, &config);
+                if let Ok(mut c) = conexiones.lock() {
+                    c.remove(&id);
+                }
 
                 logger.log_coneccion(cliente.obtener_addr(), "se desconecto usuario".to_string());
             });
@@ -115,10 +589,95 @@ impl Redis {
     }
 }
 
+impl Redis {
+    /// Inicia el hilo que supervisa el rol del servidor: mientras tenga un maestro configurado
+    /// lo replica, y cuando el maestro cambia con REPLICAOF o CONFIG SET replicaof ajusta el rol
+    fn iniciar_replicacion(&self) {
+        let tabla = Arc::clone(&self.bdd);
+        let config = Arc::clone(&self.config);
+        let registro = Arc::clone(&self.registro);
+        let logger = Logger::new(self.tx_log.clone());
+        let detenido = Arc::clone(&self.detenido);
+        thread::spawn(move || {
+            let mut maestro_anterior = None;
+            while !detenido.load(Ordering::SeqCst) {
+                let maestro = match config.lock() {
+                    Ok(c) => c.maestro(),
+                    Err(_) => return,
+                };
+                if maestro != maestro_anterior {
+                    cambiar_rol(&maestro_anterior, &maestro, &tabla, &config, &logger);
+                    maestro_anterior = maestro.clone();
+                }
+                match maestro {
+                    Some(m) => replicar_maestro(
+                        m,
+                        Arc::clone(&tabla),
+                        Arc::clone(&config),
+                        Arc::clone(&registro),
+                        &logger,
+                    ),
+                    None => thread::sleep(ESPERA_CAMBIO_DE_ROL),
+                }
+            }
+        });
+    }
+}
+
+impl Redis {
+    /// Inicia el hilo que, mientras este configurado activedefrag yes, estima periodicamente
+    /// la memoria desperdiciada por las colecciones y las compacta si supera los umbrales.
+    /// La estimacion se hace sobre una instantanea, y solo se bloquea la base para compactar
+    fn iniciar_desfragmentacion(&self) {
+        let tabla = Arc::clone(&self.bdd);
+        let config = Arc::clone(&self.config);
+        let logger = Logger::new(self.tx_log.clone());
+        let detenido = Arc::clone(&self.detenido);
+        thread::spawn(move || loop {
+            thread::sleep(INTERVALO_DESFRAGMENTACION);
+            if detenido.load(Ordering::SeqCst) {
+                return;
+            }
+            let (porcentaje, bytes) = match config.lock() {
+                Ok(c) => match c.umbrales_de_desfragmentacion() {
+                    Some(umbrales) => umbrales,
+                    None => continue,
+                },
+                Err(_) => return,
+            };
+            let (instantanea, ahora) = match tabla.lock() {
+                Ok(b) => (b.instantanea(), b.ahora()),
+                Err(_) => return,
+            };
+            let fragmentacion = desfragmentacion::estimar(&instantanea, ahora);
+            drop(instantanea);
+            if !fragmentacion.supera(porcentaje, bytes) {
+                continue;
+            }
+            let liberados = match tabla.lock() {
+                Ok(mut b) => b.desfragmentar(),
+                Err(_) => return,
+            };
+            logger.log_coneccion(
+                "Servidor".to_string(),
+                format!(
+                    "Desfragmentacion: {}% desperdiciado, {} bytes liberados",
+                    fragmentacion.porcentaje(),
+                    liberados
+                ),
+            );
+        });
+    }
+}
+
 /// Elimina recursos tomados por el servidor siendo estos
 /// los hilos de los clientes, y los hilos de log y persistencia
 impl Drop for Redis {
     fn drop(&mut self) {
+        if let Ok(c) = self.config.lock() {
+            let _ = c.supervised().notificar("STOPPING=1");
+        }
+
         for cliente in &mut self.hilos_clientes {
             if let Some(hilo_cliente) = cliente.take() {
                 if hilo_cliente.join().is_ok() {}
@@ -131,7 +690,15 @@ impl Drop for Redis {
             if hilo.join().is_ok() {}
         }
 
-        if self.tx_pers.send(MensajePersistencia::Cerrar).is_ok() {}
+        if let Ok(c) = self.config.lock() {
+            if let Some(aof) = c.aof() {
+                aof.sincronizar();
+            }
+        }
+
+        if let Some(tx_pers) = &self.tx_pers {
+            if tx_pers.send(MensajePersistencia::Cerrar).is_ok() {}
+        }
 
         if let Some(hilo) = self.hilo_pers.take() {
             if hilo.join().is_ok() {}
@@ -139,6 +706,111 @@ impl Drop for Redis {
     }
 }
 
+/// Levanta la tabla del archivo de persistencia. Si esta corrupto, antes de que el persistidor
+/// lo sobreescriba lo pone en cuarentena y levanta de la copia los registros que se puedan
+/// recuperar. Devuelve tambien la ruta de la copia, o la del archivo si no se pudo verificar
+fn levantar_dump(
+    archivo: String,
+    ahora: SystemTime,
+    logger: &Logger,
+) -> (HashMap<Cadena, Valor>, Option<String>) {
+    match poner_en_cuarentena(&archivo) {
+        Ok(Some(copia)) => {
+            logger.log_coneccion(
+                "Servidor".to_string(),
+                format!(
+                    "ATENCION: el archivo de persistencia {} esta corrupto, se movio a {} para no perderlo y se levantaron solo los registros que se pudieron recuperar",
+                    archivo, copia
+                ),
+            );
+            (levantar_tabla(copia.clone(), ahora), Some(copia))
+        }
+        Ok(None) => (levantar_tabla(archivo, ahora), None),
+        Err(e) => {
+            logger.log_coneccion(
+                "Servidor".to_string(),
+                format!(
+                    "ATENCION: no se pudo verificar el archivo de persistencia {}: {}",
+                    archivo, e
+                ),
+            );
+            (levantar_tabla(archivo.clone(), ahora), Some(archivo))
+        }
+    }
+}
+
+/// Vuelve a leer el archivo de configuracion y aplica las opciones que se pueden cambiar
+/// sin reiniciar, como el archivo de log o los limites de codificacion
+fn recargar_configuracion(
+    config: &Arc<Mutex<Config>>,
+    tabla: &Arc<Mutex<BaseDeDatos>>,
+    logger: &Logger,
+) {
+    let (limites, gracia) = match config.lock() {
+        Ok(mut c) => match c.recargar() {
+            Ok(cantidad) => {
+                logger.verbose(c.verbose());
+                logger.archivo(c.logfile());
+                c.actualizar_persistencia();
+                logger.log_coneccion(
+                    "Servidor".to_string(),
+                    format!(
+                        "Se recargaron {} opciones del archivo de configuracion",
+                        cantidad
+                    ),
+                );
+                (c.limites_de_codificacion(), c.gracia_de_expiracion())
+            }
+            Err(_) => {
+                logger.log_coneccion(
+                    "Servidor".to_string(),
+                    "No se pudo leer el archivo de configuracion".to_string(),
+                );
+                return;
+            }
+        },
+        Err(_) => return,
+    };
+    if let Ok(mut bdd) = tabla.lock() {
+        bdd.set_limites(limites);
+        bdd.set_gracia_de_expiracion(gracia);
+    }
+}
+
+/// Guarda la base de datos en segundo plano, como BGSAVE, y vuelve a abrir el archivo de log.
+/// El manejador del log abre el archivo en cada linea, asi que despues de que logrotate lo mueve
+/// el aviso de la reapertura ya se escribe en un archivo nuevo
+fn guardar_y_reabrir_log(
+    config: &Arc<Mutex<Config>>,
+    tabla: &Arc<Mutex<BaseDeDatos>>,
+    logger: &Logger,
+) {
+    let (persistidor, estado, logfile) = match config.lock() {
+        Ok(c) => (c.persistidor(), c.estado_persistencia(), c.logfile()),
+        Err(_) => return,
+    };
+    let guardado = match persistidor {
+        Some(p) => match tabla.lock() {
+            Ok(bdd) => {
+                p.forzar(bdd.instantanea(), bdd.ahora());
+                "guardando la base de datos en segundo plano"
+            }
+            Err(_) => "no se pudo acceder a la base de datos para guardarla",
+        },
+        None => "la persistencia esta deshabilitada, no se guarda la base de datos",
+    };
+    logger.log_coneccion(
+        "Servidor".to_string(),
+        format!("Se recibio SIGUSR1, {}", guardado),
+    );
+    logger.archivo(logfile.clone());
+    estado.registrar_reapertura_log();
+    logger.log_coneccion(
+        "Servidor".to_string(),
+        format!("Se reabrio el archivo de log {}", logfile),
+    );
+}
+
 /// Se encarga de manejar la conexion de un cliente, para ello delega en el cliente obtener el comando
 /// ejecuta el comando y luego envia el resultado al cliente especificamente
 ///
@@ -147,54 +819,300 @@ impl Drop for Redis {
 /// * `cliente` - instancia de un cliente en especifico
 /// * `tabla` - representa la base de datos donde se haran los cambios
 /// * `config` - la configuracion del servidor util para comandos como config get o set
+/// * `interceptores` - hooks que se ejecutan alrededor de cada comando
+/// * `registro` - comandos que entiende el servidor, incluidos los registrados por el usuario
 /// * `logger` - un ayudante para loggear resultado y mensajes
 fn manejar_cliente(
     cliente: &mut Cliente,
     tabla: Arc<Mutex<BaseDeDatos>>,
     config: Arc<Mutex<Config>>,
+    interceptores: &CadenaDeInterceptores,
+    registro: Arc<RegistroDeComandos>,
     logger: &Logger,
 ) -> Result<(), RedisError> {
-    loop {
-        if cliente.envio_informacion() {
-            let comando = match cliente.obtener_comando() {
-                Ok(Some(c)) => c,
-                Ok(None) => continue,
-                Err(e) => return Err(e),
-            };
-            logger.log_comando(cliente.obtener_addr(), comando.clone());
-
-            let resultado = manejar_comando(
-                comando,
-                cliente.clone(),
+    while cliente.esta_conectado() {
+        let comando = match cliente.obtener_comando() {
+            Ok(Some(c)) => c,
+            Ok(None) | Err(RedisError::Timeout) => continue,
+            Err(RedisError::Cierre) => break,
+            Err(RedisError::Protocolo(e)) => {
+                cliente.enviar_resultado(&e.a_resultado())?;
+                if e.es_recuperable() {
+                    continue;
+                }
+                return Err(RedisError::Protocolo(e));
+            }
+            Err(e) => return Err(e),
+        };
+        let salir = comando.get_nombre() == "QUIT";
+        let peticion = Peticion::new(cliente, comando, &registro);
+        let resultado = interceptores.ejecutar(&peticion, |peticion| {
+            manejar_comando(
+                peticion,
                 Arc::clone(&tabla),
                 Arc::clone(&config),
-            );
+                Arc::clone(&registro),
+            )
+        });
 
+        // Los comandos pipelineados se aplican en tanda: la configuracion del log y la
+        // persistencia se actualiza una sola vez, despues del ultimo comando de la tanda
+        if !cliente.hay_comando_pendiente() {
             match config.lock() {
                 Ok(mut c) => c.actualizar(logger, cliente.clone()),
                 Err(_) => return Err(RedisError::Server),
             }
+        }
 
-            match cliente.enviar_resultado(&resultado) {
-                Ok(_) => (),
-                Err(e) => return Err(e),
-            }
-        } else if !cliente.esta_conectado() {
+        match PERFILADOR.medir(Etapa::Respuesta, || cliente.enviar_resultado(&resultado)) {
+            Ok(_) => (),
+            Err(e) => return Err(e),
+        }
+        if salir {
             break;
         }
     }
     Ok(())
 }
 
-/// Ejecuta el comando ya procesado, para ello instancia al manejador correcto
+/// Ajusta el rol y la persistencia del servidor cuando cambia su maestro, registrando la transicion
+/// en el log. Al ser promovido a maestro persiste la tabla en el momento, para que el archivo
+/// corresponda a los datos con los que empieza a atender escrituras
+fn cambiar_rol(
+    anterior: &Option<String>,
+    nuevo: &Option<String>,
+    tabla: &Mutex<BaseDeDatos>,
+    config: &Mutex<Config>,
+    logger: &Logger,
+) {
+    let describir = |maestro: &Option<String>| match maestro {
+        Some(m) => format!("replica de {}", m),
+        None => "maestro".to_string(),
+    };
+    logger.log_coneccion(
+        "Servidor".to_string(),
+        format!(
+            "Cambio de rol: {} -> {}",
+            describir(anterior),
+            describir(nuevo)
+        ),
+    );
+
+    let (persistidor, replicacion) = match config.lock() {
+        Ok(c) => (c.persistidor(), c.replicacion()),
+        Err(_) => return,
+    };
+    if let Ok(mut bdd) = tabla.lock() {
+        match nuevo {
+            Some(_) => bdd.set_rol(Rol::Replica),
+            None => {
+                bdd.set_rol(Rol::Maestro);
+                replicacion.enlace_con_maestro(false);
+                if let Some(p) = persistidor {
+                    p.forzar(bdd.instantanea(), bdd.ahora());
+                }
+            }
+        }
+    }
+}
+
+/// Indica si el maestro sigue siendo el configurado
+fn maestro_vigente(config: &Mutex<Config>, maestro: &str) -> bool {
+    match config.lock() {
+        Ok(c) => c.maestro().as_deref() == Some(maestro),
+        Err(_) => false,
+    }
+}
+
+/// Mantiene la replicacion del maestro mientras siga configurado como tal: se sincroniza
+/// y aplica las escrituras que recibe. Si la conexion se corta vuelve a sincronizarse.
+/// Durante la sincronizacion completa se pausa la persistencia, de modo que el archivo
+/// tenga la tabla anterior o la del maestro, pero nunca una mezcla de ambas
+fn replicar_maestro(
+    maestro: String,
+    tabla: Arc<Mutex<BaseDeDatos>>,
+    config: Arc<Mutex<Config>>,
+    registro: Arc<RegistroDeComandos>,
+    logger: &Logger,
+) {
+    loop {
+        let (puerto, masterauth, replicacion, persistidor) = match config.lock() {
+            Ok(c) if c.maestro().as_ref() == Some(&maestro) => {
+                (c.puerto(), c.masterauth(), c.replicacion(), c.persistidor())
+            }
+            _ => return,
+        };
+        if let Some(p) = &persistidor {
+            p.pausar();
+        }
+        match sincronizar_con_maestro(&maestro, &puerto, masterauth.as_deref()) {
+            Ok(SincronizacionCompleta {
+                conexion,
+                id,
+                offset,
+                volcado,
+            }) => {
+                replicacion.adoptar(id, offset, || {
+                    if let Ok(mut bdd) = tabla.lock() {
+                        let tabla = deserializar_tabla(&volcado, bdd.ahora());
+                        bdd.reemplazar_tabla(tabla);
+                        bdd.emitir(Evento::BaseReemplazada);
+                        if let Some(p) = &persistidor {
+                            p.forzar(bdd.instantanea(), bdd.ahora());
+                            p.reanudar();
+                        }
+                    }
+                });
+                logger.log_coneccion(maestro.clone(), "Sincronizado con el maestro".to_string());
+                replicacion.enlace_con_maestro(true);
+
+                // La lectura vence cada segundo para confirmarle al maestro el offset aplicado
+                let mut enlace: Cliente = Box::new(ClienteRedis::new(TOKEN_MAESTRO, 1, conexion));
+                while maestro_vigente(&config, &maestro) {
+                    match enlace.obtener_comando() {
+                        Ok(Some(comando)) => {
+                            replicacion.actividad_del_maestro();
+                            manejar_comando(
+                                &Peticion::new(&enlace, comando, &registro),
+                                Arc::clone(&tabla),
+                                Arc::clone(&config),
+                                Arc::clone(&registro),
+                            );
+                        }
+                        Ok(None) => continue,
+                        Err(RedisError::Timeout) => {
+                            if enlace
+                                .enviar_mensaje(confirmacion(replicacion.offset()))
+                                .is_err()
+                            {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            manejar_error(logger, e, maestro.clone());
+                            break;
+                        }
+                    }
+                }
+                enlace.cerrar();
+                replicacion.enlace_con_maestro(false);
+            }
+            Err(e) => {
+                if let Some(p) = &persistidor {
+                    p.reanudar();
+                }
+                manejar_error(logger, e, maestro.clone());
+            }
+        }
+        if maestro_vigente(&config, &maestro) {
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+/// Cierra la conexion del cliente enviando las respuestas pendientes y lo quita de todos los canales
+/// a los que estaba suscripto, para que no se le sigan publicando mensajes. Si era una replica
+/// deja de recibir las escrituras
+fn desconectar_cliente(cliente: &mut Cliente, tabla: &Mutex<BaseDeDatos>, config: &Mutex<Config>) {
+    cliente.cerrar();
+    if let Ok(mut c) = config.lock() {
+        c.replicacion().quitar_replica(cliente.obtener_token());
+        c.olvidar_autenticacion(cliente.obtener_token());
+    }
+    if let Ok(mut bdd) = tabla.lock() {
+        bdd.desuscribir_de_todos(cliente);
+        bdd.desactivar_seguimiento(cliente.obtener_token());
+    }
+}
+
+/// Ejecuta el comando ya procesado, para ello valida su aridad con el registro de comandos e
+/// instancia al manejador correcto. Antes de ejecutarlo elimina las claves que ya expiraron y
+/// despues publica su ejecucion y actualiza el seguimiento de claves de CLIENT TRACKING.
+/// Las claves del comando quedan bloqueadas desde antes de ejecutarlo hasta despues de publicarlo,
+/// de modo que los comandos sobre una misma clave se ejecutan y se publican en el mismo orden.
+/// Las escrituras que no declaran claves bloquean todas
 fn manejar_comando(
-    entrada: ComandoInfo,
-    cliente: Cliente,
+    peticion: &Peticion,
     tabla: Arc<Mutex<BaseDeDatos>>,
     config: Arc<Mutex<Config>>,
+    registro: Arc<RegistroDeComandos>,
 ) -> ResultadoRedis {
-    let handler = crear_comando_handler(entrada, cliente, config);
-    handler.ejecutar(tabla)
+    let nombre = peticion.comando.get_nombre();
+    if let Err(error) = validar_modo_suscriptor(&nombre, peticion.cliente) {
+        return error;
+    }
+    if let Some(definicion) = registro.obtener(&nombre) {
+        if let Err(error) = definicion.validar_aridad(&peticion.comando) {
+            return error;
+        }
+    }
+    let token = peticion.token();
+    let es_escritura = peticion.es_escritura();
+    let (replicacion, bloqueos) = match config.lock() {
+        Ok(c) => (c.replicacion(), c.bloqueos()),
+        Err(_) => return ResultadoRedis::Error("ERR when accessing config".to_string()),
+    };
+    let mut comando = vec![nombre];
+    comando.extend_from_slice(peticion.comando.tokens());
+
+    let inicio_espera = Instant::now();
+    let _bloqueo = match es_escritura && peticion.claves.is_empty() {
+        true => bloqueos.bloquear_todas(),
+        false => bloqueos.bloquear(&peticion.claves),
+    };
+    let _escritura = match se_propaga(token, es_escritura, true) {
+        true => Some(replicacion.escritura()),
+        false => None,
+    };
+    // La espera es la de los bloqueos que se mantienen durante toda la ejecucion. Los accesos a
+    // la tabla, que se toma y se suelta varias veces, cuentan como parte de la ejecucion
+    let espera = inicio_espera.elapsed();
+    PERFILADOR.registrar(Etapa::EsperaLock, espera);
+    let inicio_ejecucion = Instant::now();
+    let estadisticas = match tabla.lock() {
+        Ok(mut bdd) => {
+            if es_escritura && bdd.rol() == Rol::Replica && token != TOKEN_MAESTRO {
+                return ResultadoRedis::Error(
+                    "READONLY You can't write against a read only replica.".to_string(),
+                );
+            }
+            bdd.expirar_claves(&peticion.claves);
+            if peticion.es_lectura() {
+                bdd.registrar_busquedas(&peticion.claves);
+            }
+            bdd.estadisticas()
+        }
+        Err(_) => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+    };
+    estadisticas.accesos(&peticion.claves);
+
+    let handler = crear_comando_handler(
+        peticion.comando.clone(),
+        peticion.cliente.clone(),
+        config,
+        registro,
+    );
+    let resultado = handler.ejecutar(Arc::clone(&tabla));
+    peticion.registrar_tiempos(espera, inicio_ejecucion.elapsed());
+    if !es_bloqueante(&peticion.comando) {
+        PERFILADOR.registrar(Etapa::Ejecucion, inicio_ejecucion.elapsed());
+    }
+    // Se publica antes de soltar las claves: otro comando sobre ellas no pudo ejecutarse en el medio
+    if let Ok(mut bdd) = tabla.lock() {
+        bdd.emitir(Evento::ComandoEjecutado {
+            cliente: token,
+            comando,
+            claves: peticion.claves.clone(),
+            escritura: es_escritura,
+            exitoso: !matches!(resultado, ResultadoRedis::Error(_)),
+        });
+        if es_escritura {
+            bdd.invalidar_claves(&peticion.claves, Some(token));
+        } else if peticion.es_lectura() {
+            bdd.registrar_lecturas(token, &peticion.claves);
+        }
+    }
+    resultado
 }
 
 /// Loggea el error obtenido en la ejecucion de un cliente en particular
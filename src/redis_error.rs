@@ -1,19 +1,111 @@
+use crate::parser::ParserError;
+use std::error::Error;
 use std::fmt;
+use std::io;
 
 /// Representa un error ocurrido por la ejecucion del servidor de Redis
+#[derive(Debug)]
 pub enum RedisError {
     Server,
     Coneccion,
     Inicializacion,
+    /// El cliente supero el tiempo de espera sin enviar mensajes
+    Timeout,
+    /// El mensaje recibido no respeta el protocolo
+    Protocolo(ParserError),
+    /// Fallo una operacion de entrada/salida sobre el socket
+    Io(io::Error),
+    /// El cliente cerro la conexion
+    Cierre,
+    /// El maestro rechazo o no completo la sincronizacion de esta replica
+    Replicacion(String),
+    /// El archivo de persistencia esta corrupto y se configuro no iniciar en ese caso
+    DumpCorrupto(String),
 }
 
 /// Mensaje mas descriptivo del porque del lanzamiento del error
 impl fmt::Display for RedisError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
            RedisError::Server => write!(f, "ServerError error del servidor"),
            RedisError::Coneccion => write!(f, "ConeccionError no se ha podido establecer conexion"),
            RedisError::Inicializacion => write!(f, "InicializacionError no se ha podido inicializar el servidor en el puerto especificado"),
+           RedisError::Timeout => write!(f, "TimeoutError se agoto el tiempo de espera del cliente"),
+           RedisError::Protocolo(e) => write!(f, "ProtocoloError el mensaje no respeta el protocolo: {}", e),
+           RedisError::Io(e) => write!(f, "IoError fallo la comunicacion con el cliente: {}", e),
+           RedisError::Cierre => write!(f, "CierreError el cliente cerro la conexion"),
+           RedisError::Replicacion(e) => write!(f, "ReplicacionError el maestro rechazo la sincronizacion: {}", e),
+           RedisError::DumpCorrupto(copia) => write!(f, "DumpCorruptoError el archivo de persistencia esta corrupto, se movio a {}", copia),
        }
     }
 }
+
+impl Error for RedisError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RedisError::Protocolo(e) => Some(e),
+            RedisError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RedisError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => RedisError::Timeout,
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => RedisError::Cierre,
+            _ => RedisError::Io(error),
+        }
+    }
+}
+
+impl From<ParserError> for RedisError {
+    fn from(error: ParserError) -> Self {
+        match error {
+            ParserError::MensajeVacioError => RedisError::Cierre,
+            ParserError::Lectura(error) => RedisError::from(error),
+            e => RedisError::Protocolo(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn un_error_de_io_conserva_su_causa() {
+        let error = RedisError::from(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "sin permisos",
+        ));
+
+        assert!(error.source().is_some());
+        assert_eq!(
+            "IoError fallo la comunicacion con el cliente: sin permisos",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn los_errores_del_parser_se_traducen_a_errores_del_cliente() {
+        assert!(matches!(
+            RedisError::from(ParserError::MensajeVacioError),
+            RedisError::Cierre
+        ));
+        assert!(matches!(
+            RedisError::from(ParserError::Lectura(io::Error::from(
+                io::ErrorKind::WouldBlock
+            ))),
+            RedisError::Timeout
+        ));
+        assert!(matches!(
+            RedisError::from(ParserError::RedisSyntaxError),
+            RedisError::Protocolo(ParserError::RedisSyntaxError)
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::base_de_datos::{BaseDeDatos, ResultadoRedis};
+use crate::comando_info::ComandoInfo;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Funcion que ejecuta un comando registrado por un usuario del crate
+pub type FuncionComando =
+    Arc<dyn Fn(&mut ComandoInfo, Arc<Mutex<BaseDeDatos>>) -> ResultadoRedis + Send + Sync>;
+
+/// Nombre, aridad, banderas, primera clave, ultima clave y paso de un comando propio
+type ComandoPropio = (
+    &'static str,
+    isize,
+    &'static [&'static str],
+    isize,
+    isize,
+    usize,
+);
+
+/// Tabla con el nombre, la aridad, las banderas y la posicion de las claves de los comandos provistos por el servidor.
+/// La aridad sigue la convencion de redis: cuenta el nombre del comando y si es negativa indica la cantidad minima.
+/// Las claves se indican con la posicion de la primera, la de la ultima (negativa si se cuenta desde el final)
+/// y el paso entre ellas, 0 si el comando no recibe claves
+const COMANDOS_PROPIOS: &[ComandoPropio] = &[
+    ("GET", -2, &["readonly"], 1, 1, 1),
+    ("SET", -3, &["write"], 1, 1, 1),
+    ("APPEND", 3, &["write"], 1, 1, 1),
+    ("STRLEN", 2, &["readonly"], 1, 1, 1),
+    ("INCRBY", 3, &["write"], 1, 1, 1),
+    ("DECRBY", 3, &["write"], 1, 1, 1),
+    ("MGET", -2, &["readonly"], 1, -1, 1),
+    ("MSET", -3, &["write"], 1, -1, 2),
+    ("GETSET", 3, &["write"], 1, 1, 1),
+    ("GETDEL", 2, &["write"], 1, 1, 1),
+    ("SADD", -3, &["write"], 1, 1, 1),
+    ("SCARD", 2, &["readonly"], 1, 1, 1),
+    ("SISMEMBER", 3, &["readonly"], 1, 1, 1),
+    ("SMEMBERS", 2, &["readonly"], 1, 1, 1),
+    ("SREM", -3, &["write"], 1, 1, 1),
+    ("COPY", -3, &["write"], 1, 2, 1),
+    ("DEL", -2, &["write"], 1, -1, 1),
+    ("EXISTS", -2, &["readonly"], 1, -1, 1),
+    ("RENAME", 3, &["write"], 1, 2, 1),
+    ("EXPIRE", -3, &["write"], 1, 1, 1),
+    ("EXPIREAT", -3, &["write"], 1, 1, 1),
+    ("PEXPIREAT", -3, &["write"], 1, 1, 1),
+    ("PERSIST", 2, &["write"], 1, 1, 1),
+    ("TTL", 2, &["readonly"], 1, 1, 1),
+    ("PTTL", 2, &["readonly"], 1, 1, 1),
+    ("TOUCH", -2, &["readonly"], 1, -1, 1),
+    ("KEYS", 2, &["readonly"], 0, 0, 0),
+    ("SCAN", -2, &["readonly"], 0, 0, 0),
+    ("SORT", -2, &["write"], 1, 1, 1),
+    ("TYPE", 2, &["readonly"], 1, 1, 1),
+    ("OBJECT", -2, &["readonly"], 2, 2, 1),
+    ("DUMP", 2, &["readonly"], 1, 1, 1),
+    ("RESTORE", -4, &["write"], 1, 1, 1),
+    ("MIGRATE", -6, &["write"], 3, 3, 1),
+    ("LINDEX", 3, &["readonly"], 1, 1, 1),
+    ("LPOP", -2, &["write"], 1, 1, 1),
+    ("RPOP", -2, &["write"], 1, 1, 1),
+    ("LPUSH", -3, &["write"], 1, 1, 1),
+    ("LPUSHX", -3, &["write"], 1, 1, 1),
+    ("RPUSH", -3, &["write"], 1, 1, 1),
+    ("RPUSHX", -3, &["write"], 1, 1, 1),
+    ("LRANGE", 4, &["readonly"], 1, 1, 1),
+    ("LREM", 4, &["write"], 1, 1, 1),
+    ("LSET", 4, &["write"], 1, 1, 1),
+    ("LLEN", 2, &["readonly"], 1, 1, 1),
+    ("SUBSCRIBE", -2, &["pubsub"], 1, -1, 1),
+    ("UNSUBSCRIBE", -1, &["pubsub"], 1, -1, 1),
+    ("PUBLISH", 3, &["pubsub"], 1, 1, 1),
+    ("PUBSUB", -2, &["pubsub"], 0, 0, 0),
+    ("CLIENT", -2, &[], 0, 0, 0),
+    ("FLUSHDB", -1, &["write"], 0, 0, 0),
+    ("DBSIZE", 1, &["readonly"], 0, 0, 0),
+    ("SAVE", 1, &["admin"], 0, 0, 0),
+    ("BGSAVE", -1, &["admin"], 0, 0, 0),
+    ("BGREWRITEAOF", 1, &["admin"], 0, 0, 0),
+    ("ASKING", 1, &[], 0, 0, 0),
+    ("AUTH", -2, &[], 0, 0, 0),
+    ("CLUSTER", -2, &[], 0, 0, 0),
+    ("CONFIG", -2, &["admin"], 0, 0, 0),
+    ("DEBUG", -2, &["admin"], 0, 0, 0),
+    ("HOTKEYS", -1, &["readonly"], 0, 0, 0),
+    ("INFO", -1, &[], 0, 0, 0),
+    ("LATENCY", -2, &["admin"], 0, 0, 0),
+    ("MEMORY", -2, &[], 0, 0, 0),
+    ("MONITOR", 1, &["admin"], 0, 0, 0),
+    ("SYNC", 1, &["admin"], 0, 0, 0),
+    ("PSYNC", -3, &["admin"], 0, 0, 0),
+    ("REPLCONF", -1, &["admin"], 0, 0, 0),
+    ("REPLICAOF", 3, &["admin"], 0, 0, 0),
+    ("SLAVEOF", 3, &["admin"], 0, 0, 0),
+    ("WAIT", 3, &[], 0, 0, 0),
+    ("PING", -1, &[], 0, 0, 0),
+    ("ECHO", 2, &[], 0, 0, 0),
+    ("QUIT", -1, &[], 0, 0, 0),
+    ("COMMAND", -1, &[], 0, 0, 0),
+];
+
+/// Errores que pueden ocurrir al registrar un comando
+#[derive(Debug, PartialEq)]
+pub enum RegistroError {
+    /// El nombre esta vacio, contiene espacios o la aridad es cero
+    Invalido(String),
+    /// Ya existe un comando con ese nombre
+    Existente(String),
+    /// El servidor ya comenzo a atender clientes
+    ServidorIniciado,
+}
+
+impl fmt::Display for RegistroError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistroError::Invalido(nombre) => {
+                write!(f, "RegistroError comando invalido {}", nombre)
+            }
+            RegistroError::Existente(nombre) => {
+                write!(f, "RegistroError el comando {} ya existe", nombre)
+            }
+            RegistroError::ServidorIniciado => {
+                write!(f, "RegistroError el servidor ya fue iniciado")
+            }
+        }
+    }
+}
+
+impl Error for RegistroError {}
+
+/// Descripcion de un comando conocido por el servidor
+#[derive(Clone)]
+pub struct DefinicionComando {
+    nombre: String,
+    aridad: isize,
+    banderas: Vec<String>,
+    primera_clave: isize,
+    ultima_clave: isize,
+    paso: usize,
+    funcion: Option<FuncionComando>,
+}
+
+impl DefinicionComando {
+    pub fn nombre(&self) -> &str {
+        &self.nombre
+    }
+
+    pub fn aridad(&self) -> isize {
+        self.aridad
+    }
+
+    pub fn banderas(&self) -> &[String] {
+        &self.banderas
+    }
+
+    /// Posicion de la primera clave, de la ultima y el paso entre ellas
+    pub fn posicion_claves(&self) -> (isize, isize, usize) {
+        (self.primera_clave, self.ultima_clave, self.paso)
+    }
+
+    /// Devuelve las claves a las que accede el comando segun la posicion declarada en el registro
+    pub fn claves(&self, comando: &ComandoInfo) -> Vec<String> {
+        if self.primera_clave <= 0 || self.paso == 0 {
+            return Vec::new();
+        }
+        let tokens = comando.tokens();
+        let ultima = if self.ultima_clave < 0 {
+            tokens.len() as isize + self.ultima_clave
+        } else {
+            self.ultima_clave - 1
+        };
+        if ultima < 0 {
+            return Vec::new();
+        }
+
+        tokens
+            .iter()
+            .take(ultima as usize + 1)
+            .skip(self.primera_clave as usize - 1)
+            .step_by(self.paso)
+            .cloned()
+            .collect()
+    }
+
+    /// Funcion a ejecutar si el comando fue registrado por un usuario,
+    /// ninguna si es un comando propio del servidor
+    pub fn funcion(&self) -> Option<FuncionComando> {
+        self.funcion.clone()
+    }
+
+    /// Predicado que indica si la cantidad de elementos del comando, contando su nombre, respeta la aridad
+    pub fn aridad_valida(&self, cantidad: usize) -> bool {
+        let cantidad = cantidad as isize;
+        if self.aridad >= 0 {
+            cantidad == self.aridad
+        } else {
+            cantidad >= -self.aridad
+        }
+    }
+
+    /// Valida la cantidad de argumentos del comando antes de construir su manejador,
+    /// devuelve el error estandar de redis si no respeta la aridad
+    pub fn validar_aridad(&self, comando: &ComandoInfo) -> Result<(), ResultadoRedis> {
+        if self.aridad_valida(comando.len() + 1) {
+            return Ok(());
+        }
+        Err(ResultadoRedis::Error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            self.nombre.to_lowercase()
+        )))
+    }
+}
+
+/// Registro de todos los comandos que entiende el servidor, tanto los propios
+/// como los agregados por quienes embeben el servidor
+pub struct RegistroDeComandos {
+    comandos: BTreeMap<String, DefinicionComando>,
+}
+
+impl RegistroDeComandos {
+    /// Instancia el registro con los comandos propios del servidor
+    pub fn new() -> Self {
+        let comandos = COMANDOS_PROPIOS
+            .iter()
+            .map(|(nombre, aridad, banderas, primera, ultima, paso)| {
+                (
+                    nombre.to_string(),
+                    DefinicionComando {
+                        nombre: nombre.to_string(),
+                        aridad: *aridad,
+                        banderas: banderas.iter().map(|b| b.to_string()).collect(),
+                        primera_clave: *primera,
+                        ultima_clave: *ultima,
+                        paso: *paso,
+                        funcion: None,
+                    },
+                )
+            })
+            .collect();
+        RegistroDeComandos { comandos }
+    }
+
+    /// Registra un nuevo comando con su aridad, sus banderas y la funcion que lo ejecuta.
+    /// Los comandos registrados no declaran claves, por lo que si son escrituras bloquean todas las claves
+    pub fn registrar(
+        &mut self,
+        nombre: &str,
+        aridad: isize,
+        banderas: &[&str],
+        funcion: FuncionComando,
+    ) -> Result<(), RegistroError> {
+        let nombre = nombre.to_uppercase();
+        if nombre.is_empty() || nombre.contains(char::is_whitespace) || aridad == 0 {
+            return Err(RegistroError::Invalido(nombre));
+        }
+        if self.comandos.contains_key(&nombre) {
+            return Err(RegistroError::Existente(nombre));
+        }
+
+        self.comandos.insert(
+            nombre.clone(),
+            DefinicionComando {
+                nombre,
+                aridad,
+                banderas: banderas.iter().map(|b| b.to_lowercase()).collect(),
+                primera_clave: 0,
+                ultima_clave: 0,
+                paso: 0,
+                funcion: Some(funcion),
+            },
+        );
+        Ok(())
+    }
+
+    /// Devuelve la definicion del comando sin importar mayusculas
+    pub fn obtener(&self, nombre: &str) -> Option<&DefinicionComando> {
+        self.comandos.get(&nombre.to_uppercase())
+    }
+
+    /// Devuelve todas las definiciones ordenadas por nombre
+    pub fn definiciones(&self) -> impl Iterator<Item = &DefinicionComando> {
+        self.comandos.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eco() -> FuncionComando {
+        Arc::new(|comando, _bdd| ResultadoRedis::BulkStr(comando.get(0).unwrap_or_default()))
+    }
+
+    #[test]
+    fn el_registro_conoce_los_comandos_propios_y_su_aridad() {
+        let registro = RegistroDeComandos::new();
+        let get = registro.obtener("get").unwrap();
+
+        assert_eq!("GET", get.nombre());
+        assert_eq!(-2, get.aridad());
+        assert!(get.funcion().is_none());
+        assert!(get.aridad_valida(2));
+        assert!(!get.aridad_valida(1));
+        let strlen = registro.obtener("strlen").unwrap();
+        assert!(strlen.aridad_valida(2));
+        assert!(!strlen.aridad_valida(3));
+        assert!(registro.obtener("mset").unwrap().aridad_valida(5));
+    }
+
+    #[test]
+    fn validar_aridad_devuelve_el_error_estandar() {
+        let registro = RegistroDeComandos::new();
+        let comando =
+            |tokens: &[&str]| ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+        let lrange = registro.obtener("LRANGE").unwrap();
+
+        assert_eq!(
+            Ok(()),
+            lrange.validar_aridad(&comando(&["lrange", "a", "0", "1"]))
+        );
+        assert_eq!(
+            Err(ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'lrange' command".to_string()
+            )),
+            lrange.validar_aridad(&comando(&["lrange", "a", "0"]))
+        );
+        assert!(registro
+            .obtener("DEL")
+            .unwrap()
+            .validar_aridad(&comando(&["del"]))
+            .is_err());
+    }
+
+    #[test]
+    fn registrar_agrega_un_comando_que_se_puede_ejecutar() {
+        let mut registro = RegistroDeComandos::new();
+        registro.registrar("eco", 2, &["READONLY"], eco()).unwrap();
+
+        let definicion = registro.obtener("ECO").unwrap();
+        let mut comando = ComandoInfo::new(vec!["eco".to_string(), "hola".to_string()]);
+        let resultado =
+            (definicion.funcion().unwrap())(&mut comando, Arc::new(Mutex::new(BaseDeDatos::new())));
+
+        assert_eq!(vec!["readonly".to_string()], definicion.banderas());
+        assert_eq!(ResultadoRedis::BulkStr("hola".to_string()), resultado);
+    }
+
+    #[test]
+    fn registrar_rechaza_nombres_existentes_o_invalidos() {
+        let mut registro = RegistroDeComandos::new();
+
+        assert_eq!(
+            Err(RegistroError::Existente("GET".to_string())),
+            registro.registrar("get", 2, &[], eco())
+        );
+        assert_eq!(
+            Err(RegistroError::Invalido("MI COMANDO".to_string())),
+            registro.registrar("mi comando", 2, &[], eco())
+        );
+        assert_eq!(
+            Err(RegistroError::Invalido("ECO".to_string())),
+            registro.registrar("eco", 0, &[], eco())
+        );
+    }
+
+    #[test]
+    fn las_claves_de_un_comando_se_obtienen_segun_su_posicion() {
+        let registro = RegistroDeComandos::new();
+        let comando =
+            |tokens: &[&str]| ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect());
+
+        assert_eq!(
+            vec!["a".to_string()],
+            registro
+                .obtener("SET")
+                .unwrap()
+                .claves(&comando(&["SET", "a", "1", "EX", "10"]))
+        );
+        assert_eq!(
+            vec!["a".to_string(), "b".to_string()],
+            registro
+                .obtener("MSET")
+                .unwrap()
+                .claves(&comando(&["MSET", "a", "1", "b", "2"]))
+        );
+        assert_eq!(
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            registro
+                .obtener("DEL")
+                .unwrap()
+                .claves(&comando(&["DEL", "a", "b", "c"]))
+        );
+        assert!(registro
+            .obtener("PING")
+            .unwrap()
+            .claves(&comando(&["PING"]))
+            .is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+use std::sync::Mutex;
+#[cfg(test)]
+use std::time::Duration;
+use std::time::SystemTime;
+
+/// Fuente de la hora con la que la base de datos decide si una clave expiro. Permite que los
+/// tests controlen el paso del tiempo en lugar de esperar a que venzan las expiraciones
+pub trait Reloj: Send + Sync {
+    fn ahora(&self) -> SystemTime;
+}
+
+/// Reloj que devuelve la hora del sistema, es el que usa el servidor
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RelojDelSistema;
+
+impl Reloj for RelojDelSistema {
+    fn ahora(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Reloj detenido que solo avanza cuando se lo pide, para tests deterministicos de expiracion
+#[cfg(test)]
+#[derive(Debug)]
+pub struct RelojSimulado {
+    ahora: Mutex<SystemTime>,
+}
+
+#[cfg(test)]
+impl RelojSimulado {
+    /// Instancia un reloj detenido en la hora actual del sistema
+    pub fn new() -> Self {
+        RelojSimulado {
+            ahora: Mutex::new(SystemTime::now()),
+        }
+    }
+
+    /// Adelanta el reloj la duracion indicada
+    pub fn avanzar(&self, duracion: Duration) {
+        match self.ahora.lock() {
+            Ok(mut ahora) => *ahora += duracion,
+            Err(envenenado) => *envenenado.into_inner() += duracion,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Default for RelojSimulado {
+    fn default() -> Self {
+        RelojSimulado::new()
+    }
+}
+
+#[cfg(test)]
+impl Reloj for RelojSimulado {
+    fn ahora(&self) -> SystemTime {
+        match self.ahora.lock() {
+            Ok(ahora) => *ahora,
+            Err(envenenado) => *envenenado.into_inner(),
+        }
+    }
+}
@@ -0,0 +1,717 @@
+use crate::base_de_datos::{Instantanea, ResultadoRedis, Rol};
+use crate::cliente::{Cliente, Token};
+use crate::cluster::id_de_nodo;
+use crate::cola_de_salida::LimiteDeSalida;
+use crate::eventos::{Evento, Suscriptor};
+use crate::redis_error::RedisError;
+use crate::resp;
+use crate::temporizador::Espera;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, Weak};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Token con el que se ejecutan los comandos que llegan del maestro. Los Token de los clientes
+/// nunca son negativos, por lo que no se confunde con ninguno
+pub const TOKEN_MAESTRO: Token = -1;
+
+/// Estado de la replicacion de este servidor. Como maestro, envia a cada replica conectada las
+/// escrituras que ejecuta; como replica, reenvia a sus propias replicas exactamente lo que recibe
+/// del maestro, de modo que las replicas pueden formar un arbol y todas comparten el
+/// identificador y el offset del maestro original
+#[derive(Debug)]
+pub struct Replicacion {
+    estado: Mutex<EstadoReplicacion>,
+    /// Las escrituras la toman compartida mientras se ejecutan y se propagan, y la sincronizacion
+    /// de una replica nueva la toma exclusiva, para que ninguna escritura quede a mitad de camino
+    /// entre la copia de la tabla y la lista de replicas
+    sincronizacion: RwLock<()>,
+}
+
+#[derive(Debug)]
+struct EstadoReplicacion {
+    /// Identificador de la historia de replicacion, las replicas adoptan el de su maestro
+    id: String,
+    /// Cantidad de bytes propagados desde el comienzo de la historia
+    offset: u64,
+    replicas: Vec<Replica>,
+    /// Limite de la cola de salida de cada replica, independiente del de pub/sub
+    limite_de_replicas: LimiteDeSalida,
+    /// Puertos informados con REPLCONF listening-port por las replicas que aun no se sincronizaron
+    puertos: HashMap<Token, String>,
+    /// Estado de la conexion con el maestro, si este servidor es replica
+    maestro_conectado: bool,
+    ultima_actividad_maestro: Option<Instant>,
+    /// Esperas de WAIT que se notifican con cada confirmacion de una replica
+    esperando_confirmaciones: Vec<Weak<Espera>>,
+}
+
+/// Replica conectada junto con lo ultimo que confirmo haber aplicado
+#[derive(Debug)]
+struct Replica {
+    cliente: Cliente,
+    puerto: Option<String>,
+    offset_confirmado: u64,
+    ultima_confirmacion: Instant,
+}
+
+impl Replicacion {
+    pub fn new() -> Self {
+        Replicacion {
+            estado: Mutex::new(EstadoReplicacion {
+                id: nuevo_id(),
+                offset: 0,
+                replicas: Vec::new(),
+                limite_de_replicas: LimiteDeSalida::de_replica(None),
+                puertos: HashMap::new(),
+                maestro_conectado: false,
+                ultima_actividad_maestro: None,
+                esperando_confirmaciones: Vec::new(),
+            }),
+            sincronizacion: RwLock::new(()),
+        }
+    }
+
+    fn estado(&self) -> MutexGuard<'_, EstadoReplicacion> {
+        match self.estado.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    /// Se debe mantener mientras se ejecuta y se propaga una escritura
+    pub fn escritura(&self) -> RwLockReadGuard<'_, ()> {
+        match self.sincronizacion.read() {
+            Ok(g) => g,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    #[cfg(test)]
+    fn id(&self) -> String {
+        self.estado().id.clone()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.estado().offset
+    }
+
+    /// Recuerda el puerto en el que escucha la replica, para informarlo en INFO
+    pub fn registrar_puerto(&self, replica: Token, puerto: String) {
+        self.estado().puertos.insert(replica, puerto);
+    }
+
+    /// Registra el offset que la replica confirmo haber aplicado con REPLCONF ACK
+    pub fn confirmar(&self, replica: Token, offset: u64) {
+        let mut estado = self.estado();
+        if let Some(r) = estado
+            .replicas
+            .iter_mut()
+            .find(|r| r.cliente.obtener_token() == replica)
+        {
+            r.offset_confirmado = r.offset_confirmado.max(offset);
+            r.ultima_confirmacion = Instant::now();
+        }
+        estado
+            .esperando_confirmaciones
+            .retain(|espera| match espera.upgrade() {
+                Some(e) => {
+                    e.notificar();
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Notifica a la espera cada vez que una replica confirma su offset, hasta que se libera
+    pub fn avisar_confirmaciones(&self, espera: &Arc<Espera>) {
+        self.estado()
+            .esperando_confirmaciones
+            .push(Arc::downgrade(espera));
+    }
+
+    /// Cantidad de replicas que confirmaron haber aplicado al menos hasta el offset
+    pub fn replicas_al_dia(&self, offset: u64) -> usize {
+        self.estado()
+            .replicas
+            .iter()
+            .filter(|r| r.offset_confirmado >= offset)
+            .count()
+    }
+
+    /// Pide a las replicas que confirmen su offset sin esperar al proximo envio periodico
+    pub fn pedir_confirmaciones(&self) {
+        self.propagar(&[
+            "REPLCONF".to_string(),
+            "GETACK".to_string(),
+            "*".to_string(),
+        ]);
+    }
+
+    /// Quita la replica de las que reciben las escrituras, por ejemplo al desconectarse
+    pub fn quitar_replica(&self, replica: Token) {
+        let mut estado = self.estado();
+        estado
+            .replicas
+            .retain(|r| r.cliente.obtener_token() != replica);
+        estado.puertos.remove(&replica);
+    }
+
+    /// Registra si la conexion con el maestro esta establecida
+    pub fn enlace_con_maestro(&self, conectado: bool) {
+        let mut estado = self.estado();
+        estado.maestro_conectado = conectado;
+        estado.ultima_actividad_maestro = Some(Instant::now());
+    }
+
+    /// Indica si hay una conexion sincronizada con el maestro
+    pub fn maestro_conectado(&self) -> bool {
+        self.estado().maestro_conectado
+    }
+
+    /// Registra que llego algo del maestro
+    pub fn actividad_del_maestro(&self) {
+        self.estado().ultima_actividad_maestro = Some(Instant::now());
+    }
+
+    /// Seccion Replication de INFO. Como maestro informa el offset y la demora de cada replica,
+    /// donde lag son los segundos desde su ultima confirmacion; como replica, el estado del enlace
+    pub fn seccion_info(&self, rol: Rol, maestro: Option<String>) -> Vec<String> {
+        let estado = self.estado();
+        let mut info = vec!["# Replication".to_string()];
+        match (rol, maestro.as_ref().and_then(|m| m.rsplit_once(':'))) {
+            (Rol::Replica, Some((host, puerto))) => {
+                info.push("role:slave".to_string());
+                info.push(format!("master_host:{}", host));
+                info.push(format!("master_port:{}", puerto));
+                info.push(format!(
+                    "master_link_status:{}",
+                    if estado.maestro_conectado {
+                        "up"
+                    } else {
+                        "down"
+                    }
+                ));
+                if let Some(actividad) = estado.ultima_actividad_maestro {
+                    info.push(format!(
+                        "master_last_io_seconds_ago:{}",
+                        actividad.elapsed().as_secs()
+                    ));
+                }
+                info.push(format!("slave_repl_offset:{}", estado.offset));
+            }
+            (Rol::Replica, None) => info.push("role:slave".to_string()),
+            (Rol::Maestro, _) => info.push("role:master".to_string()),
+        }
+        info.push(format!("connected_slaves:{}", estado.replicas.len()));
+        for (i, replica) in estado.replicas.iter().enumerate() {
+            info.push(format!(
+                "slave{}:port={},state=online,offset={},lag={}",
+                i,
+                replica.puerto.as_deref().unwrap_or("0"),
+                replica.offset_confirmado,
+                replica.ultima_confirmacion.elapsed().as_secs()
+            ));
+        }
+        info.push(format!("master_replid:{}", estado.id));
+        info.push(format!("master_repl_offset:{}", estado.offset));
+        info.push("".to_string());
+        info
+    }
+
+    /// Envia a la replica la tabla completa precedida por +FULLRESYNC con el identificador y el
+    /// offset actuales, y la agrega a las replicas que reciben las escrituras siguientes
+    pub fn sincronizar_replica<F>(&self, mut replica: Cliente, volcado: F) -> ResultadoRedis
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        let _exclusivo = match self.sincronizacion.write() {
+            Ok(g) => g,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        let contenido = match volcado() {
+            Some(c) => c,
+            None => return ResultadoRedis::Error("ERR when accessing the database".to_string()),
+        };
+        let mut estado = self.estado();
+        let respuesta = ResultadoRedis::Varios(vec![
+            ResultadoRedis::StrSimple(format!("FULLRESYNC {} {}", estado.id, estado.offset)),
+            ResultadoRedis::BulkStr(contenido),
+        ]);
+        replica.cambiar_limite_de_salida(estado.limite_de_replicas);
+        if replica.enviar_mensaje(resp::codificar(&respuesta)).is_ok() {
+            let puerto = estado.puertos.remove(&replica.obtener_token());
+            let offset_confirmado = estado.offset;
+            estado.replicas.push(Replica {
+                cliente: replica,
+                puerto,
+                offset_confirmado,
+                ultima_confirmacion: Instant::now(),
+            });
+        }
+        ResultadoRedis::Varios(Vec::new())
+    }
+
+    /// Cambia el limite de escrituras pendientes de las replicas, con replica-max-pending
+    pub fn configurar_limite(&self, valor: &str) {
+        let mut estado = self.estado();
+        estado.limite_de_replicas = LimiteDeSalida::de_replica(Some(valor));
+        for replica in estado.replicas.iter() {
+            replica
+                .cliente
+                .cambiar_limite_de_salida(estado.limite_de_replicas);
+        }
+    }
+
+    /// Encola el comando para todas las replicas sin esperar a que se envie, ya que se propaga
+    /// con la base de datos tomada. La replica que no lee a tiempo supera replica-max-pending
+    /// y se desconecta, para que vuelva a sincronizarse
+    pub fn propagar(&self, tokens: &[String]) {
+        let comando = ResultadoRedis::Vector(
+            tokens
+                .iter()
+                .map(|t| ResultadoRedis::BulkStr(t.to_string()))
+                .collect(),
+        );
+        let mut estado = self.estado();
+        estado.offset += resp::largo(&comando) as u64;
+        estado
+            .replicas
+            .retain_mut(|replica| replica.cliente.enviar_push(&comando).is_ok());
+    }
+
+    /// Adopta la historia del maestro luego de una sincronizacion completa, cargando su tabla con
+    /// `cargar`. Las replicas propias se desconectan porque su copia corresponde a la historia
+    /// anterior, y vuelven a sincronizarse
+    pub fn adoptar<F: FnOnce()>(&self, id: String, offset: u64, cargar: F) {
+        let _exclusivo = match self.sincronizacion.write() {
+            Ok(g) => g,
+            Err(envenenado) => envenenado.into_inner(),
+        };
+        cargar();
+        let mut estado = self.estado();
+        estado.id = id;
+        estado.offset = offset;
+        for replica in estado.replicas.iter_mut() {
+            replica.cliente.cerrar();
+        }
+        estado.replicas.clear();
+    }
+}
+
+/// Propaga a las replicas las escrituras que ejecuta el servidor y, como DEL, las claves que expira.
+/// Lo que llega del maestro se reenvia siempre, aunque falle, para que los offsets de las replicas coincidan
+impl Suscriptor for Replicacion {
+    fn recibir(&self, eventos: &[Evento], _tabla: &Instantanea, _ahora: SystemTime) {
+        for evento in eventos {
+            match evento {
+                Evento::ClaveExpirada(clave) => {
+                    self.propagar(&["DEL".to_string(), clave.to_string()])
+                }
+                Evento::ComandoEjecutado {
+                    cliente,
+                    comando,
+                    escritura,
+                    exitoso,
+                    ..
+                } if se_propaga(*cliente, *escritura, *exitoso) => {
+                    if let Some(comando) = forma_propagada(comando.clone()) {
+                        self.propagar(&comando);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn nombre(&self) -> &str {
+        "replicacion"
+    }
+
+    fn es_critico(&self) -> bool {
+        true
+    }
+}
+
+/// Indica si un comando ejecutado por el cliente se debe propagar a las replicas
+pub fn se_propaga(cliente: Token, escritura: bool, exitoso: bool) -> bool {
+    cliente == TOKEN_MAESTRO || (escritura && exitoso)
+}
+
+impl Default for Replicacion {
+    fn default() -> Self {
+        Replicacion::new()
+    }
+}
+
+/// Resultado de la sincronizacion inicial con el maestro
+pub struct SincronizacionCompleta {
+    pub conexion: TcpStream,
+    pub id: String,
+    pub offset: u64,
+    pub volcado: String,
+}
+
+/// Se conecta al maestro y le pide una sincronizacion completa, autenticandose antes con
+/// `masterauth` si esta configurada. Devuelve la conexion lista para recibir las escrituras
+/// siguientes junto con la historia y la tabla del maestro
+pub fn sincronizar_con_maestro(
+    maestro: &str,
+    puerto_propio: &str,
+    masterauth: Option<&str>,
+) -> Result<SincronizacionCompleta, RedisError> {
+    let mut conexion = TcpStream::connect(maestro)?;
+    let mut pedir = |tokens: &[&str]| -> Result<String, RedisError> {
+        let tokens: Vec<String> = tokens.iter().map(|t| t.to_string()).collect();
+        conexion.write_all(codificar_comando(&tokens).as_bytes())?;
+        let respuesta = leer_linea(&mut conexion)?;
+        match respuesta.strip_prefix('-') {
+            Some(error) => Err(RedisError::Replicacion(error.to_string())),
+            None => Ok(respuesta),
+        }
+    };
+
+    if let Some(contrasenia) = masterauth {
+        match pedir(&["AUTH", contrasenia]) {
+            Err(RedisError::Replicacion(error)) => {
+                return Err(RedisError::Replicacion(format!(
+                    "no se pudo autenticar con masterauth: {}",
+                    error
+                )))
+            }
+            Err(e) => return Err(e),
+            Ok(_) => (),
+        }
+    }
+    match pedir(&["REPLCONF", "listening-port", puerto_propio]) {
+        Err(RedisError::Replicacion(error)) if error.starts_with("NOAUTH") => {
+            return Err(RedisError::Replicacion(format!(
+                "el maestro requiere autenticacion y masterauth no esta configurada: {}",
+                error
+            )))
+        }
+        Err(e) => return Err(e),
+        Ok(_) => (),
+    }
+    let respuesta = pedir(&["PSYNC", "?", "-1"])?;
+    let (id, offset) = match respuesta.split_whitespace().collect::<Vec<_>>()[..] {
+        ["+FULLRESYNC", id, offset] => match offset.parse() {
+            Ok(offset) => (id.to_string(), offset),
+            Err(_) => return Err(RedisError::Replicacion(respuesta)),
+        },
+        _ => return Err(RedisError::Replicacion(respuesta)),
+    };
+
+    let encabezado = leer_linea(&mut conexion)?;
+    let largo: usize = match encabezado.strip_prefix('$').map(|l| l.parse()) {
+        Some(Ok(largo)) => largo,
+        _ => return Err(RedisError::Replicacion(encabezado)),
+    };
+    let mut volcado = vec![0; largo + 2];
+    conexion.read_exact(&mut volcado)?;
+    volcado.truncate(largo);
+    let volcado = match String::from_utf8(volcado) {
+        Ok(v) => v,
+        Err(_) => return Err(RedisError::Replicacion("invalid dump".to_string())),
+    };
+
+    Ok(SincronizacionCompleta {
+        conexion,
+        id,
+        offset,
+        volcado,
+    })
+}
+
+/// Devuelve el comando que deben ejecutar las replicas para reproducir el efecto de una escritura.
+/// MIGRATE se propaga como el DEL de la clave migrada, ya que las replicas no deben conectarse
+/// a la instancia destino
+pub fn forma_propagada(comando: Vec<String>) -> Option<Vec<String>> {
+    if comando[0] != "MIGRATE" {
+        return Some(comando);
+    }
+    if comando.iter().skip(6).any(|o| o.to_uppercase() == "COPY") {
+        return None;
+    }
+    Some(vec!["DEL".to_string(), comando.get(3)?.to_string()])
+}
+
+/// Codifica el comando como lo envia un cliente, que es la forma en la que se propaga
+pub fn codificar_comando(tokens: &[String]) -> String {
+    resp::codificar(&ResultadoRedis::Vector(
+        tokens
+            .iter()
+            .map(|t| ResultadoRedis::BulkStr(t.to_string()))
+            .collect(),
+    ))
+}
+
+/// Lee una linea sin consumir nada de lo que le sigue, ya que el resto de la conexion
+/// lo procesa el parser de comandos
+fn leer_linea(conexion: &mut TcpStream) -> Result<String, RedisError> {
+    let mut linea = Vec::new();
+    let mut byte = [0; 1];
+    while !linea.ends_with(b"\r\n") {
+        if conexion.read(&mut byte)? == 0 {
+            return Err(RedisError::Cierre);
+        }
+        linea.push(byte[0]);
+    }
+    linea.truncate(linea.len() - 2);
+    Ok(String::from_utf8_lossy(&linea).to_string())
+}
+
+fn nuevo_id() -> String {
+    let semilla = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    id_de_nodo(&format!("{}:{}", semilla, std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cliente_redis::ClienteRedis;
+    use crate::cola_de_salida::{LimiteDeSalida, PoliticaDeDesborde};
+    use crate::parser::Parser;
+    use crate::servidor_de_prueba::ServidorDePrueba;
+    use std::io::BufReader;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn tokens(valores: &[&str]) -> Vec<String> {
+        valores.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn una_replica_de_una_replica_comparte_la_historia_y_el_offset_del_maestro() {
+        let maestro = Arc::new(Replicacion::new());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let direccion = listener.local_addr().unwrap().to_string();
+        let clon = Arc::clone(&maestro);
+        let hilo = thread::spawn(move || {
+            let mut cliente: Cliente =
+                Box::new(ClienteRedis::new(1, 0, listener.accept().unwrap().0));
+            let replconf = cliente.obtener_comando().unwrap().unwrap();
+            assert_eq!("REPLCONF", replconf.get_nombre());
+            cliente
+                .enviar_resultado(&ResultadoRedis::StrSimple("OK".to_string()))
+                .unwrap();
+            assert_eq!(
+                "PSYNC",
+                cliente.obtener_comando().unwrap().unwrap().get_nombre()
+            );
+            clon.sincronizar_replica(cliente, || Some("STRING:a:1\n".to_string()));
+            clon.propagar(&tokens(&["SET", "b", "2"]));
+        });
+
+        let sincronizacion = sincronizar_con_maestro(&direccion, "7001", None).unwrap();
+        hilo.join().unwrap();
+        assert_eq!(maestro.id(), sincronizacion.id);
+        assert_eq!(0, sincronizacion.offset);
+        assert_eq!("STRING:a:1\n", sincronizacion.volcado);
+
+        let replica = Replicacion::new();
+        replica.adoptar(sincronizacion.id, sincronizacion.offset, || ());
+        let comando = Parser::new(sincronizacion.conexion)
+            .siguiente_comando()
+            .unwrap();
+        let mut recibido = vec![comando.get_nombre()];
+        recibido.extend_from_slice(comando.tokens());
+        replica.propagar(&recibido);
+
+        assert_eq!(tokens(&["SET", "b", "2"]), recibido);
+        assert_eq!(maestro.offset(), replica.offset());
+        assert_eq!(maestro.id(), replica.id());
+    }
+
+    #[test]
+    fn las_confirmaciones_de_las_replicas_se_informan_en_info() {
+        let maestro = Replicacion::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _replica = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let cliente: Cliente = Box::new(ClienteRedis::new(4, 0, listener.accept().unwrap().0));
+
+        maestro.registrar_puerto(4, "7002".to_string());
+        maestro.sincronizar_replica(cliente, || Some(String::new()));
+        maestro.propagar(&tokens(&["SET", "a", "1"]));
+        let objetivo = maestro.offset();
+        assert_eq!(0, maestro.replicas_al_dia(objetivo));
+
+        maestro.confirmar(4, objetivo);
+        maestro.confirmar(4, 0);
+        assert_eq!(1, maestro.replicas_al_dia(objetivo));
+        let info = maestro.seccion_info(Rol::Maestro, None);
+        assert!(info.contains(&format!(
+            "slave0:port=7002,state=online,offset={},lag=0",
+            objetivo
+        )));
+        assert!(info.contains(&format!("master_repl_offset:{}", objetivo)));
+
+        maestro.quitar_replica(4);
+        assert!(info.contains(&"connected_slaves:1".to_string()));
+        assert!(maestro
+            .seccion_info(Rol::Maestro, None)
+            .contains(&"connected_slaves:0".to_string()));
+    }
+
+    #[test]
+    fn propagar_no_espera_a_una_replica_que_no_lee_y_la_desconecta() {
+        let maestro = Replicacion::new();
+        maestro.configurar_limite("1");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _replica = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let cliente: Cliente = Box::new(ClienteRedis::new(5, 0, listener.accept().unwrap().0));
+        maestro.sincronizar_replica(cliente, || Some(String::new()));
+
+        let valor = "x".repeat(1024 * 1024);
+        let inicio = Instant::now();
+        for _ in 0..64 {
+            maestro.propagar(&tokens(&["SET", "a", &valor]));
+        }
+        assert!(inicio.elapsed() < Duration::from_secs(5));
+        assert!(maestro
+            .seccion_info(Rol::Maestro, None)
+            .contains(&"connected_slaves:0".to_string()));
+    }
+
+    #[test]
+    fn la_replica_no_se_desconecta_por_el_limite_de_pub_sub() {
+        let maestro = Replicacion::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _replica = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let cliente: Cliente = Box::new(
+            ClienteRedis::new(5, 0, listener.accept().unwrap().0).limitar_salida(LimiteDeSalida {
+                mensajes: 1,
+                politica: PoliticaDeDesborde::Desconectar,
+            }),
+        );
+        maestro.sincronizar_replica(cliente, || Some(String::new()));
+
+        for _ in 0..100 {
+            maestro.propagar(&tokens(&["SET", "a", "1"]));
+        }
+        assert!(maestro
+            .seccion_info(Rol::Maestro, None)
+            .contains(&"connected_slaves:1".to_string()));
+
+        maestro.configurar_limite("10");
+        for _ in 0..100 {
+            maestro.propagar(&tokens(&["SET", "a", "1"]));
+        }
+        assert!(maestro
+            .seccion_info(Rol::Maestro, None)
+            .contains(&"connected_slaves:0".to_string()));
+    }
+
+    #[test]
+    fn la_replica_se_autentica_antes_de_sincronizarse() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let direccion = listener.local_addr().unwrap().to_string();
+        let hilo = thread::spawn(move || {
+            for _ in 0..2 {
+                let mut cliente: Cliente =
+                    Box::new(ClienteRedis::new(1, 0, listener.accept().unwrap().0));
+                let comando = cliente.obtener_comando().unwrap().unwrap();
+                let respuesta = match comando.get_nombre().as_str() {
+                    "AUTH" => "WRONGPASS invalid username-password pair or user is disabled.",
+                    _ => "NOAUTH Authentication required.",
+                };
+                cliente
+                    .enviar_resultado(&ResultadoRedis::Error(respuesta.to_string()))
+                    .unwrap();
+                cliente.cerrar();
+            }
+        });
+
+        let error = sincronizar_con_maestro(&direccion, "7001", Some("incorrecta"))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("no se pudo autenticar con masterauth: WRONGPASS"));
+        let error = sincronizar_con_maestro(&direccion, "7001", None)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("masterauth no esta configurada: NOAUTH"));
+        hilo.join().unwrap();
+    }
+
+    #[test]
+    fn migrate_se_propaga_como_el_borrado_de_la_clave() {
+        assert_eq!(
+            Some(tokens(&["DEL", "clave"])),
+            forma_propagada(tokens(&["MIGRATE", "host", "7000", "clave", "0", "100"]))
+        );
+        assert_eq!(
+            None,
+            forma_propagada(tokens(&[
+                "MIGRATE", "host", "7000", "clave", "0", "100", "copy"
+            ]))
+        );
+        assert_eq!(
+            Some(tokens(&["SET", "a", "1"])),
+            forma_propagada(tokens(&["SET", "a", "1"]))
+        );
+    }
+
+    #[test]
+    fn las_escrituras_concurrentes_sobre_una_clave_se_propagan_en_el_orden_en_que_se_ejecutaron() {
+        let servidor = ServidorDePrueba::iniciar().unwrap();
+        let direccion = servidor.direccion();
+        let sincronizacion = sincronizar_con_maestro(&direccion.to_string(), "7001", None).unwrap();
+
+        let hilos: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|letra| {
+                thread::spawn(move || {
+                    let mut conexion = TcpStream::connect(direccion).unwrap();
+                    let mut lector = BufReader::new(conexion.try_clone().unwrap());
+                    for _ in 0..50 {
+                        conexion
+                            .write_all(
+                                codificar_comando(&tokens(&["APPEND", "texto", letra])).as_bytes(),
+                            )
+                            .unwrap();
+                        resp::leer(&mut lector).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for hilo in hilos {
+            hilo.join().unwrap();
+        }
+
+        let valor = {
+            let mut conexion = TcpStream::connect(direccion).unwrap();
+            let mut lector = BufReader::new(conexion.try_clone().unwrap());
+            conexion
+                .write_all(codificar_comando(&tokens(&["GET", "texto"])).as_bytes())
+                .unwrap();
+            resp::leer(&mut lector).unwrap()
+        };
+
+        let mut propagado = String::new();
+        {
+            let mut replica = BufReader::new(sincronizacion.conexion);
+            while propagado.len() < 200 {
+                if let ResultadoRedis::Vector(comando) = resp::leer(&mut replica).unwrap() {
+                    if let [ResultadoRedis::BulkStr(nombre), _, ResultadoRedis::BulkStr(sufijo)] =
+                        comando.as_slice()
+                    {
+                        if nombre == "APPEND" {
+                            propagado.push_str(sufijo);
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(ResultadoRedis::BulkStr(propagado), valor);
+        servidor.detener();
+    }
+}
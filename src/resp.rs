@@ -0,0 +1,239 @@
+use crate::base_de_datos::ResultadoRedis;
+use crate::parser::{protocolo, ParserError};
+
+use std::io::{self, BufRead, ErrorKind, Write};
+
+/// Codifica la respuesta segun el protocolo Redis (RESP)
+pub fn codificar(res: &ResultadoRedis) -> String {
+    let mut salida = Vec::with_capacity(largo(res));
+    // Escribir en un Vec no falla
+    escribir(res, &mut salida).ok();
+    String::from_utf8_lossy(&salida).into_owned()
+}
+
+/// Serializa la respuesta segun el protocolo Redis directamente sobre el destino, sin armar
+/// antes una copia serializada completa. Sobre un BufWriter la respuesta se escribe en
+/// bloques del tamaño de su buffer
+pub fn escribir<W: Write>(res: &ResultadoRedis, destino: &mut W) -> io::Result<()> {
+    match res {
+        ResultadoRedis::StrSimple(cad) => write!(destino, "+{}\r\n", cad),
+        ResultadoRedis::BulkStr(cad) => {
+            write!(destino, "${}\r\n", cad.len())?;
+            destino.write_all(cad.as_bytes())?;
+            destino.write_all(b"\r\n")
+        }
+        ResultadoRedis::Int(ent) => write!(destino, ":{}\r\n", ent),
+        ResultadoRedis::Vector(vec) => {
+            write!(destino, "*{}\r\n", vec.len())?;
+            vec.iter().try_for_each(|r| escribir(r, destino))
+        }
+        ResultadoRedis::Nil => destino.write_all(b"$-1\r\n"),
+        ResultadoRedis::Error(e) => write!(destino, "-{}\r\n", e),
+        ResultadoRedis::Varios(vec) => vec.iter().try_for_each(|r| escribir(r, destino)),
+    }
+}
+
+/// Cantidad de bytes que ocupa la respuesta serializada, sin serializarla
+pub fn largo(res: &ResultadoRedis) -> usize {
+    match res {
+        ResultadoRedis::StrSimple(cad) | ResultadoRedis::Error(cad) => cad.len() + 3,
+        ResultadoRedis::BulkStr(cad) => digitos(cad.len() as isize) + cad.len() + 5,
+        ResultadoRedis::Int(ent) => digitos(*ent) + 3,
+        ResultadoRedis::Vector(vec) => {
+            digitos(vec.len() as isize) + 3 + vec.iter().map(largo).sum::<usize>()
+        }
+        ResultadoRedis::Nil => 5,
+        ResultadoRedis::Varios(vec) => vec.iter().map(largo).sum(),
+    }
+}
+
+/// Cantidad de caracteres del numero escrito en decimal, incluido el signo
+fn digitos(numero: isize) -> usize {
+    let mut restante = numero.unsigned_abs();
+    let mut digitos = if numero < 0 { 2 } else { 1 };
+    while restante >= 10 {
+        restante /= 10;
+        digitos += 1;
+    }
+    digitos
+}
+
+/// Decodifica el valor RESP que se encuentra al principio del buffer. Devuelve el valor junto con
+/// la cantidad de bytes que ocupa, ninguno si todavia no llego completo o un error si no respeta
+/// el protocolo. Los bulk strings y los arrays nulos se decodifican como Nil
+pub fn decodificar(buffer: &[u8]) -> Result<Option<(ResultadoRedis, usize)>, ParserError> {
+    let fin = match buffer.windows(2).position(|w| w == b"\r\n") {
+        Some(f) => f,
+        None => return Ok(None),
+    };
+    let linea = String::from_utf8_lossy(&buffer[1..fin]).into_owned();
+    let mut posicion = fin + 2;
+    let numero = || -> Result<isize, ParserError> {
+        linea
+            .parse()
+            .map_err(|_| protocolo(&format!("invalid length '{}'", linea)))
+    };
+
+    let valor = match buffer[0] {
+        b'+' => ResultadoRedis::StrSimple(linea),
+        b'-' => ResultadoRedis::Error(linea),
+        b':' => ResultadoRedis::Int(numero()?),
+        b'$' => match numero()? {
+            largo if largo < 0 => ResultadoRedis::Nil,
+            largo => {
+                let fin = posicion + largo as usize;
+                if buffer.len() < fin + 2 {
+                    return Ok(None);
+                }
+                if &buffer[fin..fin + 2] != b"\r\n" {
+                    return Err(protocolo("bulk string without CRLF"));
+                }
+                let contenido = String::from_utf8_lossy(&buffer[posicion..fin]).into_owned();
+                posicion = fin + 2;
+                ResultadoRedis::BulkStr(contenido)
+            }
+        },
+        b'*' => match numero()? {
+            largo if largo < 0 => ResultadoRedis::Nil,
+            largo => {
+                let mut elementos = Vec::with_capacity(largo as usize);
+                for _ in 0..largo {
+                    match decodificar(&buffer[posicion..])? {
+                        Some((elemento, usados)) => {
+                            elementos.push(elemento);
+                            posicion += usados;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                ResultadoRedis::Vector(elementos)
+            }
+        },
+        otro => return Err(protocolo(&format!("unexpected '{}'", otro as char))),
+    };
+    Ok(Some((valor, posicion)))
+}
+
+/// Lee del stream el siguiente valor RESP sin consumir nada de lo que le sigue
+pub fn leer<R: BufRead>(lector: &mut R) -> io::Result<ResultadoRedis> {
+    let mut pendiente = Vec::new();
+    loop {
+        let disponible = lector.fill_buf()?;
+        if disponible.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "el servidor cerro la conexion",
+            ));
+        }
+        let previo = pendiente.len();
+        pendiente.extend_from_slice(disponible);
+        match decodificar(&pendiente) {
+            Ok(Some((valor, usados))) => {
+                lector.consume(usados - previo);
+                return Ok(valor);
+            }
+            Ok(None) => lector.consume(pendiente.len() - previo),
+            Err(e) => return Err(io::Error::new(ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Read};
+
+    #[test]
+    fn cuando_se_envia_un_resultado_redis_simple_string_envia_un_string_correcto() {
+        let resultado = ResultadoRedis::StrSimple("Ok".to_string());
+        assert_eq!(codificar(&resultado), "+Ok\r\n");
+    }
+
+    #[test]
+    fn cuando_se_envia_un_resultado_redis_bulk_strings_se_parsea_correctamente() {
+        let resultado = ResultadoRedis::BulkStr("foo".to_string());
+        assert_eq!(codificar(&resultado), "$3\r\nfoo\r\n");
+    }
+
+    #[test]
+    fn cuando_se_envia_un_resultado_redis_int_se_parsea_correctamente() {
+        let resultado = ResultadoRedis::Int(55);
+        assert_eq!(codificar(&resultado), ":55\r\n");
+    }
+
+    #[test]
+    fn cuando_se_envia_un_resultado_redis_vector_de_ints_se_parsea_correctamente() {
+        let resultado =
+            ResultadoRedis::Vector(vec![ResultadoRedis::Int(1), ResultadoRedis::Int(2)]);
+        assert_eq!(codificar(&resultado), "*2\r\n:1\r\n:2\r\n");
+    }
+
+    #[test]
+    fn cuando_se_envia_un_resultado_redis_vector_de_resultados_se_parsea_correctamente() {
+        let resultado = ResultadoRedis::Vector(vec![
+            ResultadoRedis::Int(1),
+            ResultadoRedis::Int(2),
+            ResultadoRedis::Int(3),
+            ResultadoRedis::Int(4),
+            ResultadoRedis::BulkStr("foobar".to_string()),
+        ]);
+        assert_eq!(
+            codificar(&resultado),
+            "*5\r\n:1\r\n:2\r\n:3\r\n:4\r\n$6\r\nfoobar\r\n"
+        );
+    }
+
+    #[test]
+    fn el_largo_calculado_coincide_con_la_respuesta_serializada() {
+        let resultado = ResultadoRedis::Varios(vec![
+            ResultadoRedis::Vector(vec![
+                ResultadoRedis::Int(-1234),
+                ResultadoRedis::BulkStr("x".repeat(1000)),
+                ResultadoRedis::Nil,
+            ]),
+            ResultadoRedis::Error("ERR algo".to_string()),
+            ResultadoRedis::StrSimple("OK".to_string()),
+            ResultadoRedis::Int(0),
+        ]);
+        assert_eq!(codificar(&resultado).len(), largo(&resultado));
+    }
+
+    #[test]
+    fn lo_que_se_codifica_se_decodifica_igual_y_lo_incompleto_espera_mas_datos() {
+        let resultado = ResultadoRedis::Vector(vec![
+            ResultadoRedis::Int(-7),
+            ResultadoRedis::BulkStr("con\r\nsalto".to_string()),
+            ResultadoRedis::Nil,
+            ResultadoRedis::Vector(vec![ResultadoRedis::StrSimple("OK".to_string())]),
+            ResultadoRedis::Error("ERR algo".to_string()),
+        ]);
+        let mut codificado = codificar(&resultado).into_bytes();
+        let total = codificado.len();
+
+        for corte in 0..total {
+            assert_eq!(None, decodificar(&codificado[..corte]).unwrap());
+        }
+        codificado.extend_from_slice(b"+siguiente\r\n");
+        assert_eq!(Some((resultado, total)), decodificar(&codificado).unwrap());
+        assert_eq!(
+            Some((ResultadoRedis::Nil, 5)),
+            decodificar(b"*-1\r\n").unwrap()
+        );
+        assert!(decodificar(b"$3\r\nfoobar\r\n").is_err());
+        assert!(decodificar(b"?\r\n").is_err());
+    }
+
+    #[test]
+    fn se_leen_del_stream_los_valores_de_a_uno_aunque_lleguen_partidos() {
+        let primera: &[u8] = b"$4\r\nho";
+        let segunda: &[u8] = b"la\r\n:3\r\n";
+        let mut lector = BufReader::with_capacity(4, primera.chain(segunda));
+
+        assert_eq!(
+            ResultadoRedis::BulkStr("hola".to_string()),
+            leer(&mut lector).unwrap()
+        );
+        assert_eq!(ResultadoRedis::Int(3), leer(&mut lector).unwrap());
+        assert!(leer(&mut lector).is_err());
+    }
+}
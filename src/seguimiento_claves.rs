@@ -0,0 +1,160 @@
+use crate::cliente::Token;
+
+use std::collections::{HashMap, HashSet};
+
+/// Canal por el que se envian los mensajes de invalidacion a los clientes que los reciben
+pub const CANAL_INVALIDACION: &str = "__redis__:invalidate";
+
+/// Modo de seguimiento que activo un cliente con CLIENT TRACKING
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcionesSeguimiento {
+    /// Cliente suscripto a __redis__:invalidate que recibe las invalidaciones
+    pub redireccion: Token,
+    /// Se informan todas las claves modificadas que empiezan con alguno de los prefijos,
+    /// no solo las que leyo el cliente
+    pub bcast: bool,
+    pub prefijos: Vec<String>,
+    /// No se informan las claves que modifico el propio cliente
+    pub noloop: bool,
+}
+
+/// Recuerda que claves leyo cada cliente con el seguimiento activado, para avisarle cuando
+/// se modifican y pueda invalidar su cache local. Igual que redis, una vez informada
+/// la invalidacion se olvida la lectura hasta que el cliente vuelva a leer la clave
+#[derive(Debug, Default)]
+pub struct SeguimientoDeClaves {
+    clientes: HashMap<Token, OpcionesSeguimiento>,
+    lecturas: HashMap<String, HashSet<Token>>,
+}
+
+impl SeguimientoDeClaves {
+    pub fn new() -> Self {
+        SeguimientoDeClaves::default()
+    }
+
+    /// Activa el seguimiento del cliente reemplazando el modo que tuviera antes
+    pub fn activar(&mut self, cliente: Token, opciones: OpcionesSeguimiento) {
+        self.desactivar(cliente);
+        self.clientes.insert(cliente, opciones);
+    }
+
+    /// Desactiva el seguimiento del cliente y olvida todas sus lecturas
+    pub fn desactivar(&mut self, cliente: Token) {
+        if self.clientes.remove(&cliente).is_none() {
+            return;
+        }
+        self.lecturas.retain(|_, lectores| {
+            lectores.remove(&cliente);
+            !lectores.is_empty()
+        });
+    }
+
+    /// Registra las claves leidas por el cliente si tiene el seguimiento activado
+    pub fn registrar_lecturas(&mut self, cliente: Token, claves: &[String]) {
+        match self.clientes.get(&cliente) {
+            Some(opciones) if !opciones.bcast => (),
+            _ => return,
+        }
+        for clave in claves {
+            self.lecturas
+                .entry(clave.to_string())
+                .or_default()
+                .insert(cliente);
+        }
+    }
+
+    /// Devuelve, por cada cliente que debe recibirlas, las claves modificadas que tiene que invalidar.
+    /// `origen` es el cliente que modifico las claves, si se conoce
+    pub fn invalidar(
+        &mut self,
+        claves: &[String],
+        origen: Option<Token>,
+    ) -> HashMap<Token, Vec<String>> {
+        let mut invalidaciones: HashMap<Token, Vec<String>> = HashMap::new();
+        for clave in claves {
+            let lectores = self.lecturas.remove(clave).unwrap_or_default();
+            for (cliente, opciones) in &self.clientes {
+                let interesado = if opciones.bcast {
+                    opciones.prefijos.is_empty()
+                        || opciones.prefijos.iter().any(|p| clave.starts_with(p))
+                } else {
+                    lectores.contains(cliente)
+                };
+                if interesado && !(opciones.noloop && origen == Some(*cliente)) {
+                    let claves = invalidaciones.entry(opciones.redireccion).or_default();
+                    if !claves.contains(clave) {
+                        claves.push(clave.to_string());
+                    }
+                }
+            }
+        }
+        invalidaciones
+    }
+
+    /// Olvida todas las lecturas y devuelve los clientes que deben invalidar toda su cache,
+    /// como ocurre al ejecutar FLUSHDB
+    pub fn invalidar_todo(&mut self) -> Vec<Token> {
+        self.lecturas.clear();
+        let mut destinos: Vec<Token> = self.clientes.values().map(|o| o.redireccion).collect();
+        destinos.sort_unstable();
+        destinos.dedup();
+        destinos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opciones(redireccion: Token, bcast: bool, prefijos: &[&str]) -> OpcionesSeguimiento {
+        OpcionesSeguimiento {
+            redireccion,
+            bcast,
+            prefijos: prefijos.iter().map(|p| p.to_string()).collect(),
+            noloop: false,
+        }
+    }
+
+    fn claves(valores: &[&str]) -> Vec<String> {
+        valores.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn se_invalidan_solo_las_claves_leidas_y_una_unica_vez() {
+        let mut seguimiento = SeguimientoDeClaves::new();
+        seguimiento.activar(1, opciones(10, false, &[]));
+        seguimiento.registrar_lecturas(1, &claves(&["a"]));
+        seguimiento.registrar_lecturas(2, &claves(&["b"]));
+
+        let invalidaciones = seguimiento.invalidar(&claves(&["a", "b"]), None);
+        assert_eq!(1, invalidaciones.len());
+        assert_eq!(claves(&["a"]), invalidaciones[&10]);
+        assert!(seguimiento.invalidar(&claves(&["a"]), None).is_empty());
+    }
+
+    #[test]
+    fn en_modo_bcast_se_invalidan_las_claves_con_los_prefijos_indicados() {
+        let mut seguimiento = SeguimientoDeClaves::new();
+        seguimiento.activar(1, opciones(10, true, &["user:"]));
+
+        let invalidaciones = seguimiento.invalidar(&claves(&["user:1", "otra"]), None);
+        assert_eq!(claves(&["user:1"]), invalidaciones[&10]);
+    }
+
+    #[test]
+    fn con_noloop_no_se_informan_las_modificaciones_propias() {
+        let mut seguimiento = SeguimientoDeClaves::new();
+        let mut noloop = opciones(10, true, &[]);
+        noloop.noloop = true;
+        seguimiento.activar(1, noloop);
+
+        assert!(seguimiento.invalidar(&claves(&["a"]), Some(1)).is_empty());
+        assert_eq!(
+            claves(&["a"]),
+            seguimiento.invalidar(&claves(&["a"]), Some(2))[&10]
+        );
+
+        seguimiento.desactivar(1);
+        assert!(seguimiento.invalidar(&claves(&["a"]), Some(2)).is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+/// Pedido que el sistema operativo le hace al servidor mediante una señal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Senal {
+    /// SIGTERM o SIGINT (Ctrl-C): dejar de atender y cerrar el servidor
+    Terminar,
+    /// SIGHUP: volver a leer el archivo de configuracion
+    Recargar,
+    /// SIGUSR1
+    Guardar,
+}
+
+/// Empieza a escuchar las señales del proceso y devuelve el canal por el que se reciben.
+/// Una vez llamada, las señales dejan de tener su efecto predeterminado (por ejemplo terminar
+/// el proceso) y queda en manos de quien reciba del canal atenderlas
+pub fn escuchar() -> io::Result<Receiver<Senal>> {
+    let (tx, rx) = channel();
+    plataforma::registrar(move |senal| tx.send(senal).is_ok())?;
+    Ok(rx)
+}
+
+#[cfg(unix)]
+mod plataforma {
+    use super::*;
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+    use signal_hook::iterator::Signals;
+
+    /// Entrega cada señal recibida hasta que `entregar` devuelva falso
+    pub fn registrar<F>(mut entregar: F) -> io::Result<()>
+    where
+        F: FnMut(Senal) -> bool + Send + 'static,
+    {
+        let mut senales = Signals::new([SIGTERM, SIGINT, SIGHUP, SIGUSR1])?;
+        thread::spawn(move || {
+            for numero in senales.forever() {
+                let senal = match numero {
+                    SIGHUP => Senal::Recargar,
+                    SIGUSR1 => Senal::Guardar,
+                    _ => Senal::Terminar,
+                };
+                if !entregar(senal) {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// En Windows solo existen los eventos de Ctrl-C y de cierre de la consola, que llegan como
+/// SIGINT y SIGTERM. No hay forma de pedir la recarga ni el guardado con una señal
+#[cfg(not(unix))]
+mod plataforma {
+    use super::*;
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::flag;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const INTERVALO_CONSULTA: Duration = Duration::from_millis(100);
+
+    pub fn registrar<F>(mut entregar: F) -> io::Result<()>
+    where
+        F: FnMut(Senal) -> bool + Send + 'static,
+    {
+        let recibida = Arc::new(AtomicBool::new(false));
+        flag::register(SIGINT, Arc::clone(&recibida))?;
+        flag::register(SIGTERM, Arc::clone(&recibida))?;
+        thread::spawn(move || loop {
+            thread::sleep(INTERVALO_CONSULTA);
+            if recibida.swap(false, Ordering::SeqCst) && !entregar(Senal::Terminar) {
+                break;
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use signal_hook::consts::SIGUSR1;
+    use signal_hook::low_level::raise;
+    use std::time::Duration;
+
+    #[test]
+    fn las_senales_del_proceso_llegan_por_el_canal() {
+        let senales = escuchar().unwrap();
+
+        raise(SIGUSR1).unwrap();
+
+        assert_eq!(
+            Ok(Senal::Guardar),
+            senales.recv_timeout(Duration::from_secs(5))
+        );
+    }
+}
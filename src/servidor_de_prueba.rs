@@ -0,0 +1,166 @@
+use crate::config::Config;
+use crate::redis::{Detencion, Redis};
+use crate::redis_error::RedisError;
+
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
+use std::thread::{self, JoinHandle};
+
+/// Numera los servidores de un mismo proceso para que cada uno use su propio archivo de log
+static SERVIDORES: AtomicUsize = AtomicUsize::new(0);
+
+/// Servidor completo que corre en otro hilo del mismo proceso, en un puerto libre elegido por el
+/// sistema operativo, para que los tests ejerciten el camino real de TCP y del parser.
+/// Guarda los datos solo en memoria y loguea en un archivo temporal que se borra al detenerlo.
+/// El servidor se detiene al liberarlo, despues de que se cierren las conexiones de los clientes
+///
+/// # Ejemplo
+/// ```no_run
+/// # use proyecto_taller_1::ServidorDePrueba;
+/// # use std::io::Write;
+/// # use std::net::TcpStream;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let servidor = ServidorDePrueba::iniciar()?;
+/// let mut conexion = TcpStream::connect(servidor.direccion())?;
+/// conexion.write_all(b"*1\r\n$4\r\nPING\r\n")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServidorDePrueba {
+    direccion: SocketAddr,
+    detencion: Detencion,
+    logfile: String,
+    hilo: Option<JoinHandle<()>>,
+}
+
+impl ServidorDePrueba {
+    /// Inicia un servidor con la configuracion predeterminada
+    pub fn iniciar() -> Result<Self, RedisError> {
+        ServidorDePrueba::iniciar_con(Config::new())
+    }
+
+    /// Inicia un servidor con la configuracion indicada, reemplazando la direccion por un puerto
+    /// libre de 127.0.0.1, la persistencia por persistence no y el archivo de log por uno temporal
+    pub fn iniciar_con(mut config: Config) -> Result<Self, RedisError> {
+        config.set("persistence".to_string(), "no".to_string());
+        ServidorDePrueba::lanzar(config)
+    }
+
+    /// Inicia un servidor que conserva la persistencia de la configuracion, para los tests del
+    /// dump o del AOF. Los archivos de la configuracion quedan a cargo del test
+    #[cfg(test)]
+    pub(crate) fn iniciar_con_persistencia(config: Config) -> Result<Self, RedisError> {
+        ServidorDePrueba::lanzar(config)
+    }
+
+    fn lanzar(mut config: Config) -> Result<Self, RedisError> {
+        let logfile = env::temp_dir()
+            .join(format!(
+                "servidor-de-prueba-{}-{}.log",
+                process::id(),
+                SERVIDORES.fetch_add(1, Ordering::SeqCst)
+            ))
+            .to_string_lossy()
+            .to_string();
+        config.set("host".to_string(), "127.0.0.1".to_string());
+        config.set("port".to_string(), "0".to_string());
+        config.set("logfile".to_string(), logfile.clone());
+
+        let (tx_inicio, rx_inicio) = channel();
+        let hilo = thread::spawn(move || {
+            let mut redis = Redis::new(config);
+            let inicio = redis.escuchar().and_then(|listener| {
+                let detencion = redis.detencion(&listener)?;
+                let direccion = match listener.local_addr() {
+                    Ok(d) => d,
+                    Err(_) => return Err(RedisError::Inicializacion),
+                };
+                Ok((listener, detencion, direccion))
+            });
+            match inicio {
+                Ok((listener, detencion, direccion)) => {
+                    if tx_inicio.send(Ok((detencion, direccion))).is_ok() {
+                        let _ = redis.atender(listener);
+                    }
+                }
+                Err(e) => {
+                    let _ = tx_inicio.send(Err(e));
+                }
+            }
+        });
+
+        match rx_inicio.recv() {
+            Ok(Ok((detencion, direccion))) => Ok(ServidorDePrueba {
+                direccion,
+                detencion,
+                logfile,
+                hilo: Some(hilo),
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(RedisError::Inicializacion),
+        }
+    }
+
+    /// Direccion en la que el servidor acepta conexiones
+    pub fn direccion(&self) -> SocketAddr {
+        self.direccion
+    }
+
+    /// Deja de aceptar conexiones y espera a que terminen las que estaban abiertas
+    pub fn detener(mut self) {
+        self.finalizar();
+    }
+
+    fn finalizar(&mut self) {
+        if let Some(hilo) = self.hilo.take() {
+            self.detencion.detener();
+            let _ = hilo.join();
+            let _ = fs::remove_file(&self.logfile);
+        }
+    }
+}
+
+impl Drop for ServidorDePrueba {
+    fn drop(&mut self) {
+        self.finalizar();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn enviar(conexion: &mut TcpStream, comando: &str, largo_respuesta: usize) -> String {
+        conexion.write_all(comando.as_bytes()).unwrap();
+        let mut respuesta = vec![0; largo_respuesta];
+        conexion.read_exact(&mut respuesta).unwrap();
+        String::from_utf8(respuesta).unwrap()
+    }
+
+    #[test]
+    fn cada_servidor_de_prueba_atiende_en_su_propio_puerto_con_sus_propios_datos() {
+        let primero = ServidorDePrueba::iniciar().unwrap();
+        let segundo = ServidorDePrueba::iniciar().unwrap();
+        assert_ne!(primero.direccion(), segundo.direccion());
+
+        let mut conexion = TcpStream::connect(primero.direccion()).unwrap();
+        let set = "*3\r\n$3\r\nSET\r\n$5\r\nclave\r\n$5\r\nvalor\r\n";
+        assert_eq!("+OK\r\n", enviar(&mut conexion, set, 5));
+        let get = "*2\r\n$3\r\nGET\r\n$5\r\nclave\r\n";
+        assert_eq!("$5\r\nvalor\r\n", enviar(&mut conexion, get, 11));
+        drop(conexion);
+
+        let mut conexion = TcpStream::connect(segundo.direccion()).unwrap();
+        assert_eq!("$-1\r\n", enviar(&mut conexion, get, 5));
+        drop(conexion);
+
+        primero.detener();
+        segundo.detener();
+    }
+}
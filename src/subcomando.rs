@@ -0,0 +1,205 @@
+use crate::base_de_datos::ResultadoRedis;
+use crate::comando_info::ComandoInfo;
+
+/// Subcomando registrado junto con su aridad y su ayuda
+struct Subcomando<F> {
+    nombre: &'static str,
+    aridad: isize,
+    uso: &'static str,
+    descripcion: &'static str,
+    funcion: F,
+}
+
+/// Despachador reutilizable para los comandos que se dividen en subcomandos (CONFIG GET, PUBSUB CHANNELS, ...).
+/// Valida el nombre y la aridad del subcomando y genera automaticamente la respuesta de HELP.
+///
+/// La aridad sigue la convencion de redis: cuenta el comando, el subcomando y sus argumentos,
+/// y si es negativa indica la cantidad minima de elementos.
+///
+/// # Ejemplo
+/// ```ignore
+/// let subcomandos = Subcomandos::<FuncionServer>::new("CONFIG")
+///     .agregar("GET", 3, "GET <parameter>", "Return the value of the parameter.", config_get);
+///
+/// match subcomandos.resolver(comando) {
+///     Ok(f) => f(comando, bdd, config),
+///     Err(respuesta) => respuesta,
+/// }
+/// ```
+pub struct Subcomandos<F> {
+    comando: &'static str,
+    subcomandos: Vec<Subcomando<F>>,
+}
+
+impl<F: Copy> Subcomandos<F> {
+    pub fn new(comando: &'static str) -> Self {
+        Subcomandos {
+            comando,
+            subcomandos: Vec::new(),
+        }
+    }
+
+    /// Registra un subcomando con su aridad, una linea de uso y una descripcion para HELP
+    pub fn agregar(
+        mut self,
+        nombre: &'static str,
+        aridad: isize,
+        uso: &'static str,
+        descripcion: &'static str,
+        funcion: F,
+    ) -> Self {
+        self.subcomandos.push(Subcomando {
+            nombre,
+            aridad,
+            uso,
+            descripcion,
+            funcion,
+        });
+        self
+    }
+
+    /// Consume el nombre del subcomando y devuelve la funcion que lo ejecuta.
+    /// Si se pidio HELP o el subcomando no es valido devuelve la respuesta que se le debe enviar al cliente
+    pub fn resolver(&self, comando: &mut ComandoInfo) -> Result<F, ResultadoRedis> {
+        let cantidad = comando.len() as isize + 1;
+        let nombre = match comando.get_parametro() {
+            Some(n) => n,
+            None => {
+                return Err(ResultadoRedis::Error(format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    self.comando.to_lowercase()
+                )))
+            }
+        };
+
+        let nombre_mayuscula = nombre.to_uppercase();
+        if nombre_mayuscula == "HELP" && cantidad == 2 {
+            return Err(self.ayuda());
+        }
+
+        let subcomando = match self
+            .subcomandos
+            .iter()
+            .find(|s| s.nombre == nombre_mayuscula)
+        {
+            Some(s) => s,
+            None => {
+                return Err(ResultadoRedis::Error(format!(
+                    "ERR unknown subcommand '{}'. Try {} HELP.",
+                    nombre, self.comando
+                )))
+            }
+        };
+
+        let aridad_valida = if subcomando.aridad >= 0 {
+            cantidad == subcomando.aridad
+        } else {
+            cantidad >= -subcomando.aridad
+        };
+        if !aridad_valida {
+            return Err(ResultadoRedis::Error(format!(
+                "ERR wrong number of arguments for '{}|{}' command",
+                self.comando.to_lowercase(),
+                subcomando.nombre.to_lowercase()
+            )));
+        }
+
+        Ok(subcomando.funcion)
+    }
+
+    /// Respuesta estandar de HELP con todos los subcomandos registrados
+    pub fn ayuda(&self) -> ResultadoRedis {
+        let mut lineas = vec![format!(
+            "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            self.comando
+        )];
+        for subcomando in &self.subcomandos {
+            lineas.push(subcomando.uso.to_string());
+            lineas.push(format!("    {}", subcomando.descripcion));
+        }
+        lineas.push("HELP".to_string());
+        lineas.push("    Print this help.".to_string());
+
+        ResultadoRedis::Vector(lineas.into_iter().map(ResultadoRedis::StrSimple).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Funcion = fn(&mut ComandoInfo) -> ResultadoRedis;
+
+    fn get(comando: &mut ComandoInfo) -> ResultadoRedis {
+        ResultadoRedis::BulkStr(comando.get_parametro().unwrap_or_default())
+    }
+
+    fn subcomandos() -> Subcomandos<Funcion> {
+        Subcomandos::<Funcion>::new("CONFIG").agregar(
+            "GET",
+            3,
+            "GET <parameter>",
+            "Return the value of the parameter.",
+            get,
+        )
+    }
+
+    fn comando(tokens: &[&str]) -> ComandoInfo {
+        ComandoInfo::new(tokens.iter().map(|t| t.to_string()).collect())
+    }
+
+    #[test]
+    fn resolver_devuelve_el_subcomando_sin_importar_mayusculas_y_consume_su_nombre() {
+        let mut comando = comando(&["config", "get", "port"]);
+        let funcion = subcomandos().resolver(&mut comando).unwrap();
+
+        assert_eq!(
+            ResultadoRedis::BulkStr("port".to_string()),
+            funcion(&mut comando)
+        );
+    }
+
+    #[test]
+    fn resolver_valida_el_nombre_y_la_aridad_del_subcomando() {
+        let subcomandos = subcomandos();
+
+        assert_eq!(
+            Some(ResultadoRedis::Error(
+                "ERR unknown subcommand 'foo'. Try CONFIG HELP.".to_string()
+            )),
+            subcomandos.resolver(&mut comando(&["config", "foo"])).err()
+        );
+        assert_eq!(
+            Some(ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'config|get' command".to_string()
+            )),
+            subcomandos.resolver(&mut comando(&["config", "get"])).err()
+        );
+        assert_eq!(
+            Some(ResultadoRedis::Error(
+                "ERR wrong number of arguments for 'config' command".to_string()
+            )),
+            subcomandos.resolver(&mut comando(&["config"])).err()
+        );
+    }
+
+    #[test]
+    fn help_lista_los_subcomandos_registrados() {
+        let ayuda = subcomandos()
+            .resolver(&mut comando(&["config", "help"]))
+            .err();
+
+        assert_eq!(
+            Some(ResultadoRedis::Vector(vec![
+                ResultadoRedis::StrSimple(
+                    "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".to_string()
+                ),
+                ResultadoRedis::StrSimple("GET <parameter>".to_string()),
+                ResultadoRedis::StrSimple("    Return the value of the parameter.".to_string()),
+                ResultadoRedis::StrSimple("HELP".to_string()),
+                ResultadoRedis::StrSimple("    Print this help.".to_string()),
+            ])),
+            ayuda
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Intervalo entre avances de la rueda, los plazos se redondean hacia arriba a un multiplo
+const RESOLUCION: Duration = Duration::from_millis(10);
+
+/// Cantidad de ranuras de la rueda, una vuelta completa cubre RANURAS * RESOLUCION
+const RANURAS: usize = 512;
+
+/// Motivo por el que termina una espera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Despertar {
+    /// Ocurrio el evento que se esperaba, conviene volver a revisar la condicion
+    Notificado,
+    /// Se cumplio el plazo programado
+    Vencido,
+}
+
+#[derive(Debug, Default)]
+struct EstadoEspera {
+    notificado: bool,
+    vencido: bool,
+}
+
+/// Espera de un comando bloqueante, la despiertan quien produce el evento esperado con
+/// `notificar` o la rueda de temporizadores al vencer su plazo. Las notificaciones que llegan
+/// mientras no se esta esperando no se pierden
+#[derive(Debug, Default)]
+pub struct Espera {
+    estado: Mutex<EstadoEspera>,
+    despertar: Condvar,
+}
+
+impl Espera {
+    pub fn new() -> Self {
+        Espera::default()
+    }
+
+    fn estado(&self) -> MutexGuard<'_, EstadoEspera> {
+        match self.estado.lock() {
+            Ok(e) => e,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    pub fn notificar(&self) {
+        self.estado().notificado = true;
+        self.despertar.notify_all();
+    }
+
+    pub fn vencer(&self) {
+        self.estado().vencido = true;
+        self.despertar.notify_all();
+    }
+
+    /// Bloquea hasta que llegue una notificacion o venza el plazo. Una vez vencido, todas
+    /// las esperas siguientes devuelven Vencido
+    pub fn esperar(&self) -> Despertar {
+        let mut estado = self.estado();
+        while !estado.notificado && !estado.vencido {
+            estado = match self.despertar.wait(estado) {
+                Ok(e) => e,
+                Err(envenenado) => envenenado.into_inner(),
+            };
+        }
+        if estado.vencido {
+            return Despertar::Vencido;
+        }
+        estado.notificado = false;
+        Despertar::Notificado
+    }
+}
+
+#[derive(Debug)]
+struct Temporizador {
+    /// Vueltas completas que faltan antes de que venza al pasar por su ranura
+    vueltas: usize,
+    espera: Weak<Espera>,
+}
+
+#[derive(Debug)]
+struct Rueda {
+    ranuras: Vec<Vec<Temporizador>>,
+    posicion: usize,
+    cantidad: usize,
+    /// Hay un hilo avanzando la rueda, termina cuando no quedan temporizadores
+    activa: bool,
+}
+
+/// Rueda de temporizadores compartida por todos los comandos bloqueantes. Un unico hilo la
+/// avanza cada RESOLUCION mientras haya temporizadores programados y vence las esperas cuyo
+/// plazo se cumplio, en lugar de que cada comando bloqueante duerma en su propio hilo.
+/// Programar y vencer cuestan O(1); una espera que termina antes de tiempo no necesita
+/// cancelarse, su temporizador se descarta al llegar a su ranura
+#[derive(Debug)]
+pub struct RuedaDeTemporizadores {
+    rueda: Mutex<Rueda>,
+}
+
+impl Default for RuedaDeTemporizadores {
+    fn default() -> Self {
+        RuedaDeTemporizadores {
+            rueda: Mutex::new(Rueda {
+                ranuras: (0..RANURAS).map(|_| Vec::new()).collect(),
+                posicion: 0,
+                cantidad: 0,
+                activa: false,
+            }),
+        }
+    }
+}
+
+impl RuedaDeTemporizadores {
+    pub fn new() -> Self {
+        RuedaDeTemporizadores::default()
+    }
+
+    fn rueda(&self) -> MutexGuard<'_, Rueda> {
+        match self.rueda.lock() {
+            Ok(r) => r,
+            Err(envenenado) => envenenado.into_inner(),
+        }
+    }
+
+    /// Programa el vencimiento de la espera dentro del plazo indicado
+    pub fn programar(self: &Arc<Self>, espera: &Arc<Espera>, plazo: Duration) {
+        let avances = plazo.as_micros().div_ceil(RESOLUCION.as_micros()).max(1) as usize;
+        let mut rueda = self.rueda();
+        let ranura = (rueda.posicion + avances) % RANURAS;
+        rueda.ranuras[ranura].push(Temporizador {
+            vueltas: (avances - 1) / RANURAS,
+            espera: Arc::downgrade(espera),
+        });
+        rueda.cantidad += 1;
+        if !rueda.activa {
+            rueda.activa = true;
+            self.iniciar();
+        }
+    }
+
+    /// Inicia el hilo que avanza la rueda segun el tiempo transcurrido, para no acumular el
+    /// desfasaje de cada sleep. Termina cuando no quedan temporizadores o se libera la rueda
+    fn iniciar(self: &Arc<Self>) {
+        let rueda = Arc::downgrade(self);
+        thread::spawn(move || {
+            let inicio = Instant::now();
+            let mut avances = 0;
+            loop {
+                thread::sleep(RESOLUCION);
+                let temporizadores = match rueda.upgrade() {
+                    Some(t) => t,
+                    None => return,
+                };
+                let objetivo = inicio.elapsed().as_micros() / RESOLUCION.as_micros();
+                while avances < objetivo {
+                    temporizadores.avanzar();
+                    avances += 1;
+                }
+                let mut rueda = temporizadores.rueda();
+                if rueda.cantidad == 0 {
+                    rueda.activa = false;
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Avanza una ranura y vence los temporizadores que completaron sus vueltas
+    fn avanzar(&self) {
+        let mut vencidas = Vec::new();
+        {
+            let mut rueda = self.rueda();
+            rueda.posicion = (rueda.posicion + 1) % RANURAS;
+            let posicion = rueda.posicion;
+            let ranura = std::mem::take(&mut rueda.ranuras[posicion]);
+            let mut quedan = Vec::new();
+            for mut temporizador in ranura {
+                if temporizador.vueltas > 0 {
+                    temporizador.vueltas -= 1;
+                    quedan.push(temporizador);
+                } else {
+                    vencidas.push(temporizador.espera);
+                }
+            }
+            rueda.cantidad -= vencidas.len();
+            rueda.ranuras[posicion] = quedan;
+        }
+        for espera in vencidas.iter().filter_map(Weak::upgrade) {
+            espera.vencer();
+        }
+    }
+
+    /// Cantidad de temporizadores programados que aun no vencieron
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.rueda().cantidad
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn la_espera_vence_al_cumplirse_el_plazo() {
+        let rueda = Arc::new(RuedaDeTemporizadores::new());
+        let espera = Arc::new(Espera::new());
+        let inicio = Instant::now();
+        rueda.programar(&espera, Duration::from_millis(50));
+
+        assert_eq!(Despertar::Vencido, espera.esperar());
+        assert!(inicio.elapsed() >= Duration::from_millis(50));
+        assert_eq!(Despertar::Vencido, espera.esperar());
+        assert_eq!(0, rueda.len());
+    }
+
+    #[test]
+    fn una_notificacion_despierta_antes_del_plazo_y_no_se_pierde() {
+        let rueda = Arc::new(RuedaDeTemporizadores::new());
+        let espera = Arc::new(Espera::new());
+        rueda.programar(&espera, Duration::from_secs(60));
+
+        espera.notificar();
+        assert_eq!(Despertar::Notificado, espera.esperar());
+
+        let notificadora = Arc::clone(&espera);
+        let hilo = thread::spawn(move || notificadora.notificar());
+        assert_eq!(Despertar::Notificado, espera.esperar());
+        hilo.join().unwrap();
+    }
+
+    #[test]
+    fn los_plazos_de_mas_de_una_vuelta_esperan_las_vueltas_completas() {
+        let rueda = RuedaDeTemporizadores::new();
+        let espera = Arc::new(Espera::new());
+        rueda.rueda().ranuras[2].push(Temporizador {
+            vueltas: 1,
+            espera: Arc::downgrade(&espera),
+        });
+        rueda.rueda().cantidad = 1;
+
+        for _ in 0..2 {
+            rueda.avanzar();
+        }
+        assert_eq!(1, rueda.len());
+        for _ in 0..RANURAS {
+            rueda.avanzar();
+        }
+        assert_eq!(0, rueda.len());
+        assert_eq!(Despertar::Vencido, espera.esperar());
+    }
+}
@@ -1,25 +1,25 @@
 use crate::base_de_datos::TipoRedis;
+use crate::codificacion::LimitesDeCodificacion;
+use crate::desfragmentacion;
 
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Representa el valor que se almacena en la base de datos,
-/// este esta compuesto por un TipoRedis y su expiracion
+/// este esta compuesto por un TipoRedis y el instante absoluto en el que expira
 #[derive(Clone)]
 pub struct Valor {
     valor: TipoRedis,
-    momento_de_creacion: Instant,
     ultimo_acceso: Instant,
-    vida_util: Option<Duration>,
+    expira_en: Option<SystemTime>,
 }
 
 impl Valor {
-    /// Instancia un Valor expirable con una determinada vida util
-    pub fn expirable(valor: TipoRedis, vida_util: u64) -> Self {
+    /// Instancia un Valor que expira en el instante absoluto indicado
+    pub fn expirable_en(valor: TipoRedis, expira_en: SystemTime) -> Self {
         Valor {
             valor,
-            momento_de_creacion: Instant::now(),
             ultimo_acceso: Instant::now(),
-            vida_util: Some(Duration::from_secs(vida_util)),
+            expira_en: Some(expira_en),
         }
     }
 
@@ -27,51 +27,83 @@ impl Valor {
     pub fn no_expirable(valor: TipoRedis) -> Self {
         Valor {
             valor,
-            momento_de_creacion: Instant::now(),
             ultimo_acceso: Instant::now(),
-            vida_util: None,
+            expira_en: None,
         }
     }
 
-    /// Predicado que responde si un valor expiro o no
-    pub fn expiro(&self) -> bool {
-        match self.vida_util {
-            Some(vida) => self.momento_de_creacion.elapsed() >= vida,
+    /// Predicado que responde si un valor ya estaba expirado en el instante indicado
+    pub fn esta_expirado_al(&self, ahora: SystemTime) -> bool {
+        match self.expira_en {
+            Some(instante) => ahora >= instante,
             None => false,
         }
     }
 
-    /// Obtiene el valor encapsulado,
-    /// devuelve algun valor en caso de que no haya expirado o ninguno si ya expiro
-    pub fn get(&self) -> Option<&TipoRedis> {
-        if !self.expiro() {
+    /// Obtiene el valor encapsulado, devuelve algun valor en caso de que no
+    /// hubiera expirado en el instante indicado o ninguno si ya expiro
+    pub fn get_al(&self, ahora: SystemTime) -> Option<&TipoRedis> {
+        if !self.esta_expirado_al(ahora) {
             Some(&self.valor)
         } else {
             None
         }
     }
 
-    /// Devuelve la duracion de la expiracion del valor en segundos,
-    /// puede ser alguna expiracion o ninguna en caso de que no expire
-    pub fn get_tiempo(&self) -> Option<Duration> {
-        self.vida_util
+    /// Reemplaza el dato encapsulado conservando la expiracion
+    pub fn reemplazar(&mut self, valor: TipoRedis) {
+        self.valor = valor;
     }
 
-    pub fn obtener_expiracion(&self) -> isize {
-        match self.vida_util {
-            Some(d) => d.as_secs() as isize,
+    /// Pasa el dato a su representacion general si supera los limites de la compacta
+    pub fn ajustar_codificacion(&mut self, limites: &LimitesDeCodificacion) {
+        limites.ajustar(&mut self.valor);
+    }
+
+    /// Libera la capacidad sobrante de las colecciones del dato
+    pub fn compactar(&mut self) {
+        desfragmentacion::compactar(&mut self.valor);
+    }
+
+    /// Devuelve el instante absoluto en el que expira el valor
+    /// o ninguno en caso de que no expire
+    pub fn expira_en(&self) -> Option<SystemTime> {
+        self.expira_en
+    }
+
+    /// Devuelve el tiempo que le queda de vida al valor a partir del instante indicado,
+    /// cero si ya expiro o ninguno en caso de que no expire
+    pub fn tiempo_restante_al(&self, ahora: SystemTime) -> Option<Duration> {
+        self.expira_en
+            .map(|instante| instante.duration_since(ahora).unwrap_or(Duration::ZERO))
+    }
+
+    /// Devuelve los segundos que le quedan de vida al valor, contando desde el instante indicado,
+    /// o -1 si no expira
+    pub fn obtener_expiracion_al(&self, ahora: SystemTime) -> isize {
+        match self.tiempo_restante_al(ahora) {
+            Some(d) => (d.as_millis() as isize + 500) / 1000,
             None => -1,
         }
     }
 
-    /// Resetea la expiracion con la nueva a partir de llamar a este mensaje
-    pub fn actualizar_expiracion(&mut self, nueva_expiracion: u64) {
-        self.momento_de_creacion = Instant::now();
-        self.vida_util = Some(Duration::from_secs(nueva_expiracion));
+    /// Devuelve los milisegundos que le quedan de vida al valor, contando desde el instante
+    /// indicado, o -1 si no expira
+    pub fn obtener_expiracion_en_milisegundos_al(&self, ahora: SystemTime) -> isize {
+        match self.tiempo_restante_al(ahora) {
+            Some(d) => d.as_millis() as isize,
+            None => -1,
+        }
+    }
+
+    /// Hace que el valor expire en el instante absoluto indicado
+    pub fn expirar_en(&mut self, instante: SystemTime) {
+        self.expira_en = Some(instante);
     }
 
-    pub fn hacer_persistente(&mut self) {
-        self.vida_util = None;
+    /// Quita la expiracion del valor, devuelve si el valor tenia una expiracion asociada
+    pub fn persistir(&mut self) -> bool {
+        self.expira_en.take().is_some()
     }
 
     pub fn actualizar_ultimo_acceso(&mut self) {
@@ -82,22 +114,60 @@ impl Valor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
+    use crate::reloj::{Reloj, RelojSimulado};
 
     #[test]
     fn cuando_se_crea_una_valor_no_expirable_este_no_expira_nunca() {
-        let valor = Valor::no_expirable(TipoRedis::Str("miClave".to_string()));
+        let valor = Valor::no_expirable(TipoRedis::Str("miClave".into()));
 
-        assert!(!valor.expiro());
+        assert!(!valor.esta_expirado_al(SystemTime::now() + Duration::from_secs(3600)));
+        assert_eq!(None, valor.expira_en());
     }
 
     #[test]
     fn cuando_se_espera_mas_tiempo_del_que_se_dijo_que_una_clave_expiraba_la_clave_efectivamente_esta_espirada(
     ) {
-        let valor = Valor::expirable(TipoRedis::Str("miClave".to_string()), 1);
+        let reloj = RelojSimulado::new();
+        let valor = Valor::expirable_en(
+            TipoRedis::Str("miClave".into()),
+            reloj.ahora() + Duration::from_millis(100),
+        );
+
+        reloj.avanzar(Duration::from_millis(99));
+        assert!(!valor.esta_expirado_al(reloj.ahora()));
+        reloj.avanzar(Duration::from_millis(1));
+
+        assert!(valor.esta_expirado_al(reloj.ahora()));
+        assert!(valor.get_al(reloj.ahora()).is_none());
+    }
 
-        thread::sleep(Duration::from_secs(2));
+    #[test]
+    fn el_tiempo_restante_se_calcula_a_partir_del_instante_de_expiracion() {
+        let reloj = RelojSimulado::new();
+        let valor = Valor::expirable_en(
+            TipoRedis::Str("miClave".into()),
+            reloj.ahora() + Duration::from_secs(10),
+        );
+
+        reloj.avanzar(Duration::from_millis(50));
+
+        assert_eq!(
+            9950,
+            valor.obtener_expiracion_en_milisegundos_al(reloj.ahora())
+        );
+        assert_eq!(10, valor.obtener_expiracion_al(reloj.ahora()));
+    }
 
-        assert!(valor.expiro());
+    #[test]
+    fn persistir_quita_la_expiracion_e_indica_si_el_valor_era_volatil() {
+        let reloj = RelojSimulado::new();
+        let mut valor = Valor::expirable_en(
+            TipoRedis::Str("miClave".into()),
+            reloj.ahora() + Duration::from_secs(10),
+        );
+
+        assert!(valor.persistir());
+        assert!(!valor.persistir());
+        assert_eq!(-1, valor.obtener_expiracion_al(reloj.ahora()));
     }
 }